@@ -1,18 +1,57 @@
 use crate::config::config_command::ConfigCommand;
 use crate::config::loader::{load_config, load_config_file};
 use crate::config::schema::Config;
-use crate::error::{CpxError, CpxResult};
+use crate::core::archive::{self, ArchiveFormat};
+use crate::core::bench::{self, Backend, BenchConfig};
+use crate::core::check;
+use crate::core::compress::{self, Compression};
+use crate::core::copy::plan_sources;
+use crate::core::diff;
+use crate::core::doctor;
+use crate::core::extract;
+use crate::core::plan::{PlanFile, PlanFormat};
+use crate::core::remote::RemoteTarget;
+use crate::core::verify::{self, Compare};
+use crate::error::{CpxError, CpxResult, ExitCode};
 use crate::utility::helper::parse_progress_bar;
-use crate::utility::progress_bar::ProgressOptions;
+use crate::utility::progress_bar::{ProgressMode, ProgressOptions, ProgressSetting, ScanProgress};
 use crate::utility::{
-    exclude::{ExcludePattern, ExcludeRules, build_exclude_rules, parse_exclude_pattern_list},
-    helper::{parse_backup_mode, parse_follow_symlink, parse_reflink_mode, parse_symlink_mode},
-    preserve::PreserveAttr,
+    autotune,
+    barrier::{BarrierSpec, WriteBarrier},
+    chattr::Chattr,
+    chmod::Chmod,
+    control::CopyControl,
+    error_report::ErrorReportFormat,
+    events::EventBus,
+    exclude::{
+        ExcludeMarkers, ExcludePattern, ExcludeRules, build_exclude_rules, builtin_patterns,
+        parse_exclude_pattern_list,
+    },
+    glob,
+    hash::HashAlgo,
+    helper::{
+        parse_backup_mode, parse_conflict_policy, parse_copy_backend, parse_dest_symlinks,
+        parse_file_order, parse_follow_symlink, parse_fsync_mode, parse_hidden_mode,
+        parse_io_priority, parse_junction_mode, parse_modify_window, parse_notify,
+        parse_reflink_mode, parse_sanitize_mode, parse_symlink_fallback, parse_symlink_mode,
+        parse_write_barrier,
+    },
+    history,
+    job::{self, Job},
+    journal::{self, Journal},
+    logger::{self, LogFormat, LogLevel, Logger},
+    notify::DEFAULT_NOTIFY_AFTER,
+    output::Verbosity,
+    preserve::{Chown, PreserveAttr},
+    stats::StatsFormat,
+    status::{self, StatusRequest},
 };
-use clap::{Args, Parser, Subcommand, ValueEnum};
-use std::path::PathBuf;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum SymlinkMode {
@@ -38,6 +77,219 @@ pub enum BackupMode {
     Simple,
 }
 
+/// How `--sanitize-names` rewrites characters the destination refuses.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum SanitizeMode {
+    /// with an underscore
+    #[default]
+    Replace,
+    /// as %XX escapes of their UTF-8 bytes
+    Percent,
+}
+
+/// The order files are handed to the copy workers in.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum FileOrder {
+    /// largest first, so the big files overlap the small ones
+    #[default]
+    SizeDesc,
+    /// smallest first, so most files are done early
+    SizeAsc,
+    /// by destination path, for deterministic output
+    Path,
+    /// as the scan found them
+    None,
+}
+
+/// `-j`: a fixed number of parallel copies, or `auto` to tune it while
+/// the copy runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jobs {
+    Auto,
+    Count(usize),
+}
+
+impl std::str::FromStr for Jobs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Jobs::Auto),
+            _ => match s.parse() {
+                Ok(0) => Err("must be at least 1".to_string()),
+                Ok(count) => Ok(Jobs::Count(count)),
+                Err(_) => Err(format!("expected a number or 'auto', got '{}'", s)),
+            },
+        }
+    }
+}
+
+impl Jobs {
+    /// Workers to start: the count, or the most `auto` may grow to.
+    pub fn workers(self) -> usize {
+        match self {
+            Jobs::Auto => autotune::MAX_WORKERS,
+            Jobs::Count(count) => count,
+        }
+    }
+}
+
+/// What is flushed to disk before a copy reports success.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum FsyncMode {
+    #[default]
+    None,
+    /// each destination file
+    File,
+    /// the directories holding new entries and backups
+    Dir,
+    /// both
+    All,
+}
+
+impl FsyncMode {
+    pub fn files(self) -> bool {
+        matches!(self, FsyncMode::File | FsyncMode::All)
+    }
+
+    pub fn dirs(self) -> bool {
+        matches!(self, FsyncMode::Dir | FsyncMode::All)
+    }
+}
+
+/// The disk priority the copy runs at (`--ionice`).
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Default)]
+pub enum IoPriority {
+    #[default]
+    Normal,
+    /// the lowest best-effort level; background mode on Windows
+    Low,
+    /// only when no other process is using the disk
+    Idle,
+}
+
+/// How file contents are copied once reflinking is ruled out (`--backend`).
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Default)]
+pub enum CopyBackend {
+    /// copy_file_range where it works, mmap for files of 1-64 MiB on local
+    /// filesystems, read/write otherwise
+    #[default]
+    Auto,
+    /// in-kernel copy (Linux)
+    CopyFileRange,
+    /// map the source and write from the mapping, on local filesystems
+    Mmap,
+    /// read/write through a buffer
+    Buffered,
+}
+
+/// What happens when a destination already exists.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// replace it
+    #[default]
+    Overwrite,
+    /// keep it
+    Skip,
+    /// replace it if the source is newer
+    Newer,
+    /// replace it if the source is larger
+    Larger,
+    /// keep it and write the copy as "name (1).ext"
+    Rename,
+    /// ask for each one
+    Prompt,
+    /// fail that file
+    Error,
+}
+
+/// How `--verify` checks each copied file against the source digest.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum VerifyMode {
+    /// re-read the destination
+    #[default]
+    Hash,
+    /// flush it and re-read it from the disk, past the page cache
+    Readback,
+}
+
+/// What a recursive copy does with Windows junctions and mount points.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum JunctionMode {
+    /// leave them out
+    Skip,
+    /// create a junction to the same target
+    #[default]
+    Recreate,
+    /// copy what they point to, skipping junctions that loop
+    Follow,
+}
+
+/// What a copy does with a symlink already where it writes a file or
+/// directory.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DestSymlinks {
+    /// write through it, into what it points to
+    #[default]
+    Follow,
+    /// replace the link itself (`--no-dereference-dest`)
+    Replace,
+    /// replace it unless it points to a directory (`--keep-directory-symlink`)
+    KeepDirs,
+}
+
+impl DestSymlinks {
+    /// Whether `path` is a symlink this policy replaces rather than
+    /// follows.
+    pub fn replaces(self, path: &Path) -> bool {
+        let is_link = || std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+        match self {
+            DestSymlinks::Follow => false,
+            DestSymlinks::Replace => is_link(),
+            DestSymlinks::KeepDirs => {
+                is_link() && !std::fs::metadata(path).is_ok_and(|m| m.is_dir())
+            }
+        }
+    }
+}
+
+/// Whether a recursive copy takes hidden entries: dotfiles on Unix, the
+/// hidden attribute on Windows.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum HiddenMode {
+    /// copy them like anything else
+    #[default]
+    Include,
+    /// leave them out, directories with everything in them
+    Skip,
+}
+
+/// What to do when the OS won't let cpx create a symlink (no privilege on
+/// Windows, a filesystem without symlinks).
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum SymlinkFallback {
+    /// copy the file it points to; directories become junctions on Windows
+    Copy,
+    /// leave it out with a warning
+    Skip,
+    /// fail the link
+    #[default]
+    Error,
+}
+
+/// What to do with a file another process holds open exclusively (a
+/// sharing violation on Windows).
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum LockedFiles {
+    /// fail the file
+    #[default]
+    Fail,
+    /// retry with backoff, as often as --retries allows (at least 3 times)
+    Retry,
+    /// leave it out with a warning
+    Skip,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum FollowSymlink {
     NoDereference,
@@ -48,23 +300,94 @@ pub enum FollowSymlink {
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Default (Implicit)
-    Copy(CopyArgs),
+    Copy(Box<CopyArgs>),
+
+    /// Copy, then keep copying sources as they change
+    Watch(Box<WatchArgs>),
+
+    /// Compare a copy against its source
+    Verify(VerifyArgs),
+
+    /// Show what copying SOURCE over DESTINATION would change
+    Diff(DiffArgs),
+
+    /// Print the copy plan as JSON or CSV without copying anything
+    Plan(Box<PlanArgs>),
+
+    /// Run a plan written by `cpx plan`
+    Apply(ApplyArgs),
+
+    /// Check that every destination path can be created, without copying
+    Check(Box<CheckArgs>),
+
+    /// Revert the changes recorded by --journal
+    Undo(UndoArgs),
+
+    /// Time copy backends, buffer sizes and parallelism on this machine
+    Bench(BenchArgs),
+
+    /// Show what the source and destination filesystems support, and what a
+    /// copy between them can't preserve
+    Doctor(DoctorArgs),
+
+    /// Show running copies, or pause and resume them
+    Status(StatusArgs),
+
+    /// List past runs, or show one in detail
+    History(HistoryArgs),
 
     /// Manage configuration
     Config {
         #[command(subcommand)]
         command: ConfigCommand,
     },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
 }
 
+const VERIFY_EXIT_CODES_HELP: &str = "\
+Exit status:
+  0    source and destination match
+  1    missing, extra or differing entries were found, or a side could not be read
+  2    invalid arguments or exclude patterns";
+
+const DIFF_EXIT_CODES_HELP: &str = "\
+Exit status:
+  0    nothing would change
+  1    the trees differ, or a side could not be read
+  2    invalid arguments or exclude patterns";
+
+const UNDO_EXIT_CODES_HELP: &str = "\
+Exit status:
+  0    every change was undone
+  1    some changes could not be undone, or the journal could not be read";
+
+const CHECK_EXIT_CODES_HELP: &str = "\
+Exit status:
+  0    every destination path can be created
+  1    some paths are too long, invalid or collide on the destination
+  2    invalid arguments, options or configuration";
+
+const EXIT_CODES_HELP: &str = "\
+Exit status:
+  0    all files copied
+  1    one or more files failed to copy
+  2    invalid arguments, options or configuration
+  130  interrupted by SIGINT/SIGTERM (resume with --resume)";
+
 #[derive(Parser, Debug)]
-#[command(name = "cpx",version = env!("CARGO_PKG_VERSION"))]
+#[command(name = "cpx",version = env!("CARGO_PKG_VERSION"), after_help = EXIT_CODES_HELP)]
 pub struct CLIArgs {
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXIT_CODES_HELP)]
 pub struct CopyArgs {
     // Input/Output Options
     #[arg(help = "Source file(s) or directory(ies)", required = true)]
@@ -81,6 +404,12 @@ pub struct CopyArgs {
     )]
     pub target_directory: Option<PathBuf>,
 
+    #[arg(
+        long = "glob",
+        help = "expand wildcards in SOURCE arguments the shell left alone (always on Windows)"
+    )]
+    pub glob: bool,
+
     #[arg(
         short = 'e',
         long = "exclude",
@@ -89,129 +418,1143 @@ pub struct CopyArgs {
     )]
     pub exclude: Vec<String>,
 
+    #[arg(
+        long = "exclude-if-present",
+        value_name = "FILE",
+        help = "skip directories containing FILE (can be specified multiple times)"
+    )]
+    pub exclude_if_present: Vec<String>,
+
+    #[arg(
+        long = "exclude-vcs",
+        help = "exclude version control directories (.git, .hg, .svn, ...)"
+    )]
+    pub exclude_vcs: bool,
+
+    #[arg(
+        long = "exclude-common",
+        help = "exclude version control, node_modules, target, __pycache__ and .DS_Store"
+    )]
+    pub exclude_common: bool,
+
+    #[arg(
+        long = "hidden",
+        value_name = "POLICY",
+        help = "hidden files and directories in recursive copies (include, skip)"
+    )]
+    pub hidden: Option<HiddenMode>,
+
+    #[arg(
+        long = "exclude-caches",
+        help = "skip directories tagged with a CACHEDIR.TAG file"
+    )]
+    pub exclude_caches: bool,
+
+    #[arg(
+        long = "prune-empty-dirs",
+        help = "don't create directories left with no files after excludes and filters"
+    )]
+    pub prune_empty_dirs: bool,
+
+    #[arg(
+        long = "sanitize-names",
+        value_name = "MODE",
+        default_missing_value = "replace",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "rename entries whose names the destination filesystem refuses (replace, percent)"
+    )]
+    pub sanitize_names: Option<SanitizeMode>,
+
     // Copy Behavior Options
     #[arg(short, long, help = "Copy directories recursively")]
     pub recursive: bool,
 
     #[arg(
-        short = 'j',
-        default_value_t = 4,
-        help = "Number of parallel copy operations for multiple files"
+        short = 'a',
+        long = "archive",
+        help = "same as -r -P --preserve=all, like cp -a"
+    )]
+    pub archive_mode: bool,
+
+    #[arg(
+        short = 'j',
+        value_name = "N|auto",
+        default_value = "4",
+        help = "Number of parallel copy operations for multiple files, or auto to tune it as the copy runs"
+    )]
+    pub parallel: Jobs,
+
+    #[arg(long = "resume", help = "resume interrupted transfers")]
+    pub resume: bool,
+
+    #[arg(
+        long = "partial",
+        help = "keep unfinished files as name.cpx-partial and continue them on the next run"
+    )]
+    pub partial: bool,
+
+    #[arg(
+        long = "partial-dir",
+        value_name = "DIR",
+        help = "keep unfinished files in DIR (relative to each file's directory) instead; implies --partial"
+    )]
+    pub partial_dir: Option<PathBuf>,
+
+    #[arg(
+        long = "inplace",
+        conflicts_with_all = ["remove_destination", "partial", "partial_dir"],
+        help = "overwrite existing destinations in place instead of truncating them first (e.g. block devices)"
+    )]
+    pub inplace: bool,
+
+    #[arg(
+        short = 'f',
+        long,
+        help = "if an existing destination file cannot be opened, remove it and try again; also allows writing to a block device"
+    )]
+    pub force: bool,
+
+    #[arg(short = 'i', long, help = "prompt before overwrite")]
+    pub interactive: bool,
+
+    #[arg(
+        long = "on-conflict",
+        value_name = "POLICY",
+        conflicts_with = "interactive",
+        help = "what to do when a destination already exists (overwrite, skip, newer, larger, rename, prompt, error)"
+    )]
+    pub on_conflict: Option<ConflictPolicy>,
+
+    #[arg(
+        long = "confirm",
+        help = "show what the copy will do and ask before writing anything"
+    )]
+    pub confirm: bool,
+
+    #[arg(
+        short = 'y',
+        long = "yes",
+        visible_alias = "assume-yes",
+        conflicts_with = "confirm",
+        help = "answer yes to every prompt: overwrite without asking, and don't ask for confirmation even if the config sets confirm"
+    )]
+    pub yes: bool,
+
+    #[arg(
+        long = "assume-no",
+        conflicts_with = "yes",
+        help = "answer no to every prompt: keep existing destinations instead of asking, and decline --confirm"
+    )]
+    pub assume_no: bool,
+
+    #[arg(
+        long = "list-conflicts",
+        value_name = "FILE",
+        default_missing_value = "-",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "list the existing destinations the copy would overwrite, to stdout or FILE, and copy nothing"
+    )]
+    pub list_conflicts: Option<PathBuf>,
+
+    #[arg(long, help = "use full source file name under DIRECTORY")]
+    pub parents: bool,
+
+    #[arg(
+        long = "attributes-only",
+        help = "don't copy the file data, just the attributes"
+    )]
+    pub attributes_only: bool,
+
+    #[arg(
+        long = "remove-destination",
+        help = "remove each existing destination file before attempting to open it"
+    )]
+    pub remove_destination: bool,
+
+    // Link and Symlink Options
+    #[arg(
+        short = 's',
+        long = "symbolic-link",
+        value_name = "MODE",
+        default_missing_value = "auto",
+        num_args = 0..=1,
+        help = "make symbolic links instead of copying (auto, absolute, or relative)"
+    )]
+    pub symbolic_link: Option<SymlinkMode>,
+
+    #[arg(
+        short = 'l',
+        long = "link",
+        help = "hard link files instead of copying"
+    )]
+    pub hard_link: bool,
+
+    #[arg(
+        short = 'P',
+        long = "no-dereference",
+        help = "never follow symbolic links in SOURCE"
+    )]
+    pub no_dereference: bool,
+
+    #[arg(
+        long = "junctions",
+        value_name = "MODE",
+        help = "what to do with Windows junctions and mount points (skip, recreate, follow)"
+    )]
+    pub junctions: Option<JunctionMode>,
+
+    #[arg(
+        long = "symlink-fallback",
+        value_name = "POLICY",
+        help = "when symlinks can't be created: copy the target, skip, or error (copy, skip, error)"
+    )]
+    pub symlink_fallback: Option<SymlinkFallback>,
+
+    #[arg(
+        long = "no-dereference-dest",
+        help = "replace symlinks at the destination instead of writing through them"
+    )]
+    pub no_dereference_dest: bool,
+
+    #[arg(
+        long = "keep-directory-symlink",
+        help = "like --no-dereference-dest, but write through symlinks to directories"
+    )]
+    pub keep_directory_symlink: bool,
+
+    #[arg(
+        short = 'L',
+        long = "dereference",
+        help = "always follow symbolic links in SOURCE"
+    )]
+    pub dereference: bool,
+
+    #[arg(
+        short = 'H',
+        long = "dereference-command-line",
+        help = "follow symbolic links only on command line"
+    )]
+    pub dereference_command_line: bool,
+
+    // Preservation Options
+    #[arg(
+        short = 'p',
+        long = "preserve",
+        value_name = "ATTR_LIST",
+        default_missing_value = "",
+        help = "preserve the specified attributes"
+    )]
+    pub preserve: Option<String>,
+
+    #[arg(
+        long = "no-preserve",
+        value_name = "ATTR_LIST",
+        help = "don't preserve the specified attributes (e.g. attrs for Windows attribute bits)"
+    )]
+    pub no_preserve: Option<String>,
+
+    #[arg(
+        long = "chown",
+        value_name = "USER:GROUP",
+        help = "set the owner and/or group of every copy (USER, USER:GROUP or :GROUP)"
+    )]
+    pub chown: Option<Chown>,
+
+    #[arg(
+        long = "chmod",
+        value_name = "SPEC",
+        help = "adjust the permissions of every copy (e.g. D755,F644 or go-w,Dg+s)"
+    )]
+    pub chmod: Option<Chmod>,
+
+    #[arg(
+        long = "set-attr",
+        value_name = "FLAGS",
+        help = "set or clear file attribute flags on every copy, as chattr (e.g. +C or +c,-d; Linux)"
+    )]
+    pub set_attr: Option<Chattr>,
+
+    // Backup and Reflink Options
+    #[arg(
+        short = 'b',
+        long = "backup",
+        value_name = "CONTROL",
+        default_missing_value = "existing",
+        num_args = 0..=1,
+        help = "make a backup of each existing destination file (none, numbered, existing, simple)"
+    )]
+    pub backup: Option<BackupMode>,
+
+    #[arg(
+        long = "reflink",
+        value_name = "WHEN",
+        default_missing_value = "auto",
+        num_args = 0..=1,
+        help = "control clone/CoW copies (auto, always, never)"
+    )]
+    pub reflink: Option<ReflinkMode>,
+
+    #[arg(
+        long = "backend",
+        value_name = "BACKEND",
+        help = "how to copy file contents (auto, copy-file-range, mmap, buffered)"
+    )]
+    pub backend: Option<CopyBackend>,
+
+    #[arg(
+        long = "fsync",
+        value_name = "WHAT",
+        default_missing_value = "all",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "flush to disk before reporting success (none, file, dir, all)"
+    )]
+    pub fsync: Option<FsyncMode>,
+
+    #[arg(
+        long = "write-barrier",
+        value_name = "FILES,SIZE",
+        help = "fsync copied files and their directories in batches, every FILES files or SIZE bytes (e.g. 1000, 1G or 1000,1G)"
+    )]
+    pub write_barrier: Option<BarrierSpec>,
+
+    #[arg(
+        long = "order",
+        value_name = "ORDER",
+        help = "order files are copied in (size-desc, size-asc, path, none)"
+    )]
+    pub order: Option<FileOrder>,
+
+    #[arg(
+        long = "nice",
+        value_name = "N",
+        value_parser = clap::value_parser!(i32).range(0..=19),
+        help = "lower the CPU priority to niceness N (0-19)"
+    )]
+    pub nice: Option<i32>,
+
+    #[arg(
+        long = "ionice",
+        value_name = "CLASS",
+        help = "lower the disk priority (normal, low, idle)"
+    )]
+    pub ionice: Option<IoPriority>,
+
+    #[arg(
+        long = "deterministic",
+        help = "walk, copy and link in path order one at a time, so repeated runs log the same operations (implies -j 1 and --order=path)"
+    )]
+    pub deterministic: bool,
+
+    #[arg(
+        long = "modify-window",
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        help = "treat mtimes this close as equal, e.g. 2s for FAT or SMB destinations"
+    )]
+    pub modify_window: Option<Duration>,
+
+    #[arg(
+        long = "strip-zone-identifier",
+        help = "don't copy the Zone.Identifier stream that marks downloaded files (Windows)"
+    )]
+    pub strip_zone_identifier: bool,
+
+    #[arg(
+        long = "to-archive",
+        value_name = "FORMAT",
+        help = "write a tar archive instead of copying (tar, tar.zst); implied by a .tar or .tar.zst destination, - writes to stdout"
+    )]
+    pub to_archive: Option<ArchiveFormat>,
+
+    #[arg(
+        long = "compress",
+        value_name = "ALGO",
+        help = "compress each destination file on the fly, adding a .zst suffix (zstd)"
+    )]
+    pub compress: Option<Compression>,
+
+    #[arg(
+        long = "compress-level",
+        value_name = "LEVEL",
+        default_value_t = compress::DEFAULT_LEVEL,
+        value_parser = clap::value_parser!(i32).range(1..=22),
+        requires = "compress",
+        help = "compression level for --compress (1-22)"
+    )]
+    pub compress_level: i32,
+
+    #[arg(
+        long = "dedupe",
+        help = "hard-link destination files with identical content instead of writing them again"
+    )]
+    pub dedupe: bool,
+
+    #[arg(
+        long = "link-dest",
+        value_name = "DIR",
+        help = "hard-link files unchanged since the copy in DIR instead of copying them (relative to DESTINATION)"
+    )]
+    pub link_dest: Option<PathBuf>,
+
+    #[arg(
+        long = "decompress",
+        conflicts_with = "compress",
+        help = "decompress .zst sources, dropping the suffix; other files are copied as-is"
+    )]
+    pub decompress: bool,
+
+    // Output Options
+    #[arg(
+        long = "progress",
+        value_name = "STYLE|WHEN",
+        action = ArgAction::Append,
+        help = "progress style (default, detailed, json) or when to show it (auto, always, plain, never)"
+    )]
+    pub progress: Vec<ProgressSetting>,
+
+    #[arg(
+        long = "progress-interval",
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        help = "least time between plain status lines, e.g. 1m [default: 10s, or [progress] status_interval]"
+    )]
+    pub progress_interval: Option<Duration>,
+
+    #[arg(
+        long = "tui",
+        conflicts_with_all = ["progress", "verbose", "quiet", "stats", "interactive"],
+        help = "full-screen view with per-worker files, throughput and errors; p pauses, s skips, q aborts"
+    )]
+    pub tui: bool,
+
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        action = ArgAction::Count,
+        help = "explain what is being done (repeat for skip and exclude reasons)"
+    )]
+    pub verbose: u8,
+
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        conflicts_with = "verbose",
+        help = "suppress all output except errors"
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        long = "log-file",
+        value_name = "PATH",
+        help = "append a timestamped record of copied, skipped and failed files to PATH"
+    )]
+    pub log_file: Option<PathBuf>,
+
+    #[arg(
+        long = "log-level",
+        value_name = "LEVEL",
+        default_value = "info",
+        requires = "log_file",
+        help = "most detailed level written to the log file (error, warn, info, debug)"
+    )]
+    pub log_level: LogLevel,
+
+    #[arg(
+        long = "log-format",
+        value_name = "FORMAT",
+        default_value = "text",
+        requires = "log_file",
+        help = "log file format (text, json)"
+    )]
+    pub log_format: LogFormat,
+
+    #[arg(
+        long = "journal",
+        value_name = "PATH",
+        help = "append every file and directory written to PATH so `cpx undo PATH` can revert them"
+    )]
+    pub journal: Option<PathBuf>,
+
+    #[arg(
+        long = "job",
+        value_name = "NAME",
+        value_parser = job::parse_job_name,
+        help = "run as the named job: journaled for `cpx undo --job NAME`, listed by `cpx history --job NAME`, one run at a time"
+    )]
+    pub job: Option<String>,
+
+    #[arg(
+        long = "stats",
+        value_name = "FORMAT",
+        default_missing_value = "text",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "print a summary of the run when done (text, json)"
+    )]
+    pub stats: Option<StatsFormat>,
+
+    #[arg(
+        long = "output-manifest",
+        value_name = "PATH",
+        help = "write a checksum manifest of the copied files to PATH (or into PATH if it is a directory)"
+    )]
+    pub output_manifest: Option<PathBuf>,
+
+    #[arg(
+        long = "manifest-algo",
+        value_name = "ALGO",
+        requires = "output_manifest",
+        help = "checksum algorithm for --output-manifest (sha256, xxh3, crc32, blake3) [default: --checksum-algo, else sha256]"
+    )]
+    pub manifest_algo: Option<HashAlgo>,
+
+    #[arg(
+        long = "checksum-algo",
+        value_name = "ALGO",
+        help = "checksum for --verify, --resume and --dedupe comparisons, and manifests (sha256, xxh3, crc32, blake3) [default: xxh3]"
+    )]
+    pub checksum_algo: Option<HashAlgo>,
+
+    #[arg(
+        long = "verify",
+        value_name = "MODE",
+        default_missing_value = "hash",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "hash each file while copying and compare against a read-back of the destination (hash, readback)"
+    )]
+    pub verify: Option<VerifyMode>,
+
+    #[arg(
+        long = "store-checksum",
+        help = "record each copy's checksum in its user.cpx.checksum xattr, so --resume can skip re-reading it"
+    )]
+    pub store_checksum: bool,
+
+    #[arg(
+        long = "ignore-errors",
+        help = "keep going when directories or whole sources fail, and report every failure at the end"
+    )]
+    pub ignore_errors: bool,
+
+    #[arg(
+        long = "strict",
+        help = "fail when the walk is denied access to an entry instead of leaving it out"
+    )]
+    pub strict: bool,
+
+    #[arg(
+        long = "error-report",
+        value_name = "PATH",
+        help = "also write the failure report to PATH"
+    )]
+    pub error_report: Option<PathBuf>,
+
+    #[arg(
+        long = "error-report-format",
+        value_name = "FORMAT",
+        default_value = "text",
+        requires = "error_report",
+        help = "error report format (text, json, tsv)"
+    )]
+    pub error_report_format: ErrorReportFormat,
+
+    #[arg(
+        long = "metrics-file",
+        value_name = "PATH",
+        help = "write run metrics to PATH in the Prometheus text format"
+    )]
+    pub metrics_file: Option<PathBuf>,
+
+    #[arg(
+        long = "retries",
+        value_name = "N",
+        default_value_t = 0,
+        help = "retry a file up to N times after transient I/O errors (EIO, timeouts)"
+    )]
+    pub retries: u32,
+
+    #[arg(
+        long = "retry-delay",
+        value_name = "DURATION",
+        default_value = "1s",
+        value_parser = humantime::parse_duration,
+        help = "delay before the first retry, doubled on each further attempt"
+    )]
+    pub retry_delay: Duration,
+
+    #[arg(
+        long = "locked",
+        value_name = "POLICY",
+        default_value = "fail",
+        help = "files locked by another process: fail, retry with backoff, or skip (fail, retry, skip)"
+    )]
+    pub locked: LockedFiles,
+
+    #[arg(
+        long = "timeout-per-file",
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        help = "fail a file still copying after DURATION, e.g. 10m; checked between chunks"
+    )]
+    pub timeout_per_file: Option<Duration>,
+
+    #[arg(
+        long = "timeout-total",
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        help = "stop the run after DURATION, e.g. 6h, cleaning up files in progress"
+    )]
+    pub timeout_total: Option<Duration>,
+
+    #[arg(
+        long = "bwlimit",
+        value_name = "RATE",
+        value_parser = bench::parse_size,
+        help = "copy at most RATE bytes per second, e.g. 50M; change it while running with cpx status --bwlimit"
+    )]
+    pub bwlimit: Option<u64>,
+
+    #[arg(
+        long = "max-errors",
+        value_name = "N",
+        help = "stop the run once more than N files have failed"
+    )]
+    pub max_errors: Option<usize>,
+
+    #[arg(
+        long = "notify",
+        help = "send a desktop notification when a copy that took long enough finishes"
+    )]
+    pub notify: bool,
+
+    #[arg(
+        long = "notify-after",
+        value_name = "DURATION",
+        value_parser = humantime::parse_duration,
+        help = "minimum run time before --notify fires [default: 30s, or [notify] min_duration]"
+    )]
+    pub notify_after: Option<Duration>,
+
+    #[arg(
+        long = "history",
+        help = "record this run in the history `cpx history` lists"
+    )]
+    pub history: bool,
+
+    #[arg(
+        long = "status-socket",
+        help = "answer `cpx status` while the copy runs"
+    )]
+    pub status_socket: bool,
+
+    // Config Options (Placed last as meta)
+    #[arg(long, value_name = "PATH", help = "Use custom config file")]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, help = "Ignore all config files")]
+    pub no_config: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXIT_CODES_HELP)]
+pub struct WatchArgs {
+    #[command(flatten)]
+    pub copy: CopyArgs,
+
+    #[arg(
+        long = "debounce",
+        value_name = "DURATION",
+        default_value = "500ms",
+        value_parser = humantime::parse_duration,
+        help = "wait until sources have been quiet this long before copying changes"
+    )]
+    pub debounce: Duration,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXIT_CODES_HELP)]
+pub struct PlanArgs {
+    #[command(flatten)]
+    pub copy: CopyArgs,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value_t = PlanFormat::Json,
+        help = "plan format; cpx apply reads json"
+    )]
+    pub format: PlanFormat,
+
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "PATH",
+        help = "write the plan to PATH instead of stdout"
+    )]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(after_help = CHECK_EXIT_CODES_HELP)]
+pub struct CheckArgs {
+    #[command(flatten)]
+    pub copy: CopyArgs,
+
+    #[arg(
+        long = "windows-names",
+        help = "hold names to Windows rules whatever the destination filesystem"
+    )]
+    pub windows_names: bool,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value_t = StatsFormat::Text,
+        help = "report format"
+    )]
+    pub format: StatsFormat,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXIT_CODES_HELP)]
+pub struct ApplyArgs {
+    #[arg(help = "Plan written by cpx plan --format json")]
+    pub plan: PathBuf,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(after_help = VERIFY_EXIT_CODES_HELP)]
+pub struct VerifyArgs {
+    #[arg(help = "Source file or directory")]
+    pub source: PathBuf,
+
+    #[arg(help = "Copy to check against the source")]
+    pub destination: PathBuf,
+
+    #[arg(
+        long = "algo",
+        value_enum,
+        default_value_t = HashAlgo::Xxh3,
+        help = "checksum used to compare file contents"
+    )]
+    pub algo: HashAlgo,
+
+    #[arg(
+        long = "quick",
+        conflicts_with = "algo",
+        help = "compare sizes and modification times only, without reading file contents"
+    )]
+    pub quick: bool,
+
+    #[arg(
+        short = 'e',
+        long = "exclude",
+        value_name = "PATTERN",
+        help = "Exclude files matching pattern from both sides (can be specified multiple times, supports comma-separated values)"
+    )]
+    pub exclude: Vec<String>,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value_t = StatsFormat::Text,
+        help = "report format"
+    )]
+    pub format: StatsFormat,
+}
+
+impl VerifyArgs {
+    /// Prints the report; returns whether the two sides matched.
+    pub fn execute(&self) -> CpxResult<bool> {
+        let exclude_rules = exclude_rules_from(&self.exclude)?;
+        let compare = if self.quick {
+            Compare::Quick
+        } else {
+            Compare::Checksum(self.algo)
+        };
+
+        let report = verify::verify_trees(&self.source, &self.destination, compare, exclude_rules)
+            .map_err(CpxError::Copy)?;
+        print_report(self.format, &report.to_text(), &report)?;
+        Ok(report.is_identical())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(after_help = DIFF_EXIT_CODES_HELP)]
+pub struct DiffArgs {
+    #[arg(help = "Source file or directory")]
+    pub source: PathBuf,
+
+    #[arg(help = "Destination file or directory to compare with")]
+    pub destination: PathBuf,
+
+    #[arg(
+        long = "checksum",
+        help = "compare file contents (xxh3) instead of sizes and modification times"
+    )]
+    pub checksum: bool,
+
+    #[arg(
+        short = 'e',
+        long = "exclude",
+        value_name = "PATTERN",
+        help = "Exclude files matching pattern from both sides (can be specified multiple times, supports comma-separated values)"
+    )]
+    pub exclude: Vec<String>,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value_t = StatsFormat::Text,
+        help = "output format"
+    )]
+    pub format: StatsFormat,
+}
+
+impl DiffArgs {
+    /// Prints the changes; returns whether there were none.
+    pub fn execute(&self) -> CpxResult<bool> {
+        let exclude_rules = exclude_rules_from(&self.exclude)?;
+        let compare = if self.checksum {
+            Compare::Checksum(HashAlgo::Xxh3)
+        } else {
+            Compare::Quick
+        };
+
+        let diff = diff::diff_trees(&self.source, &self.destination, compare, exclude_rules)
+            .map_err(CpxError::Copy)?;
+        print_report(self.format, &diff.to_text(), &diff)?;
+        Ok(diff.is_empty())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(after_help = UNDO_EXIT_CODES_HELP)]
+pub struct UndoArgs {
+    #[arg(required_unless_present = "job", help = "Journal written by --journal")]
+    pub journal: Option<PathBuf>,
+
+    #[arg(
+        long = "job",
+        value_name = "NAME",
+        conflicts_with = "journal",
+        value_parser = job::parse_job_name,
+        help = "undo the latest run of the job started with --job NAME"
+    )]
+    pub job: Option<String>,
+
+    #[arg(
+        long = "dry-run",
+        help = "show what would be undone without changing anything"
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value_t = StatsFormat::Text,
+        help = "report format"
+    )]
+    pub format: StatsFormat,
+}
+
+impl UndoArgs {
+    /// Prints what was undone; returns whether everything could be.
+    pub fn execute(&self) -> CpxResult<bool> {
+        let path = match (&self.journal, &self.job) {
+            (Some(path), _) => path.clone(),
+            (None, Some(name)) => job::journal_path(name).ok_or_else(|| {
+                CpxError::Validation("no data directory to find jobs in".to_string())
+            })?,
+            (None, None) => unreachable!(),
+        };
+        let entries = journal::read_journal(&path).map_err(CpxError::Io)?;
+        let report = journal::undo(&entries, self.dry_run);
+        print_report(self.format, &report.to_text(), &report)?;
+        Ok(report.is_complete())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BenchArgs {
+    #[arg(
+        long = "dir",
+        value_name = "DIR",
+        help = "run in DIR, on the filesystem to tune for [default: the system temp directory]"
+    )]
+    pub dir: Option<PathBuf>,
+
+    #[arg(
+        long = "sample",
+        value_name = "PATH",
+        help = "copy real files from PATH instead of generated ones"
     )]
-    pub parallel: usize,
-
-    #[arg(long = "resume", help = "resume interrupted transfers")]
-    pub resume: bool,
+    pub sample: Option<PathBuf>,
 
     #[arg(
-        short = 'f',
-        long,
-        help = "if an existing destination file cannot be opened, remove it and try again"
+        long = "files",
+        default_value_t = 64,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "number of files in the test set (at most, with --sample)"
     )]
-    pub force: bool,
+    pub files: u64,
 
-    #[arg(short = 'i', long, help = "prompt before overwrite")]
-    pub interactive: bool,
-
-    #[arg(long, help = "use full source file name under DIRECTORY")]
-    pub parents: bool,
+    #[arg(
+        long = "file-size",
+        value_name = "SIZE",
+        default_value = "1M",
+        value_parser = bench::parse_size,
+        conflicts_with = "sample",
+        help = "size of each generated file, e.g. 64K or 4M"
+    )]
+    pub file_size: u64,
 
     #[arg(
-        long = "attributes-only",
-        help = "don't copy the file data, just the attributes"
+        long = "backends",
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [Backend::Buffered, Backend::CopyFileRange, Backend::Reflink, Backend::Mmap],
+        help = "backends to time"
     )]
-    pub attributes_only: bool,
+    pub backends: Vec<Backend>,
 
     #[arg(
-        long = "remove-destination",
-        help = "remove each existing destination file before attempting to open it"
+        long = "buffer-sizes",
+        value_name = "SIZES",
+        value_delimiter = ',',
+        default_value = "64K,256K,1M,4M",
+        value_parser = bench::parse_size,
+        help = "buffer sizes to time the buffered backend with"
     )]
-    pub remove_destination: bool,
+    pub buffer_sizes: Vec<u64>,
 
-    // Link and Symlink Options
     #[arg(
-        short = 's',
-        long = "symbolic-link",
-        value_name = "MODE",
-        default_missing_value = "auto",
-        num_args = 0..=1,
-        help = "make symbolic links instead of copying (auto, absolute, or relative)"
+        long = "parallel",
+        value_name = "COUNTS",
+        value_delimiter = ',',
+        default_value = "1,2,4,8",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "numbers of files copied at once"
     )]
-    pub symbolic_link: Option<SymlinkMode>,
+    pub parallel: Vec<u64>,
 
     #[arg(
-        short = 'l',
-        long = "link",
-        help = "hard link files instead of copying"
+        long = "format",
+        value_enum,
+        default_value_t = StatsFormat::Text,
+        help = "report format"
     )]
-    pub hard_link: bool,
+    pub format: StatsFormat,
+}
+
+impl BenchArgs {
+    pub fn execute(&self) -> CpxResult<()> {
+        let config = BenchConfig {
+            work_dir: self.dir.clone().unwrap_or_else(std::env::temp_dir),
+            sample: self.sample.clone(),
+            files: self.files as usize,
+            file_size: self.file_size,
+            backends: self.backends.clone(),
+            buffer_sizes: self.buffer_sizes.clone(),
+            parallel: self.parallel.iter().map(|&count| count as usize).collect(),
+        };
+        let report = bench::run_bench(&config).map_err(CpxError::Io)?;
+        print_report(self.format, &report.to_text(), &report)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DoctorArgs {
+    #[arg(help = "Source file or directory; only read")]
+    pub source: PathBuf,
+
+    #[arg(help = "Destination; probed in a scratch directory that is removed afterwards")]
+    pub destination: PathBuf,
 
     #[arg(
-        short = 'P',
-        long = "no-dereference",
-        help = "never follow symbolic links in SOURCE"
+        long = "format",
+        value_enum,
+        default_value_t = StatsFormat::Text,
+        help = "report format"
     )]
-    pub no_dereference: bool,
+    pub format: StatsFormat,
+}
+
+impl DoctorArgs {
+    pub fn execute(&self) -> CpxResult<()> {
+        let report = doctor::run_doctor(&self.source, &self.destination).map_err(CpxError::Io)?;
+        print_report(self.format, &report.to_text(), &report)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StatusArgs {
+    #[arg(help = "only the copy with this process id")]
+    pub pid: Option<u32>,
+
+    #[arg(long = "pause", help = "pause the copies between chunks")]
+    pub pause: bool,
 
     #[arg(
-        short = 'L',
-        long = "dereference",
-        help = "always follow symbolic links in SOURCE"
+        long = "resume",
+        conflicts_with = "pause",
+        help = "resume paused copies"
     )]
-    pub dereference: bool,
+    pub resume: bool,
 
     #[arg(
-        short = 'H',
-        long = "dereference-command-line",
-        help = "follow symbolic links only on command line"
+        long = "bwlimit",
+        value_name = "RATE",
+        value_parser = status::parse_limit,
+        conflicts_with_all = ["pause", "resume"],
+        help = "change the copies' --bwlimit to RATE bytes per second, e.g. 10M, or lift it with off"
     )]
-    pub dereference_command_line: bool,
+    pub bwlimit: Option<u64>,
 
-    // Preservation Options
     #[arg(
-        short = 'p',
-        long = "preserve",
-        value_name = "ATTR_LIST",
-        default_missing_value = "",
-        help = "preserve the specified attributes"
+        long = "format",
+        value_enum,
+        default_value_t = StatsFormat::Text,
+        help = "report format"
     )]
-    pub preserve: Option<String>,
+    pub format: StatsFormat,
+}
+
+impl StatusArgs {
+    pub fn execute(&self) -> CpxResult<()> {
+        let request = if self.pause {
+            StatusRequest::Pause
+        } else if self.resume {
+            StatusRequest::Resume
+        } else if let Some(rate) = self.bwlimit {
+            StatusRequest::Limit(rate)
+        } else {
+            StatusRequest::Status
+        };
+        let runs = status::query(self.pid, request).map_err(CpxError::Io)?;
+        if let Some(pid) = self.pid
+            && runs.is_empty()
+        {
+            return Err(CpxError::Validation(format!(
+                "no running copy with process id {}",
+                pid
+            )));
+        }
+        let text = if runs.is_empty() {
+            "No running copies\n".to_string()
+        } else {
+            runs.iter()
+                .map(|run| run.to_text())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        print_report(self.format, &text, &runs)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct HistoryArgs {
+    #[command(subcommand)]
+    pub command: Option<HistoryCommand>,
+
+    #[arg(long = "limit", value_name = "N", help = "only the N most recent runs")]
+    pub limit: Option<usize>,
 
-    // Backup and Reflink Options
     #[arg(
-        short = 'b',
-        long = "backup",
-        value_name = "CONTROL",
-        default_missing_value = "existing",
-        num_args = 0..=1,
-        help = "make a backup of each existing destination file (none, numbered, existing, simple)"
+        long = "job",
+        value_name = "NAME",
+        help = "only runs of the job started with --job NAME"
     )]
-    pub backup: Option<BackupMode>,
+    pub job: Option<String>,
 
     #[arg(
-        long = "reflink",
-        value_name = "WHEN",
-        default_missing_value = "auto",
-        num_args = 0..=1,
-        help = "control clone/CoW copies (auto, always, never)"
+        long = "format",
+        value_enum,
+        global = true,
+        default_value_t = StatsFormat::Text,
+        help = "report format"
     )]
-    pub reflink: Option<ReflinkMode>,
+    pub format: StatsFormat,
+}
 
-    // Config Options (Placed last as meta)
-    #[arg(long, value_name = "PATH", help = "Use custom config file")]
-    pub config: Option<PathBuf>,
+#[derive(Debug, Clone, Subcommand)]
+pub enum HistoryCommand {
+    /// Show everything recorded for one run
+    Show {
+        #[arg(help = "run id, as listed by `cpx history`")]
+        id: usize,
+    },
+}
 
-    #[arg(long, help = "Ignore all config files")]
-    pub no_config: bool,
+impl HistoryArgs {
+    pub fn execute(&self) -> CpxResult<()> {
+        let mut runs = history::load().map_err(CpxError::Io)?;
+        if let Some(name) = &self.job {
+            runs.retain(|run| run.job.as_ref() == Some(name));
+        }
+        if let Some(HistoryCommand::Show { id }) = &self.command {
+            let run = runs.iter().find(|run| run.id == *id).ok_or_else(|| {
+                CpxError::Validation(format!("no run with id {} in the history", id))
+            })?;
+            return print_report(self.format, &run.to_text(), run);
+        }
+        let skip = self
+            .limit
+            .map_or(0, |limit| runs.len().saturating_sub(limit));
+        let runs = &runs[skip..];
+        let text = if runs.is_empty() {
+            "No recorded runs\n".to_string()
+        } else {
+            runs.iter().map(|run| run.to_line()).collect()
+        };
+        print_report(self.format, &text, &runs)
+    }
+}
+
+fn exclude_rules_from(patterns: &[String]) -> CpxResult<Option<ExcludeRules>> {
+    let mut parsed = Vec::new();
+    for pattern_str in patterns {
+        parsed.extend(parse_exclude_pattern_list(pattern_str).map_err(CpxError::Exclude)?);
+    }
+    build_exclude_rules(parsed).map_err(CpxError::Exclude)
+}
+
+fn print_report(format: StatsFormat, text: &str, report: &impl serde::Serialize) -> CpxResult<()> {
+    match format {
+        StatsFormat::Text => print!("{}", text),
+        StatsFormat::Json => println!(
+            "{}",
+            serde_json::to_string(report).map_err(|e| CpxError::Io(e.into()))?
+        ),
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
 pub struct CopyOptions {
     pub recursive: bool,
     pub parallel: usize,
+    /// `-j auto`: `parallel` is the ceiling, the live count is tuned.
+    pub auto_jobs: bool,
     pub resume: bool,
     pub force: bool,
     pub interactive: bool,
+    /// `--on-conflict`; unset, the policy follows `interactive`, `force`
+    /// and `remove_destination` (see [`CopyOptions::conflict_policy`]).
+    pub on_conflict: Option<ConflictPolicy>,
+    /// Ask once, with a summary of the plan, before writing anything.
+    pub confirm: bool,
+    /// `--yes` or `--assume-no`: the answer to every prompt, which is then
+    /// not asked.
+    pub assume: Option<bool>,
+    /// Only list the destinations the plan would overwrite, to this file
+    /// or `-` for stdout.
+    pub list_conflicts: Option<PathBuf>,
     pub parents: bool,
     pub preserve: PreserveAttr,
     pub attributes_only: bool,
@@ -219,11 +1562,95 @@ pub struct CopyOptions {
     pub symbolic_link: Option<SymlinkMode>,
     pub hard_link: bool,
     pub follow_symlink: FollowSymlink,
+    pub junctions: JunctionMode,
+    pub symlink_fallback: SymlinkFallback,
+    /// Symlinks found where files and directories are written.
+    pub dest_symlinks: DestSymlinks,
     pub progress_bar: ProgressOptions,
     pub backup: Option<BackupMode>,
     pub reflink: Option<ReflinkMode>,
+    pub fsync: FsyncMode,
+    /// Batched fsyncs (`--write-barrier`), shared by the workers.
+    pub write_barrier: Option<Arc<WriteBarrier>>,
+    pub order: FileOrder,
+    /// Niceness to run at (`--nice`); 0 leaves the CPU priority alone.
+    pub nice: i32,
+    pub io_priority: IoPriority,
+    pub backend: CopyBackend,
+    /// `--deterministic`: the walk is sorted and log timestamps are pinned
+    /// to `SOURCE_DATE_EPOCH`; `parallel` and `order` are set to match.
+    pub deterministic: bool,
+    /// How far a destination mtime may lag the source's and still count
+    /// as up to date (`--resume`).
+    pub modify_window: Duration,
+    /// Keep unfinished files for the next run to continue (`--partial`).
+    pub partial: bool,
+    /// Where they are kept instead of beside the destination.
+    pub partial_dir: Option<PathBuf>,
+    /// Write over existing destinations without truncating them first.
+    pub inplace: bool,
+    /// An earlier copy to hard-link unchanged files from (`--link-dest`).
+    pub link_dest: Option<PathBuf>,
+    /// Leave out the `Zone.Identifier` alternate data stream.
+    pub strip_zone_identifier: bool,
     pub exclude_rules: Option<ExcludeRules>,
-    pub abort: Arc<AtomicBool>,
+    /// Directories skipped for a marker file they contain.
+    pub exclude_markers: ExcludeMarkers,
+    /// Whether a recursive copy takes hidden entries.
+    pub hidden: HiddenMode,
+    /// Leave out directories that would hold no files, links or other
+    /// directories once the tree is filtered.
+    pub prune_empty_dirs: bool,
+    /// Rename entries the destination filesystem can't take.
+    pub sanitize_names: Option<SanitizeMode>,
+    pub verbosity: Verbosity,
+    pub logger: Option<Arc<Logger>>,
+    pub stats: Option<StatsFormat>,
+    pub manifest: Option<PathBuf>,
+    pub manifest_algo: HashAlgo,
+    /// Compares contents for `--verify`, `--resume` and `--dedupe`.
+    pub checksum_algo: HashAlgo,
+    pub verify: Option<VerifyMode>,
+    /// Record digests on the copies (`--store-checksum`).
+    pub store_checksum: bool,
+    pub ignore_errors: bool,
+    pub strict: bool,
+    pub error_report: Option<PathBuf>,
+    pub error_report_format: ErrorReportFormat,
+    /// Prometheus textfile-collector output, rewritten at the end of a run.
+    pub metrics_file: Option<PathBuf>,
+    pub retries: u32,
+    pub retry_delay: Duration,
+    pub locked: LockedFiles,
+    pub timeout_per_file: Option<Duration>,
+    pub timeout_total: Option<Duration>,
+    /// Bytes per second across all workers; see [`CopyControl::set_bwlimit`].
+    pub bwlimit: Option<u64>,
+    pub max_errors: Option<usize>,
+    pub notify: Option<Duration>,
+    /// Record the run for `cpx history`.
+    pub history: bool,
+    /// Answer `cpx status` on a socket while the run lasts.
+    pub status_socket: bool,
+    pub tui: bool,
+    pub control: Arc<CopyControl>,
+    pub events: Arc<EventBus>,
+    /// Counters the scan spinner reads while the sources are walked.
+    pub scan_progress: Arc<ScanProgress>,
+    /// Set when the destination is `[user@]host:path`.
+    pub remote: Option<RemoteTarget>,
+    pub archive: Option<ArchiveFormat>,
+    pub compress: Option<Compression>,
+    pub compress_level: i32,
+    pub decompress: bool,
+    pub dedupe: bool,
+    /// Quiet period before copying changes, set by `cpx watch`.
+    pub watch: Option<Duration>,
+    /// Plan file to run instead of planning the sources, set by `cpx apply`.
+    pub apply: Option<PathBuf>,
+    pub journal: Option<Arc<Journal>>,
+    /// `--job`, held for the whole run.
+    pub job: Option<Arc<Job>>,
 }
 
 impl CopyOptions {
@@ -231,21 +1658,82 @@ impl CopyOptions {
         Self {
             recursive: false,
             parallel: 4,
+            auto_jobs: false,
             resume: false,
             force: false,
             interactive: false,
+            on_conflict: None,
+            confirm: false,
+            assume: None,
+            list_conflicts: None,
             parents: false,
-            preserve: PreserveAttr::none(),
+            preserve: PreserveAttr::implicit(),
             attributes_only: false,
             remove_destination: false,
             symbolic_link: None,
             hard_link: false,
             follow_symlink: FollowSymlink::NoDereference,
+            junctions: JunctionMode::Recreate,
+            symlink_fallback: SymlinkFallback::Error,
+            dest_symlinks: DestSymlinks::Follow,
             progress_bar: ProgressOptions::default(),
             backup: None,
             reflink: None,
+            fsync: FsyncMode::None,
+            write_barrier: None,
+            nice: 0,
+            io_priority: IoPriority::Normal,
+            backend: CopyBackend::Auto,
+            order: FileOrder::SizeDesc,
+            deterministic: false,
+            modify_window: Duration::ZERO,
+            partial: false,
+            partial_dir: None,
+            inplace: false,
+            link_dest: None,
+            strip_zone_identifier: false,
             exclude_rules: None,
-            abort: Arc::new(AtomicBool::new(false)),
+            exclude_markers: ExcludeMarkers::default(),
+            hidden: HiddenMode::Include,
+            prune_empty_dirs: false,
+            sanitize_names: None,
+            verbosity: Verbosity::Normal,
+            logger: None,
+            stats: None,
+            manifest: None,
+            manifest_algo: HashAlgo::Sha256,
+            checksum_algo: HashAlgo::Xxh3,
+            verify: None,
+            store_checksum: false,
+            ignore_errors: false,
+            strict: false,
+            error_report: None,
+            error_report_format: ErrorReportFormat::Text,
+            metrics_file: None,
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
+            locked: LockedFiles::Fail,
+            timeout_per_file: None,
+            timeout_total: None,
+            bwlimit: None,
+            max_errors: None,
+            notify: None,
+            history: false,
+            status_socket: false,
+            tui: false,
+            control: Arc::new(CopyControl::new()),
+            events: Arc::new(EventBus::new()),
+            scan_progress: Arc::default(),
+            remote: None,
+            archive: None,
+            compress: None,
+            compress_level: compress::DEFAULT_LEVEL,
+            decompress: false,
+            dedupe: false,
+            watch: None,
+            apply: None,
+            journal: None,
+            job: None,
         }
     }
 
@@ -253,9 +1741,14 @@ impl CopyOptions {
         Self {
             recursive: config.copy.recursive,
             parallel: config.copy.parallel,
+            auto_jobs: false,
             resume: config.copy.resume,
             force: config.copy.force,
             interactive: config.copy.interactive,
+            on_conflict: parse_conflict_policy(&config.copy.on_conflict),
+            confirm: config.copy.confirm,
+            assume: None,
+            list_conflicts: None,
             parents: config.copy.parents,
             preserve: PreserveAttr::from_string(&config.preserve.mode)
                 .unwrap_or_else(|_| PreserveAttr::default()),
@@ -264,11 +1757,123 @@ impl CopyOptions {
             symbolic_link: parse_symlink_mode(&config.symlink.mode),
             hard_link: false,
             follow_symlink: parse_follow_symlink(&config.symlink.follow),
+            junctions: parse_junction_mode(&config.symlink.junctions),
+            symlink_fallback: parse_symlink_fallback(&config.symlink.fallback),
+            dest_symlinks: parse_dest_symlinks(&config.symlink.destination),
             progress_bar: parse_progress_bar(config),
             backup: parse_backup_mode(&config.backup.mode),
             reflink: parse_reflink_mode(&config.reflink.mode),
+            fsync: parse_fsync_mode(&config.copy.fsync),
+            write_barrier: parse_write_barrier(&config.copy.write_barrier)
+                .map(|spec| Arc::new(WriteBarrier::new(spec))),
+            nice: config.copy.nice.clamp(0, 19),
+            io_priority: parse_io_priority(&config.copy.ionice),
+            backend: parse_copy_backend(&config.copy.backend),
+            order: parse_file_order(&config.copy.order),
+            deterministic: false,
+            modify_window: parse_modify_window(&config.copy.modify_window),
+            partial: false,
+            partial_dir: None,
+            inplace: false,
+            link_dest: None,
+            strip_zone_identifier: false,
             exclude_rules: None,
-            abort: Arc::new(AtomicBool::new(false)),
+            exclude_markers: ExcludeMarkers {
+                files: config.exclude.if_present.clone(),
+                caches: config.exclude.caches,
+            },
+            hidden: parse_hidden_mode(&config.exclude.hidden),
+            prune_empty_dirs: config.exclude.prune_empty_dirs,
+            sanitize_names: parse_sanitize_mode(&config.copy.sanitize_names),
+            verbosity: Verbosity::Normal,
+            logger: None,
+            stats: None,
+            manifest: None,
+            manifest_algo: HashAlgo::Sha256,
+            checksum_algo: HashAlgo::Xxh3,
+            verify: None,
+            store_checksum: false,
+            ignore_errors: false,
+            strict: false,
+            error_report: None,
+            error_report_format: ErrorReportFormat::Text,
+            metrics_file: None,
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
+            locked: LockedFiles::Fail,
+            timeout_per_file: None,
+            timeout_total: None,
+            bwlimit: None,
+            max_errors: None,
+            notify: parse_notify(config),
+            history: config.copy.history,
+            status_socket: config.copy.status_socket,
+            tui: false,
+            control: Arc::new(CopyControl::new()),
+            events: Arc::new(EventBus::new()),
+            scan_progress: Arc::default(),
+            remote: None,
+            archive: None,
+            compress: None,
+            compress_level: compress::DEFAULT_LEVEL,
+            decompress: false,
+            dedupe: false,
+            watch: None,
+            apply: None,
+            journal: None,
+            job: None,
+        }
+    }
+
+    /// Whether copies must produce a content digest of what they wrote.
+    pub fn wants_digest(&self) -> bool {
+        self.manifest.is_some() || self.verify.is_some() || self.store_checksum
+    }
+
+    /// What to do about a destination that already exists. Without
+    /// `--on-conflict`, `-i` prompts and files are overwritten; links, as in
+    /// cp, only replace what is there with `-f` or `--remove-destination`.
+    pub fn conflict_policy(&self, link: bool) -> ConflictPolicy {
+        let policy = match self.on_conflict {
+            Some(policy) => policy,
+            None if self.interactive => ConflictPolicy::Prompt,
+            None if link && !(self.force || self.remove_destination) => ConflictPolicy::Error,
+            None => ConflictPolicy::Overwrite,
+        };
+        match (policy, self.assume) {
+            (ConflictPolicy::Prompt, Some(true)) => ConflictPolicy::Overwrite,
+            (ConflictPolicy::Prompt, Some(false)) => ConflictPolicy::Skip,
+            _ => policy,
+        }
+    }
+
+    /// Whether the run may stop to ask about overwriting.
+    pub fn prompts(&self) -> bool {
+        self.conflict_policy(false) == ConflictPolicy::Prompt
+    }
+
+    /// Whether the run may stop to ask anything, overwrites or `confirm`.
+    pub fn asks(&self) -> bool {
+        self.prompts() || (self.confirm && self.assume.is_none())
+    }
+
+    /// Whether the run draws bars or status lines on stderr.
+    pub fn shows_progress(&self) -> bool {
+        !self.tui
+            && !self.prompts()
+            && !self.attributes_only
+            && self.verbosity > Verbosity::Quiet
+            && !self.progress_bar.is_json()
+            && self.progress_bar.mode != ProgressMode::Never
+    }
+
+    /// Manifests dictate the algorithm; verification alone uses
+    /// `--checksum-algo`.
+    pub fn digest_algo(&self) -> HashAlgo {
+        if self.manifest.is_some() {
+            self.manifest_algo
+        } else {
+            self.checksum_algo
         }
     }
 }
@@ -276,16 +1881,40 @@ impl CopyOptions {
 impl From<&CopyArgs> for CopyOptions {
     fn from(cli: &CopyArgs) -> Self {
         Self {
-            recursive: cli.recursive,
-            parallel: cli.parallel,
+            recursive: cli.recursive || cli.archive_mode,
+            parallel: if cli.deterministic {
+                1
+            } else {
+                cli.parallel.workers()
+            },
+            auto_jobs: cli.parallel == Jobs::Auto && !cli.deterministic,
             resume: cli.resume,
             force: cli.force,
             interactive: cli.interactive,
+            on_conflict: cli.on_conflict,
+            confirm: cli.confirm && !cli.yes,
+            assume: answer(cli),
+            list_conflicts: cli.list_conflicts.clone(),
             parents: cli.parents,
-            preserve: match &cli.preserve {
-                None => PreserveAttr::none(),
-                Some(s) => {
-                    PreserveAttr::from_string(s).expect("unable to parse preserve attribute")
+            preserve: {
+                let preserve = match &cli.preserve {
+                    None if cli.archive_mode => PreserveAttr::all(),
+                    None => PreserveAttr::implicit(),
+                    Some(s) => {
+                        PreserveAttr::from_string(s).expect("unable to parse preserve attribute")
+                    }
+                };
+                let preserve = match &cli.no_preserve {
+                    None => preserve,
+                    Some(s) => preserve
+                        .without(s)
+                        .expect("unable to parse no-preserve attribute"),
+                };
+                PreserveAttr {
+                    chown: cli.chown,
+                    chmod: cli.chmod,
+                    set_attr: cli.set_attr,
+                    ..preserve
                 }
             },
             attributes_only: cli.attributes_only,
@@ -293,11 +1922,77 @@ impl From<&CopyArgs> for CopyOptions {
             symbolic_link: cli.symbolic_link,
             hard_link: cli.hard_link,
             follow_symlink: FollowSymlink::NoDereference,
+            junctions: cli.junctions.unwrap_or_default(),
+            symlink_fallback: cli.symlink_fallback.unwrap_or_default(),
+            dest_symlinks: dest_symlinks(cli).unwrap_or_default(),
             progress_bar: ProgressOptions::default(),
             backup: cli.backup,
             reflink: cli.reflink,
+            fsync: cli.fsync.unwrap_or_default(),
+            write_barrier: cli
+                .write_barrier
+                .map(|spec| Arc::new(WriteBarrier::new(spec))),
+            nice: cli.nice.unwrap_or(0),
+            io_priority: cli.ionice.unwrap_or_default(),
+            backend: cli.backend.unwrap_or_default(),
+            order: if cli.deterministic {
+                FileOrder::Path
+            } else {
+                cli.order.unwrap_or_default()
+            },
+            deterministic: cli.deterministic,
+            modify_window: cli.modify_window.unwrap_or_default(),
+            partial: cli.partial || cli.partial_dir.is_some(),
+            partial_dir: cli.partial_dir.clone(),
+            inplace: cli.inplace,
+            link_dest: cli.link_dest.clone(),
+            strip_zone_identifier: cli.strip_zone_identifier,
             exclude_rules: None,
-            abort: Arc::new(AtomicBool::new(false)),
+            exclude_markers: ExcludeMarkers {
+                files: cli.exclude_if_present.clone(),
+                caches: cli.exclude_caches,
+            },
+            hidden: cli.hidden.unwrap_or_default(),
+            prune_empty_dirs: cli.prune_empty_dirs,
+            sanitize_names: cli.sanitize_names,
+            verbosity: Verbosity::from_flags(cli.verbose, cli.quiet),
+            logger: None,
+            stats: cli.stats,
+            manifest: cli.output_manifest.clone(),
+            manifest_algo: cli.manifest_algo.or(cli.checksum_algo).unwrap_or_default(),
+            checksum_algo: cli.checksum_algo.unwrap_or(HashAlgo::Xxh3),
+            verify: cli.verify,
+            store_checksum: cli.store_checksum,
+            ignore_errors: cli.ignore_errors,
+            strict: cli.strict,
+            error_report: cli.error_report.clone(),
+            error_report_format: cli.error_report_format,
+            metrics_file: cli.metrics_file.clone(),
+            retries: cli.retries,
+            retry_delay: cli.retry_delay,
+            locked: cli.locked,
+            timeout_per_file: cli.timeout_per_file,
+            timeout_total: cli.timeout_total,
+            bwlimit: cli.bwlimit,
+            max_errors: cli.max_errors,
+            notify: (cli.notify || cli.notify_after.is_some())
+                .then(|| cli.notify_after.unwrap_or(DEFAULT_NOTIFY_AFTER)),
+            history: cli.history,
+            status_socket: cli.status_socket,
+            tui: cli.tui,
+            control: Arc::new(CopyControl::new()),
+            events: Arc::new(EventBus::new()),
+            scan_progress: Arc::default(),
+            remote: None,
+            archive: None,
+            compress: cli.compress,
+            compress_level: cli.compress_level,
+            decompress: cli.decompress,
+            dedupe: cli.dedupe,
+            watch: None,
+            apply: None,
+            journal: None,
+            job: None,
         }
     }
 }
@@ -311,7 +2006,24 @@ impl CLIArgs {
             let first_arg = &args[1];
             let is_subcommand = matches!(
                 first_arg.as_str(),
-                "config" | "copy" | "-h" | "--help" | "-V" | "--version"
+                "config"
+                    | "copy"
+                    | "watch"
+                    | "verify"
+                    | "diff"
+                    | "plan"
+                    | "apply"
+                    | "undo"
+                    | "bench"
+                    | "doctor"
+                    | "status"
+                    | "history"
+                    | "check"
+                    | "completions"
+                    | "-h"
+                    | "--help"
+                    | "-V"
+                    | "--version"
             );
             if !is_subcommand {
                 args.insert(1, "copy".to_string());
@@ -330,46 +2042,303 @@ impl CLIArgs {
             std::process::exit(0);
         }
 
-        // Get copy args from the Copy subcommand
-        let copy_args = match self.command {
-            Commands::Copy(args) => args,
-            _ => unreachable!(),
-        };
+        if let Commands::Completions { shell } = &self.command {
+            let mut command = <Self as clap::CommandFactory>::command();
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut command, "cpx", &mut script);
+            // a closed pipe (`cpx completions bash | head`) is not an error
+            let _ = std::io::stdout().write_all(&script);
+            std::process::exit(0);
+        }
 
-        let config = load_config_if_needed(&copy_args).map_err(CpxError::Config)?;
+        if let Commands::Bench(args) = &self.command {
+            args.execute()?;
+            std::process::exit(0);
+        }
 
-        // Start with config or defaults
-        let mut options = if let Some(ref cfg) = config {
-            CopyOptions::from_config(cfg)
-        } else {
-            CopyOptions::none()
+        if let Commands::Status(args) = &self.command {
+            args.execute()?;
+            std::process::exit(0);
+        }
+
+        if let Commands::Doctor(args) = &self.command {
+            args.execute()?;
+            std::process::exit(0);
+        }
+
+        if let Commands::History(args) = &self.command {
+            args.execute()?;
+            std::process::exit(0);
+        }
+
+        let compared = match &self.command {
+            Commands::Verify(args) => Some(args.execute()?),
+            Commands::Diff(args) => Some(args.execute()?),
+            Commands::Undo(args) => Some(args.execute()?),
+            _ => None,
         };
+        if let Some(identical) = compared {
+            let code = if identical {
+                ExitCode::Success
+            } else {
+                ExitCode::Failure
+            };
+            std::process::exit(code.code());
+        }
+
+        match self.command {
+            Commands::Copy(args) => copy_options(*args).and_then(with_job_journal),
+            Commands::Watch(args) => {
+                let (sources, destination, mut options) = copy_options(args.copy)?;
+                validate_watch(&options, &sources).map_err(CpxError::Validation)?;
+                options.watch = Some(args.debounce);
+                Ok((sources, destination, options))
+            }
+            Commands::Plan(args) => {
+                let PlanArgs {
+                    copy,
+                    format,
+                    output,
+                } = *args;
+                let (sources, destination, options) = copy_options(copy)?;
+                validate_plan("plan", &options, &sources).map_err(CpxError::Validation)?;
+                export_plan(&sources, &destination, &options, format, output.as_deref())?;
+                std::process::exit(0);
+            }
+            Commands::Check(args) => {
+                let CheckArgs {
+                    copy,
+                    windows_names,
+                    format,
+                } = *args;
+                let (sources, destination, options) = copy_options(copy)?;
+                validate_plan("check", &options, &sources).map_err(CpxError::Validation)?;
+                let plan =
+                    plan_sources(&sources, &destination, &options).map_err(CpxError::Copy)?;
+                let report =
+                    check::run_check(&plan, &destination, windows_names).map_err(CpxError::Io)?;
+                print_report(format, &report.to_text(), &report)?;
+                let code = if report.problems.is_empty() {
+                    ExitCode::Success
+                } else {
+                    ExitCode::Failure
+                };
+                std::process::exit(code.code());
+            }
+            Commands::Apply(args) => {
+                // the options are whatever `cpx plan` was given
+                let planned = PlanFile::read_args(&args.plan).map_err(CpxError::Copy)?;
+                let command = ["cpx", "plan"].into_iter().map(String::from).chain(planned);
+                let Commands::Plan(plan_args) = <Self as clap::Parser>::try_parse_from(command)
+                    .map_err(|e| {
+                        CpxError::Validation(format!(
+                            "'{}' holds unusable arguments: {}",
+                            args.plan.display(),
+                            e
+                        ))
+                    })?
+                    .command
+                else {
+                    unreachable!()
+                };
+                let (sources, destination, mut options) = copy_options(plan_args.copy)?;
+                options.apply = Some(args.plan);
+                with_job_journal((sources, destination, options))
+            }
+            _ => unreachable!(),
+        }
+    }
+}
 
-        // CLI args override config
-        apply_cli_overrides(&mut options, &copy_args).map_err(CpxError::Validation)?;
+/// Builds the options for a copy from its arguments and the config file.
+fn copy_options(copy_args: CopyArgs) -> CpxResult<(Vec<PathBuf>, PathBuf, CopyOptions)> {
+    let config = load_config_if_needed(&copy_args).map_err(CpxError::Config)?;
+
+    // Start with config or defaults
+    let mut options = if let Some(ref cfg) = config {
+        CopyOptions::from_config(cfg)
+    } else {
+        CopyOptions::none()
+    };
+
+    // CLI args override config
+    apply_cli_overrides(&mut options, &copy_args).map_err(CpxError::Validation)?;
+
+    // Build exclude rules
+    let all_patterns =
+        build_all_exclude_patterns(&copy_args, config.as_ref()).map_err(CpxError::Exclude)?;
+    options.exclude_rules = build_exclude_rules(all_patterns).map_err(CpxError::Exclude)?;
+
+    // Validate conflicts
+    validate_conflicts(&options).map_err(CpxError::Validation)?;
+    // a prompt read from a pipe or /dev/null would hang or answer itself
+    if options.asks() && !std::io::stdin().is_terminal() {
+        return Err(CpxError::Validation(
+            "the copy would prompt, but stdin is not a terminal; pass --assume-yes or --assume-no"
+                .to_string(),
+        ));
+    }
 
-        // Build exclude rules
-        let all_patterns =
-            build_all_exclude_patterns(&copy_args, config.as_ref()).map_err(CpxError::Exclude)?;
-        options.exclude_rules = build_exclude_rules(all_patterns).map_err(CpxError::Exclude)?;
+    if let Some(log_path) = &copy_args.log_file {
+        let mut logger = Logger::open(log_path, copy_args.log_level, copy_args.log_format)
+            .map_err(CpxError::Io)?;
+        if options.deterministic {
+            logger = logger.at_fixed_time(logger::source_date_epoch());
+        }
+        options.logger = Some(Arc::new(logger));
+    }
+    if let Some(journal_path) = &copy_args.journal {
+        options.journal = Some(Arc::new(Journal::open(journal_path).map_err(CpxError::Io)?));
+    }
+    if let Some(name) = &copy_args.job {
+        options.job = Some(Arc::new(Job::start(name).map_err(CpxError::Io)?));
+    }
 
-        // Validate conflicts
-        validate_conflicts(&options).map_err(CpxError::Validation)?;
+    // Handle attributes_only special case
+    if options.attributes_only {
+        options.preserve = PreserveAttr::all();
+    }
+    if copy_args.chown.is_some() {
+        options.preserve.chown = copy_args.chown;
+    }
+    if copy_args.chmod.is_some() {
+        options.preserve.chmod = copy_args.chmod;
+    }
+    if copy_args.set_attr.is_some() {
+        options.preserve.set_attr = copy_args.set_attr;
+    }
 
-        // Handle attributes_only special case
-        if options.attributes_only {
-            options.preserve = PreserveAttr::all();
+    let (sources, destination) = if let Some(target) = copy_args.target_directory {
+        let mut sources = copy_args.sources;
+        sources.push(copy_args.destination);
+        (sources, target)
+    } else {
+        (copy_args.sources, copy_args.destination)
+    };
+    // Windows shells pass wildcards on as they are
+    let sources = if copy_args.glob || cfg!(windows) {
+        glob::expand_sources(sources).map_err(|e| CpxError::Validation(e.to_string()))?
+    } else {
+        sources
+    };
+
+    // an existing local path wins over `host:path` syntax, as with scp
+    if !destination.exists()
+        && let Some(target) = destination.to_str().and_then(RemoteTarget::parse)
+    {
+        if copy_args.to_archive.is_some() {
+            return Err(CpxError::Validation(
+                "--to-archive cannot write to a remote destination".to_string(),
+            ));
+        }
+        validate_non_local(&options, "a remote destination").map_err(CpxError::Validation)?;
+        if options.fsync != FsyncMode::None {
+            return Err(CpxError::Validation(
+                "--fsync cannot be used with a remote destination".to_string(),
+            ));
         }
+        options.remote = Some(target);
+    } else if let Some(format) = copy_args
+        .to_archive
+        .or_else(|| ArchiveFormat::from_path(&destination).filter(|_| !destination.is_dir()))
+    {
+        validate_archive(&options, &destination).map_err(CpxError::Validation)?;
+        options.archive = Some(format);
+    } else if sources
+        .iter()
+        .any(|source| extract::is_archive_source(source, &options))
+    {
+        validate_extract(&options).map_err(CpxError::Validation)?;
+    }
 
-        let (sources, destination) = if let Some(target) = copy_args.target_directory {
-            let mut sources = copy_args.sources;
-            sources.push(copy_args.destination);
-            (sources, target)
-        } else {
-            (copy_args.sources, copy_args.destination)
-        };
+    if options.dedupe {
+        validate_links("--dedupe", &options, &sources).map_err(CpxError::Validation)?;
+    }
+    if options.link_dest.is_some() {
+        validate_links("--link-dest", &options, &sources).map_err(CpxError::Validation)?;
+    }
+    if options.compress.is_some() || options.decompress {
+        validate_transcode(&options, &sources).map_err(CpxError::Validation)?;
+    }
+
+    Ok((sources, destination, options))
+}
+
+/// `--job` without `--journal`: the run is journaled in the job's directory
+/// (see [`Job::open_journal`]). Left out where `--journal` isn't supported,
+/// and for `cpx watch`.
+fn with_job_journal(
+    (sources, destination, mut options): (Vec<PathBuf>, PathBuf, CopyOptions),
+) -> CpxResult<(Vec<PathBuf>, PathBuf, CopyOptions)> {
+    if let Some(job) = &options.job
+        && options.journal.is_none()
+        && options.remote.is_none()
+        && options.archive.is_none()
+        && !sources
+            .iter()
+            .any(|source| extract::is_archive_source(source, &options))
+    {
+        options.journal = Some(Arc::new(job.open_journal().map_err(CpxError::Io)?));
+    }
+    Ok((sources, destination, options))
+}
+
+/// `cpx plan`: writes the plan for `sources` to `output`, or stdout.
+fn export_plan(
+    sources: &[PathBuf],
+    destination: &Path,
+    options: &CopyOptions,
+    format: PlanFormat,
+    output: Option<&Path>,
+) -> CpxResult<()> {
+    // everything after `plan`, so `cpx apply` can parse it again
+    let args = std::env::args()
+        .skip(1)
+        .skip_while(|arg| arg != "plan")
+        .skip(1)
+        .collect();
+    // absolute paths keep the plan usable from another directory
+    let sources = sources
+        .iter()
+        .map(std::path::absolute)
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(CpxError::Io)?;
+    let destination = std::path::absolute(destination).map_err(CpxError::Io)?;
+
+    let plan = PlanFile::build(args, &sources, &destination, options).map_err(CpxError::Copy)?;
+    let text = match format {
+        PlanFormat::Json => plan.to_json().map_err(|e| CpxError::Io(e.into()))? + "\n",
+        PlanFormat::Csv => plan.to_csv(),
+    };
+    match output {
+        Some(path) => std::fs::write(path, text).map_err(CpxError::Io),
+        None => {
+            // a closed pipe (`cpx plan ... | head`) is not an error
+            let _ = std::io::stdout().write_all(text.as_bytes());
+            Ok(())
+        }
+    }
+}
+
+/// The answer `--yes` or `--assume-no` gives every prompt.
+fn answer(copy_args: &CopyArgs) -> Option<bool> {
+    if copy_args.yes {
+        Some(true)
+    } else if copy_args.assume_no {
+        Some(false)
+    } else {
+        None
+    }
+}
 
-        Ok((sources, destination, options))
+/// The destination symlink policy the flags ask for, if any.
+fn dest_symlinks(copy_args: &CopyArgs) -> Option<DestSymlinks> {
+    if copy_args.keep_directory_symlink {
+        Some(DestSymlinks::KeepDirs)
+    } else if copy_args.no_dereference_dest {
+        Some(DestSymlinks::Replace)
+    } else {
+        None
     }
 }
 
@@ -387,7 +2356,7 @@ fn load_config_if_needed(copy_args: &CopyArgs) -> crate::error::ConfigResult<Opt
 
 fn apply_cli_overrides(options: &mut CopyOptions, copy_args: &CopyArgs) -> Result<(), String> {
     // Boolean flags - when present, they override
-    if copy_args.recursive {
+    if copy_args.recursive || copy_args.archive_mode {
         options.recursive = true;
     }
     if copy_args.force {
@@ -395,6 +2364,19 @@ fn apply_cli_overrides(options: &mut CopyOptions, copy_args: &CopyArgs) -> Resul
     }
     if copy_args.interactive {
         options.interactive = true;
+        options.on_conflict = Some(ConflictPolicy::Prompt);
+    }
+    if copy_args.confirm {
+        options.confirm = true;
+    }
+    if copy_args.yes {
+        options.confirm = false;
+    }
+    if copy_args.yes || copy_args.assume_no {
+        options.assume = answer(copy_args);
+    }
+    if copy_args.list_conflicts.is_some() {
+        options.list_conflicts = copy_args.list_conflicts.clone();
     }
     if copy_args.resume {
         options.resume = true;
@@ -413,6 +2395,9 @@ fn apply_cli_overrides(options: &mut CopyOptions, copy_args: &CopyArgs) -> Resul
     }
 
     // Optional fields - when Some, they override
+    if copy_args.on_conflict.is_some() {
+        options.on_conflict = copy_args.on_conflict;
+    }
     if copy_args.symbolic_link.is_some() {
         options.symbolic_link = copy_args.symbolic_link;
     }
@@ -422,12 +2407,142 @@ fn apply_cli_overrides(options: &mut CopyOptions, copy_args: &CopyArgs) -> Resul
     if copy_args.reflink.is_some() {
         options.reflink = copy_args.reflink;
     }
+    if let Some(fsync) = copy_args.fsync {
+        options.fsync = fsync;
+    }
+    if let Some(spec) = copy_args.write_barrier {
+        options.write_barrier = Some(Arc::new(WriteBarrier::new(spec)));
+    }
+    if let Some(order) = copy_args.order {
+        options.order = order;
+    }
+    if let Some(nice) = copy_args.nice {
+        options.nice = nice;
+    }
+    if let Some(io_priority) = copy_args.ionice {
+        options.io_priority = io_priority;
+    }
+    if let Some(backend) = copy_args.backend {
+        options.backend = backend;
+    }
+    if let Some(window) = copy_args.modify_window {
+        options.modify_window = window;
+    }
+    if copy_args.inplace {
+        options.inplace = true;
+    }
+    if copy_args.link_dest.is_some() {
+        options.link_dest = copy_args.link_dest.clone();
+    }
+    options
+        .exclude_markers
+        .files
+        .extend(copy_args.exclude_if_present.iter().cloned());
+    if copy_args.exclude_caches {
+        options.exclude_markers.caches = true;
+    }
+    if let Some(hidden) = copy_args.hidden {
+        options.hidden = hidden;
+    }
+    if copy_args.prune_empty_dirs {
+        options.prune_empty_dirs = true;
+    }
+    if copy_args.sanitize_names.is_some() {
+        options.sanitize_names = copy_args.sanitize_names;
+    }
+    if copy_args.partial || copy_args.partial_dir.is_some() {
+        options.partial = true;
+        options.partial_dir = copy_args.partial_dir.clone();
+    }
+    if let Some(junctions) = copy_args.junctions {
+        options.junctions = junctions;
+    }
+    if let Some(fallback) = copy_args.symlink_fallback {
+        options.symlink_fallback = fallback;
+    }
+    if let Some(dest_symlinks) = dest_symlinks(copy_args) {
+        options.dest_symlinks = dest_symlinks;
+    }
+    for setting in &copy_args.progress {
+        match *setting {
+            ProgressSetting::Style(style) => options.progress_bar.style = style,
+            ProgressSetting::Mode(mode) => options.progress_bar.mode = mode,
+        }
+    }
+    if let Some(interval) = copy_args.progress_interval {
+        options.progress_bar.status_interval = interval;
+    }
+    options.verbosity = Verbosity::from_flags(copy_args.verbose, copy_args.quiet);
+    if copy_args.tui {
+        // the screen replaces bars and messages
+        options.tui = true;
+        options.verbosity = Verbosity::Quiet;
+    }
+    if copy_args.stats.is_some() {
+        options.stats = copy_args.stats;
+    }
+    options.manifest = copy_args.output_manifest.clone();
+    if let Some(algo) = copy_args.manifest_algo.or(copy_args.checksum_algo) {
+        options.manifest_algo = algo;
+    }
+    if let Some(algo) = copy_args.checksum_algo {
+        options.checksum_algo = algo;
+    }
+    options.verify = copy_args.verify;
+    options.store_checksum = copy_args.store_checksum;
+    options.ignore_errors = copy_args.ignore_errors;
+    options.strict = copy_args.strict;
+    options.error_report = copy_args.error_report.clone();
+    options.error_report_format = copy_args.error_report_format;
+    options.metrics_file = copy_args.metrics_file.clone();
+    if copy_args.compress.is_some() {
+        options.compress = copy_args.compress;
+        options.compress_level = copy_args.compress_level;
+    }
+    options.decompress = copy_args.decompress;
+    if copy_args.dedupe {
+        options.dedupe = true;
+    }
+    options.retries = copy_args.retries;
+    options.retry_delay = copy_args.retry_delay;
+    options.locked = copy_args.locked;
+    options.timeout_per_file = copy_args.timeout_per_file;
+    options.timeout_total = copy_args.timeout_total;
+    options.bwlimit = copy_args.bwlimit;
+    options.max_errors = copy_args.max_errors;
+    if copy_args.notify || copy_args.notify_after.is_some() {
+        options.notify = copy_args
+            .notify_after
+            .or(options.notify)
+            .or(Some(DEFAULT_NOTIFY_AFTER));
+    }
+    if copy_args.history {
+        options.history = true;
+    }
+    if copy_args.status_socket {
+        options.status_socket = true;
+    }
     if let Some(preserve_str) = &copy_args.preserve {
         options.preserve = PreserveAttr::from_string(preserve_str)
             .map_err(|e| format!("unable to parse preserve attribute: {}", e))?;
+    } else if copy_args.archive_mode {
+        options.preserve = PreserveAttr::all();
+    }
+    if let Some(no_preserve_str) = &copy_args.no_preserve {
+        options.preserve = options
+            .preserve
+            .without(no_preserve_str)
+            .map_err(|e| format!("unable to parse no-preserve attribute: {}", e))?;
     }
 
-    options.parallel = copy_args.parallel;
+    options.parallel = copy_args.parallel.workers();
+    options.auto_jobs = copy_args.parallel == Jobs::Auto;
+    if copy_args.deterministic {
+        options.deterministic = true;
+        options.parallel = 1;
+        options.auto_jobs = false;
+        options.order = FileOrder::Path;
+    }
 
     options.follow_symlink = copy_args.follow_symlink_mode()?;
 
@@ -441,6 +2556,7 @@ fn build_all_exclude_patterns(
     let mut all_patterns = Vec::new();
 
     if let Some(cfg) = config {
+        all_patterns.extend(builtin_patterns(cfg.exclude.vcs, cfg.exclude.common));
         for pattern_str in &cfg.exclude.patterns {
             all_patterns.extend(parse_exclude_pattern_list(pattern_str)?);
         }
@@ -450,7 +2566,205 @@ fn build_all_exclude_patterns(
     Ok(all_patterns)
 }
 
+fn validate_archive(options: &CopyOptions, destination: &Path) -> Result<(), String> {
+    validate_non_local(options, "an archive destination")?;
+    if options.resume {
+        return Err("--resume cannot be used with an archive destination".to_string());
+    }
+    // stdout carries the archive itself
+    if destination == Path::new(archive::STDOUT)
+        && (options.progress_bar.is_json() || options.verbosity >= Verbosity::Verbose)
+    {
+        return Err(
+            "--progress=json and --verbose cannot be used when writing the archive to stdout"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// `--dedupe` and `--link-dest` hard-link plain file copies, which these
+/// modes don't make.
+fn validate_links(flag: &str, options: &CopyOptions, sources: &[PathBuf]) -> Result<(), String> {
+    let unsupported = [
+        ("a remote destination", options.remote.is_some()),
+        ("an archive destination", options.archive.is_some()),
+        (
+            "an archive source",
+            sources
+                .iter()
+                .any(|source| extract::is_archive_source(source, options)),
+        ),
+        ("--link", options.hard_link),
+        ("--symbolic-link", options.symbolic_link.is_some()),
+        ("--attributes-only", options.attributes_only),
+    ];
+    match unsupported.iter().find(|(_, set)| *set) {
+        Some((other, _)) => Err(format!("{} cannot be used with {}", flag, other)),
+        None => Ok(()),
+    }
+}
+
+fn validate_watch(options: &CopyOptions, sources: &[PathBuf]) -> Result<(), String> {
+    let unsupported = [
+        ("a remote destination", options.remote.is_some()),
+        ("an archive destination", options.archive.is_some()),
+        (
+            "an archive source",
+            sources
+                .iter()
+                .any(|source| extract::is_archive_source(source, options)),
+        ),
+        ("--parents", options.parents),
+        ("--interactive", options.prompts()),
+        ("--tui", options.tui),
+        ("--output-manifest", options.manifest.is_some()),
+        ("--journal", options.journal.is_some()),
+    ];
+    match unsupported.iter().find(|(_, set)| *set) {
+        Some((other, _)) => Err(format!("watch cannot be used with {}", other)),
+        None => Ok(()),
+    }
+}
+
+/// `cpx plan` and `cpx check` only plan local copies.
+fn validate_plan(command: &str, options: &CopyOptions, sources: &[PathBuf]) -> Result<(), String> {
+    let unsupported = [
+        ("a remote destination", options.remote.is_some()),
+        ("an archive destination", options.archive.is_some()),
+        (
+            "an archive source",
+            sources
+                .iter()
+                .any(|source| extract::is_archive_source(source, options)),
+        ),
+    ];
+    match unsupported.iter().find(|(_, set)| *set) {
+        Some((other, _)) => Err(format!("{} cannot be used with {}", command, other)),
+        None => Ok(()),
+    }
+}
+
+fn validate_transcode(options: &CopyOptions, sources: &[PathBuf]) -> Result<(), String> {
+    let flag = if options.decompress {
+        "--decompress"
+    } else {
+        "--compress"
+    };
+    let unsupported = [
+        ("a remote destination", options.remote.is_some()),
+        ("an archive destination", options.archive.is_some()),
+        (
+            "an archive source",
+            sources
+                .iter()
+                .any(|source| extract::is_archive_source(source, options)),
+        ),
+        ("--link", options.hard_link),
+        ("--symbolic-link", options.symbolic_link.is_some()),
+        ("--attributes-only", options.attributes_only),
+        (
+            "--reflink=always",
+            options.reflink == Some(ReflinkMode::Always),
+        ),
+        // the destination holds different bytes than the source
+        (
+            "--verify",
+            options.verify.is_some() && options.compress.is_some(),
+        ),
+    ];
+    match unsupported.iter().find(|(_, set)| *set) {
+        Some((other, _)) => Err(format!("{} cannot be used with {}", flag, other)),
+        None => Ok(()),
+    }
+}
+
+/// Options that need a source file on disk, which archive entries aren't.
+fn validate_extract(options: &CopyOptions) -> Result<(), String> {
+    let unsupported = [
+        ("--link", options.hard_link),
+        ("--symbolic-link", options.symbolic_link.is_some()),
+        ("--attributes-only", options.attributes_only),
+        (
+            "--reflink=always",
+            options.reflink == Some(ReflinkMode::Always),
+        ),
+        ("--output-manifest", options.manifest.is_some()),
+        ("--verify", options.verify.is_some()),
+        ("--parents", options.parents),
+        ("--journal", options.journal.is_some()),
+        ("--write-barrier", options.write_barrier.is_some()),
+        ("--store-checksum", options.store_checksum),
+        // these compare with or rename around a source file
+        (
+            "--on-conflict=newer",
+            options.on_conflict == Some(ConflictPolicy::Newer),
+        ),
+        (
+            "--on-conflict=larger",
+            options.on_conflict == Some(ConflictPolicy::Larger),
+        ),
+        (
+            "--on-conflict=rename",
+            options.on_conflict == Some(ConflictPolicy::Rename),
+        ),
+    ];
+    match unsupported.iter().find(|(_, set)| *set) {
+        Some((flag, _)) => Err(format!("{} cannot be used with an archive source", flag)),
+        None => Ok(()),
+    }
+}
+
+/// Options that only make sense when writing to the local filesystem.
+fn validate_non_local(options: &CopyOptions, destination: &str) -> Result<(), String> {
+    let unsupported = [
+        ("--link", options.hard_link),
+        ("--symbolic-link", options.symbolic_link.is_some()),
+        ("--attributes-only", options.attributes_only),
+        // the config's default `mode = "none"` parses to Some(None)
+        (
+            "--backup",
+            !matches!(options.backup, None | Some(BackupMode::None)),
+        ),
+        (
+            "--reflink=always",
+            options.reflink == Some(ReflinkMode::Always),
+        ),
+        ("--output-manifest", options.manifest.is_some()),
+        ("--verify", options.verify.is_some()),
+        ("--interactive", options.interactive),
+        ("--on-conflict", options.on_conflict.is_some()),
+        ("--remove-destination", options.remove_destination),
+        ("--parents", options.parents),
+        ("--journal", options.journal.is_some()),
+        ("--list-conflicts", options.list_conflicts.is_some()),
+        ("--write-barrier", options.write_barrier.is_some()),
+        ("--store-checksum", options.store_checksum),
+        (
+            "--no-dereference-dest",
+            options.dest_symlinks != DestSymlinks::Follow,
+        ),
+        ("--sanitize-names", options.sanitize_names.is_some()),
+    ];
+    match unsupported.iter().find(|(_, set)| *set) {
+        Some((flag, _)) => Err(format!("{} cannot be used with {}", flag, destination)),
+        None => Ok(()),
+    }
+}
+
 fn validate_conflicts(options: &CopyOptions) -> Result<(), String> {
+    if options.tui {
+        if options.prompts() {
+            return Err("--tui and --interactive cannot be used together".to_string());
+        }
+        if options.confirm {
+            return Err("--tui and --confirm cannot be used together".to_string());
+        }
+        if !std::io::stdout().is_terminal() {
+            return Err("--tui requires stdout to be a terminal".to_string());
+        }
+    }
+
     if options.reflink.is_some() {
         if options.hard_link {
             return Err("--reflink and --link cannot be used together".to_string());
@@ -504,6 +2818,7 @@ impl CopyArgs {
     pub fn parse_exclude_patterns(&self) -> crate::error::ExcludeResult<Vec<ExcludePattern>> {
         let mut patterns = Vec::new();
 
+        patterns.extend(builtin_patterns(self.exclude_vcs, self.exclude_common));
         for pattern_str in &self.exclude {
             patterns.extend(parse_exclude_pattern_list(pattern_str)?);
         }
@@ -519,17 +2834,32 @@ mod tests {
     #[test]
     fn test_validate_symlink_and_hardlink_conflict() {
         let args = CLIArgs {
-            command: Commands::Copy(CopyArgs {
+            command: Commands::Copy(Box::new(CopyArgs {
                 sources: vec![PathBuf::from("source.txt")],
                 destination: PathBuf::from("dest.txt"),
                 target_directory: None,
+                glob: false,
                 recursive: false,
-                parallel: 4,
+                archive_mode: false,
+                parallel: Jobs::Count(4),
                 resume: false,
                 force: false,
                 interactive: false,
+                on_conflict: None,
+                confirm: false,
+                yes: false,
+                assume_no: false,
+                list_conflicts: None,
                 parents: false,
                 preserve: None,
+                no_preserve: None,
+                chown: None,
+                chmod: None,
+                set_attr: None,
+                junctions: None,
+                symlink_fallback: None,
+                no_dereference_dest: false,
+                keep_directory_symlink: false,
                 attributes_only: false,
                 remove_destination: false,
                 symbolic_link: Some(SymlinkMode::Auto),
@@ -539,10 +2869,67 @@ mod tests {
                 dereference_command_line: false,
                 backup: None,
                 reflink: None,
+                backend: None,
+                strip_zone_identifier: false,
+                fsync: None,
+                write_barrier: None,
+                nice: None,
+                ionice: None,
+                order: None,
+                deterministic: false,
+                modify_window: None,
+                partial: false,
+                partial_dir: None,
+                inplace: false,
+                link_dest: None,
+                to_archive: None,
+                compress: None,
+                compress_level: compress::DEFAULT_LEVEL,
+                decompress: false,
+                dedupe: false,
+                progress: Vec::new(),
+                progress_interval: None,
+                tui: false,
+                verbose: 0,
+                quiet: false,
+                log_file: None,
+                log_level: LogLevel::Info,
+                log_format: LogFormat::Text,
+                journal: None,
+                job: None,
+                stats: None,
+                output_manifest: None,
+                manifest_algo: None,
+                checksum_algo: None,
+                verify: None,
+                store_checksum: false,
+                ignore_errors: false,
+                strict: false,
+                error_report: None,
+                error_report_format: ErrorReportFormat::Text,
+                metrics_file: None,
+                retries: 0,
+                retry_delay: Duration::from_secs(1),
+                locked: LockedFiles::Fail,
+                timeout_per_file: None,
+                timeout_total: None,
+                bwlimit: None,
+                max_errors: None,
+                notify: false,
+                notify_after: None,
+                history: false,
+                status_socket: false,
                 exclude: Vec::new(),
+                exclude_if_present: Vec::new(),
+                exclude_caches: false,
+                prune_empty_dirs: false,
+                sanitize_names: None,
+                exclude_vcs: false,
+                exclude_common: false,
+                hidden: None,
                 no_config: false,
                 config: None,
-            }),
+            })),
         };
 
         let result = args.validate();
@@ -553,17 +2940,32 @@ mod tests {
     #[test]
     fn test_validate_symlink_and_resume_conflict() {
         let args = CLIArgs {
-            command: Commands::Copy(CopyArgs {
+            command: Commands::Copy(Box::new(CopyArgs {
                 sources: vec![PathBuf::from("source.txt")],
                 destination: PathBuf::from("dest.txt"),
                 target_directory: None,
+                glob: false,
                 recursive: false,
-                parallel: 4,
+                archive_mode: false,
+                parallel: Jobs::Count(4),
                 resume: true,
                 force: false,
                 interactive: false,
+                on_conflict: None,
+                confirm: false,
+                yes: false,
+                assume_no: false,
+                list_conflicts: None,
                 parents: false,
                 preserve: None,
+                no_preserve: None,
+                chown: None,
+                chmod: None,
+                set_attr: None,
+                junctions: None,
+                symlink_fallback: None,
+                no_dereference_dest: false,
+                keep_directory_symlink: false,
                 attributes_only: false,
                 remove_destination: false,
                 symbolic_link: Some(SymlinkMode::Auto),
@@ -573,10 +2975,67 @@ mod tests {
                 dereference_command_line: false,
                 backup: None,
                 reflink: None,
+                backend: None,
+                strip_zone_identifier: false,
+                fsync: None,
+                write_barrier: None,
+                nice: None,
+                ionice: None,
+                order: None,
+                deterministic: false,
+                modify_window: None,
+                partial: false,
+                partial_dir: None,
+                inplace: false,
+                link_dest: None,
+                to_archive: None,
+                compress: None,
+                compress_level: compress::DEFAULT_LEVEL,
+                decompress: false,
+                dedupe: false,
+                progress: Vec::new(),
+                progress_interval: None,
+                tui: false,
+                verbose: 0,
+                quiet: false,
+                log_file: None,
+                log_level: LogLevel::Info,
+                log_format: LogFormat::Text,
+                journal: None,
+                job: None,
+                stats: None,
+                output_manifest: None,
+                manifest_algo: None,
+                checksum_algo: None,
+                verify: None,
+                store_checksum: false,
+                ignore_errors: false,
+                strict: false,
+                error_report: None,
+                error_report_format: ErrorReportFormat::Text,
+                metrics_file: None,
+                retries: 0,
+                retry_delay: Duration::from_secs(1),
+                locked: LockedFiles::Fail,
+                timeout_per_file: None,
+                timeout_total: None,
+                bwlimit: None,
+                max_errors: None,
+                notify: false,
+                notify_after: None,
+                history: false,
+                status_socket: false,
                 exclude: Vec::new(),
+                exclude_if_present: Vec::new(),
+                exclude_caches: false,
+                prune_empty_dirs: false,
+                sanitize_names: None,
+                exclude_vcs: false,
+                exclude_common: false,
+                hidden: None,
                 no_config: false,
                 config: None,
-            }),
+            })),
         };
 
         let result = args.validate();
@@ -587,17 +3046,32 @@ mod tests {
     #[test]
     fn test_validate_hardlink_and_resume_conflict() {
         let args = CLIArgs {
-            command: Commands::Copy(CopyArgs {
+            command: Commands::Copy(Box::new(CopyArgs {
                 sources: vec![PathBuf::from("source.txt")],
                 destination: PathBuf::from("dest.txt"),
                 target_directory: None,
+                glob: false,
                 recursive: false,
-                parallel: 4,
+                archive_mode: false,
+                parallel: Jobs::Count(4),
                 resume: true,
                 force: false,
                 interactive: false,
+                on_conflict: None,
+                confirm: false,
+                yes: false,
+                assume_no: false,
+                list_conflicts: None,
                 parents: false,
                 preserve: None,
+                no_preserve: None,
+                chown: None,
+                chmod: None,
+                set_attr: None,
+                junctions: None,
+                symlink_fallback: None,
+                no_dereference_dest: false,
+                keep_directory_symlink: false,
                 attributes_only: false,
                 remove_destination: false,
                 symbolic_link: None,
@@ -607,10 +3081,67 @@ mod tests {
                 dereference_command_line: false,
                 backup: None,
                 reflink: None,
+                backend: None,
+                strip_zone_identifier: false,
+                fsync: None,
+                write_barrier: None,
+                nice: None,
+                ionice: None,
+                order: None,
+                deterministic: false,
+                modify_window: None,
+                partial: false,
+                partial_dir: None,
+                inplace: false,
+                link_dest: None,
+                to_archive: None,
+                compress: None,
+                compress_level: compress::DEFAULT_LEVEL,
+                decompress: false,
+                dedupe: false,
+                progress: Vec::new(),
+                progress_interval: None,
+                tui: false,
+                verbose: 0,
+                quiet: false,
+                log_file: None,
+                log_level: LogLevel::Info,
+                log_format: LogFormat::Text,
+                journal: None,
+                job: None,
+                stats: None,
+                output_manifest: None,
+                manifest_algo: None,
+                checksum_algo: None,
+                verify: None,
+                store_checksum: false,
+                ignore_errors: false,
+                strict: false,
+                error_report: None,
+                error_report_format: ErrorReportFormat::Text,
+                metrics_file: None,
+                retries: 0,
+                retry_delay: Duration::from_secs(1),
+                locked: LockedFiles::Fail,
+                timeout_per_file: None,
+                timeout_total: None,
+                bwlimit: None,
+                max_errors: None,
+                notify: false,
+                notify_after: None,
+                history: false,
+                status_socket: false,
                 exclude: Vec::new(),
+                exclude_if_present: Vec::new(),
+                exclude_caches: false,
+                prune_empty_dirs: false,
+                sanitize_names: None,
+                exclude_vcs: false,
+                exclude_common: false,
+                hidden: None,
                 no_config: false,
                 config: None,
-            }),
+            })),
         };
 
         let result = args.validate();
@@ -621,17 +3152,32 @@ mod tests {
     #[test]
     fn test_validate_success() {
         let args = CLIArgs {
-            command: Commands::Copy(CopyArgs {
+            command: Commands::Copy(Box::new(CopyArgs {
                 sources: vec![PathBuf::from("source.txt")],
                 destination: PathBuf::from("dest.txt"),
                 target_directory: None,
+                glob: false,
                 recursive: false,
-                parallel: 4,
+                archive_mode: false,
+                parallel: Jobs::Count(4),
                 resume: false,
                 force: false,
                 interactive: false,
+                on_conflict: None,
+                confirm: false,
+                yes: false,
+                assume_no: false,
+                list_conflicts: None,
                 parents: false,
                 preserve: None,
+                no_preserve: None,
+                chown: None,
+                chmod: None,
+                set_attr: None,
+                junctions: None,
+                symlink_fallback: None,
+                no_dereference_dest: false,
+                keep_directory_symlink: false,
                 attributes_only: false,
                 remove_destination: false,
                 symbolic_link: None,
@@ -641,10 +3187,67 @@ mod tests {
                 dereference_command_line: false,
                 backup: None,
                 reflink: None,
+                backend: None,
+                strip_zone_identifier: false,
+                fsync: None,
+                write_barrier: None,
+                nice: None,
+                ionice: None,
+                order: None,
+                deterministic: false,
+                modify_window: None,
+                partial: false,
+                partial_dir: None,
+                inplace: false,
+                link_dest: None,
+                to_archive: None,
+                compress: None,
+                compress_level: compress::DEFAULT_LEVEL,
+                decompress: false,
+                dedupe: false,
+                progress: Vec::new(),
+                progress_interval: None,
+                tui: false,
+                verbose: 0,
+                quiet: false,
+                log_file: None,
+                log_level: LogLevel::Info,
+                log_format: LogFormat::Text,
+                journal: None,
+                job: None,
+                stats: None,
+                output_manifest: None,
+                manifest_algo: None,
+                checksum_algo: None,
+                verify: None,
+                store_checksum: false,
+                ignore_errors: false,
+                strict: false,
+                error_report: None,
+                error_report_format: ErrorReportFormat::Text,
+                metrics_file: None,
+                retries: 0,
+                retry_delay: Duration::from_secs(1),
+                locked: LockedFiles::Fail,
+                timeout_per_file: None,
+                timeout_total: None,
+                bwlimit: None,
+                max_errors: None,
+                notify: false,
+                notify_after: None,
+                history: false,
+                status_socket: false,
                 exclude: Vec::new(),
+                exclude_if_present: Vec::new(),
+                exclude_caches: false,
+                prune_empty_dirs: false,
+                sanitize_names: None,
+                exclude_vcs: false,
+                exclude_common: false,
+                hidden: None,
                 no_config: false,
                 config: None,
-            }),
+            })),
         };
 
         let result = args.validate();
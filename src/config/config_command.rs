@@ -197,6 +197,10 @@ fn add_comments_to_config(toml: &str) -> String {
                 result.push_str("# mode: \"auto\", \"absolute\", \"relative\"\n");
                 result
                     .push_str("# follow: \"never\" (-P), \"always\" (-L), \"command-line\" (-H)\n");
+                result.push_str("# junctions: \"skip\", \"recreate\", \"follow\" (Windows)\n");
+                result.push_str(
+                    "# fallback: \"copy\", \"skip\", \"error\" when symlinks aren't permitted\n",
+                );
             }
             l if l.starts_with("[backup]") => {
                 result.push_str("\n# Backup settings\n");
@@ -208,6 +212,10 @@ fn add_comments_to_config(toml: &str) -> String {
                 result.push_str("\n# Copy-on-Write (reflink) settings\n");
                 result.push_str("# mode: \"auto\", \"always\", \"never\"\n");
             }
+            l if l.starts_with("[notify]") => {
+                result
+                    .push_str("\n# Desktop notification when a copy takes at least min_duration\n");
+            }
             l if l.starts_with("[progress]") => {
                 result.push_str("\n# Progress bar settings\n");
             }
@@ -5,6 +5,17 @@ use serde::{Deserialize, Serialize};
 #[derive(Default)]
 pub struct ExcludeConfig {
     pub patterns: Vec<String>,
+    /// Add the built-in version control patterns.
+    pub vcs: bool,
+    /// Add the version control and common build/cache patterns.
+    pub common: bool,
+    /// Leave out directories holding a file with one of these names.
+    pub if_present: Vec<String>,
+    /// Leave out directories tagged with a valid `CACHEDIR.TAG`.
+    pub caches: bool,
+    pub hidden: String, // "include", "skip"
+    /// Leave out directories that would end up empty.
+    pub prune_empty_dirs: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,9 +26,21 @@ pub struct CopyConfig {
     pub parents: bool,
     pub force: bool,
     pub interactive: bool,
+    pub on_conflict: String, // "overwrite", "skip", "newer", "larger", "rename", "prompt", "error"
+    pub confirm: bool,
     pub resume: bool,
     pub attributes_only: bool,
     pub remove_destination: bool,
+    pub fsync: String,         // "none", "file", "dir", "all"
+    pub order: String,         // "size-desc", "size-asc", "path", "none"
+    pub modify_window: String, // e.g. "2s"
+    pub nice: i32,             // 0-19, 0 leaves the priority alone
+    pub ionice: String,        // "normal", "low", "idle"
+    pub backend: String,       // "auto", "copy-file-range", "mmap", "buffered"
+    pub write_barrier: String, // e.g. "1000", "1G" or "1000,1G"; "" for none
+    pub history: bool,
+    pub status_socket: bool,
+    pub sanitize_names: String, // "replace", "percent"; "" leaves names alone
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,8 +52,12 @@ pub struct PreserveConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct SymlinkConfig {
-    pub mode: String,   // "auto", "absolute", "relative"
-    pub follow: String, // "never", "always", "command-line"
+    pub mode: String,      // "auto", "absolute", "relative"
+    pub follow: String,    // "never", "always", "command-line"
+    pub junctions: String, // "skip", "recreate", "follow"
+    pub fallback: String,  // "copy", "skip", "error"
+    /// Symlinks at the destination: "follow", "replace", "keep-dirs".
+    pub destination: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,10 +72,22 @@ pub struct ReflinkConfig {
     pub mode: String, // "auto", "always", "never"
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub enabled: bool,
+    pub min_duration: String, // humantime, e.g. "30s", "5m"
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ProgressConfig {
-    pub style: String, // "default", "detailed"
+    pub style: String, // "default", "detailed", "json"
+    pub mode: String,  // "auto", "always", "plain", "never"
+    /// Bars for up to this many workers' files; 0 turns them off.
+    pub file_bars: usize,
+    pub refresh_hz: u8,          // bar redraws per second
+    pub status_interval: String, // between plain status lines, e.g. "10s"
     pub bar: ProgressBarConfig,
     pub color: ProgressColorConfig,
 }
@@ -79,6 +118,7 @@ pub struct Config {
     pub backup: BackupConfig,
     pub reflink: ReflinkConfig,
     pub progress: ProgressConfig,
+    pub notify: NotifyConfig,
 }
 
 impl Default for CopyConfig {
@@ -89,9 +129,21 @@ impl Default for CopyConfig {
             parents: false,
             force: false,
             interactive: false,
+            on_conflict: "".to_string(),
+            confirm: false,
             resume: false,
             attributes_only: false,
             remove_destination: false,
+            fsync: "none".to_string(),
+            order: "size-desc".to_string(),
+            modify_window: "0s".to_string(),
+            nice: 0,
+            ionice: "normal".to_string(),
+            backend: "auto".to_string(),
+            write_barrier: "".to_string(),
+            history: false,
+            status_socket: false,
+            sanitize_names: "".to_string(),
         }
     }
 }
@@ -109,6 +161,9 @@ impl Default for SymlinkConfig {
         Self {
             mode: "".to_string(),
             follow: "".to_string(),
+            junctions: "recreate".to_string(),
+            fallback: "error".to_string(),
+            destination: "follow".to_string(),
         }
     }
 }
@@ -129,10 +184,23 @@ impl Default for ReflinkConfig {
     }
 }
 
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_duration: "30s".to_string(),
+        }
+    }
+}
+
 impl Default for ProgressConfig {
     fn default() -> Self {
         Self {
             style: "default".to_string(),
+            mode: "auto".to_string(),
+            file_bars: 0,
+            refresh_hz: 20,
+            status_interval: "10s".to_string(),
             bar: ProgressBarConfig::default(),
             color: ProgressColorConfig::default(),
         }
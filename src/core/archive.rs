@@ -0,0 +1,342 @@
+use crate::cli::args::CopyOptions;
+use crate::core::copy::{checkpoint_error, failures_to_result, finish_run, report_bytes};
+use crate::error::{CopyError, CopyResult, FailedFile};
+use crate::utility::control::Checkpoint;
+use crate::utility::events::CopyEvent;
+use crate::utility::helper::{open_source, sync_parent_dirs, sync_path};
+use crate::utility::output;
+use crate::utility::preprocess::{CopyPlan, SymlinkKind, preprocess_tree};
+use crate::utility::reporter::{self, ProgressReporter};
+use crate::utility::stats::CopyStats;
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Destination that means "write the archive to stdout".
+pub const STDOUT: &str = "-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveFormat {
+    Tar,
+    /// Tar compressed with zstd
+    #[value(name = "tar.zst")]
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// Guesses the format from a destination such as `backup.tar.zst`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Streams `sources` into a tar archive at `destination` (`-` for stdout),
+/// preserving modes, owners and mtimes. Directories keep their own name
+/// inside the archive, like `tar -C parent name` would.
+pub fn write_archive(
+    sources: &[PathBuf],
+    destination: &Path,
+    format: ArchiveFormat,
+    options: &CopyOptions,
+    stats: &CopyStats,
+) -> CopyResult<()> {
+    let reporter = reporter::for_options(options);
+    reporter.scan_started(&sources.iter().map(PathBuf::as_path).collect::<Vec<_>>());
+    let plan = plan_archive(sources, options)?;
+    reporter.scan_finished(plan.total_files, plan.total_size, plan.skipped_files);
+    stats.record_unreadable(&plan.unreadable);
+    options.events.emit(|| CopyEvent::Scanned {
+        total_files: plan.total_files,
+        total_bytes: plan.total_size,
+        skipped_files: plan.skipped_files,
+    });
+
+    let to_stdout = destination == Path::new(STDOUT);
+    let out: Box<dyn Write> = if to_stdout {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(destination)?)
+    };
+    let out = BufWriter::new(out);
+
+    let result = (|| -> CopyResult<Vec<FailedFile>> {
+        match format {
+            ArchiveFormat::Tar => {
+                let mut builder = tar::Builder::new(out);
+                let failures = append_plan(&mut builder, &plan, options, stats, reporter.as_ref())?;
+                builder.into_inner()?.flush()?;
+                Ok(failures)
+            }
+            ArchiveFormat::TarZst => {
+                let mut builder = tar::Builder::new(zstd::Encoder::new(out, 0)?);
+                let failures = append_plan(&mut builder, &plan, options, stats, reporter.as_ref())?;
+                builder.into_inner()?.finish()?.flush()?;
+                Ok(failures)
+            }
+        }
+    })();
+
+    let result = result.and_then(|failures| {
+        if !to_stdout {
+            if options.fsync.files() {
+                sync_path(destination)?;
+            }
+            if options.fsync.dirs() {
+                sync_parent_dirs([destination])?;
+            }
+        }
+        Ok(failures)
+    });
+
+    let result = match result {
+        Ok(failures) if failures.is_empty() => {
+            reporter.finish("Done");
+            Ok(())
+        }
+        Ok(failures) => {
+            reporter.abandon("Completed with errors");
+            failures_to_result(failures)
+        }
+        Err(e) => {
+            reporter.abandon("Archive incomplete");
+            if !to_stdout {
+                // a truncated archive is worse than none
+                let _ = std::fs::remove_file(destination);
+            }
+            Err(e)
+        }
+    };
+    finish_run(result, stats, options, reporter.as_ref())
+}
+
+fn plan_archive(sources: &[PathBuf], options: &CopyOptions) -> CopyResult<CopyPlan> {
+    // entry names are relative, so nothing may be compared against the local
+    // filesystem as a destination
+    let mut planning = options.clone();
+    planning.resume = false;
+
+    let mut plan = CopyPlan::new();
+    for source in sources {
+        let metadata =
+            std::fs::metadata(source).map_err(|_| CopyError::InvalidSource(source.clone()))?;
+        let name = source
+            .file_name()
+            .map(PathBuf::from)
+            .ok_or_else(|| CopyError::InvalidSource(source.clone()))?;
+        if metadata.is_dir() {
+            if !options.recursive {
+                return Err(CopyError::CopyFailed {
+                    source: source.clone(),
+                    destination: name,
+                    reason: "'src' is a directory (not copied, use -r to copy recursively)"
+                        .to_string(),
+                });
+            }
+            plan.merge(preprocess_tree(source, &name, &planning)?);
+        } else {
+            plan.add_file(source.clone(), name, metadata.len());
+        }
+    }
+    // archive order follows the walk so directories precede their contents
+    Ok(plan)
+}
+
+/// Appends every planned entry. Sources that can't be opened are reported as
+/// failures; errors writing the archive itself end the run.
+fn append_plan<W: Write>(
+    builder: &mut tar::Builder<W>,
+    plan: &CopyPlan,
+    options: &CopyOptions,
+    stats: &CopyStats,
+    reporter: &dyn ProgressReporter,
+) -> CopyResult<Vec<FailedFile>> {
+    let mut failures = Vec::new();
+    builder.follow_symlinks(false);
+
+    for dir in &plan.directories {
+        if let Some(source) = &dir.source {
+            builder.append_dir(&dir.destination, source)?;
+        }
+    }
+
+    for link in &plan.symlinks {
+        if !matches!(
+            link.kind,
+            SymlinkKind::PreserveExact | SymlinkKind::Junction
+        ) {
+            continue;
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        builder.append_link(&mut header, &link.destination, &link.source)?;
+        stats.record_symlink();
+    }
+
+    reporter.begin(
+        plan.total_files,
+        plan.total_size,
+        plan.skipped_files,
+        plan.skipped_size,
+    );
+    options
+        .control
+        .set_totals(plan.total_files, plan.total_size);
+
+    for task in &plan.files {
+        let checkpoint = options.control.checkpoint();
+        if checkpoint == Checkpoint::Abort {
+            return Err(checkpoint_error(checkpoint, &task.source));
+        }
+
+        reporter.file_begin(&task.source, &task.destination, task.size);
+        options.control.start_file(&task.source, task.size);
+        let started = Instant::now();
+
+        let opened = open_source(&task.source).and_then(|file| Ok((file.metadata()?, file)));
+        let (metadata, file) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                let e = CopyError::Io(e);
+                options
+                    .control
+                    .finish_file(Some(format!("{}: {}", task.source.display(), e)));
+                reporter.file_failed(&task.source, &task.destination, &e);
+                stats.record_failed();
+                if let Some(logger) = &options.logger {
+                    logger.failed(&task.source, &task.destination, &e.to_string());
+                }
+                failures.push(FailedFile::new(&task.source, &task.destination, e));
+                continue;
+            }
+        };
+
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+        let reader = ProgressReader {
+            file,
+            source: &task.source,
+            options,
+            stats,
+            reporter,
+        };
+        builder.append_data(&mut header, &task.destination, reader)?;
+
+        options.control.finish_file(None);
+        stats.record_copied();
+        stats.record_duration(&task.source, started.elapsed());
+        reporter.file_done(&task.source, &task.destination, None);
+        options.events.emit(|| CopyEvent::Finished {
+            source: task.source.clone(),
+            destination: task.destination.clone(),
+        });
+        output::copied(options, reporter, &task.source, &task.destination);
+    }
+    Ok(failures)
+}
+
+/// Feeds a file into the archive while reporting progress. Once its header
+/// is written an entry can't be dropped, so only an abort stops it midway.
+struct ProgressReader<'a> {
+    file: File,
+    source: &'a Path,
+    options: &'a CopyOptions,
+    stats: &'a CopyStats,
+    reporter: &'a dyn ProgressReporter,
+}
+
+impl Read for ProgressReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.options.control.checkpoint() == Checkpoint::Abort {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Operation aborted by user",
+            ));
+        }
+        let read = self.file.read(buf)?;
+        report_bytes(
+            self.reporter,
+            self.stats,
+            self.source,
+            read as u64,
+            self.options,
+        );
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::output::Verbosity;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn entries(archive: impl Read) -> Vec<String> {
+        tar::Archive::new(archive)
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("backup.tar")),
+            Some(ArchiveFormat::Tar)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("/tmp/backup.tar.zst")),
+            Some(ArchiveFormat::TarZst)
+        );
+        assert_eq!(ArchiveFormat::from_path(Path::new("backup.zip")), None);
+    }
+
+    #[test]
+    fn test_archive_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("data");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("a.txt"), "alpha").unwrap();
+        fs::write(source.join("sub/b.txt"), "beta").unwrap();
+        let mut options = CopyOptions::none();
+        options.recursive = true;
+        options.verbosity = Verbosity::Silent;
+
+        for (name, format) in [
+            ("out.tar", ArchiveFormat::Tar),
+            ("out.tar.zst", ArchiveFormat::TarZst),
+        ] {
+            let archive = temp_dir.path().join(name);
+            let stats = CopyStats::new();
+            write_archive(
+                std::slice::from_ref(&source),
+                &archive,
+                format,
+                &options,
+                &stats,
+            )
+            .unwrap();
+            assert_eq!(stats.files_copied(), 2);
+
+            let file = File::open(&archive).unwrap();
+            let mut paths = match format {
+                ArchiveFormat::Tar => entries(file),
+                ArchiveFormat::TarZst => entries(zstd::Decoder::new(file).unwrap()),
+            };
+            paths.sort();
+            assert_eq!(paths, ["data", "data/a.txt", "data/sub", "data/sub/b.txt"]);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use clap::ValueEnum;
+use indicatif::BinaryBytes;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A way of copying file contents, timed on its own by `cpx bench`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// read/write through a buffer
+    Buffered,
+    /// in-kernel copy, no data through userspace (Linux)
+    CopyFileRange,
+    /// copy-on-write clone, needs btrfs, xfs or similar
+    Reflink,
+    /// write out a memory mapping of the source (Unix)
+    Mmap,
+}
+
+impl Backend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Backend::Buffered => "buffered",
+            Backend::CopyFileRange => "copy_file_range",
+            Backend::Reflink => "reflink",
+            Backend::Mmap => "mmap",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Where the test set and the copies are written; should be on the
+    /// filesystem being tuned for.
+    pub work_dir: PathBuf,
+    /// Copy real files from here instead of generating them.
+    pub sample: Option<PathBuf>,
+    pub files: usize,
+    pub file_size: u64,
+    pub backends: Vec<Backend>,
+    /// Only used by [`Backend::Buffered`].
+    pub buffer_sizes: Vec<u64>,
+    pub parallel: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    pub backend: Backend,
+    pub buffer_size: Option<u64>,
+    pub parallel: usize,
+    pub seconds: f64,
+    pub bytes_per_second: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Unsupported {
+    pub backend: Backend,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BenchReport {
+    pub files: usize,
+    pub total_bytes: u64,
+    pub results: Vec<BenchResult>,
+    pub unsupported: Vec<Unsupported>,
+}
+
+impl BenchReport {
+    pub fn fastest(&self) -> Option<&BenchResult> {
+        self.results
+            .iter()
+            .max_by(|a, b| a.bytes_per_second.total_cmp(&b.bytes_per_second))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "{} files, {} per run\n\n{:<16} {:>10} {:>8} {:>9} {:>14}\n",
+            self.files,
+            BinaryBytes(self.total_bytes),
+            "backend",
+            "buffer",
+            "parallel",
+            "time",
+            "throughput"
+        );
+        for result in &self.results {
+            let buffer = result
+                .buffer_size
+                .map_or_else(|| "-".to_string(), |size| BinaryBytes(size).to_string());
+            out.push_str(&format!(
+                "{:<16} {:>10} {:>8} {:>8.3}s {:>12}/s\n",
+                result.backend.as_str(),
+                buffer,
+                result.parallel,
+                result.seconds,
+                BinaryBytes(result.bytes_per_second as u64).to_string()
+            ));
+        }
+        for unsupported in &self.unsupported {
+            out.push_str(&format!(
+                "{:<16} unsupported: {}\n",
+                unsupported.backend.as_str(),
+                unsupported.reason
+            ));
+        }
+        if let Some(best) = self.fastest() {
+            out.push_str(&format!(
+                "\nFastest: {} with {} parallel copies\nSuggested config:\n  [copy]\n  parallel = {}\n",
+                best.backend.as_str(),
+                best.parallel,
+                best.parallel
+            ));
+            // and only maps files of 1-64 MiB unless told to map them all
+            if best.backend == Backend::Mmap {
+                out.push_str("  backend = \"mmap\"\n");
+            }
+            // cpx only tries reflinks when asked to
+            if best.backend == Backend::Reflink {
+                out.push_str("  [reflink]\n  mode = \"auto\"\n");
+            }
+        }
+        out
+    }
+}
+
+/// Copies the test set once per backend, buffer size and parallelism and
+/// times each run. A backend that fails on this filesystem is reported as
+/// unsupported rather than failing the benchmark.
+pub fn run_bench(config: &BenchConfig) -> io::Result<BenchReport> {
+    let root = config
+        .work_dir
+        .join(format!("cpx-bench-{}", std::process::id()));
+    fs::create_dir_all(&root)?;
+    let result = bench_in(&root, config);
+    let _ = fs::remove_dir_all(&root);
+    result
+}
+
+fn bench_in(root: &Path, config: &BenchConfig) -> io::Result<BenchReport> {
+    let sources = match &config.sample {
+        Some(sample) => sample_files(sample, config.files)?,
+        None => generate_files(&root.join("src"), config.files, config.file_size)?,
+    };
+    let mut report = BenchReport {
+        files: sources.len(),
+        total_bytes: sources.iter().map(|(_, size)| size).sum(),
+        ..BenchReport::default()
+    };
+    let sources: Vec<PathBuf> = sources.into_iter().map(|(path, _)| path).collect();
+    let destination = root.join("dst");
+
+    for &backend in &config.backends {
+        let buffer_sizes: Vec<Option<u64>> = match backend {
+            Backend::Buffered => config.buffer_sizes.iter().copied().map(Some).collect(),
+            _ => vec![None],
+        };
+        'cases: for buffer_size in buffer_sizes {
+            for &parallel in &config.parallel {
+                let elapsed =
+                    match time_copy(&sources, &destination, backend, buffer_size, parallel) {
+                        Ok(elapsed) => elapsed,
+                        Err(e) => {
+                            report.unsupported.push(Unsupported {
+                                backend,
+                                reason: e.to_string(),
+                            });
+                            break 'cases;
+                        }
+                    };
+                let seconds = elapsed.as_secs_f64();
+                report.results.push(BenchResult {
+                    backend,
+                    buffer_size,
+                    parallel,
+                    seconds,
+                    bytes_per_second: report.total_bytes as f64 / seconds.max(f64::EPSILON),
+                });
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn time_copy(
+    sources: &[PathBuf],
+    destination: &Path,
+    backend: Backend,
+    buffer_size: Option<u64>,
+    parallel: usize,
+) -> io::Result<Duration> {
+    fs::create_dir_all(destination)?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(parallel)
+        .build()
+        .map_err(io::Error::other)?;
+    let started = Instant::now();
+    let copied = pool.install(|| {
+        sources.par_iter().enumerate().try_for_each(|(i, source)| {
+            let target = destination.join(i.to_string());
+            match backend {
+                Backend::Buffered => {
+                    copy_buffered(source, &target, buffer_size.unwrap_or(64 * 1024) as usize)
+                }
+                Backend::CopyFileRange => copy_range(source, &target),
+                Backend::Reflink => reflink_copy::reflink(source, &target),
+                Backend::Mmap => copy_mapped(source, &target),
+            }
+        })
+    });
+    let elapsed = started.elapsed();
+    fs::remove_dir_all(destination)?;
+    copied.map(|()| elapsed)
+}
+
+fn copy_buffered(source: &Path, destination: &Path, buffer_size: usize) -> io::Result<()> {
+    let mut reader = File::open(source)?;
+    let mut writer = File::create(destination)?;
+    let mut buffer = vec![0u8; buffer_size];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buffer[..read])?;
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn copy_range(source: &Path, destination: &Path) -> io::Result<()> {
+    let reader = File::open(source)?;
+    let writer = File::create(destination)?;
+    let mut remaining = reader.metadata()?.len();
+    while remaining > 0 {
+        let copied = nix::fcntl::copy_file_range(&reader, None, &writer, None, remaining as usize)?;
+        if copied == 0 {
+            break;
+        }
+        remaining -= copied as u64;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_range(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "copy_file_range is only available on Linux",
+    ))
+}
+
+#[cfg(unix)]
+fn copy_mapped(source: &Path, destination: &Path) -> io::Result<()> {
+    use crate::core::fast_copy::Mapping;
+
+    let reader = File::open(source)?;
+    let mut writer = File::create(destination)?;
+    let len = reader.metadata()?.len() as usize;
+    if len > 0 {
+        writer.write_all(&Mapping::new(&reader, len)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_mapped(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "mmap copies are only available on Unix",
+    ))
+}
+
+/// Writes `count` files of `size` bytes that don't compress or dedupe, so
+/// filesystems with either don't flatter the numbers.
+fn generate_files(dir: &Path, count: usize, size: u64) -> io::Result<Vec<(PathBuf, u64)>> {
+    fs::create_dir_all(dir)?;
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut chunk = vec![0u8; 64 * 1024];
+    (0..count)
+        .map(|i| {
+            let path = dir.join(format!("file-{}", i));
+            let mut file = io::BufWriter::new(File::create(&path)?);
+            let mut remaining = size;
+            while remaining > 0 {
+                for word in chunk.chunks_exact_mut(8) {
+                    // xorshift64
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    word.copy_from_slice(&state.to_le_bytes());
+                }
+                let len = remaining.min(chunk.len() as u64) as usize;
+                file.write_all(&chunk[..len])?;
+                remaining -= len as u64;
+            }
+            file.flush()?;
+            Ok((path, size))
+        })
+        .collect()
+}
+
+/// Up to `count` regular files from `sample`, which may itself be a file.
+fn sample_files(sample: &Path, count: usize) -> io::Result<Vec<(PathBuf, u64)>> {
+    let metadata = fs::metadata(sample)?;
+    let files: Vec<(PathBuf, u64)> = if metadata.is_file() {
+        vec![(sample.to_path_buf(), metadata.len())]
+    } else {
+        jwalk::WalkDir::new(sample)
+            .skip_hidden(false)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let size = entry.metadata().ok()?.len();
+                Some((entry.path(), size))
+            })
+            .take(count)
+            .collect()
+    };
+    if files.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no regular files to sample in '{}'", sample.display()),
+        ));
+    }
+    Ok(files)
+}
+
+/// Parses `4096`, `64K`, `1M`, `1MiB` or `2G` as a byte count; suffixes are
+/// binary.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let number: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", s))?;
+    let shift = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        _ => return Err(format!("invalid size '{}': use a suffix of K, M or G", s)),
+    };
+    number
+        .checked_mul(1 << shift)
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("invalid size '{}'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("1MiB"), Ok(1024 * 1024));
+        assert_eq!(parse_size("2g"), Ok(2 << 30));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_bench_times_each_case_and_cleans_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = BenchConfig {
+            work_dir: temp_dir.path().to_path_buf(),
+            sample: None,
+            files: 3,
+            file_size: 10_000,
+            backends: vec![Backend::Buffered],
+            buffer_sizes: vec![4096, 8192],
+            parallel: vec![1, 2],
+        };
+
+        let report = run_bench(&config).unwrap();
+        assert_eq!(report.files, 3);
+        assert_eq!(report.total_bytes, 30_000);
+        let cases: Vec<_> = report
+            .results
+            .iter()
+            .map(|result| (result.buffer_size, result.parallel))
+            .collect();
+        assert_eq!(
+            cases,
+            vec![
+                (Some(4096), 1),
+                (Some(4096), 2),
+                (Some(8192), 1),
+                (Some(8192), 2)
+            ]
+        );
+        assert!(report.to_text().contains("Suggested config:"));
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+}
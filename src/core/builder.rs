@@ -0,0 +1,443 @@
+use crate::cli::args::{
+    ConflictPolicy, CopyOptions, FileOrder, FollowSymlink, LockedFiles, ReflinkMode, VerifyMode,
+};
+use crate::core::copy::{copy_with_stats, multiple_copy_with_stats};
+use crate::error::{CopyError, CopyResult, CpxError, CpxResult, FailedFile};
+use crate::utility::control::{CancellationToken, CopyControl};
+use crate::utility::events::CopyEvent;
+use crate::utility::exclude::{
+    ExcludePattern, build_exclude_rules, builtin_patterns, parse_exclude_pattern_list,
+};
+use crate::utility::hash::HashAlgo;
+use crate::utility::output::Verbosity;
+use crate::utility::preserve::PreserveAttr;
+use crate::utility::progress_bar::ProgressMode;
+use crate::utility::stats::{CopyStats, StatsSummary};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+/// Configures a copy without going through the command line.
+///
+/// Defaults match `cpx` with no flags, except that nothing is written to the
+/// terminal: no progress bars, warnings or error lines.
+///
+/// ```no_run
+/// use cpx::CopyBuilder;
+///
+/// let summary = CopyBuilder::new()
+///     .source("/data/photos")
+///     .destination("/backup")
+///     .recursive(true)
+///     .exclude("*.tmp,.cache")?
+///     .parallel(8)
+///     .build()?
+///     .run()?;
+/// println!("copied {} files", summary.stats.files_copied);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CopyBuilder {
+    sources: Vec<PathBuf>,
+    destination: Option<PathBuf>,
+    exclude: Vec<ExcludePattern>,
+    options: CopyOptions,
+}
+
+/// A validated copy, ready to [`run`](Copier::run) (more than once if needed).
+#[derive(Debug, Clone)]
+pub struct Copier {
+    sources: Vec<PathBuf>,
+    destination: PathBuf,
+    options: CopyOptions,
+}
+
+/// Outcome of a run that got through every file.
+#[derive(Debug)]
+pub struct CopySummary {
+    pub stats: StatsSummary,
+    /// Files that could not be copied while the rest of the run continued.
+    pub failures: Vec<FailedFile>,
+    /// The run was cancelled; `stats` covers only what finished before that.
+    pub cancelled: bool,
+}
+
+impl Default for CopyBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CopyBuilder {
+    pub fn new() -> Self {
+        let mut options = CopyOptions::none();
+        options.verbosity = Verbosity::Silent;
+        options.progress_bar.mode = ProgressMode::Never;
+        Self {
+            sources: Vec::new(),
+            destination: None,
+            exclude: Vec::new(),
+            options,
+        }
+    }
+
+    pub fn source(mut self, source: impl Into<PathBuf>) -> Self {
+        self.sources.push(source.into());
+        self
+    }
+
+    pub fn sources<I, P>(mut self, sources: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.sources.extend(sources.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn destination(mut self, destination: impl Into<PathBuf>) -> Self {
+        self.destination = Some(destination.into());
+        self
+    }
+
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.options.recursive = recursive;
+        self
+    }
+
+    /// Number of files copied at once.
+    pub fn parallel(mut self, parallel: usize) -> Self {
+        self.options.parallel = parallel;
+        self
+    }
+
+    /// Tunes the number of files copied at once while the copy runs, up to
+    /// `parallel`, as `-j auto` does.
+    pub fn auto_parallel(mut self, auto: bool) -> Self {
+        self.options.auto_jobs = auto;
+        self
+    }
+
+    /// Copies one file at a time in path order, after a sorted walk, as
+    /// `--deterministic` does. Overrides `parallel` and `auto_parallel`.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// Adds exclude patterns, using the same comma-separated syntax as `-e`.
+    pub fn exclude(mut self, patterns: &str) -> CpxResult<Self> {
+        self.exclude.extend(parse_exclude_pattern_list(patterns)?);
+        Ok(self)
+    }
+
+    /// Leave out version control directories (`.git`, `.hg`, `.svn`, ...).
+    pub fn exclude_vcs(mut self) -> Self {
+        self.exclude.extend(builtin_patterns(true, false));
+        self
+    }
+
+    /// Leave out version control directories and common build and cache
+    /// clutter (`node_modules`, `target`, `__pycache__`, `.DS_Store`).
+    pub fn exclude_common(mut self) -> Self {
+        self.exclude.extend(builtin_patterns(false, true));
+        self
+    }
+
+    pub fn preserve(mut self, preserve: PreserveAttr) -> Self {
+        self.options.preserve = preserve;
+        self
+    }
+
+    pub fn follow_symlinks(mut self, follow: FollowSymlink) -> Self {
+        self.options.follow_symlink = follow;
+        self
+    }
+
+    pub fn reflink(mut self, reflink: ReflinkMode) -> Self {
+        self.options.reflink = Some(reflink);
+        self
+    }
+
+    /// Skip files that already exist at the destination with the same content.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.options.resume = resume;
+        self
+    }
+
+    /// Keep files left unfinished by a failure or abort, and continue them
+    /// on the next run.
+    pub fn partial(mut self, partial: bool) -> Self {
+        self.options.partial = partial;
+        self
+    }
+
+    /// Hard-link files unchanged since an earlier copy in `dir` instead of
+    /// copying them again. A relative `dir` is taken from the destination.
+    pub fn link_dest(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.link_dest = Some(dir.into());
+        self
+    }
+
+    /// Write over existing destinations in place instead of truncating
+    /// them first.
+    pub fn inplace(mut self, inplace: bool) -> Self {
+        self.options.inplace = inplace;
+        self
+    }
+
+    /// How much older a destination mtime may be and still count as up
+    /// to date under [`resume`](Self::resume).
+    pub fn modify_window(mut self, window: Duration) -> Self {
+        self.options.modify_window = window;
+        self
+    }
+
+    pub fn force(mut self, force: bool) -> Self {
+        self.options.force = force;
+        self
+    }
+
+    /// What to do when a destination already exists. Unset, files are
+    /// overwritten and existing links are an error unless `force` is set.
+    pub fn on_conflict(mut self, policy: ConflictPolicy) -> Self {
+        self.options.on_conflict = Some(policy);
+        self
+    }
+
+    /// Hash each file while copying and compare against the destination.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.options.verify = verify.then_some(VerifyMode::Hash);
+        self
+    }
+
+    /// Like `verify`, choosing how the destination is read back.
+    pub fn verify_mode(mut self, mode: VerifyMode) -> Self {
+        self.options.verify = Some(mode);
+        self
+    }
+
+    /// Checksum used by `verify`, `resume` and content deduplication.
+    pub fn checksum_algo(mut self, algo: HashAlgo) -> Self {
+        self.options.checksum_algo = algo;
+        self
+    }
+
+    /// Retry transient I/O errors, waiting `delay` (doubled each attempt).
+    pub fn retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.options.retries = retries;
+        self.options.retry_delay = delay;
+        self
+    }
+
+    /// What to do with files another process has locked.
+    pub fn locked(mut self, locked: LockedFiles) -> Self {
+        self.options.locked = locked;
+        self
+    }
+
+    /// Fail a file still copying after `per_file`, and stop the run after
+    /// `total`. Both are checked between chunks.
+    pub fn timeouts(mut self, per_file: Option<Duration>, total: Option<Duration>) -> Self {
+        self.options.timeout_per_file = per_file;
+        self.options.timeout_total = total;
+        self
+    }
+
+    /// Copy at most `bytes_per_second`, across all workers.
+    pub fn bwlimit(mut self, bytes_per_second: u64) -> Self {
+        self.options.bwlimit = Some(bytes_per_second);
+        self
+    }
+
+    /// Keep going past failed directories and sources.
+    pub fn ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.options.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Fail on entries the walk can't read instead of leaving them out.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// Stop once more than `limit` files have failed.
+    pub fn max_errors(mut self, limit: usize) -> Self {
+        self.options.max_errors = Some(limit);
+        self
+    }
+
+    /// Shares a control so the caller can pause, skip or abort the run.
+    pub fn control(mut self, control: Arc<CopyControl>) -> Self {
+        self.options.control = control;
+        self
+    }
+
+    /// Stops the run once `token` is cancelled. Replaces any control set
+    /// with [`control`](Self::control).
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.options.control = Arc::new(CopyControl::with_token(token));
+        self
+    }
+
+    pub fn build(self) -> CpxResult<Copier> {
+        if self.sources.is_empty() {
+            return Err(CpxError::Validation("no source given".to_string()));
+        }
+        let destination = self
+            .destination
+            .ok_or_else(|| CpxError::Validation("no destination given".to_string()))?;
+        if self.options.parallel == 0 {
+            return Err(CpxError::Validation(
+                "parallel must be at least 1".to_string(),
+            ));
+        }
+
+        let mut options = self.options;
+        options.exclude_rules = build_exclude_rules(self.exclude)?;
+        if options.deterministic {
+            options.parallel = 1;
+            options.auto_jobs = false;
+            options.order = FileOrder::Path;
+        }
+        Ok(Copier {
+            sources: self.sources,
+            destination,
+            options,
+        })
+    }
+}
+
+impl Copier {
+    /// Copies everything. Per-file failures end up in
+    /// [`CopySummary::failures`] and a cancelled run still returns its
+    /// partial summary; only errors that stop the run are returned as `Err`.
+    pub fn run(&self) -> CopyResult<CopySummary> {
+        let stats = CopyStats::new();
+        self.options
+            .control
+            .set_timeouts(self.options.timeout_per_file, self.options.timeout_total);
+        self.options.control.set_bwlimit(self.options.bwlimit);
+        let result = match self.sources.as_slice() {
+            [source] => copy_with_stats(source, &self.destination, &self.options, &stats),
+            sources => multiple_copy_with_stats(sources, &self.destination, &self.options, &stats),
+        };
+        let cancelled = self.options.control.is_aborted();
+        let failures = match result {
+            Ok(()) => Vec::new(),
+            Err(CopyError::PartialFailure(failures)) => failures,
+            Err(e) if cancelled && e.kind() == io::ErrorKind::Interrupted => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(CopySummary {
+            stats: stats.summary(),
+            failures,
+            cancelled,
+        })
+    }
+
+    /// Subscribes to progress events for the following runs. The channel
+    /// closes once this copier and all of its clones are dropped.
+    pub fn events(&self) -> Receiver<CopyEvent> {
+        self.options.events.subscribe().1
+    }
+
+    pub fn options(&self) -> &CopyOptions {
+        &self.options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_builder_copies_tree_with_excludes() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("keep.txt"), "keep").unwrap();
+        fs::write(source.join("sub/drop.tmp"), "drop").unwrap();
+        let destination = temp_dir.path().join("dst");
+
+        let summary = CopyBuilder::new()
+            .source(&source)
+            .destination(&destination)
+            .recursive(true)
+            .exclude("*.tmp")
+            .unwrap()
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(summary.stats.files_copied, 1);
+        assert!(summary.failures.is_empty());
+        assert!(destination.join("src/keep.txt").exists());
+        assert!(!destination.join("src/sub/drop.tmp").exists());
+    }
+
+    #[test]
+    fn test_copier_reports_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("a.txt");
+        fs::write(&source, "hello").unwrap();
+
+        let copier = CopyBuilder::new()
+            .source(&source)
+            .destination(temp_dir.path().join("b.txt"))
+            .build()
+            .unwrap();
+        let events = copier.events();
+        copier.run().unwrap();
+        drop(copier);
+
+        let events: Vec<CopyEvent> = events.iter().collect();
+        assert!(matches!(
+            events.first(),
+            Some(CopyEvent::Scanned { total_files: 1, .. })
+        ));
+        let bytes: u64 = events
+            .iter()
+            .map(|event| match event {
+                CopyEvent::Progress { bytes, .. } => *bytes,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(bytes, 5);
+        assert!(matches!(events.last(), Some(CopyEvent::Finished { .. })));
+    }
+
+    #[test]
+    fn test_cancelled_run_returns_partial_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("a.txt");
+        fs::write(&source, "hello").unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let summary = CopyBuilder::new()
+            .source(&source)
+            .destination(temp_dir.path().join("b.txt"))
+            .cancellation(token)
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert!(summary.cancelled);
+        assert_eq!(summary.stats.files_copied, 0);
+        assert!(!temp_dir.path().join("b.txt").exists());
+    }
+
+    #[test]
+    fn test_builder_requires_destination() {
+        let result = CopyBuilder::new().source("a").build();
+        assert!(matches!(result, Err(CpxError::Validation(_))));
+    }
+}
@@ -0,0 +1,337 @@
+use crate::core::doctor::{self, Limit};
+use crate::utility::preprocess::CopyPlan;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Filesystems that take Windows names wherever they are mounted.
+const WINDOWS_FILESYSTEMS: &[&str] = &["vfat", "exfat", "ntfs3", "cifs", "smb2", "msdos", "ntfs"];
+
+/// Names Windows keeps for devices, with or without an extension.
+pub(crate) const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters Windows refuses in names, besides control characters.
+pub(crate) const INVALID_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// What the destination accepts as names.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct NameRules {
+    /// Windows restrictions: no `:` or `?`, no `CON` or `NUL`, no trailing
+    /// dot or space.
+    pub windows: bool,
+    pub case_sensitive: bool,
+    /// Longest name, in bytes (in characters with Windows rules).
+    pub name_max: Option<u64>,
+    /// Longest path, in bytes.
+    pub path_max: Option<u64>,
+}
+
+impl NameRules {
+    /// The rules of the filesystem `destination` is on, or would be created
+    /// on. `windows` forces Windows rules, for trees bound for a Windows
+    /// machine later.
+    pub fn for_destination(
+        destination: &Path,
+        windows: bool,
+    ) -> io::Result<(Self, Option<String>)> {
+        let dir = doctor::existing_dir(destination)?;
+        let filesystem = doctor::filesystem_name(&dir);
+        let windows = windows
+            || cfg!(windows)
+            || filesystem
+                .as_deref()
+                .is_some_and(|name| WINDOWS_FILESYSTEMS.contains(&name));
+        let case_sensitive = match doctor::case_sensitive_by_lookup(&dir) {
+            Some(Ok(sensitive)) => sensitive && !windows,
+            // macOS volumes are case-insensitive unless made otherwise
+            _ => !windows && !cfg!(target_os = "macos"),
+        };
+        let rules = NameRules {
+            windows,
+            case_sensitive,
+            name_max: doctor::path_limit(&dir, Limit::Name).or(windows.then_some(255)),
+            path_max: doctor::path_limit(&dir, Limit::Path),
+        };
+        Ok((rules, filesystem))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    NameTooLong,
+    PathTooLong,
+    InvalidCharacter,
+    ReservedName,
+    CaseCollision,
+}
+
+/// A planned destination the copy would fail to create, or would create
+/// over another.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathProblem {
+    pub path: PathBuf,
+    pub problem: Problem,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckReport {
+    pub destination: PathBuf,
+    pub filesystem: Option<String>,
+    pub rules: NameRules,
+    /// Destination paths looked at.
+    pub checked: usize,
+    pub problems: Vec<PathProblem>,
+}
+
+impl CheckReport {
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "Destination: {} ({})\n",
+            self.destination.display(),
+            self.filesystem.as_deref().unwrap_or("unknown filesystem")
+        );
+        out.push_str(&format!(
+            "  Names:     {}, {}\n",
+            if self.rules.windows {
+                "Windows rules"
+            } else {
+                "any character but /"
+            },
+            if self.rules.case_sensitive {
+                "case-sensitive"
+            } else {
+                "case-insensitive"
+            }
+        ));
+        let limit = |value: Option<u64>| value.map_or("none".to_string(), |v| v.to_string());
+        out.push_str(&format!(
+            "  Limits:    {} per name, {} per path\n",
+            limit(self.rules.name_max),
+            limit(self.rules.path_max)
+        ));
+        out.push_str(&format!("  Checked:   {} paths\n\n", self.checked));
+        if self.problems.is_empty() {
+            out.push_str("Every destination path can be created.\n");
+        } else {
+            out.push_str(&format!("{} problems:\n", self.problems.len()));
+            for problem in &self.problems {
+                out.push_str(&format!(
+                    "  {}: {}\n",
+                    problem.path.display(),
+                    problem.detail
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// Checks every destination in `plan` against the naming rules of the
+/// filesystem under `destination`, without writing anything.
+pub fn run_check(plan: &CopyPlan, destination: &Path, windows: bool) -> io::Result<CheckReport> {
+    let (rules, filesystem) = NameRules::for_destination(destination, windows)?;
+    let mut paths: Vec<PathBuf> = plan
+        .directories
+        .iter()
+        .map(|task| task.destination.clone())
+        .chain(plan.files.iter().map(|task| task.destination.clone()))
+        .chain(plan.symlinks.iter().map(|task| task.destination.clone()))
+        .chain(plan.hardlinks.iter().map(|task| task.destination.clone()))
+        .chain(
+            plan.duplicates
+                .iter()
+                .map(|task| task.file.destination.clone()),
+        )
+        .map(|path| std::path::absolute(&path).unwrap_or(path))
+        .collect();
+    paths.sort();
+    paths.dedup();
+    Ok(CheckReport {
+        destination: destination.to_path_buf(),
+        filesystem,
+        rules,
+        checked: paths.len(),
+        problems: check_paths(&paths, rules),
+    })
+}
+
+/// The problems with `paths` under `rules`. Only the last name of each is
+/// judged: the directories above are checked as paths of their own, or
+/// exist already.
+pub fn check_paths(paths: &[PathBuf], rules: NameRules) -> Vec<PathProblem> {
+    let mut problems = Vec::new();
+    let mut report = |path: &Path, problem, detail: String| {
+        problems.push(PathProblem {
+            path: path.to_path_buf(),
+            problem,
+            detail,
+        })
+    };
+    let mut seen: HashMap<PathBuf, PathBuf> = HashMap::new();
+    // one report per colliding name, not one per path below it
+    let mut collided: HashSet<&Path> = HashSet::new();
+
+    for path in paths {
+        if let Some(max) = rules.path_max
+            && path.as_os_str().len() as u64 >= max
+        {
+            report(
+                path,
+                Problem::PathTooLong,
+                format!(
+                    "path is {} bytes, the limit is {}",
+                    path.as_os_str().len(),
+                    max
+                ),
+            );
+        }
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        if let Some((problem, detail)) = check_name(&name, rules) {
+            report(path, problem, detail);
+        }
+        if !rules.case_sensitive {
+            let folded = PathBuf::from(path.to_string_lossy().to_lowercase());
+            match seen.get(&folded) {
+                Some(_) if path.parent().is_some_and(|dir| collided.contains(dir)) => {
+                    collided.insert(path);
+                }
+                Some(other) if other != path => {
+                    report(
+                        path,
+                        Problem::CaseCollision,
+                        format!("same name as '{}' apart from case", other.display()),
+                    );
+                    collided.insert(path);
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(folded, path.clone());
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn check_name(name: &str, rules: NameRules) -> Option<(Problem, String)> {
+    let length = if rules.windows {
+        name.encode_utf16().count()
+    } else {
+        name.len()
+    };
+    if let Some(max) = rules.name_max
+        && length as u64 > max
+    {
+        return Some((
+            Problem::NameTooLong,
+            format!("name is {} long, the limit is {}", length, max),
+        ));
+    }
+    if !rules.windows {
+        return None;
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| INVALID_CHARS.contains(c) || c.is_control())
+    {
+        return Some((
+            Problem::InvalidCharacter,
+            format!("'{}' can't be used in a name", c.escape_default()),
+        ));
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some((
+            Problem::InvalidCharacter,
+            "names can't end with a dot or a space".to_string(),
+        ));
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        return Some((
+            Problem::ReservedName,
+            format!("'{}' is a reserved device name", stem),
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_paths_under_windows_rules() {
+        let rules = NameRules {
+            windows: true,
+            case_sensitive: false,
+            name_max: Some(255),
+            path_max: Some(300),
+        };
+        let root = Path::new("/dest");
+        let paths: Vec<PathBuf> = [
+            "/dest/ok.txt",
+            "/dest/time 12:30.log",
+            "/dest/what?",
+            "/dest/trailing.",
+            "/dest/con.txt",
+            "/dest/Readme.md",
+            "/dest/README.md",
+            "/dest/Docs",
+            "/dest/Docs/a.md",
+            "/dest/docs",
+            "/dest/docs/a.md",
+            "/dest/console.txt",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .chain([
+            root.join("a".repeat(256)),
+            root.join("b".repeat(255)).join("c".repeat(40)),
+        ])
+        .collect();
+
+        let problems: Vec<_> = check_paths(&paths, rules)
+            .into_iter()
+            .map(|p| (p.path, p.problem))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                (
+                    PathBuf::from("/dest/time 12:30.log"),
+                    Problem::InvalidCharacter
+                ),
+                (PathBuf::from("/dest/what?"), Problem::InvalidCharacter),
+                (PathBuf::from("/dest/trailing."), Problem::InvalidCharacter),
+                (PathBuf::from("/dest/con.txt"), Problem::ReservedName),
+                (PathBuf::from("/dest/README.md"), Problem::CaseCollision),
+                (PathBuf::from("/dest/docs"), Problem::CaseCollision),
+                (root.join("a".repeat(256)), Problem::NameTooLong),
+                (
+                    root.join("b".repeat(255)).join("c".repeat(40)),
+                    Problem::PathTooLong
+                ),
+            ]
+        );
+
+        // the same names are fine on a case-sensitive Unix filesystem
+        let unix = NameRules {
+            windows: false,
+            case_sensitive: true,
+            ..rules
+        };
+        assert_eq!(check_paths(&paths[..12], unix), []);
+    }
+}
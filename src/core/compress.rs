@@ -0,0 +1,231 @@
+use crate::cli::args::CopyOptions;
+use crate::core::copy::{checkpoint_error, create_destination, remove_incomplete, report_bytes};
+use crate::error::{CopyError, CopyResult};
+use crate::utility::control::Checkpoint;
+use crate::utility::hash::FileHasher;
+use crate::utility::helper::open_source;
+use crate::utility::preserve::{self, PreserveAttr};
+use crate::utility::reporter::ProgressReporter;
+use crate::utility::stats::CopyStats;
+use clap::ValueEnum;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const CHUNK_SIZE: usize = 256 * 1024;
+pub const DEFAULT_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Zstd,
+}
+
+impl Compression {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// The name a planned file is written under: `name.zst` with --compress,
+/// `name` for a `name.zst` source with --decompress.
+pub fn transcoded_path(destination: PathBuf, options: &CopyOptions) -> PathBuf {
+    if let Some(compression) = options.compress {
+        let mut name = destination
+            .file_name()
+            .map(OsString::from)
+            .unwrap_or_default();
+        name.push(".");
+        name.push(compression.suffix());
+        destination.with_file_name(name)
+    } else if options.decompress && is_compressed(&destination) {
+        destination.with_extension("")
+    } else {
+        destination
+    }
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == Compression::Zstd.suffix())
+}
+
+/// Whether copying `source` goes through [`transcode`] rather than a plain copy.
+pub fn transcodes(source: &Path, options: &CopyOptions) -> bool {
+    options.compress.is_some() || (options.decompress && is_compressed(source))
+}
+
+/// Copies `source` through a zstd encoder or decoder. Progress counts bytes
+/// read from the source so the ETA matches the planned sizes; the returned
+/// digest is always of the uncompressed data.
+pub fn transcode(
+    source: &Path,
+    destination: &Path,
+    file_size: u64,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    options: &CopyOptions,
+) -> CopyResult<Option<String>> {
+    let input = InputProgress {
+        file: open_source(source)?,
+        source,
+        reporter,
+        stats,
+        options,
+    };
+    let output = BufWriter::with_capacity(
+        CHUNK_SIZE,
+        create_destination(Some(source), destination, options)?,
+    );
+    let mut hasher = options
+        .wants_digest()
+        .then(|| FileHasher::new(options.digest_algo()));
+
+    let result = if options.compress.is_some() {
+        let mut encoder = zstd::Encoder::new(output, options.compress_level)?;
+        // lets `zstd -l` and decoders see the original size
+        encoder.set_pledged_src_size(Some(file_size))?;
+        pump(input, &mut encoder, hasher.as_mut(), source, options)
+            .and_then(|()| Ok(encoder.finish()?.flush()?))
+    } else {
+        let mut output = output;
+        zstd::Decoder::new(input)
+            .map_err(Into::into)
+            .and_then(|decoder| pump(decoder, &mut output, hasher.as_mut(), source, options))
+            .and_then(|()| Ok(output.flush()?))
+    };
+
+    if let Err(e) = result {
+        if matches!(e, CopyError::Skipped(_))
+            || e.kind() == io::ErrorKind::Interrupted
+            || e.is_out_of_space()
+        {
+            remove_incomplete(destination, options);
+        }
+        return Err(e);
+    }
+
+    stats.record_copied();
+    if options.preserve != PreserveAttr::none() {
+        preserve::apply_preserve_attrs(source, destination, options.preserve)?;
+    }
+    Ok(hasher.map(FileHasher::finish))
+}
+
+/// Moves everything from `reader` to `writer`. The buffer always holds
+/// uncompressed data, which is what gets hashed.
+fn pump(
+    mut reader: impl Read,
+    writer: &mut impl Write,
+    mut hasher: Option<&mut FileHasher>,
+    source: &Path,
+    options: &CopyOptions,
+) -> CopyResult<()> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let checkpoint = options.control.checkpoint();
+        if checkpoint != Checkpoint::Continue {
+            return Err(checkpoint_error(checkpoint, source));
+        }
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buffer[..read])?;
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&buffer[..read]);
+        }
+    }
+}
+
+/// Reports bytes as they are read from the source file.
+struct InputProgress<'a> {
+    file: File,
+    source: &'a Path,
+    reporter: &'a dyn ProgressReporter,
+    stats: &'a CopyStats,
+    options: &'a CopyOptions,
+}
+
+impl Read for InputProgress<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.file.read(buf)?;
+        report_bytes(
+            self.reporter,
+            self.stats,
+            self.source,
+            read as u64,
+            self.options,
+        );
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::copy::copy;
+    use crate::utility::output::Verbosity;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn options() -> CopyOptions {
+        let mut options = CopyOptions::none();
+        options.recursive = true;
+        options.verbosity = Verbosity::Silent;
+        options
+    }
+
+    #[test]
+    fn test_transcoded_path() {
+        let mut options = options();
+        options.compress = Some(Compression::Zstd);
+        assert_eq!(
+            transcoded_path(PathBuf::from("dst/a.tar"), &options),
+            PathBuf::from("dst/a.tar.zst")
+        );
+
+        options.compress = None;
+        options.decompress = true;
+        assert_eq!(
+            transcoded_path(PathBuf::from("dst/a.tar.zst"), &options),
+            PathBuf::from("dst/a.tar")
+        );
+        assert_eq!(
+            transcoded_path(PathBuf::from("dst/a.txt"), &options),
+            PathBuf::from("dst/a.txt")
+        );
+    }
+
+    #[test]
+    fn test_compress_and_decompress_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        let text = "compressible ".repeat(10_000);
+        fs::write(source.join("a.txt"), &text).unwrap();
+        fs::write(source.join("sub/b.txt"), "beta").unwrap();
+
+        let backup = temp_dir.path().join("backup");
+        let mut compress = options();
+        compress.compress = Some(Compression::Zstd);
+        copy(&source, &backup, &compress).unwrap();
+
+        let compressed = backup.join("src/a.txt.zst");
+        assert!(fs::metadata(&compressed).unwrap().len() < text.len() as u64);
+        assert!(!backup.join("src/a.txt").exists());
+
+        let restore = temp_dir.path().join("restore");
+        let mut decompress = options();
+        decompress.decompress = true;
+        copy(&backup.join("src"), &restore, &decompress).unwrap();
+
+        assert_eq!(fs::read_to_string(restore.join("src/a.txt")).unwrap(), text);
+        assert_eq!(
+            fs::read_to_string(restore.join("src/sub/b.txt")).unwrap(),
+            "beta"
+        );
+    }
+}
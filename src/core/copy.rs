@@ -1,24 +1,103 @@
-use crate::cli::args::{BackupMode, CopyOptions, FollowSymlink};
-#[cfg(target_os = "linux")]
+use crate::cli::args::{
+    BackupMode, CopyOptions, DestSymlinks, FollowSymlink, LockedFiles, SymlinkFallback, VerifyMode,
+};
+use crate::core::check::NameRules;
+#[cfg(unix)]
 use crate::core::fast_copy::fast_copy;
-use crate::error::{CopyError, CopyResult};
+use crate::core::{archive, compress, device, extract, remote};
+use crate::error::{CopyError, CopyResult, FailedFile};
+use crate::utility::autotune::{self, Limiter, Tuner};
 use crate::utility::backup::{create_backup, generate_backup_path};
+use crate::utility::buffers;
+use crate::utility::chattr;
+use crate::utility::conflict::{self, Resolution};
+use crate::utility::control::Checkpoint;
+use crate::utility::events::CopyEvent;
+use crate::utility::extents::{self, SharedRange};
+use crate::utility::fd_budget;
+use crate::utility::hash::{FileHasher, hash_file, hash_file_uncached, hash_prefix, store_digest};
 use crate::utility::helper::{
-    create_directories, create_hardlink, create_symlink, prompt_overwrite,
+    create_directories_with, create_hardlink, create_symlink, open_source, prompt_proceed,
+    symlink_fallback_source, symlink_not_permitted, sync_parent_dirs, sync_path,
 };
+use crate::utility::junction;
+use crate::utility::manifest::Manifest;
+use crate::utility::metrics;
+use crate::utility::notify;
+use crate::utility::output;
 use crate::utility::preprocess::{
-    CopyPlan, preprocess_directory, preprocess_file, preprocess_multiple,
+    CopyPlan, DuplicateTask, FileTask, SymlinkTask, preprocess_directory, preprocess_file,
+    preprocess_multiple,
 };
 use crate::utility::preserve::{self, HardLinkTracker, PreserveAttr};
 use crate::utility::progress_bar::ProgressBarStyle;
-use indicatif::ProgressBar;
-use rayon::prelude::*;
-use std::io::{self, Read, Write};
+use crate::utility::readahead;
+use crate::utility::rename::rename_or_copy;
+use crate::utility::reporter::{self, ProgressReporter};
+use crate::utility::sanitize;
+use crate::utility::space;
+use crate::utility::stats::{CopyStats, StatsFormat};
+use crate::utility::streams;
+use indicatif::BinaryBytes;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{path::Path, path::PathBuf};
 
 pub fn copy(source: &Path, destination: &Path, options: &CopyOptions) -> CopyResult<()> {
+    copy_with_stats(source, destination, options, &CopyStats::new())
+}
+
+/// [`copy`], recording counters into `stats` so callers can inspect the run.
+pub fn copy_with_stats(
+    source: &Path,
+    destination: &Path,
+    options: &CopyOptions,
+    stats: &CopyStats,
+) -> CopyResult<()> {
+    if let Some(target) = &options.remote {
+        return remote::copy_to_remote(&[source.to_path_buf()], target, options, stats);
+    }
+    if let Some(format) = options.archive {
+        return archive::write_archive(
+            &[source.to_path_buf()],
+            destination,
+            format,
+            options,
+            stats,
+        );
+    }
+    if extract::is_archive_source(source, options) {
+        return extract::extract_archives(&[source.to_path_buf()], destination, options, stats);
+    }
+    let reporter = reporter::for_options(options);
+    reporter.scan_started(&[source]);
+    let plan = plan_source(source, destination, options)?;
+    execute_copy(plan, options, stats, reporter.as_ref())
+}
+
+/// Plans copying each of `sources` into `destination` without writing
+/// anything, as [`copy`] does for one source and [`multiple_copy`] for more.
+pub fn plan_sources(
+    sources: &[PathBuf],
+    destination: &Path,
+    options: &CopyOptions,
+) -> CopyResult<CopyPlan> {
+    match sources {
+        [source] => plan_source(source, destination, options),
+        _ => plan_multiple(sources, destination, options),
+    }
+}
+
+/// Runs a plan from [`plan_sources`], such as one read back by `cpx apply`.
+pub fn execute_planned(plan: CopyPlan, options: &CopyOptions, stats: &CopyStats) -> CopyResult<()> {
+    let reporter = reporter::for_options(options);
+    reporter.scan_started(&[]);
+    execute_copy(plan, options, stats, reporter.as_ref())
+}
+
+fn plan_source(source: &Path, destination: &Path, options: &CopyOptions) -> CopyResult<CopyPlan> {
     let source_metadata = match options.follow_symlink {
         FollowSymlink::Dereference | FollowSymlink::CommandLineSymlink => std::fs::metadata(source)
             .map_err(|_e| CopyError::InvalidSource(source.to_path_buf()))?,
@@ -26,9 +105,14 @@ pub fn copy(source: &Path, destination: &Path, options: &CopyOptions) -> CopyRes
             .map_err(|_e| CopyError::InvalidSource(source.to_path_buf()))?,
     };
     let source_root = source.parent().unwrap_or(source);
-    let destination_metadata = std::fs::metadata(destination).ok();
+    // a link that is to be replaced is copied over, not into
+    let destination_metadata = if options.dest_symlinks.replaces(destination) {
+        None
+    } else {
+        std::fs::metadata(destination).ok()
+    };
 
-    let plan = if source_metadata.is_dir() {
+    if source_metadata.is_dir() {
         if !options.recursive {
             return Err(CopyError::CopyFailed {
                 source: source.to_path_buf(),
@@ -43,13 +127,14 @@ pub fn copy(source: &Path, destination: &Path, options: &CopyOptions) -> CopyRes
             return Err(CopyError::InvalidDestination(destination.to_path_buf()));
         }
 
-        preprocess_directory(source, source_root, destination, options).map_err(|e| {
-            CopyError::CopyFailed {
+        preprocess_directory(source, source_root, destination, options)
+            .map_err(|e| CopyError::CopyFailed {
                 source: source.to_path_buf(),
                 destination: destination.to_path_buf(),
                 reason: e.to_string(),
-            }
-        })?
+            })
+            .map(|plan| sanitize_names(plan, destination, options))
+            .map(|plan| link_unchanged(plan, destination, options))
     } else {
         preprocess_file(
             source,
@@ -63,14 +148,10 @@ pub fn copy(source: &Path, destination: &Path, options: &CopyOptions) -> CopyRes
             source: source.to_path_buf(),
             destination: destination.to_path_buf(),
             reason: e.to_string(),
-        })?
-    };
-
-    if plan.skipped_files > 0 {
-        eprintln!("Skipping {} files that already exist", plan.skipped_files);
+        })
+        .map(|plan| sanitize_names(plan, destination, options))
+        .map(|plan| link_unchanged(plan, destination, options))
     }
-
-    execute_copy(plan, options)
 }
 
 pub fn multiple_copy(
@@ -78,74 +159,502 @@ pub fn multiple_copy(
     destination: PathBuf,
     options: &CopyOptions,
 ) -> CopyResult<()> {
-    let plan = preprocess_multiple(&sources, &destination, options).map_err(|e| {
-        CopyError::CopyFailed {
+    multiple_copy_with_stats(&sources, &destination, options, &CopyStats::new())
+}
+
+/// [`multiple_copy`], recording counters into `stats`.
+pub fn multiple_copy_with_stats(
+    sources: &[PathBuf],
+    destination: &Path,
+    options: &CopyOptions,
+    stats: &CopyStats,
+) -> CopyResult<()> {
+    if let Some(target) = &options.remote {
+        return remote::copy_to_remote(sources, target, options, stats);
+    }
+    if let Some(format) = options.archive {
+        return archive::write_archive(sources, destination, format, options, stats);
+    }
+    if sources
+        .iter()
+        .any(|source| extract::is_archive_source(source, options))
+    {
+        return extract::extract_archives(sources, destination, options, stats);
+    }
+    let reporter = reporter::for_options(options);
+    reporter.scan_started(&sources.iter().map(PathBuf::as_path).collect::<Vec<_>>());
+    let plan = plan_multiple(sources, destination, options)?;
+    execute_copy(plan, options, stats, reporter.as_ref())
+}
+
+fn plan_multiple(
+    sources: &[PathBuf],
+    destination: &Path,
+    options: &CopyOptions,
+) -> CopyResult<CopyPlan> {
+    preprocess_multiple(sources, destination, options)
+        .map_err(|e| CopyError::CopyFailed {
             source: sources[0].clone(),
-            destination: destination.clone(),
+            destination: destination.to_path_buf(),
             reason: e.to_string(),
+        })
+        .map(|plan| sanitize_names(plan, destination, options))
+        .map(|plan| link_unchanged(plan, destination, options))
+}
+
+/// `--sanitize-names`: renames what the destination filesystem would
+/// refuse. Filesystems that take any name but `/` are left alone.
+fn sanitize_names(mut plan: CopyPlan, destination: &Path, options: &CopyOptions) -> CopyPlan {
+    let Some(mode) = options.sanitize_names else {
+        return plan;
+    };
+    if !NameRules::for_destination(destination, false).is_ok_and(|(rules, _)| rules.windows) {
+        return plan;
+    }
+    let renames = sanitize::sanitize_plan(&mut plan, destination, mode);
+    if !renames.is_empty() {
+        output::warn(
+            options,
+            format!(
+                "Renaming {} entries the destination filesystem doesn't allow",
+                renames.len()
+            ),
+        );
+    }
+    if let Some(logger) = &options.logger {
+        for (from, to) in &renames {
+            logger.warning(from, &format!("renamed to '{}'", to.display()));
+        }
+    }
+    plan.renamed.extend(renames);
+    plan
+}
+
+/// Plans `--link-dest` links; they run with the `--dedupe` ones.
+fn link_unchanged(mut plan: CopyPlan, destination: &Path, options: &CopyOptions) -> CopyPlan {
+    if let Some(link_dest) = &options.link_dest {
+        let linked = plan.link_unchanged(link_dest, destination, options);
+        if let Some(logger) = &options.logger {
+            logger.debug(&format!(
+                "{} files unchanged since {}",
+                linked,
+                link_dest.display()
+            ));
         }
-    })?;
+    }
+    plan
+}
+
+fn execute_copy(
+    mut plan: CopyPlan,
+    options: &CopyOptions,
+    stats: &CopyStats,
+    reporter: &dyn ProgressReporter,
+) -> CopyResult<()> {
+    plan.resolve_conflicts(options)?;
     if plan.skipped_files > 0 {
-        eprintln!("Skipping {} files that already exist", plan.skipped_files);
+        output::warn(
+            options,
+            format!("Skipping {} files that already exist", plan.skipped_files),
+        );
+    }
+    if options.dedupe {
+        let duplicates = plan.dedupe_content(options.checksum_algo);
+        if let Some(logger) = &options.logger {
+            logger.debug(&format!("{} files have identical copies", duplicates));
+        }
+    }
+    if let Some(logger) = &options.logger {
+        logger.debug(&format!(
+            "planned {} files ({} bytes), {} directories, {} skipped",
+            plan.total_files,
+            plan.total_size,
+            plan.directories.len(),
+            plan.skipped_files
+        ));
+    }
+    reporter.scan_finished(plan.total_files, plan.total_size, plan.skipped_files);
+
+    stats.record_skipped(plan.skipped_files);
+    stats.record_excluded(plan.excluded);
+    stats.record_unreadable(&plan.unreadable);
+    stats.record_renamed(&plan.renamed);
+    options.events.emit(|| CopyEvent::Scanned {
+        total_files: plan.total_files,
+        total_bytes: plan.total_size,
+        skipped_files: plan.skipped_files,
+    });
+
+    if let Some(target) = &options.list_conflicts {
+        reporter.finish("Listed conflicts");
+        return list_conflicts(&plan, target, options).map_err(CopyError::Io);
+    }
+    if options.confirm && plan.total_files > 0 && !confirm_plan(&plan, options)? {
+        reporter.abandon("Cancelled");
+        return Err(CopyError::Declined);
+    }
+
+    let manifest = options.manifest.as_ref().map(|_| Manifest::new());
+    let written = if options.fsync.dirs() {
+        written_paths(&plan)
+    } else {
+        Vec::new()
+    };
+
+    let mut result = execute_plan(plan, options, stats, reporter, manifest.as_ref());
+    if options.fsync.dirs() {
+        // new entries and backup renames only last once their directory is synced
+        result = result
+            .and(sync_parent_dirs(written.iter().map(PathBuf::as_path)).map_err(CopyError::Io));
+    }
+    if let Some(barrier) = &options.write_barrier {
+        // what was copied since the last barrier, even when the run failed
+        result = result.and(barrier.flush().map_err(CopyError::Io));
+    }
+    if options.dedupe {
+        output::info(
+            options,
+            format!(
+                "Deduplicated {} files, saved {}",
+                stats.summary().deduplicated,
+                BinaryBytes(stats.bytes_deduplicated())
+            ),
+        );
+    }
+    if let (Some(path), Some(manifest)) = (&options.manifest, &manifest)
+        && let Err(e) = manifest.write(path, options.digest_algo())
+    {
+        let e = CopyError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to write manifest {}: {}", path.display(), e),
+        ));
+        result = result.and(Err(e));
+    }
+    finish_run(result, stats, options, reporter)
+}
+
+/// `--confirm`: sums up what the plan will write and asks whether to go on,
+/// unless `--assume-no` already answered.
+fn confirm_plan(plan: &CopyPlan, options: &CopyOptions) -> CopyResult<bool> {
+    let question = format!(
+        "{} files, {}, {} to overwrite, {} to skip — proceed?",
+        plan.total_files,
+        BinaryBytes(plan.total_size),
+        plan.conflicts().len(),
+        plan.skipped_files
+    );
+    if let Some(answer) = options.assume {
+        eprintln!("{} [y/N] {}", question, if answer { "y" } else { "n" });
+        return Ok(answer);
     }
-    execute_copy(plan, options)
+    prompt_proceed(&question).map_err(CopyError::Io)
 }
 
-fn execute_copy(plan: CopyPlan, options: &CopyOptions) -> CopyResult<()> {
+/// `--list-conflicts`: the destinations the plan would overwrite, one per
+/// line on stdout or in `target`, in place of the copy.
+fn list_conflicts(plan: &CopyPlan, target: &Path, options: &CopyOptions) -> io::Result<()> {
+    let conflicts = plan.conflicts();
+    if target == Path::new("-") {
+        for path in &conflicts {
+            output::report(options, path.display());
+        }
+    } else {
+        let mut out = io::BufWriter::new(std::fs::File::create(target)?);
+        for path in &conflicts {
+            writeln!(out, "{}", path.display())?;
+        }
+        out.flush()?;
+    }
+    output::warn(
+        options,
+        format!(
+            "{} existing destinations would be overwritten",
+            conflicts.len()
+        ),
+    );
+    Ok(())
+}
+
+/// Every destination a plan may create, rename or replace.
+fn written_paths(plan: &CopyPlan) -> Vec<PathBuf> {
+    let directories = plan.directories.iter().map(|dir| &dir.destination);
+    let files = plan.files.iter().map(|file| &file.destination);
+    let duplicates = plan
+        .duplicates
+        .iter()
+        .map(|duplicate| &duplicate.file.destination);
+    let symlinks = plan.symlinks.iter().map(|link| &link.destination);
+    let hardlinks = plan.hardlinks.iter().map(|link| &link.destination);
+    directories
+        .chain(files)
+        .chain(duplicates)
+        .chain(symlinks)
+        .chain(hardlinks)
+        .cloned()
+        .collect()
+}
+
+/// Summary output and the desktop notification shared by every kind of run.
+pub(crate) fn finish_run(
+    result: CopyResult<()>,
+    stats: &CopyStats,
+    options: &CopyOptions,
+    reporter: &dyn ProgressReporter,
+) -> CopyResult<()> {
+    report_summary(stats, options, reporter);
+    report_unreadable(stats, options);
+    if let Some(path) = &options.metrics_file
+        && let Err(e) = metrics::write_metrics(path, &stats.summary(), result.is_ok())
+    {
+        output::warn(
+            options,
+            format!("Failed to write metrics file {}: {}", path.display(), e),
+        );
+    }
+    if let Some(after) = options.notify
+        && stats.elapsed() >= after
+        && let Err(e) = notify::send(&stats.summary(), &result)
+        && let Some(logger) = &options.logger
+    {
+        logger.debug(&format!("desktop notification failed: {}", e));
+    }
+    result
+}
+
+fn report_unreadable(stats: &CopyStats, options: &CopyOptions) {
+    let unreadable = stats.summary().unreadable;
+    if unreadable.is_empty() {
+        return;
+    }
+    output::warn(
+        options,
+        format!(
+            "Left out {} unreadable entries (permission denied):",
+            unreadable.len()
+        ),
+    );
+    for path in &unreadable {
+        output::warn(options, format!("  {}", path.display()));
+    }
+}
+
+fn report_summary(stats: &CopyStats, options: &CopyOptions, reporter: &dyn ProgressReporter) {
+    reporter.summary(stats);
+
+    match options.stats {
+        Some(StatsFormat::Text) => output::report(options, stats.summary().to_text().trim_end()),
+        Some(StatsFormat::Json) => {
+            if let Ok(line) = serde_json::to_string(&stats.summary()) {
+                output::report(options, line);
+            }
+        }
+        None => {}
+    }
+}
+
+fn execute_plan(
+    mut plan: CopyPlan,
+    options: &CopyOptions,
+    stats: &CopyStats,
+    reporter: &dyn ProgressReporter,
+    manifest: Option<&Manifest>,
+) -> CopyResult<()> {
+    let mut failures: Vec<FailedFile> = std::mem::take(&mut plan.failures);
+
+    let created_dir = |path: &Path| match &options.journal {
+        Some(journal) => journal.created_dir(path),
+        None => Ok(()),
+    };
     if !options.attributes_only {
-        create_directories(&plan.directories)?;
+        if options.dest_symlinks != DestSymlinks::Follow {
+            // outermost first: once a link is gone, nothing below it is
+            // looked up through it
+            let mut dirs: Vec<&Path> = plan
+                .directories
+                .iter()
+                .map(|d| d.destination.as_path())
+                .collect();
+            dirs.sort_by_key(|dir| dir.components().count());
+            for dir in dirs {
+                if options.dest_symlinks.replaces(dir) {
+                    remove_link(dir)?;
+                }
+            }
+        }
+        if options.ignore_errors {
+            // one directory at a time so a failure only costs its own subtree
+            for dir_task in &plan.directories {
+                if let Err(e) = create_directories_with(std::slice::from_ref(dir_task), created_dir)
+                {
+                    let source = dir_task.source.as_deref().unwrap_or(&dir_task.destination);
+                    failures.push(FailedFile::new(source, &dir_task.destination, e.into()));
+                }
+            }
+        } else {
+            create_directories_with(&plan.directories, created_dir)?;
+        }
     } else {
         for dir_task in &plan.directories {
             if let Some(src) = &dir_task.source
                 && std::fs::symlink_metadata(&dir_task.destination).is_ok()
+                && let Err(e) =
+                    preserve::apply_preserve_attrs(src, &dir_task.destination, options.preserve)
             {
-                preserve::apply_preserve_attrs(src, &dir_task.destination, options.preserve)
-                    .map_err(|e| CopyError::CopyFailed {
-                        source: src.clone(),
-                        destination: dir_task.destination.clone(),
-                        reason: e.to_string(),
-                    })?;
+                let e = CopyError::CopyFailed {
+                    source: src.clone(),
+                    destination: dir_task.destination.clone(),
+                    reason: e.to_string(),
+                };
+                if !options.ignore_errors {
+                    return Err(e);
+                }
+                failures.push(FailedFile::new(src, &dir_task.destination, e));
             }
         }
     }
 
     if options.hard_link {
         for hardlink_task in &plan.hardlinks {
-            create_hardlink(hardlink_task, options)?;
+            let existed = options.journal.is_some()
+                && std::fs::symlink_metadata(&hardlink_task.destination).is_ok();
+            match create_hardlink(hardlink_task, options).and_then(|created| {
+                if created {
+                    journal_write(&hardlink_task.destination, existed, None, options)?;
+                }
+                Ok(created)
+            }) {
+                Ok(true) => {}
+                Ok(false) => {
+                    stats.record_skipped(1);
+                    continue;
+                }
+                Err(e) => {
+                    failures.push(FailedFile::new(
+                        &hardlink_task.source,
+                        &hardlink_task.destination,
+                        e,
+                    ));
+                    continue;
+                }
+            }
+            stats.record_copied();
+            stats.record_hardlink();
+            output::copied(
+                options,
+                reporter,
+                &hardlink_task.source,
+                &hardlink_task.destination,
+            );
         }
 
-        if plan.total_hardlinks > 0 {
-            println!("Created {} hard links", plan.total_hardlinks);
+        if plan.total_hardlinks > failures.len() {
+            output::info(
+                options,
+                format!(
+                    "Created {} hard links",
+                    plan.total_hardlinks - failures.len()
+                ),
+            );
         }
-        return Ok(());
+        return failures_to_result(failures);
     }
 
+    let mut fallback_files = Vec::new();
     if !plan.symlinks.is_empty() {
+        let mut created = 0;
         for symlink_task in &plan.symlinks {
-            create_symlink(symlink_task).map_err(|_e| CopyError::SymlinkFailed {
-                source: symlink_task.source.clone(),
-                destination: symlink_task.destination.clone(),
-            })?;
+            // for an exact copy `source` is the link's target
+            let source = symlink_task
+                .origin
+                .as_deref()
+                .unwrap_or(&symlink_task.source);
+            let existed = options.journal.is_some()
+                && std::fs::symlink_metadata(&symlink_task.destination).is_ok();
+            match conflict::prepare_link(&symlink_task.destination, options) {
+                Ok(true) => {}
+                Ok(false) => {
+                    stats.record_skipped(1);
+                    continue;
+                }
+                Err(e) => {
+                    failures.push(FailedFile::new(source, &symlink_task.destination, e));
+                    continue;
+                }
+            }
+            let result = match create_symlink(symlink_task) {
+                Err(e)
+                    if options.symlink_fallback != SymlinkFallback::Error
+                        && symlink_not_permitted(&e) =>
+                {
+                    match symlink_fallback(symlink_task, options) {
+                        Ok(LinkFallback::Skipped) => continue,
+                        Ok(LinkFallback::Copy(file_task)) => {
+                            fallback_files.push(file_task);
+                            continue;
+                        }
+                        Ok(LinkFallback::Junction) => Ok(()),
+                        Err(e) => Err(e),
+                    }
+                }
+                result => result.map_err(CopyError::Io),
+            };
+            if let Err(e) = result
+                .and_then(|()| journal_write(&symlink_task.destination, existed, None, options))
+            {
+                failures.push(FailedFile::new(source, &symlink_task.destination, e));
+                continue;
+            }
+            if options.symbolic_link.is_some() {
+                stats.record_copied();
+            }
+            created += 1;
+            stats.record_symlink();
+            output::copied(options, reporter, source, &symlink_task.destination);
         }
-        if plan.total_symlinks > 0 {
-            println!("Created {} symbolic links", plan.total_symlinks);
+        if created > 0 {
+            output::info(options, format!("Created {} symbolic links", created));
         }
 
-        if options.symbolic_link.is_some() {
-            return Ok(());
+        if options.symbolic_link.is_some() && fallback_files.is_empty() {
+            return failures_to_result(failures);
         }
     }
+    for file_task in fallback_files {
+        plan.add_file(file_task.source, file_task.destination, file_task.size);
+    }
+
+    let workers = fd_budget::worker_count(options.parallel, options);
+    buffers::shared().set_capacity(workers * 2);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .build()
+        .map_err(|e| CopyError::CopyFailed {
+            source: PathBuf::new(),
+            destination: PathBuf::new(),
+            reason: format!("Failed to create thread pool: {}", e),
+        })?;
 
-    let overall_pb = if plan.total_files >= 1 && !options.interactive && !options.attributes_only {
-        let pb = ProgressBar::new(plan.total_size);
-        options.progress_bar.apply(&pb, plan.total_files);
-        Some(Arc::new(pb))
+    reporter.begin(
+        plan.total_files,
+        plan.total_size,
+        plan.skipped_files,
+        plan.skipped_size,
+    );
+    options
+        .control
+        .set_totals(plan.total_files, plan.total_size);
+
+    // read before anything is copied, while the sources are as planned
+    let shared = if options.preserve.extents
+        && !options.attributes_only
+        && options.compress.is_none()
+        && !options.decompress
+    {
+        extents::find_shared(&plan.files)
     } else {
-        None
+        Vec::new()
     };
 
-    let completed_files = Arc::new(AtomicUsize::new(0));
-
     // Initialize hard link tracker if preserve.links is enabled
     let hardlink_tracker = if options.preserve.links {
         Some(Arc::new(Mutex::new(HardLinkTracker::new())))
@@ -153,109 +662,626 @@ fn execute_copy(plan: CopyPlan, options: &CopyOptions) -> CopyResult<()> {
         None
     };
 
-    // For interactive mode, process sequentially
-    if options.interactive {
-        for file_task in plan.files {
-            copy_core(
-                &file_task.source,
-                &file_task.destination,
-                file_task.size,
-                overall_pb.as_deref(),
-                &completed_files,
-                plan.total_files,
+    let run = |file_task: &FileTask| {
+        run_file_task(file_task, reporter, stats, options, manifest, || {
+            copy_with_retries(
+                file_task,
+                reporter,
+                stats,
                 options,
                 hardlink_tracker.as_ref(),
-            )?;
+            )
+        })
+        .map_err(|e| FailedFile::new(&file_task.source, &file_task.destination, e))
+    };
+
+    // For interactive mode, process sequentially
+    let file_failures: Vec<FailedFile> = if options.prompts() {
+        let mut file_failures = Vec::new();
+        for file_task in &plan.files {
+            if let Err(failure) = run(file_task) {
+                let interrupted = failure.error.kind() == io::ErrorKind::Interrupted;
+                file_failures.push(failure);
+                if interrupted {
+                    break;
+                }
+            }
         }
+        file_failures
     } else {
-        let pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(options.parallel)
-            .build()
-            .map_err(|e| CopyError::CopyFailed {
-                source: PathBuf::new(),
-                destination: PathBuf::new(),
-                reason: format!("Failed to create thread pool: {}", e),
-            })?;
-
-        let results: Vec<_> = pool.install(|| {
-            plan.files
-                .par_iter()
-                .map(|file_task| {
-                    let result = copy_core(
-                        &file_task.source,
-                        &file_task.destination,
-                        file_task.size,
-                        overall_pb.as_deref(),
-                        &completed_files,
-                        plan.total_files,
-                        options,
-                        hardlink_tracker.as_ref(),
+        run_file_tasks(
+            &pool,
+            &plan.files,
+            options,
+            stats,
+            || (),
+            |_, task| run(task),
+        )
+    };
+
+    let mut interrupted = false;
+    for failure in file_failures {
+        if failure.error.kind() == io::ErrorKind::Interrupted {
+            interrupted = true;
+        } else {
+            failures.push(failure);
+        }
+    }
+
+    // duplicates link to the copies made above, so they come last
+    if !interrupted {
+        for duplicate in &plan.duplicates {
+            let file_task = &duplicate.file;
+            let result = run_file_task(file_task, reporter, stats, options, manifest, || {
+                link_duplicate(
+                    duplicate,
+                    reporter,
+                    stats,
+                    options,
+                    hardlink_tracker.as_ref(),
+                )
+            });
+            match result {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    interrupted = true;
+                    break;
+                }
+                Err(e) => failures.push(FailedFile::new(
+                    &file_task.source,
+                    &file_task.destination,
+                    e,
+                )),
+                Ok(()) => {}
+            }
+        }
+    }
+
+    if !interrupted && !shared.is_empty() {
+        share_extents(&shared, options);
+    }
+
+    if interrupted {
+        let completed = stats.files_copied();
+
+        output::run_error(options, format!("\nCompleted:  {} files", completed));
+        output::run_error(
+            options,
+            format!("Remaining:  {} files", plan.total_files - completed),
+        );
+
+        if let Some(limit) = options
+            .max_errors
+            .filter(|limit| stats.files_failed() > *limit)
+        {
+            reporter.abandon("Stopped after too many errors");
+            return Err(CopyError::ErrorLimitExceeded { limit, failures });
+        }
+        if let Some(limit) = options
+            .timeout_total
+            .filter(|_| options.control.timed_out())
+        {
+            reporter.abandon("Stopped at the time limit");
+            return Err(CopyError::TimeLimitExceeded { limit, failures });
+        }
+        if options.control.out_of_space() {
+            reporter.abandon("Stopped: the destination is full");
+            // linked duplicates take no space of their own
+            let separate = plan
+                .duplicates
+                .iter()
+                .filter(|duplicate| duplicate.separate)
+                .map(|duplicate| &duplicate.file);
+            let remaining = space::remaining_bytes(plan.files.iter().chain(separate));
+            let full = failures
+                .iter()
+                .find(|failure| failure.error.is_out_of_space())
+                .map(|failure| failure.destination.clone())
+                .unwrap_or_default();
+            let available = space::available_space(&full).unwrap_or(0);
+            return Err(CopyError::DestinationFull {
+                remaining,
+                available,
+                failures,
+            });
+        }
+
+        return Err(CopyError::Io(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Operation interrupted by user",
+        )));
+    }
+
+    if !failures.is_empty() {
+        reporter.abandon("Completed with errors");
+        return failures_to_result(failures);
+    }
+
+    if matches!(options.progress_bar.style, ProgressBarStyle::Detailed) {
+        reporter.finish(&format!("Copied {} files successfully", plan.total_files));
+    } else {
+        reporter.finish("Done");
+    }
+
+    Ok(())
+}
+
+/// `--preserve=extents`: clones the ranges the sources shared between their
+/// copies. Ranges that can't be cloned are left as separate copies.
+fn share_extents(ranges: &[SharedRange], options: &CopyOptions) {
+    let mut shared = 0u64;
+    for range in ranges {
+        match extents::clone_range(range) {
+            Ok(bytes) => shared += bytes,
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EOPNOTSUPP | libc::EXDEV)) => {
+                output::warn(
+                    options,
+                    format!(
+                        "Cannot share extents on '{}': {}",
+                        range.destination.display(),
+                        e
+                    ),
+                );
+                break;
+            }
+            Err(e) => {
+                if let Some(logger) = &options.logger {
+                    logger.warning(
+                        &range.destination,
+                        &format!(
+                            "cannot share extents with '{}': {}",
+                            range.original.display(),
+                            e
+                        ),
                     );
+                }
+            }
+        }
+    }
+    if shared > 0 {
+        output::info(
+            options,
+            format!(
+                "Shared {} between copies, as in the sources",
+                BinaryBytes(shared)
+            ),
+        );
+    }
+}
 
-                    match result {
-                        Ok(()) => Ok(()),
-                        Err(e) => Err((file_task.source.clone(), file_task.destination.clone(), e)),
-                    }
-                })
-                .collect()
+/// What `--symlink-fallback` put in place of a symlink the OS refused.
+enum LinkFallback {
+    Skipped,
+    /// The file the link resolves to, copied with the other files.
+    Copy(FileTask),
+    /// A junction, standing in for a link to a directory.
+    Junction,
+}
+
+fn symlink_fallback(task: &SymlinkTask, options: &CopyOptions) -> CopyResult<LinkFallback> {
+    if options.symlink_fallback == SymlinkFallback::Skip {
+        let source = task.origin.as_ref().unwrap_or(&task.source);
+        output::warn(
+            options,
+            format!(
+                "Skipping symlink '{}': symlinks are not permitted here",
+                task.destination.display()
+            ),
+        );
+        output::skipped(options, source, "symlinks not permitted");
+        options.events.emit(|| CopyEvent::Skipped {
+            source: source.clone(),
+            reason: "symlinks not permitted".to_string(),
         });
+        return Ok(LinkFallback::Skipped);
+    }
 
-        let mut interrupted = false;
-        let mut errors: Vec<(PathBuf, PathBuf, CopyError)> = Vec::new();
+    let target = symlink_fallback_source(task)?;
+    let metadata = std::fs::metadata(&target)?;
+    if metadata.is_dir() {
+        junction::create(&target, &task.destination)?;
+        return Ok(LinkFallback::Junction);
+    }
+    Ok(LinkFallback::Copy(FileTask {
+        source: target,
+        destination: task.destination.clone(),
+        size: metadata.len(),
+        inode_group: None,
+    }))
+}
 
-        for result in results.into_iter() {
-            if let Err((source, dest, e)) = result {
-                match e {
-                    CopyError::Io(ref io_err) if io_err.kind() == io::ErrorKind::Interrupted => {
-                        interrupted = true;
-                    }
-                    _ => {
-                        errors.push((source, dest, e));
-                    }
+pub(crate) fn failures_to_result(failures: Vec<FailedFile>) -> CopyResult<()> {
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(CopyError::PartialFailure(failures))
+    }
+}
+
+/// Runs `files` on `pool` with [`run_workers`]. Under `-j auto` only as
+/// many workers as the tuner allows copy at once.
+pub(crate) fn run_file_tasks<S>(
+    pool: &rayon::ThreadPool,
+    files: &[FileTask],
+    options: &CopyOptions,
+    stats: &CopyStats,
+    init: impl Fn() -> S + Sync,
+    run: impl Fn(&mut S, &FileTask) -> Result<(), FailedFile> + Sync,
+) -> Vec<FailedFile> {
+    if !options.auto_jobs {
+        return run_workers(pool, files, None, init, run);
+    }
+    let limiter = Limiter::new(autotune::START_WORKERS);
+    let tuner = Tuner::new(autotune::START_WORKERS, pool.current_num_threads());
+    let progress = || {
+        let done = stats.files_copied() + stats.files_failed() + stats.files_skipped();
+        (done as u64, stats.bytes_copied())
+    };
+    let failures = autotune::with_tuning(&limiter, tuner, progress, || {
+        run_workers(pool, files, Some(&limiter), init, run)
+    });
+    if let Some(logger) = &options.logger {
+        logger.debug(&format!("-j auto ended with {} workers", limiter.limit()));
+    }
+    failures
+}
+
+/// Runs `files` on every worker of `pool`, each taking the next task from a
+/// shared cursor, so tasks start in plan order and only failures are kept.
+/// `init` builds per-worker state, such as a connection; with a `limiter`
+/// a worker holds a permit while it takes and runs a task. Taking a task
+/// starts the read-ahead of the one queued after it.
+pub(crate) fn run_workers<S>(
+    pool: &rayon::ThreadPool,
+    files: &[FileTask],
+    limiter: Option<&Limiter>,
+    init: impl Fn() -> S + Sync,
+    run: impl Fn(&mut S, &FileTask) -> Result<(), FailedFile> + Sync,
+) -> Vec<FailedFile> {
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    pool.broadcast(|_| {
+        let mut state = init();
+        loop {
+            let _permit = limiter.map(Limiter::acquire);
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let Some(file_task) = files.get(index) else {
+                break;
+            };
+            // every later task was hinted when the one before it was taken
+            if index == 0 {
+                readahead::prefetch(&file_task.source, file_task.size);
+            }
+            if let Some(queued) = files.get(index + 1) {
+                readahead::prefetch(&queued.source, queued.size);
+            }
+            if let Err(failure) = run(&mut state, file_task)
+                && let Ok(mut failures) = failures.lock()
+            {
+                failures.push(failure);
+            }
+        }
+    });
+    failures.into_inner().unwrap_or_default()
+}
+
+/// Reports, verifies and logs one file around `copy`, which returns the
+/// content digest when one is wanted.
+fn run_file_task(
+    file_task: &FileTask,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    options: &CopyOptions,
+    manifest: Option<&Manifest>,
+    copy: impl FnOnce() -> CopyResult<Option<String>>,
+) -> CopyResult<()> {
+    reporter.file_begin(&file_task.source, &file_task.destination, file_task.size);
+    options.events.emit(|| CopyEvent::Started {
+        source: file_task.source.clone(),
+        destination: file_task.destination.clone(),
+        size: file_task.size,
+    });
+    options
+        .control
+        .start_file(&file_task.source, file_task.size);
+    let started = Instant::now();
+    let result = copy()
+        .and_then(|digest| {
+            sync_destination(&file_task.destination, options)?;
+            if let Some(barrier) = &options.write_barrier {
+                barrier
+                    .written(&file_task.destination, file_task.size)
+                    .map_err(CopyError::Io)?;
+            }
+            Ok(digest)
+        })
+        .and_then(|digest| match &digest {
+            Some(expected) if options.verify.is_some() => verify_destination(
+                &file_task.source,
+                &file_task.destination,
+                file_task.size,
+                expected,
+                options,
+            )
+            .map(|()| digest),
+            _ => Ok(digest),
+        });
+
+    let skip_reason = match &result {
+        Err(CopyError::Skipped(_)) => Some("skipped by user"),
+        Err(e) if e.is_locked() && options.locked == LockedFiles::Skip => {
+            output::warn(
+                options,
+                format!(
+                    "Skipping '{}': locked by another process",
+                    file_task.source.display()
+                ),
+            );
+            Some("locked by another process")
+        }
+        _ => None,
+    };
+    if let Some(reason) = skip_reason {
+        options.control.finish_file(None);
+        stats.record_skipped(1);
+        options.events.emit(|| CopyEvent::Skipped {
+            source: file_task.source.clone(),
+            reason: reason.to_string(),
+        });
+        reporter.file_skipped(&file_task.source, reason);
+        output::skipped(options, &file_task.source, reason);
+        return Ok(());
+    }
+    options.control.finish_file(match &result {
+        Err(e) if e.kind() != io::ErrorKind::Interrupted => {
+            Some(format!("{}: {}", file_task.source.display(), e))
+        }
+        _ => None,
+    });
+
+    match &result {
+        Ok(digest) => {
+            if options.store_checksum
+                && let Some(digest) = digest
+                && let Err(e) = store_digest(&file_task.destination, options.digest_algo(), digest)
+                && let Some(logger) = &options.logger
+            {
+                // only a shortcut for the next --resume, which can read the file instead
+                logger.debug(&format!(
+                    "cannot store checksum on '{}': {}",
+                    file_task.destination.display(),
+                    e
+                ));
+            }
+            if let (Some(manifest), Some(digest)) = (manifest, digest) {
+                if options.compress.is_some() {
+                    manifest.add_original(&file_task.destination, digest, file_task.size);
+                } else {
+                    manifest.add(&file_task.destination, digest);
+                }
+            }
+            stats.record_duration(&file_task.source, started.elapsed());
+            options.events.emit(|| CopyEvent::Finished {
+                source: file_task.source.clone(),
+                destination: file_task.destination.clone(),
+            });
+            reporter.file_done(&file_task.source, &file_task.destination, digest.as_deref());
+            output::copied(options, reporter, &file_task.source, &file_task.destination);
+        }
+        Err(e) => {
+            reporter.file_failed(&file_task.source, &file_task.destination, e);
+            if !matches!(e, CopyError::Io(io_err) if io_err.kind() == io::ErrorKind::Interrupted) {
+                stats.record_failed();
+                options.events.emit(|| CopyEvent::Failed {
+                    source: file_task.source.clone(),
+                    destination: file_task.destination.clone(),
+                    category: e.category(),
+                    error: e.to_string(),
+                });
+                // cancel the remaining tasks through the same flag as Ctrl+C
+                if options
+                    .max_errors
+                    .is_some_and(|limit| stats.files_failed() > limit)
+                {
+                    options.control.abort();
+                }
+                // every file after this one would fail the same way
+                if e.is_out_of_space() {
+                    options.control.stop_out_of_space();
+                }
+            }
+            if let Some(logger) = &options.logger {
+                logger.failed(&file_task.source, &file_task.destination, &e.to_string());
+            }
+        }
+    }
+    result.map(|_| ())
+}
+
+/// Hard-links a `--dedupe` duplicate to its original's copy, copying it
+/// normally when that isn't possible (the original failed, or the
+/// filesystem has no hard links). Separate duplicates are copied from it.
+fn link_duplicate(
+    duplicate: &DuplicateTask,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    options: &CopyOptions,
+    hardlink_tracker: Option<&Arc<Mutex<HardLinkTracker>>>,
+) -> CopyResult<Option<String>> {
+    let file_task = &duplicate.file;
+    let checkpoint = options.control.checkpoint();
+    if checkpoint != Checkpoint::Continue {
+        return Err(checkpoint_error(checkpoint, &file_task.source));
+    }
+    if !prepare_destination(&file_task.destination, options, reporter)? {
+        return Ok(None);
+    }
+    if std::fs::symlink_metadata(&file_task.destination).is_ok() {
+        let _ = std::fs::remove_file(&file_task.destination);
+    }
+    if duplicate.separate {
+        return match copy_separate(duplicate, stats, options) {
+            Ok(()) => source_digest(&file_task.source, options),
+            Err(_) => copy_with_retries(file_task, reporter, stats, options, hardlink_tracker),
+        };
+    }
+    match std::fs::hard_link(&duplicate.original, &file_task.destination) {
+        Ok(()) => {
+            stats.record_hardlink();
+            stats.record_deduplicated(file_task.size);
+            stats.record_copied();
+            source_digest(&file_task.source, options)
+        }
+        Err(_) => copy_with_retries(file_task, reporter, stats, options, hardlink_tracker),
+    }
+}
+
+/// Copies another name of a hard-linked source from the first name's copy,
+/// reflinking it where the filesystem can. The copy must be complete: one
+/// that failed or was cut short is copied from the source instead.
+fn copy_separate(
+    duplicate: &DuplicateTask,
+    stats: &CopyStats,
+    options: &CopyOptions,
+) -> CopyResult<()> {
+    let file_task = &duplicate.file;
+    if std::fs::metadata(&duplicate.original)?.len() != file_task.size {
+        return Err(CopyError::CopyFailed {
+            source: duplicate.original.clone(),
+            destination: file_task.destination.clone(),
+            reason: "copy of the first name is incomplete".to_string(),
+        });
+    }
+    // None: reflinked rather than copied
+    if reflink_copy::reflink_or_copy(&duplicate.original, &file_task.destination)?.is_none() {
+        stats.record_reflink();
+    }
+    stats.record_copied();
+    if options.preserve != PreserveAttr::none() {
+        preserve::apply_preserve_attrs(&file_task.source, &file_task.destination, options.preserve)
+            .map_err(CopyError::from)?;
+    }
+    Ok(())
+}
+
+fn copy_with_retries(
+    file_task: &FileTask,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    options: &CopyOptions,
+    hardlink_tracker: Option<&Arc<Mutex<HardLinkTracker>>>,
+) -> CopyResult<Option<String>> {
+    let mut attempt = 0;
+    loop {
+        let result = copy_core(
+            &file_task.source,
+            &file_task.destination,
+            file_task.size,
+            reporter,
+            stats,
+            options,
+            hardlink_tracker,
+        );
+        let max_attempts = match &result {
+            Err(e) if e.is_locked() && options.locked == LockedFiles::Retry => {
+                options.retries.max(LOCKED_RETRIES)
+            }
+            Err(e) if e.is_out_of_descriptors() => options.retries.max(DESCRIPTOR_RETRIES),
+            Err(e) if e.is_transient() => options.retries,
+            _ => 0,
+        };
+        match result {
+            Err(e) if attempt < max_attempts && !options.control.is_aborted() => {
+                attempt += 1;
+                options.control.record_retry(&file_task.source);
+                let delay = retry_delay(options.retry_delay, attempt);
+                options.events.emit(|| CopyEvent::Retrying {
+                    source: file_task.source.clone(),
+                    attempt,
+                    max_attempts,
+                });
+                reporter.message(&format!(
+                    "Retrying {} ({}/{})",
+                    file_task.source.display(),
+                    attempt,
+                    max_attempts
+                ));
+                if let Some(logger) = &options.logger {
+                    logger.warning(
+                        &file_task.source,
+                        &format!(
+                            "attempt {} failed: {}; retrying in {}",
+                            attempt,
+                            e,
+                            humantime::format_duration(delay)
+                        ),
+                    );
                 }
+                clear_for_retry(&file_task.destination, options);
+                sleep_unless_aborted(delay, options);
             }
+            result => return result,
         }
+    }
+}
+
+/// Starts the next attempt from a clean destination. `--inplace` keeps the
+/// partly updated file, as [`abandon_incomplete`] does, and a device is
+/// written in place with its node never removed.
+fn clear_for_retry(destination: &Path, options: &CopyOptions) {
+    if options.inplace || device::is_block_device_path(destination) {
+        return;
+    }
+    let _ = std::fs::remove_file(destination);
+}
 
-        if interrupted {
-            let completed = completed_files.load(Ordering::Relaxed);
+/// Attempts `--locked=retry` makes even without `--retries`.
+const LOCKED_RETRIES: u32 = 3;
 
-            eprintln!("\nCompleted:  {} files", completed);
-            eprintln!("Remaining:  {} files", plan.total_files - completed);
+/// Attempts made after running out of file descriptors, waiting for other
+/// workers to close theirs.
+const DESCRIPTOR_RETRIES: u32 = 5;
 
-            return Err(CopyError::Io(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "Operation interrupted by user",
-            )));
-        }
+/// Exponential backoff: `base`, `2 * base`, `4 * base`, ...
+fn retry_delay(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(1u32 << attempt.saturating_sub(1).min(16))
+}
 
-        if !errors.is_empty() {
-            if let Some(pb) = overall_pb {
-                pb.abandon_with_message("Completed with errors");
-            }
-            eprintln!("\nFailed to copy {} file(s):", errors.len());
-            for (source, _dest, err) in errors.iter().take(3) {
-                eprintln!("  {} - {}", source.display(), err);
-            }
-            if errors.len() > 3 {
-                eprintln!("  ... and {} more", errors.len() - 5);
-            }
-            return Err(CopyError::Io(io::Error::other(format!(
-                "{} file(s) failed to copy",
-                errors.len()
-            ))));
+fn sleep_unless_aborted(delay: Duration, options: &CopyOptions) {
+    const STEP: Duration = Duration::from_millis(100);
+    let deadline = Instant::now() + delay;
+    while !options.control.is_aborted() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
         }
+        std::thread::sleep(remaining.min(STEP));
     }
+}
 
-    if let Some(pb) = overall_pb {
-        if matches!(options.progress_bar.style, ProgressBarStyle::Detailed)
-            && !options.attributes_only
-        {
-            pb.finish_with_message(format!("Copied {} files successfully", plan.total_files));
-        } else {
-            pb.finish_with_message("Done".to_string());
-        }
+// The source digest came out of the copy loop, so only the destination is read back
+fn verify_destination(
+    source: &Path,
+    destination: &Path,
+    size: u64,
+    expected: &str,
+    options: &CopyOptions,
+) -> CopyResult<()> {
+    let algo = options.digest_algo();
+    let readback = options.verify == Some(VerifyMode::Readback);
+    let actual = if device::is_block_device_path(destination) {
+        // the rest of the device was never written
+        hash_prefix(destination, size, algo, readback)?
+    } else if readback {
+        hash_file_uncached(destination, algo)?
+    } else {
+        hash_file(destination, algo)?
+    };
+    if actual != expected {
+        return Err(CopyError::VerifyFailed {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+        });
     }
-
     Ok(())
 }
 
@@ -264,37 +1290,28 @@ fn copy_core(
     source: &Path,
     destination: &Path,
     file_size: u64,
-    overall_pb: Option<&ProgressBar>,
-    completed_files: &AtomicUsize,
-    total_files: usize,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
     options: &CopyOptions,
     hardlink_tracker: Option<&Arc<Mutex<HardLinkTracker>>>,
-) -> CopyResult<()> {
+) -> CopyResult<Option<String>> {
     if options.attributes_only {
         if std::fs::symlink_metadata(destination).is_err() {
-            return Ok(());
+            return Ok(None);
         }
         preserve::apply_preserve_attrs(source, destination, options.preserve)?;
-        return Ok(());
+        return Ok(None);
     }
 
-    if options.interactive
-        && destination.try_exists().unwrap_or(false)
-        && !prompt_overwrite(destination)?
-    {
-        return Ok(());
-    }
-
-    if let Some(backup_mode) = options.backup
-        && backup_mode != BackupMode::None
-        && destination.try_exists().unwrap_or(false)
+    // compressing reads a device like any other source
+    if device::is_block_device_path(destination)
+        || (device::is_block_device_path(source) && !compress::transcodes(source, options))
     {
-        let backup_path = generate_backup_path(destination, backup_mode)?;
-        let _ = create_backup(destination, &backup_path);
+        return device::copy_device(source, destination, file_size, reporter, stats, options);
     }
 
-    if options.remove_destination {
-        let _ = std::fs::remove_file(destination);
+    if !prepare_destination(destination, options, reporter)? {
+        return Ok(None);
     }
 
     // Handle hard link preservation
@@ -305,16 +1322,21 @@ fn copy_core(
 
         if tracker_guard.track_and_create_link(source, destination)? {
             // Hard link was created, no need to copy file content
-            update_progress(overall_pb, completed_files, total_files, options);
+            stats.record_hardlink();
+            stats.record_copied();
             if options.preserve != PreserveAttr::none() {
                 preserve::apply_preserve_attrs(source, destination, options.preserve)
                     .map_err(CopyError::from)?;
             }
-            return Ok(());
+            return source_digest(source, options);
         }
         // Continue with normal file copy if this is the first file in the inode group
     }
 
+    if compress::transcodes(source, options) {
+        return compress::transcode(source, destination, file_size, reporter, stats, options);
+    }
+
     if let Some(reflink_mode) = options.reflink {
         use crate::cli::args::ReflinkMode;
         if reflink_mode != ReflinkMode::Never {
@@ -327,17 +1349,17 @@ fn copy_core(
 
             match reflink_copy::reflink(source, destination) {
                 Ok(()) => {
-                    if let Some(pb) = overall_pb {
-                        pb.inc(file_size);
-                    }
-                    update_progress(overall_pb, completed_files, total_files, options);
+                    stats.record_reflink();
+                    count_bytes(reporter, stats, source, file_size, options);
+                    stats.record_copied();
+                    copy_data_streams(source, destination, options)?;
                     if options.preserve != PreserveAttr::none() {
                         preserve::apply_preserve_attrs(source, destination, options.preserve)
                             .map_err(CopyError::from)?;
                     }
-                    return Ok(());
+                    return source_digest(source, options);
                 }
-                Err(e) if reflink_mode == ReflinkMode::Always => {
+                Err(_) if reflink_mode == ReflinkMode::Always => {
                     return Err(CopyError::ReflinkFailed {
                         source: source.to_path_buf(),
                         destination: destination.to_path_buf(),
@@ -348,32 +1370,41 @@ fn copy_core(
         }
     }
 
-    #[cfg(target_os = "linux")]
+    let resume_from = if options.partial {
+        restore_partial(source, destination, file_size, options)
+    } else {
+        0
+    };
+
+    #[cfg(unix)]
     {
-        if options.abort.load(Ordering::Relaxed) {
-            return Err(CopyError::Io(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "Operation aborted by user",
-            )));
-        }
-        if let Ok(true) = fast_copy(source, destination, file_size, overall_pb, options) {
-            update_progress(overall_pb, completed_files, total_files, options);
+        let checkpoint = options.control.checkpoint();
+        if checkpoint != Checkpoint::Continue {
+            return Err(checkpoint_error(checkpoint, source));
+        }
+        // copy_file_range never exposes the data, so hashing needs the buffered
+        // path; so does carrying on from a kept partial file
+        if !options.wants_digest()
+            && resume_from == 0
+            && let Ok(true) = fast_copy(source, destination, file_size, reporter, stats, options)
+        {
+            stats.record_copied();
+            copy_data_streams(source, destination, options)?;
             if options.preserve != PreserveAttr::none() {
                 preserve::apply_preserve_attrs(source, destination, options.preserve)
                     .map_err(CopyError::from)?;
             }
-            return Ok(());
+            return Ok(None);
         }
     }
 
-    let mut src_file = std::fs::File::open(source)?;
-    let dest_file = match std::fs::File::create(destination) {
-        Ok(file) => file,
-        Err(_e) if options.force => {
-            let _ = std::fs::remove_file(destination);
-            std::fs::File::create(destination)?
-        }
-        Err(e) => return Err(CopyError::Io(e)),
+    let mut src_file = open_source(source)?;
+    let dest_file = if resume_from > 0 {
+        let mut file = std::fs::OpenOptions::new().write(true).open(destination)?;
+        file.seek(SeekFrom::Start(resume_from))?;
+        file
+    } else {
+        create_destination(Some(source), destination, options)?
     };
 
     let buffer_size: usize = if file_size < 1024 * 1024 {
@@ -388,8 +1419,10 @@ fn copy_core(
         2 * 1024 * 1024
     };
 
-    let mut dest_file = std::io::BufWriter::with_capacity(buffer_size, dest_file);
-    let mut buffer = vec![0u8; buffer_size];
+    // every write is a whole buffer already, so the file isn't wrapped in
+    // a BufWriter with a second one of its own
+    let mut dest_file = dest_file;
+    let mut buffer = buffers::shared().take(buffer_size);
 
     const MAX_UPDATES: u64 = 128;
     let update_threshold = if file_size > MAX_UPDATES * buffer_size as u64 {
@@ -399,80 +1432,432 @@ fn copy_core(
     };
 
     let mut accumulated_bytes = 0u64;
+    let mut hasher = options
+        .wants_digest()
+        .then(|| FileHasher::new(options.digest_algo()));
+    if resume_from > 0 {
+        // the kept bytes count as copied, but a digest still has to cover them
+        match hasher.as_mut() {
+            Some(hasher) => {
+                let mut prefix = (&mut src_file).take(resume_from);
+                loop {
+                    let n = prefix.read(&mut buffer)?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[..n]);
+                }
+            }
+            None => {
+                src_file.seek(SeekFrom::Start(resume_from))?;
+            }
+        }
+        count_bytes(reporter, stats, source, resume_from, options);
+    }
 
-    loop {
-        if options.abort.load(Ordering::Relaxed) {
-            dest_file.flush()?;
-            drop(dest_file);
-            if let Err(e) = std::fs::remove_file(destination) {
-                eprintln!(
-                    "Could not remove incomplete file {}: {}",
-                    destination.display(),
-                    e
-                );
-            } else {
-                eprintln!("Cleaned up incomplete file: {}", destination.display());
+    let mut stopped = false;
+    let written: CopyResult<()> = 'copy: {
+        loop {
+            let checkpoint = options.control.checkpoint();
+            if checkpoint != Checkpoint::Continue {
+                stopped = true;
+                break 'copy Err(checkpoint_error(checkpoint, source));
+            }
+
+            let bytes_read = match src_file.read(&mut buffer) {
+                Ok(n) => n,
+                Err(e) => break 'copy Err(e.into()),
+            };
+            if bytes_read == 0 {
+                break;
+            }
+            if let Err(e) = dest_file.write_all(&buffer[..bytes_read]) {
+                break 'copy Err(e.into());
+            }
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&buffer[..bytes_read]);
             }
 
-            return Err(CopyError::Io(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "Operation aborted by user",
-            )));
+            accumulated_bytes += bytes_read as u64;
+            if accumulated_bytes >= update_threshold {
+                report_bytes(reporter, stats, source, accumulated_bytes, options);
+                accumulated_bytes = 0;
+            }
         }
 
-        let bytes_read = src_file.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
+        if accumulated_bytes > 0 {
+            report_bytes(reporter, stats, source, accumulated_bytes, options);
+        }
+        trim_inplace(&dest_file, file_size, options).map_err(CopyError::from)
+    };
+    drop(buffer);
+    if let Err(e) = written {
+        drop(dest_file);
+        // a stopped copy, or one cut short by a full disk, is never left in
+        // place; another failed one is only moved aside when --partial keeps it
+        if stopped || options.partial || e.is_out_of_space() {
+            abandon_incomplete(destination, options);
         }
-        dest_file.write_all(&buffer[..bytes_read])?;
+        return Err(e);
+    }
+    drop(dest_file);
+
+    stats.record_copied();
+    copy_data_streams(source, destination, options)?;
+
+    if options.preserve != PreserveAttr::none() {
+        preserve::apply_preserve_attrs(source, destination, options.preserve)
+            .map_err(CopyError::from)?;
+    }
+
+    Ok(hasher.map(FileHasher::finish))
+}
+
+/// Carries NTFS alternate data streams over; before attributes are
+/// preserved, since writing a stream updates the mtime.
+fn copy_data_streams(source: &Path, destination: &Path, options: &CopyOptions) -> CopyResult<()> {
+    streams::copy_streams(source, destination, options.strip_zone_identifier)
+        .map(|_| ())
+        .map_err(|e| CopyError::CopyFailed {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            reason: format!("Failed to copy alternate data streams: {}", e),
+        })
+}
+
+/// Applies the overwrite policy (--on-conflict, --backup, --remove-destination)
+/// to an existing destination. Returns false when it is kept.
+pub(crate) fn prepare_destination(
+    destination: &Path,
+    options: &CopyOptions,
+    reporter: &dyn ProgressReporter,
+) -> CopyResult<bool> {
+    if let Resolution::Keep(reason) =
+        conflict::resolve_existing(destination, options.conflict_policy(false))?
+    {
+        output::skipped(options, destination, reason);
+        return Ok(false);
+    }
 
-        accumulated_bytes += bytes_read as u64;
-        if accumulated_bytes >= update_threshold {
-            if let Some(pb) = overall_pb {
-                pb.inc(accumulated_bytes);
+    let existed = options.journal.is_some() && std::fs::symlink_metadata(destination).is_ok();
+    let mut backup = None;
+    if let Some(backup_mode) = options.backup
+        && backup_mode != BackupMode::None
+        && destination.try_exists().unwrap_or(false)
+    {
+        let backup_path = generate_backup_path(destination, backup_mode)?;
+        let mut copied = 0u64;
+        let progress = |bytes| {
+            copied += bytes;
+            reporter.message(&format!(
+                "Backing up {} to another filesystem ({})",
+                destination.display(),
+                BinaryBytes(copied)
+            ));
+        };
+        match create_backup(destination, &backup_path, progress) {
+            Ok(()) => backup = Some(backup_path),
+            Err(e) => {
+                if let Some(logger) = &options.logger {
+                    logger.warning(destination, &e.to_string());
+                }
             }
-            accumulated_bytes = 0;
         }
     }
 
-    if accumulated_bytes > 0
-        && let Some(pb) = overall_pb
-    {
-        pb.inc(accumulated_bytes);
+    if options.remove_destination {
+        let _ = preserve::remove_forced(destination);
+    } else if options.dest_symlinks.replaces(destination) {
+        remove_link(destination)?;
     }
+    journal_write(destination, existed, backup.as_deref(), options)?;
+    Ok(true)
+}
+
+/// Removes a destination symlink that `--no-dereference-dest` replaces.
+/// Windows links to directories are directories themselves.
+fn remove_link(link: &Path) -> io::Result<()> {
+    std::fs::remove_file(link)
+        .or_else(|_| std::fs::remove_dir(link))
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot replace symlink '{}': {}", link.display(), e),
+            )
+        })
+}
 
-    dest_file.flush()?;
+/// `--fsync=file|all`: flushes a finished destination file to disk.
+pub(crate) fn sync_destination(destination: &Path, options: &CopyOptions) -> CopyResult<()> {
+    if !options.fsync.files() {
+        return Ok(());
+    }
+    match std::fs::symlink_metadata(destination) {
+        Ok(metadata) if metadata.is_file() => sync_path(destination).map_err(|e| {
+            CopyError::Io(io::Error::new(
+                e.kind(),
+                format!("cannot sync '{}': {}", destination.display(), e),
+            ))
+        }),
+        // declined with --interactive, or a link
+        _ => Ok(()),
+    }
+}
 
-    update_progress(overall_pb, completed_files, total_files, options);
+/// Records a destination about to be written, before anything is lost.
+fn journal_write(
+    destination: &Path,
+    existed: bool,
+    backup: Option<&Path>,
+    options: &CopyOptions,
+) -> CopyResult<()> {
+    let Some(journal) = &options.journal else {
+        return Ok(());
+    };
+    let result = if existed {
+        journal.overwritten(destination, backup)
+    } else {
+        journal.created(destination)
+    };
+    result.map_err(|e| {
+        CopyError::Io(io::Error::new(
+            e.kind(),
+            format!("Failed to write journal: {}", e),
+        ))
+    })
+}
 
-    if options.preserve != PreserveAttr::none() {
-        preserve::apply_preserve_attrs(source, destination, options.preserve)
-            .map_err(CopyError::from)?;
+/// Creates (or truncates) the copy of `source` at `destination`, with the
+/// attribute flags that must precede its data already set. With `--inplace`
+/// an existing file is opened without truncating; with `--force` an
+/// unwritable one is removed first.
+pub(crate) fn create_destination(
+    source: Option<&Path>,
+    destination: &Path,
+    options: &CopyOptions,
+) -> CopyResult<std::fs::File> {
+    let open = || {
+        if options.inplace {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(destination)
+        } else {
+            std::fs::File::create(destination)
+        }
+    };
+    let file = match open() {
+        Ok(file) => file,
+        Err(_e) if options.force => {
+            let _ = preserve::remove_forced(destination);
+            open()?
+        }
+        Err(e) => return Err(CopyError::Io(e)),
+    };
+    let preserve = &options.preserve;
+    if preserve.flags || preserve.set_attr.is_some() {
+        chattr::prepare(&file, source.filter(|_| preserve.flags), preserve.set_attr);
     }
+    Ok(file)
+}
 
+/// `--inplace` writes over the old contents without truncating first, so
+/// whatever lies past the new end is cut off once the copy is done. Block
+/// devices keep their size.
+pub(crate) fn trim_inplace(
+    file: &std::fs::File,
+    size: u64,
+    options: &CopyOptions,
+) -> io::Result<()> {
+    if options.inplace && file.metadata()?.is_file() {
+        file.set_len(size)?;
+    }
     Ok(())
 }
 
-fn update_progress(
-    overall_pb: Option<&ProgressBar>,
-    completed_files: &AtomicUsize,
-    total_files: usize,
+pub(crate) fn remove_incomplete(destination: &Path, options: &CopyOptions) {
+    if let Err(e) = std::fs::remove_file(destination) {
+        output::run_error(
+            options,
+            format!(
+                "Could not remove incomplete file {}: {}",
+                destination.display(),
+                e
+            ),
+        );
+    } else {
+        output::warn(
+            options,
+            format!("Cleaned up incomplete file: {}", destination.display()),
+        );
+    }
+}
+
+/// Where `--partial` keeps an unfinished `destination`: beside it as
+/// `name.cpx-partial`, or in `--partial-dir` (taken relative to the
+/// destination's directory unless absolute).
+pub(crate) fn partial_path(destination: &Path, options: &CopyOptions) -> PathBuf {
+    let name = destination.file_name().unwrap_or_default();
+    match &options.partial_dir {
+        Some(dir) => destination
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(dir)
+            .join(name),
+        None => {
+            let mut name = name.to_os_string();
+            name.push(".cpx-partial");
+            destination.with_file_name(name)
+        }
+    }
+}
+
+/// Removes an unfinished destination, or with `--partial` moves it to
+/// [`partial_path`] for a later run to continue. `--inplace` leaves it.
+pub(crate) fn abandon_incomplete(destination: &Path, options: &CopyOptions) {
+    if options.inplace {
+        // the old contents are half overwritten already, and a block device
+        // must never be unlinked
+        return output::warn(
+            options,
+            format!("Left partly updated in place: {}", destination.display()),
+        );
+    }
+    if !options.partial {
+        return remove_incomplete(destination, options);
+    }
+    let partial = partial_path(destination, options);
+    let kept = partial
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| rename_or_copy(destination, &partial, |_| {}));
+    match kept {
+        Ok(()) => output::warn(
+            options,
+            format!("Kept incomplete file as {}", partial.display()),
+        ),
+        Err(e) => output::run_error(
+            options,
+            format!(
+                "Could not keep incomplete file {}: {}",
+                destination.display(),
+                e
+            ),
+        ),
+    }
+}
+
+/// Moves a partial file kept for `destination` back into place and returns
+/// its length, the offset to continue from. A partial longer than the
+/// source, or whose last block no longer matches it, is thrown away.
+fn restore_partial(
+    source: &Path,
+    destination: &Path,
+    file_size: u64,
     options: &CopyOptions,
-) {
-    let completed = completed_files.fetch_add(1, Ordering::Relaxed) + 1;
-    if let Some(pb) = overall_pb
-        && matches!(options.progress_bar.style, ProgressBarStyle::Detailed)
-    {
-        pb.set_message(format!("Copying: {}/{} files", completed, total_files));
+) -> u64 {
+    let partial = partial_path(destination, options);
+    let Ok(len) = std::fs::metadata(&partial).map(|meta| meta.len()) else {
+        return 0;
+    };
+    let usable =
+        len > 0 && len <= file_size && tail_matches(source, &partial, len).unwrap_or(false);
+    if usable && rename_or_copy(&partial, destination, |_| {}).is_ok() {
+        if let Some(logger) = &options.logger {
+            logger.debug(&format!(
+                "Continuing {} from {} kept bytes",
+                destination.display(),
+                len
+            ));
+        }
+        return len;
+    }
+    let _ = std::fs::remove_file(&partial);
+    0
+}
+
+/// Whether the last block of `partial`, `len` bytes long, is what `source`
+/// holds at the same offset.
+fn tail_matches(source: &Path, partial: &Path, len: u64) -> io::Result<bool> {
+    const TAIL: u64 = 64 * 1024;
+    let start = len.saturating_sub(TAIL);
+    let read_tail = |file: &mut std::fs::File| -> io::Result<Vec<u8>> {
+        let mut tail = vec![0u8; (len - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut tail)?;
+        Ok(tail)
+    };
+    Ok(read_tail(&mut open_source(source)?)? == read_tail(&mut std::fs::File::open(partial)?)?)
+}
+
+/// The error a copy loop returns when its checkpoint says to stop.
+pub(crate) fn checkpoint_error(checkpoint: Checkpoint, source: &Path) -> CopyError {
+    match checkpoint {
+        Checkpoint::Skip => CopyError::Skipped(source.to_path_buf()),
+        Checkpoint::TimedOut(limit) => CopyError::TimedOut {
+            path: source.to_path_buf(),
+            limit,
+        },
+        _ => CopyError::Io(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Operation aborted by user",
+        )),
+    }
+}
+
+// Content digest for copies that never passed through the read loop
+fn source_digest(source: &Path, options: &CopyOptions) -> CopyResult<Option<String>> {
+    if !options.wants_digest() {
+        return Ok(None);
     }
+    Ok(Some(hash_file(source, options.digest_algo())?))
+}
+
+/// Counts `delta` more bytes copied from `source`, then waits for
+/// `--bwlimit` to let them through.
+pub(crate) fn report_bytes(
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    source: &Path,
+    delta: u64,
+    options: &CopyOptions,
+) {
+    count_bytes(reporter, stats, source, delta, options);
+    options.control.throttle(delta);
+}
+
+/// Counts bytes that reached the destination without being copied, a
+/// reflink's or the part of a file already there, which `--bwlimit` lets
+/// through.
+fn count_bytes(
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    source: &Path,
+    delta: u64,
+    options: &CopyOptions,
+) {
+    stats.add_bytes(delta);
+    options.control.add_bytes(delta);
+    options.events.emit(|| CopyEvent::Progress {
+        source: source.to_path_buf(),
+        bytes: delta,
+    });
+    reporter.file_inc(source, delta);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::args::HiddenMode;
+    use crate::utility::control::CopyControl;
+    use crate::utility::error_report::ErrorReportFormat;
+    use crate::utility::events::EventBus;
+    use crate::utility::exclude::ExcludeMarkers;
+    use crate::utility::hash::HashAlgo;
+    use crate::utility::output::Verbosity;
     use crate::utility::progress_bar::ProgressOptions;
     use std::fs;
-    use std::sync::atomic::AtomicBool;
     use tempfile::TempDir;
     fn default_copy_options() -> CopyOptions {
         CopyOptions {
@@ -480,19 +1865,80 @@ mod tests {
             resume: false,
             force: false,
             interactive: false,
+            on_conflict: None,
+            confirm: false,
+            assume: None,
+            list_conflicts: None,
             preserve: PreserveAttr::none(),
             backup: None,
             symbolic_link: None,
             hard_link: false,
             follow_symlink: FollowSymlink::NoDereference,
+            junctions: crate::cli::args::JunctionMode::Recreate,
+            symlink_fallback: crate::cli::args::SymlinkFallback::Error,
+            dest_symlinks: crate::cli::args::DestSymlinks::Follow,
             attributes_only: false,
             remove_destination: false,
             reflink: None,
+            fsync: crate::cli::args::FsyncMode::None,
+            write_barrier: None,
+            nice: 0,
+            io_priority: crate::cli::args::IoPriority::Normal,
+            backend: crate::cli::args::CopyBackend::Auto,
+            order: crate::cli::args::FileOrder::SizeDesc,
+            deterministic: false,
+            modify_window: Duration::ZERO,
+            partial: false,
+            partial_dir: None,
+            inplace: false,
+            link_dest: None,
+            strip_zone_identifier: false,
             parents: false,
             parallel: 1,
+            auto_jobs: false,
             exclude_rules: None,
+            exclude_markers: ExcludeMarkers::default(),
+            hidden: HiddenMode::Include,
+            prune_empty_dirs: false,
+            sanitize_names: None,
             progress_bar: ProgressOptions::default(),
-            abort: Arc::new(AtomicBool::new(false)),
+            verbosity: Verbosity::Normal,
+            logger: None,
+            stats: None,
+            manifest: None,
+            manifest_algo: HashAlgo::Sha256,
+            checksum_algo: HashAlgo::Xxh3,
+            verify: None,
+            store_checksum: false,
+            ignore_errors: false,
+            strict: false,
+            error_report: None,
+            error_report_format: ErrorReportFormat::Text,
+            metrics_file: None,
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
+            locked: LockedFiles::Fail,
+            timeout_per_file: None,
+            timeout_total: None,
+            bwlimit: None,
+            max_errors: None,
+            notify: None,
+            history: false,
+            status_socket: false,
+            tui: false,
+            control: Arc::new(CopyControl::new()),
+            events: Arc::new(EventBus::new()),
+            scan_progress: Arc::default(),
+            remote: None,
+            archive: None,
+            dedupe: false,
+            watch: None,
+            apply: None,
+            journal: None,
+            job: None,
+            compress: None,
+            compress_level: compress::DEFAULT_LEVEL,
+            decompress: false,
         }
     }
 
@@ -512,6 +1958,114 @@ mod tests {
         assert_eq!(content, "test content");
     }
 
+    #[test]
+    fn test_retry_delay_backoff() {
+        let base = Duration::from_millis(250);
+        assert_eq!(retry_delay(base, 1), Duration::from_millis(250));
+        assert_eq!(retry_delay(base, 2), Duration::from_millis(500));
+        assert_eq!(retry_delay(base, 4), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_only_transient_errors_are_retried() {
+        let eio = CopyError::Io(io::Error::from_raw_os_error(libc::EIO));
+        let timeout = CopyError::Io(io::Error::from(io::ErrorKind::TimedOut));
+        let missing = CopyError::Io(io::Error::from(io::ErrorKind::NotFound));
+        assert!(eio.is_transient());
+        assert!(timeout.is_transient());
+        assert!(!missing.is_transient());
+        assert!(!CopyError::FileExists(PathBuf::from("x")).is_transient());
+    }
+
+    #[test]
+    fn test_run_workers_takes_tasks_in_plan_order() {
+        let files: Vec<FileTask> = (0..50)
+            .map(|i| FileTask {
+                source: PathBuf::from(i.to_string()),
+                destination: PathBuf::from(i.to_string()),
+                size: 0,
+                inode_group: None,
+            })
+            .collect();
+        let started = Mutex::new(Vec::new());
+        let run = |_: &mut (), task: &FileTask| {
+            started.lock().unwrap().push(task.source.clone());
+            if task.source.as_os_str().len() == 1 {
+                Err(FailedFile::new(
+                    &task.source,
+                    &task.destination,
+                    CopyError::InvalidSource(task.source.clone()),
+                ))
+            } else {
+                Ok(())
+            }
+        };
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let failures = run_workers(&pool, &files, None, || (), run);
+        let sources: Vec<PathBuf> = files.iter().map(|f| f.source.clone()).collect();
+        assert_eq!(*started.lock().unwrap(), sources);
+        assert_eq!(failures.len(), 10);
+
+        started.lock().unwrap().clear();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        assert_eq!(run_workers(&pool, &files, None, || (), run).len(), 10);
+        assert_eq!(started.lock().unwrap().len(), 50);
+    }
+
+    #[test]
+    fn test_sharing_violations_count_as_locked() {
+        let sharing = CopyError::Io(io::Error::from_raw_os_error(32));
+        assert_eq!(sharing.is_locked(), cfg!(windows));
+        assert!(!CopyError::Io(io::Error::from(io::ErrorKind::PermissionDenied)).is_locked());
+    }
+
+    #[test]
+    fn test_verify_detects_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.txt");
+        let dest = temp_dir.path().join("dest.txt");
+
+        fs::write(&source, b"original").unwrap();
+        fs::write(&dest, b"tampered").unwrap();
+
+        let mut options = default_copy_options();
+        options.verify = Some(VerifyMode::Hash);
+        let expected = hash_file(&source, options.digest_algo()).unwrap();
+
+        assert!(verify_destination(&source, &source, 8, &expected, &options).is_ok());
+        assert!(matches!(
+            verify_destination(&source, &dest, 8, &expected, &options),
+            Err(CopyError::VerifyFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_readback_detects_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.txt");
+        let dest = temp_dir.path().join("dest.txt");
+
+        fs::write(&source, b"original").unwrap();
+        fs::write(&dest, b"tampered").unwrap();
+
+        let mut options = default_copy_options();
+        options.verify = Some(VerifyMode::Readback);
+        let expected = hash_file(&source, options.digest_algo()).unwrap();
+
+        assert!(verify_destination(&source, &source, 8, &expected, &options).is_ok());
+        assert!(matches!(
+            verify_destination(&source, &dest, 8, &expected, &options),
+            Err(CopyError::VerifyFailed { .. })
+        ));
+    }
+
     #[test]
     fn test_copy_directory_without_recursive_fails() {
         let temp_dir = TempDir::new().unwrap();
@@ -548,6 +2102,34 @@ mod tests {
         assert_eq!(content, "content");
     }
 
+    #[test]
+    fn test_failures_carry_paths_and_category() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source_dir");
+        let dest_dir = temp_dir.path().join("dest_dir");
+
+        fs::create_dir(&source_dir).unwrap();
+        fs::write(source_dir.join("ok.txt"), b"ok").unwrap();
+        fs::write(source_dir.join("blocked.txt"), b"blocked").unwrap();
+        fs::create_dir_all(dest_dir.join("source_dir").join("blocked.txt")).unwrap();
+
+        let mut options = default_copy_options();
+        options.recursive = true;
+
+        let Err(CopyError::PartialFailure(failures)) = copy(&source_dir, &dest_dir, &options)
+        else {
+            panic!("expected a partial failure");
+        };
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].source, source_dir.join("blocked.txt"));
+        assert_eq!(
+            failures[0].destination,
+            dest_dir.join("source_dir").join("blocked.txt")
+        );
+        assert!(failures[0].to_string().starts_with("[io] "));
+        assert!(dest_dir.join("source_dir").join("ok.txt").exists());
+    }
+
     #[test]
     fn test_copy_with_force_overwrites() {
         let temp_dir = TempDir::new().unwrap();
@@ -627,6 +2209,120 @@ mod tests {
         assert_eq!(content.len(), 0);
     }
 
+    #[test]
+    fn test_partial_files_are_kept_and_continued() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.bin");
+        let dest = temp_dir.path().join("dest.bin");
+        let content: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &content).unwrap();
+
+        let mut options = default_copy_options();
+        options.partial = true;
+        let partial = partial_path(&dest, &options);
+        assert_eq!(partial, temp_dir.path().join("dest.bin.cpx-partial"));
+
+        // an unfinished copy is moved aside instead of deleted
+        fs::write(&dest, &content[..100_000]).unwrap();
+        abandon_incomplete(&dest, &options);
+        assert!(!dest.exists());
+        assert_eq!(fs::metadata(&partial).unwrap().len(), 100_000);
+
+        // and the next run continues from it
+        options.verify = Some(VerifyMode::Hash);
+        copy(&source, &dest, &options).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), content);
+        assert!(!partial.exists());
+
+        // a partial that no longer matches the source is started over
+        fs::write(&partial, vec![0xffu8; 100_000]).unwrap();
+        fs::remove_file(&dest).unwrap();
+        copy(&source, &dest, &options).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), content);
+        assert!(!partial.exists());
+
+        options.partial_dir = Some(PathBuf::from(".partial"));
+        assert_eq!(
+            partial_path(&dest, &options),
+            temp_dir.path().join(".partial/dest.bin")
+        );
+    }
+
+    #[test]
+    fn test_retry_keeps_an_inplace_destination() {
+        let temp_dir = TempDir::new().unwrap();
+        let destination = temp_dir.path().join("dest.bin");
+        fs::write(&destination, b"half new, half old").unwrap();
+
+        let mut options = default_copy_options();
+        options.inplace = true;
+        clear_for_retry(&destination, &options);
+        assert_eq!(fs::read(&destination).unwrap(), b"half new, half old");
+
+        options.inplace = false;
+        clear_for_retry(&destination, &options);
+        assert!(!destination.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_inplace_rewrites_the_same_file() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.txt");
+        let dest = temp_dir.path().join("dest.txt");
+        fs::write(&source, b"new").unwrap();
+        fs::write(&dest, b"old and longer").unwrap();
+        let inode = fs::metadata(&dest).unwrap().ino();
+
+        let mut options = default_copy_options();
+        options.inplace = true;
+        copy(&source, &dest, &options).unwrap();
+
+        assert_eq!(fs::read(&dest).unwrap(), b"new");
+        assert_eq!(fs::metadata(&dest).unwrap().ino(), inode);
+
+        // an unfinished in-place copy is left where it is
+        abandon_incomplete(&dest, &options);
+        assert!(dest.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_dest_links_unchanged_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("same.txt"), b"unchanged").unwrap();
+        fs::write(source.join("edited.txt"), b"before").unwrap();
+        let mut options = default_copy_options();
+        options.recursive = true;
+        let first = temp_dir.path().join("first");
+        copy(&source, &first, &options).unwrap();
+
+        fs::write(source.join("edited.txt"), b"after, longer").unwrap();
+        options.link_dest = Some(PathBuf::from("../first"));
+        let second = temp_dir.path().join("second");
+        copy(&source, &second, &options).unwrap();
+
+        let inode = |path: PathBuf| fs::metadata(path).unwrap().ino();
+        assert_eq!(
+            inode(second.join("src/same.txt")),
+            inode(first.join("src/same.txt"))
+        );
+        assert_ne!(
+            inode(second.join("src/edited.txt")),
+            inode(first.join("src/edited.txt"))
+        );
+        assert_eq!(
+            fs::read(second.join("src/edited.txt")).unwrap(),
+            b"after, longer"
+        );
+    }
+
     #[test]
     fn test_copy_large_buffer_calculation() {
         let temp_dir = TempDir::new().unwrap();
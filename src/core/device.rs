@@ -0,0 +1,237 @@
+use crate::cli::args::CopyOptions;
+use crate::core::copy::{
+    abandon_incomplete, checkpoint_error, create_destination, prepare_destination, report_bytes,
+};
+use crate::error::{CopyError, CopyResult};
+use crate::utility::conflict::{self, Resolution};
+use crate::utility::control::Checkpoint;
+use crate::utility::hash::FileHasher;
+use crate::utility::helper::open_source;
+use crate::utility::output;
+use crate::utility::preserve::{self, PreserveAttr};
+use crate::utility::reporter::ProgressReporter;
+use crate::utility::stats::CopyStats;
+use indicatif::BinaryBytes;
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Reads and writes go through one buffer this large, a whole number of
+/// sectors on any device.
+const BUFFER_SIZE: usize = 4 * 1024 * 1024;
+/// The buffer starts on a page boundary, as devices prefer.
+const ALIGNMENT: usize = 4096;
+
+#[cfg(unix)]
+pub fn is_block_device(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    metadata.file_type().is_block_device()
+}
+
+#[cfg(not(unix))]
+pub fn is_block_device(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Whether `path` is a block device node, following symbolic links.
+pub fn is_block_device_path(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| is_block_device(&metadata))
+}
+
+/// Capacity of the device open as `file`: `BLKGETSIZE64` on Linux, where a
+/// block device's metadata reports no length.
+#[cfg(target_os = "linux")]
+pub fn device_size(file: &File) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    /// `_IOR(0x12, 114, size_t)`; other encodings fall back to seeking.
+    const BLKGETSIZE64: libc::Ioctl = 0x8008_1272_u32 as libc::Ioctl;
+    let mut size = 0u64;
+    if unsafe { libc::ioctl(file.as_raw_fd(), BLKGETSIZE64, &mut size) } == 0 {
+        return Ok(size);
+    }
+    seek_size(file)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn device_size(file: &File) -> io::Result<u64> {
+    seek_size(file)
+}
+
+fn seek_size(mut file: &File) -> io::Result<u64> {
+    let size = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(size)
+}
+
+/// Copies `size` bytes from a block device to a file, or from a file to a
+/// block device, in large aligned blocks. A device is written in place:
+/// never truncated, removed or backed up, and only with `--force`.
+pub fn copy_device(
+    source: &Path,
+    destination: &Path,
+    size: u64,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    options: &CopyOptions,
+) -> CopyResult<Option<String>> {
+    let to_device = is_block_device_path(destination);
+    let dest_file = if to_device {
+        match open_device(source, destination, size, options)? {
+            Some(file) => file,
+            None => return Ok(None),
+        }
+    } else {
+        if !prepare_destination(destination, options, reporter)? {
+            return Ok(None);
+        }
+        create_destination(Some(source), destination, options)?
+    };
+    let mut src_file = open_source(source)?.take(size);
+
+    let mut storage = vec![0u8; BUFFER_SIZE + ALIGNMENT];
+    let offset = storage.as_ptr().align_offset(ALIGNMENT);
+    let buffer = &mut storage[offset..offset + BUFFER_SIZE];
+    let mut hasher = options
+        .wants_digest()
+        .then(|| FileHasher::new(options.digest_algo()));
+
+    let mut stopped = false;
+    let written: CopyResult<()> = 'copy: {
+        loop {
+            let checkpoint = options.control.checkpoint();
+            if checkpoint != Checkpoint::Continue {
+                stopped = true;
+                break 'copy Err(checkpoint_error(checkpoint, source));
+            }
+            let filled = match fill(&mut src_file, buffer) {
+                Ok(n) => n,
+                Err(e) => break 'copy Err(e.into()),
+            };
+            if filled == 0 {
+                break;
+            }
+            if let Err(e) = (&dest_file).write_all(&buffer[..filled]) {
+                break 'copy Err(e.into());
+            }
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&buffer[..filled]);
+            }
+            report_bytes(reporter, stats, source, filled as u64, options);
+        }
+        // a device counts as written once the data is on it, not in the
+        // page cache
+        if to_device {
+            dest_file.sync_all()?;
+        }
+        Ok(())
+    };
+    drop(dest_file);
+    if let Err(e) = written {
+        if to_device {
+            output::warn(
+                options,
+                format!("Left partly written: {}", destination.display()),
+            );
+        } else if stopped || options.partial || e.is_out_of_space() {
+            abandon_incomplete(destination, options);
+        }
+        return Err(e);
+    }
+
+    stats.record_copied();
+    // the attributes of a device node describe the node, not its contents
+    if !to_device && options.preserve != PreserveAttr::none() {
+        preserve::apply_preserve_attrs(source, destination, options.preserve)
+            .map_err(CopyError::from)?;
+    }
+    Ok(hasher.map(FileHasher::finish))
+}
+
+/// Opens a device to write `size` bytes to, after the checks a file would
+/// get from [`prepare_destination`]. None when the conflict policy keeps it.
+fn open_device(
+    source: &Path,
+    destination: &Path,
+    size: u64,
+    options: &CopyOptions,
+) -> CopyResult<Option<File>> {
+    let refuse = |reason: String| CopyError::CopyFailed {
+        source: source.to_path_buf(),
+        destination: destination.to_path_buf(),
+        reason,
+    };
+    if !options.force {
+        return Err(refuse(format!(
+            "'{}' is a block device; use --force to write to it",
+            destination.display()
+        )));
+    }
+    if let Resolution::Keep(reason) =
+        conflict::resolve_existing(destination, options.conflict_policy(false))?
+    {
+        output::skipped(options, destination, reason);
+        return Ok(None);
+    }
+    let file = std::fs::OpenOptions::new().write(true).open(destination)?;
+    let capacity = device_size(&file)?;
+    if size > capacity {
+        return Err(refuse(format!(
+            "{} doesn't fit on the {} device",
+            BinaryBytes(size),
+            BinaryBytes(capacity)
+        )));
+    }
+    Ok(Some(file))
+}
+
+/// Reads until `buffer` is full or the input ends, so every write but the
+/// last is a whole buffer.
+fn fill(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_fill_reads_whole_buffers() {
+        // hands out at most 3 bytes per read, like a pipe
+        struct Trickle(Cursor<Vec<u8>>);
+        impl Read for Trickle {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let end = buf.len().min(3);
+                self.0.read(&mut buf[..end])
+            }
+        }
+
+        let mut reader = Trickle(Cursor::new((0..10).collect()));
+        let mut buffer = [0u8; 8];
+        assert_eq!(fill(&mut reader, &mut buffer).unwrap(), 8);
+        assert_eq!(buffer, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(fill(&mut reader, &mut buffer).unwrap(), 2);
+        assert_eq!(fill(&mut reader, &mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_regular_files_are_not_devices() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("image.img");
+        std::fs::write(&path, vec![0u8; 1000]).unwrap();
+        assert!(!is_block_device_path(&path));
+        // seeking works on any file, and is how other platforms measure a device
+        assert_eq!(device_size(&File::open(&path).unwrap()).unwrap(), 1000);
+    }
+}
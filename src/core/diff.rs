@@ -0,0 +1,160 @@
+use crate::core::verify::{Compare, Mismatch, compare_trees};
+use crate::error::CopyResult;
+use crate::utility::exclude::ExcludeRules;
+use indicatif::BinaryBytes;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffEntry {
+    pub path: PathBuf,
+    /// Bytes in the file, or in every file under the directory.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Modified {
+    pub path: PathBuf,
+    pub reason: Mismatch,
+    pub source_size: u64,
+    pub destination_size: u64,
+}
+
+impl Modified {
+    /// How much the destination would grow (or shrink) if the source were
+    /// copied over it.
+    pub fn size_delta(&self) -> i128 {
+        i128::from(self.source_size) - i128::from(self.destination_size)
+    }
+}
+
+/// Result of `cpx diff`. Paths are relative to the two roots.
+#[derive(Debug, Default, Serialize)]
+pub struct TreeDiff {
+    pub only_in_source: Vec<DiffEntry>,
+    pub only_in_destination: Vec<DiffEntry>,
+    pub modified: Vec<Modified>,
+    pub unchanged: usize,
+}
+
+impl TreeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_source.is_empty()
+            && self.only_in_destination.is_empty()
+            && self.modified.is_empty()
+    }
+
+    /// `+` only in the source, `-` only in the destination, `~` modified.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.only_in_source {
+            out.push_str(&format!(
+                "+ {} ({})\n",
+                entry.path.display(),
+                BinaryBytes(entry.size)
+            ));
+        }
+        for entry in &self.only_in_destination {
+            out.push_str(&format!(
+                "- {} ({})\n",
+                entry.path.display(),
+                BinaryBytes(entry.size)
+            ));
+        }
+        for modified in &self.modified {
+            let delta = modified.size_delta();
+            let sign = if delta < 0 { "-" } else { "+" };
+            out.push_str(&format!(
+                "~ {} ({}, {}{})\n",
+                modified.path.display(),
+                modified.reason.as_str(),
+                sign,
+                BinaryBytes(delta.unsigned_abs() as u64)
+            ));
+        }
+        let total = |entries: &[DiffEntry]| entries.iter().map(|entry| entry.size).sum::<u64>();
+        out.push_str(&format!(
+            "{} only in source ({}), {} only in destination ({}), {} modified, {} unchanged\n",
+            self.only_in_source.len(),
+            BinaryBytes(total(&self.only_in_source)),
+            self.only_in_destination.len(),
+            BinaryBytes(total(&self.only_in_destination)),
+            self.modified.len(),
+            self.unchanged
+        ));
+        out
+    }
+}
+
+/// Lists what copying `source` over `destination` would change, without
+/// writing anything.
+pub fn diff_trees(
+    source: &Path,
+    destination: &Path,
+    compare: Compare,
+    exclude_rules: Option<ExcludeRules>,
+) -> CopyResult<TreeDiff> {
+    let comparison = compare_trees(source, destination, compare, exclude_rules)?;
+    let entries = |entries: Vec<(PathBuf, u64)>| {
+        entries
+            .into_iter()
+            .map(|(path, size)| DiffEntry { path, size })
+            .collect()
+    };
+    Ok(TreeDiff {
+        only_in_source: entries(comparison.missing),
+        only_in_destination: entries(comparison.extra),
+        modified: comparison
+            .differing
+            .into_iter()
+            .map(|changed| Modified {
+                path: changed.difference.path,
+                reason: changed.difference.reason,
+                source_size: changed.source_size,
+                destination_size: changed.destination_size,
+            })
+            .collect(),
+        unchanged: comparison.matched,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_diff_sizes_and_deltas() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        let destination = temp_dir.path().join("dst");
+        fs::create_dir_all(source.join("new/nested")).unwrap();
+        fs::create_dir_all(&destination).unwrap();
+        fs::write(source.join("new/one.txt"), "12345").unwrap();
+        fs::write(source.join("new/nested/two.txt"), "123").unwrap();
+        fs::write(source.join("grown.txt"), "longer text").unwrap();
+        fs::write(destination.join("grown.txt"), "short").unwrap();
+        fs::write(destination.join("old.log"), "ab").unwrap();
+
+        let diff = diff_trees(&source, &destination, Compare::Quick, None).unwrap();
+        assert_eq!(
+            diff.only_in_source,
+            vec![DiffEntry {
+                path: PathBuf::from("new"),
+                size: 8,
+            }]
+        );
+        assert_eq!(
+            diff.only_in_destination,
+            vec![DiffEntry {
+                path: PathBuf::from("old.log"),
+                size: 2,
+            }]
+        );
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].reason, Mismatch::Size);
+        assert_eq!(diff.modified[0].size_delta(), 6);
+        assert!(diff.to_text().contains("~ grown.txt (size, +6 B)"));
+    }
+}
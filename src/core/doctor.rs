@@ -0,0 +1,648 @@
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Whether a filesystem can do something cpx may ask of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Support {
+    Yes,
+    No,
+    /// Not probed, or the probe couldn't tell.
+    Unknown,
+}
+
+impl Support {
+    fn as_str(self) -> &'static str {
+        match self {
+            Support::Yes => "yes",
+            Support::No => "no",
+            Support::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Reflink,
+    Sparse,
+    Xattr,
+    Acl,
+    Hardlink,
+    CaseSensitive,
+    NameMax,
+    PathMax,
+    DirectIo,
+}
+
+impl Capability {
+    fn as_str(self) -> &'static str {
+        match self {
+            Capability::Reflink => "reflinks",
+            Capability::Sparse => "sparse files",
+            Capability::Xattr => "xattrs",
+            Capability::Acl => "POSIX ACLs",
+            Capability::Hardlink => "hard links",
+            Capability::CaseSensitive => "case-sensitive",
+            Capability::NameMax => "max name length",
+            Capability::PathMax => "max path length",
+            Capability::DirectIo => "O_DIRECT",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub capability: Capability,
+    pub support: Support,
+    /// A limit, or why the answer is what it is.
+    pub detail: Option<String>,
+    /// For limits such as [`Capability::NameMax`].
+    pub value: Option<u64>,
+}
+
+impl Check {
+    fn new(capability: Capability, support: Support, detail: impl Into<Option<String>>) -> Self {
+        Self {
+            capability,
+            support,
+            detail: detail.into(),
+            value: None,
+        }
+    }
+
+    fn from_result(capability: Capability, result: io::Result<bool>) -> Self {
+        match result {
+            Ok(true) => Self::new(capability, Support::Yes, None),
+            Ok(false) => Self::new(capability, Support::No, None),
+            Err(e) if unsupported(&e) => Self::new(capability, Support::No, e.to_string()),
+            Err(e) => Self::new(capability, Support::Unknown, e.to_string()),
+        }
+    }
+
+    fn limit(capability: Capability, value: Option<u64>) -> Self {
+        Self {
+            capability,
+            support: if value.is_some() {
+                Support::Yes
+            } else {
+                Support::Unknown
+            },
+            detail: value.map(|value| format!("{} bytes", value)),
+            value,
+        }
+    }
+}
+
+/// What one side's filesystem can do.
+#[derive(Debug, Clone, Serialize)]
+pub struct FsReport {
+    pub path: PathBuf,
+    /// The existing directory the checks ran in.
+    pub probed: PathBuf,
+    pub filesystem: Option<String>,
+    pub checks: Vec<Check>,
+}
+
+impl FsReport {
+    pub fn support(&self, capability: Capability) -> Support {
+        self.check(capability)
+            .map_or(Support::Unknown, |check| check.support)
+    }
+
+    fn check(&self, capability: Capability) -> Option<&Check> {
+        self.checks
+            .iter()
+            .find(|check| check.capability == capability)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub source: FsReport,
+    pub destination: FsReport,
+    pub same_filesystem: Option<bool>,
+    /// What a copy between the two will lose or fail at.
+    pub warnings: Vec<String>,
+}
+
+impl DoctorReport {
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (side, report) in [("Source", &self.source), ("Destination", &self.destination)] {
+            out.push_str(&format!(
+                "{}: {} ({})\n",
+                side,
+                report.path.display(),
+                report.filesystem.as_deref().unwrap_or("unknown filesystem")
+            ));
+            for check in &report.checks {
+                let answer = match (&check.value, &check.detail) {
+                    (Some(_), Some(detail)) => detail.clone(),
+                    (None, Some(detail)) => {
+                        format!("{} ({})", check.support.as_str(), detail)
+                    }
+                    _ => check.support.as_str().to_string(),
+                };
+                out.push_str(&format!("  {:<17} {}\n", check.capability.as_str(), answer));
+            }
+            out.push('\n');
+        }
+        let same = match self.same_filesystem {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "unknown",
+        };
+        out.push_str(&format!("Same filesystem: {}\n\n", same));
+        if self.warnings.is_empty() {
+            out.push_str("Nothing cpx preserves is lost between these filesystems.\n");
+        } else {
+            out.push_str("What cpx can't do here:\n");
+            for warning in &self.warnings {
+                out.push_str(&format!("  - {}\n", warning));
+            }
+        }
+        out
+    }
+}
+
+/// Probes the filesystems `source` and `destination` live on. The source is
+/// only read; the destination's checks create files in a scratch directory
+/// that is removed afterwards. A destination that doesn't exist yet is
+/// probed in its nearest existing ancestor.
+pub fn run_doctor(source: &Path, destination: &Path) -> io::Result<DoctorReport> {
+    fs::symlink_metadata(source)?;
+    let source_dir = if source.is_dir() {
+        source.to_path_buf()
+    } else {
+        existing_dir(source)?
+    };
+    let destination_dir = existing_dir(destination)?;
+
+    let source_report = FsReport {
+        path: source.to_path_buf(),
+        probed: source_dir.clone(),
+        filesystem: filesystem_name(&source_dir),
+        checks: probe_read_only(source, &source_dir),
+    };
+    let destination_report = FsReport {
+        path: destination.to_path_buf(),
+        probed: destination_dir.clone(),
+        filesystem: filesystem_name(&destination_dir),
+        checks: probe_writable(&destination_dir),
+    };
+    let same_filesystem = same_device(&source_dir, &destination_dir);
+    let warnings = warnings(&source_report, &destination_report, same_filesystem);
+    Ok(DoctorReport {
+        source: source_report,
+        destination: destination_report,
+        same_filesystem,
+        warnings,
+    })
+}
+
+/// `path` itself when it is a directory, else the nearest ancestor that is.
+pub(crate) fn existing_dir(path: &Path) -> io::Result<PathBuf> {
+    let absolute = std::path::absolute(path)?;
+    absolute
+        .ancestors()
+        .find(|dir| dir.is_dir())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no existing directory above '{}'", path.display()),
+            )
+        })
+}
+
+/// The checks that don't write anything.
+fn probe_read_only(source: &Path, dir: &Path) -> Vec<Check> {
+    let mut checks = vec![
+        Check::from_result(Capability::Xattr, xattr_readable(source)),
+        acl_check(dir),
+        match case_sensitive_by_lookup(source) {
+            Some(sensitive) => Check::from_result(Capability::CaseSensitive, sensitive),
+            None => Check::new(
+                Capability::CaseSensitive,
+                Support::Unknown,
+                "the path has no letters to look up in another case".to_string(),
+            ),
+        },
+    ];
+    checks.extend(limits(dir));
+    checks
+}
+
+/// Every check, run in a scratch directory under `dir`.
+fn probe_writable(dir: &Path) -> Vec<Check> {
+    let scratch = dir.join(format!(".cpx-doctor-{}", std::process::id()));
+    if let Err(e) = fs::create_dir(&scratch) {
+        let reason = format!("cannot write to '{}': {}", dir.display(), e);
+        let mut checks: Vec<Check> = [
+            Capability::Reflink,
+            Capability::Sparse,
+            Capability::Xattr,
+            Capability::Hardlink,
+            Capability::CaseSensitive,
+            Capability::DirectIo,
+        ]
+        .into_iter()
+        .map(|capability| Check::new(capability, Support::Unknown, reason.clone()))
+        .collect();
+        checks.push(acl_check(dir));
+        checks.extend(limits(dir));
+        return checks;
+    }
+    let checks = probe_in(&scratch, dir);
+    let _ = fs::remove_dir_all(&scratch);
+    checks
+}
+
+fn probe_in(scratch: &Path, dir: &Path) -> Vec<Check> {
+    let sample = scratch.join("sample");
+    let written = File::create(&sample).and_then(|mut file| file.write_all(&[0x5a; 64 * 1024]));
+    let with_sample =
+        |capability: Capability, probe: &dyn Fn(&Path) -> io::Result<bool>| match &written {
+            Ok(()) => Check::from_result(capability, probe(&sample)),
+            Err(e) => Check::new(capability, Support::Unknown, e.to_string()),
+        };
+
+    let mut checks = vec![
+        with_sample(Capability::Reflink, &|sample| {
+            reflink_copy::reflink(sample, scratch.join("reflink")).map(|()| true)
+        }),
+        Check::from_result(Capability::Sparse, sparse(&scratch.join("sparse"))),
+        with_sample(Capability::Xattr, &xattr_writable),
+        acl_check(dir),
+        with_sample(Capability::Hardlink, &|sample| {
+            fs::hard_link(sample, scratch.join("hardlink")).map(|()| true)
+        }),
+        Check::from_result(Capability::CaseSensitive, case_sensitive(scratch)),
+    ];
+    checks.extend(limits(dir));
+    checks.push(with_sample(Capability::DirectIo, &direct_io));
+    checks
+}
+
+/// Errors that mean the filesystem doesn't do it, rather than that the
+/// probe went wrong.
+fn unsupported(e: &io::Error) -> bool {
+    if matches!(
+        e.kind(),
+        io::ErrorKind::Unsupported | io::ErrorKind::CrossesDevices
+    ) {
+        return true;
+    }
+    #[cfg(unix)]
+    if matches!(
+        e.raw_os_error(),
+        Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL | libc::EPERM | libc::EMLINK)
+    ) {
+        return true;
+    }
+    false
+}
+
+#[cfg(unix)]
+fn sparse(path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::{FileExt, MetadataExt};
+
+    const LEN: u64 = 16 * 1024 * 1024;
+    let file = File::create(path)?;
+    // a hole of 16 MiB, then one block of data
+    file.write_all_at(&[1; 4096], LEN - 4096)?;
+    file.sync_all()?;
+    Ok(file.metadata()?.blocks() * 512 < LEN / 2)
+}
+
+#[cfg(not(unix))]
+fn sparse(_path: &Path) -> io::Result<bool> {
+    Err(io::Error::other("not checked on this platform"))
+}
+
+fn xattr_readable(path: &Path) -> io::Result<bool> {
+    if !xattr::SUPPORTED_PLATFORM {
+        return Ok(false);
+    }
+    xattr::list(path).map(|_| true)
+}
+
+fn xattr_writable(path: &Path) -> io::Result<bool> {
+    if !xattr::SUPPORTED_PLATFORM {
+        return Ok(false);
+    }
+    xattr::set(path, "user.cpx-doctor", b"1")?;
+    Ok(xattr::get(path, "user.cpx-doctor")?.is_some())
+}
+
+/// POSIX ACLs, which `-p xattr` carries as `system.posix_acl_*` attributes.
+#[cfg(target_os = "linux")]
+fn acl_check(dir: &Path) -> Check {
+    // a filesystem with ACLs turned off refuses the name; one with them
+    // reports it as absent or returns the directory's
+    Check::from_result(
+        Capability::Acl,
+        xattr::get(dir, "system.posix_acl_access").map(|_| true),
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn acl_check(_dir: &Path) -> Check {
+    Check::new(
+        Capability::Acl,
+        Support::Unknown,
+        "not checked on this platform".to_string(),
+    )
+}
+
+fn case_sensitive(scratch: &Path) -> io::Result<bool> {
+    File::create(scratch.join("Case"))?;
+    match fs::symlink_metadata(scratch.join("CASE")) {
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Case sensitivity without writing: looks `path` up with the case of its
+/// name flipped. None when the name has no letters.
+pub(crate) fn case_sensitive_by_lookup(path: &Path) -> Option<io::Result<bool>> {
+    let absolute = std::path::absolute(path).ok()?;
+    let name = absolute.file_name()?.to_str()?;
+    let flipped: String = name
+        .chars()
+        .map(|c| {
+            if c.is_lowercase() {
+                c.to_uppercase().next().unwrap_or(c)
+            } else {
+                c.to_lowercase().next().unwrap_or(c)
+            }
+        })
+        .collect();
+    if flipped == name {
+        return None;
+    }
+    let other = absolute.with_file_name(flipped);
+    Some(match fs::symlink_metadata(&other) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e),
+        // the flipped name may be a different entry of its own
+        Ok(_) => same_file(&absolute, &other)
+            .map(|same| !same)
+            .ok_or_else(|| io::Error::other("cannot compare files on this platform")),
+    })
+}
+
+fn limits(dir: &Path) -> [Check; 2] {
+    [
+        Check::limit(Capability::NameMax, path_limit(dir, Limit::Name)),
+        Check::limit(Capability::PathMax, path_limit(dir, Limit::Path)),
+    ]
+}
+
+pub(crate) enum Limit {
+    Name,
+    Path,
+}
+
+#[cfg(unix)]
+pub(crate) fn path_limit(dir: &Path, limit: Limit) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let name = match limit {
+        Limit::Name => libc::_PC_NAME_MAX,
+        Limit::Path => libc::_PC_PATH_MAX,
+    };
+    // -1 without an error means there is no limit
+    let value = unsafe { libc::pathconf(path.as_ptr(), name) };
+    u64::try_from(value).ok()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn path_limit(_dir: &Path, _limit: Limit) -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn direct_io(path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    File::options()
+        .read(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+        .map(|_| true)
+}
+
+#[cfg(target_os = "macos")]
+fn direct_io(path: &Path) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    // macOS has no O_DIRECT; F_NOCACHE bypasses the cache the same way
+    let file = File::open(path)?;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(true)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn direct_io(_path: &Path) -> io::Result<bool> {
+    Err(io::Error::other("not checked on this platform"))
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (a, b) = (fs::symlink_metadata(a).ok()?, fs::symlink_metadata(b).ok()?);
+    Some(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> Option<bool> {
+    None
+}
+
+#[cfg(unix)]
+fn same_device(a: &Path, b: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(fs::metadata(a).ok()?.dev() == fs::metadata(b).ok()?.dev())
+}
+
+#[cfg(not(unix))]
+fn same_device(_a: &Path, _b: &Path) -> Option<bool> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn filesystem_name(dir: &Path) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    const NAMES: &[(u32, &str)] = &[
+        (0xEF53, "ext4"),
+        (0x9123_683E, "btrfs"),
+        (0x5846_5342, "xfs"),
+        (0x2FC1_2FC1, "zfs"),
+        (0xF2F5_2010, "f2fs"),
+        (0x0102_1994, "tmpfs"),
+        (0x794C_7630, "overlayfs"),
+        (0x6969, "nfs"),
+        (0xFF53_4D42, "cifs"),
+        (0xFE53_4D42, "smb2"),
+        (0x6573_5546, "fuse"),
+        (0x4D44, "vfat"),
+        (0x2011_BAB0, "exfat"),
+        (0x7366_746E, "ntfs3"),
+        (0x0102_1997, "9p"),
+    ];
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // the field's width varies by architecture; every magic fits 32 bits
+    let magic = stat.f_type as u32;
+    Some(
+        NAMES
+            .iter()
+            .find(|(known, _)| *known == magic)
+            .map_or_else(|| format!("0x{:x}", magic), |(_, name)| name.to_string()),
+    )
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn filesystem_name(dir: &Path) -> Option<String> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn filesystem_name(_dir: &Path) -> Option<String> {
+    None
+}
+
+/// What copying from `source` to `destination` can't keep, or will fail
+/// at, given what each side supports.
+fn warnings(source: &FsReport, destination: &FsReport, same: Option<bool>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut warn = |warning: &str| warnings.push(warning.to_string());
+    let dst = |capability| destination.support(capability);
+    let src = |capability| source.support(capability);
+
+    if same == Some(false) {
+        warn(
+            "reflinks: source and destination are on different filesystems, so \
+             --reflink=always fails and the data is copied",
+        );
+    } else if dst(Capability::Reflink) == Support::No {
+        warn("reflinks: --reflink=always fails, and -p extents shares no blocks");
+    }
+    if dst(Capability::Sparse) == Support::No {
+        warn("sparse files: holes take up real space on the destination");
+    }
+    if dst(Capability::Xattr) == Support::No && src(Capability::Xattr) != Support::No {
+        warn("-p xattr: extended attributes are dropped");
+    }
+    if dst(Capability::Acl) == Support::No && src(Capability::Acl) == Support::Yes {
+        warn("-p xattr: POSIX ACLs are dropped");
+    }
+    if dst(Capability::Hardlink) == Support::No {
+        warn(
+            "-p links and --link: hard links can't be created, so linked files are copied apart or fail",
+        );
+    }
+    if src(Capability::CaseSensitive) != Support::No
+        && dst(Capability::CaseSensitive) == Support::No
+    {
+        warn("names that differ only in case collide on the destination");
+    }
+    let limit = |report: &FsReport, capability| report.check(capability).and_then(|c| c.value);
+    if let (Some(src_max), Some(dst_max)) = (
+        limit(source, Capability::NameMax),
+        limit(destination, Capability::NameMax),
+    ) && dst_max < src_max
+    {
+        warnings.push(format!(
+            "names longer than {} bytes can't be created on the destination",
+            dst_max
+        ));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_doctor_probes_and_cleans_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("Source");
+        fs::create_dir(&source).unwrap();
+        let destination = temp_dir.path().join("not/yet/there");
+
+        let report = run_doctor(&source, &destination).unwrap();
+        assert_eq!(report.destination.probed, temp_dir.path());
+        assert_eq!(
+            report.destination.support(Capability::Hardlink),
+            Support::Yes
+        );
+        assert_ne!(
+            report.destination.support(Capability::CaseSensitive),
+            Support::Unknown
+        );
+        assert_eq!(report.same_filesystem.is_some(), cfg!(unix));
+        #[cfg(unix)]
+        assert!(
+            report
+                .destination
+                .check(Capability::NameMax)
+                .unwrap()
+                .value
+                .is_some()
+        );
+        // nothing is left behind
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        assert!(report.to_text().contains("Destination:"));
+
+        assert!(run_doctor(&temp_dir.path().join("missing"), &destination).is_err());
+    }
+
+    #[test]
+    fn test_warnings_follow_what_the_destination_lacks() {
+        let report = |checks: Vec<Check>| FsReport {
+            path: PathBuf::from("/x"),
+            probed: PathBuf::from("/x"),
+            filesystem: None,
+            checks,
+        };
+        let source = report(vec![
+            Check::new(Capability::CaseSensitive, Support::Yes, None),
+            Check::limit(Capability::NameMax, Some(255)),
+        ]);
+        let destination = report(vec![
+            Check::new(Capability::Hardlink, Support::No, None),
+            Check::new(Capability::CaseSensitive, Support::No, None),
+            Check::limit(Capability::NameMax, Some(143)),
+        ]);
+
+        let lost = warnings(&source, &destination, Some(true));
+        assert_eq!(lost.len(), 3, "{:?}", lost);
+        assert!(lost.iter().any(|w| w.contains("143 bytes")));
+        assert!(warnings(&source, &source, Some(true)).is_empty());
+    }
+}
@@ -0,0 +1,807 @@
+use crate::cli::args::CopyOptions;
+use crate::core::archive::ArchiveFormat;
+use crate::core::copy::{
+    checkpoint_error, create_destination, failures_to_result, finish_run, prepare_destination,
+    remove_incomplete, report_bytes,
+};
+use crate::error::{CopyError, CopyResult, FailedFile};
+use crate::utility::control::Checkpoint;
+use crate::utility::events::CopyEvent;
+use crate::utility::exclude::should_exclude;
+use crate::utility::helper::{create_symlink, sync_parent_dirs};
+use crate::utility::output;
+use crate::utility::preprocess::{SymlinkKind, SymlinkTask};
+use crate::utility::preserve::{ArchivedAttrs, apply_archived_attrs};
+use crate::utility::reporter::{self, ProgressReporter};
+use crate::utility::stats::CopyStats;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Instant, UNIX_EPOCH};
+
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// How an archive source is packed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveSource {
+    Tar(ArchiveFormat),
+    Zip,
+}
+
+impl ArchiveSource {
+    /// Recognises `.tar`, `.tar.zst`, `.tzst` and `.zip` files.
+    pub fn detect(path: &Path) -> Option<Self> {
+        if !path.is_file() {
+            return None;
+        }
+        if let Some(format) = ArchiveFormat::from_path(path) {
+            return Some(ArchiveSource::Tar(format));
+        }
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+            .then_some(ArchiveSource::Zip)
+    }
+}
+
+/// Whether `source` is unpacked rather than copied: archives are treated as
+/// directories by a recursive copy.
+pub fn is_archive_source(source: &Path, options: &CopyOptions) -> bool {
+    options.recursive && ArchiveSource::detect(source).is_some()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Directory,
+    Symlink(PathBuf),
+    /// Target is another entry of the same archive.
+    Hardlink(PathBuf),
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    kind: EntryKind,
+    size: u64,
+    attrs: ArchivedAttrs,
+}
+
+enum Disposition {
+    Extract,
+    Excluded,
+    UpToDate,
+}
+
+/// Unpacks every archive in `sources` into `destination`, honouring excludes,
+/// --resume, the overwrite flags and --preserve like a directory copy would.
+pub fn extract_archives(
+    sources: &[PathBuf],
+    destination: &Path,
+    options: &CopyOptions,
+    stats: &CopyStats,
+) -> CopyResult<()> {
+    let archives = sources
+        .iter()
+        .map(|source| {
+            ArchiveSource::detect(source)
+                .map(|packing| (source.as_path(), packing))
+                .ok_or_else(|| CopyError::CopyFailed {
+                    source: source.clone(),
+                    destination: destination.to_path_buf(),
+                    reason: "archives cannot be extracted together with other sources".to_string(),
+                })
+        })
+        .collect::<CopyResult<Vec<_>>>()?;
+    if destination.is_file() {
+        return Err(CopyError::InvalidDestination(destination.to_path_buf()));
+    }
+
+    let reporter = reporter::for_options(options);
+    reporter.scan_started(&sources.iter().map(PathBuf::as_path).collect::<Vec<_>>());
+
+    // entries can only be counted by reading the archive once beforehand
+    let (mut total_files, mut total_bytes, mut skipped_files, mut skipped_bytes) = (0, 0, 0, 0);
+    for (archive, packing) in &archives {
+        for_each_entry(archive, *packing, true, |entry, _| {
+            let counted = matches!(entry.kind, EntryKind::File | EntryKind::Hardlink(_));
+            match disposition(archive, destination, &entry, options) {
+                Disposition::Extract if counted => {
+                    total_files += 1;
+                    total_bytes += entry.size;
+                }
+                Disposition::UpToDate => {
+                    skipped_files += 1;
+                    skipped_bytes += entry.size;
+                }
+                _ => {}
+            }
+            Ok(())
+        })
+        .map_err(|e| archive_error(archive, destination, e))?;
+    }
+
+    if let Some(logger) = &options.logger {
+        logger.debug(&format!(
+            "planned {} files ({} bytes) from {} archives, {} skipped",
+            total_files,
+            total_bytes,
+            archives.len(),
+            skipped_files
+        ));
+    }
+    reporter.scan_finished(total_files, total_bytes, skipped_files);
+    stats.record_skipped(skipped_files);
+    options.events.emit(|| CopyEvent::Scanned {
+        total_files,
+        total_bytes,
+        skipped_files,
+    });
+    if skipped_files > 0 {
+        output::warn(
+            options,
+            format!("Skipping {} files that already exist", skipped_files),
+        );
+    }
+    reporter.begin(total_files, total_bytes, skipped_files, skipped_bytes);
+    options.control.set_totals(total_files, total_bytes);
+
+    let mut extraction = Extraction {
+        destination: destination.to_path_buf(),
+        root: PathBuf::new(),
+        options,
+        stats,
+        reporter: reporter.as_ref(),
+        failures: Vec::new(),
+        directories: Vec::new(),
+        written: vec![destination.to_path_buf()],
+    };
+    let result = (|| -> CopyResult<()> {
+        std::fs::create_dir_all(destination)?;
+        extraction.root = destination.canonicalize()?;
+        for (archive, packing) in &archives {
+            for_each_entry(archive, *packing, false, |entry, reader| {
+                extraction.extract(archive, entry, reader)
+            })
+            .map_err(|e| archive_error(archive, destination, e))?;
+        }
+        Ok(())
+    })();
+    extraction.finish_directories();
+    let result = result.and_then(|()| {
+        if options.fsync.dirs() {
+            sync_parent_dirs(extraction.written.iter().map(PathBuf::as_path))?;
+        }
+        Ok(())
+    });
+
+    let result = match result {
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+            if let Some(limit) = options
+                .max_errors
+                .filter(|limit| stats.files_failed() > *limit)
+            {
+                reporter.abandon("Stopped after too many errors");
+                Err(CopyError::ErrorLimitExceeded {
+                    limit,
+                    failures: extraction.failures,
+                })
+            } else {
+                reporter.abandon("Interrupted");
+                Err(e)
+            }
+        }
+        Err(e) => {
+            reporter.abandon("Archive incomplete");
+            Err(e)
+        }
+        Ok(()) if extraction.failures.is_empty() => {
+            reporter.finish("Done");
+            Ok(())
+        }
+        Ok(()) => {
+            reporter.abandon("Completed with errors");
+            failures_to_result(extraction.failures)
+        }
+    };
+    finish_run(result, stats, options, reporter.as_ref())
+}
+
+fn archive_error(archive: &Path, destination: &Path, e: CopyError) -> CopyError {
+    match e {
+        CopyError::Io(io_err) if io_err.kind() != io::ErrorKind::Interrupted => {
+            CopyError::CopyFailed {
+                source: archive.to_path_buf(),
+                destination: destination.to_path_buf(),
+                reason: format!("unreadable archive: {}", io_err),
+            }
+        }
+        e => e,
+    }
+}
+
+/// Where an entry lands under `destination`, or `None` when its name is
+/// absolute or climbs out with `..`.
+fn enclosed_path(destination: &Path, name: &Path) -> Option<PathBuf> {
+    let mut path = destination.to_path_buf();
+    let mut depth = 0;
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => {
+                path.push(part);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (depth > 0).then_some(path)
+}
+
+fn disposition(
+    archive: &Path,
+    destination: &Path,
+    entry: &Entry,
+    options: &CopyOptions,
+) -> Disposition {
+    // an excluded directory takes its contents with it
+    if let Some(rules) = &options.exclude_rules
+        && entry
+            .path
+            .ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .any(|path| should_exclude(&archive.join(path), archive, rules))
+    {
+        return Disposition::Excluded;
+    }
+    if options.resume
+        && entry.kind == EntryKind::File
+        && let Some(target) = enclosed_path(destination, &entry.path)
+        && let Ok(metadata) = std::fs::symlink_metadata(&target)
+        && metadata.is_file()
+        && metadata.len() == entry.size
+        && entry.attrs.mtime.is_none_or(|mtime| {
+            metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .is_some_and(|since| since.as_secs() as i64 == mtime)
+        })
+    {
+        return Disposition::UpToDate;
+    }
+    Disposition::Extract
+}
+
+/// Calls `visit` with each entry in archive order. With `headers_only` the
+/// reader may be empty, which lets plain tar and zip skip over file data.
+fn for_each_entry(
+    archive: &Path,
+    packing: ArchiveSource,
+    headers_only: bool,
+    mut visit: impl FnMut(Entry, &mut dyn Read) -> CopyResult<()>,
+) -> CopyResult<()> {
+    let file = BufReader::new(File::open(archive)?);
+    match packing {
+        ArchiveSource::Tar(ArchiveFormat::Tar) if headers_only => {
+            visit_tar(tar::Archive::new(file).entries_with_seek()?, visit)
+        }
+        ArchiveSource::Tar(ArchiveFormat::Tar) => {
+            visit_tar(tar::Archive::new(file).entries()?, visit)
+        }
+        ArchiveSource::Tar(ArchiveFormat::TarZst) => {
+            let decoder = zstd::Decoder::with_buffer(file)?;
+            visit_tar(tar::Archive::new(decoder).entries()?, visit)
+        }
+        ArchiveSource::Zip => {
+            let mut zip = zip::ZipArchive::new(file).map_err(io::Error::from)?;
+            for index in 0..zip.len() {
+                let mut file = zip.by_index(index).map_err(io::Error::from)?;
+                let path = PathBuf::from(file.name().map_err(io::Error::from)?.as_ref());
+                let kind = if file.is_dir() {
+                    EntryKind::Directory
+                } else if file.is_symlink() {
+                    let mut target = String::new();
+                    file.read_to_string(&mut target)?;
+                    EntryKind::Symlink(PathBuf::from(target))
+                } else {
+                    EntryKind::File
+                };
+                let mtime = file
+                    .extra_data_fields()
+                    .find_map(|field| match field {
+                        zip::ExtraField::ExtendedTimestamp(ts) => ts.mod_time(),
+                        _ => None,
+                    })
+                    .map(i64::from)
+                    .or_else(|| file.last_modified().and_then(dos_time));
+                let entry = Entry {
+                    path,
+                    size: if kind == EntryKind::File {
+                        file.size()
+                    } else {
+                        0
+                    },
+                    kind,
+                    attrs: ArchivedAttrs {
+                        mode: file.unix_mode(),
+                        mtime,
+                        uid: None,
+                        gid: None,
+                    },
+                };
+                visit(entry, &mut file)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn visit_tar<R: Read>(
+    entries: tar::Entries<'_, R>,
+    mut visit: impl FnMut(Entry, &mut dyn Read) -> CopyResult<()>,
+) -> CopyResult<()> {
+    for entry in entries {
+        let mut entry = entry?;
+        let link = || -> io::Result<PathBuf> {
+            Ok(entry
+                .link_name()?
+                .ok_or_else(|| io::Error::other("link entry without a target"))?
+                .into_owned())
+        };
+        let kind = match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+            tar::EntryType::Directory => EntryKind::Directory,
+            tar::EntryType::Symlink => EntryKind::Symlink(link()?),
+            tar::EntryType::Link => EntryKind::Hardlink(link()?),
+            // devices and fifos aren't copied from the filesystem either
+            _ => continue,
+        };
+        let header = entry.header();
+        let attrs = ArchivedAttrs {
+            mode: header.mode().ok(),
+            mtime: header.mtime().ok().map(|mtime| mtime as i64),
+            uid: header.uid().ok().and_then(|uid| u32::try_from(uid).ok()),
+            gid: header.gid().ok().and_then(|gid| u32::try_from(gid).ok()),
+        };
+        let entry_meta = Entry {
+            path: entry.path()?.into_owned(),
+            size: if kind == EntryKind::File {
+                entry.size()
+            } else {
+                0
+            },
+            kind,
+            attrs,
+        };
+        visit(entry_meta, &mut entry)?;
+    }
+    Ok(())
+}
+
+// zip stores local time without a zone, as unzip assumes
+#[cfg(unix)]
+fn dos_time(time: zip::DateTime) -> Option<i64> {
+    // SAFETY: an all-zero tm is valid and mktime only reads and normalises it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = i32::from(time.year()) - 1900;
+    tm.tm_mon = i32::from(time.month()) - 1;
+    tm.tm_mday = time.day().into();
+    tm.tm_hour = time.hour().into();
+    tm.tm_min = time.minute().into();
+    tm.tm_sec = time.second().into();
+    tm.tm_isdst = -1;
+    let seconds = unsafe { libc::mktime(&mut tm) };
+    (seconds != -1).then_some(seconds as i64)
+}
+
+#[cfg(not(unix))]
+fn dos_time(_time: zip::DateTime) -> Option<i64> {
+    None
+}
+
+struct Extraction<'a> {
+    destination: PathBuf,
+    /// Canonical destination; nothing may be written outside it.
+    root: PathBuf,
+    options: &'a CopyOptions,
+    stats: &'a CopyStats,
+    reporter: &'a dyn ProgressReporter,
+    failures: Vec<FailedFile>,
+    directories: Vec<(PathBuf, ArchivedAttrs)>,
+    /// Entries whose directories are synced at the end with --fsync=dir|all.
+    written: Vec<PathBuf>,
+}
+
+impl Extraction<'_> {
+    /// Unpacks one entry. Only an abort ends the run; anything else is
+    /// recorded as a failure of that entry.
+    fn extract(&mut self, archive: &Path, entry: Entry, reader: &mut dyn Read) -> CopyResult<()> {
+        let checkpoint = self.options.control.checkpoint();
+        if checkpoint == Checkpoint::Abort {
+            return Err(checkpoint_error(checkpoint, archive));
+        }
+        // shown as `backup.tar/dir/file` in output and logs
+        let source = archive.join(&entry.path);
+        if !matches!(
+            disposition(archive, &self.destination, &entry, self.options),
+            Disposition::Extract
+        ) {
+            return Ok(());
+        }
+        let Some(destination) = enclosed_path(&self.destination, &entry.path) else {
+            let destination = self.destination.clone();
+            let e = CopyError::CopyFailed {
+                source: source.clone(),
+                destination: destination.clone(),
+                reason: "entry path leaves the destination".to_string(),
+            };
+            self.fail(&source, &destination, e);
+            return Ok(());
+        };
+        if self.options.fsync.dirs() {
+            self.written.push(destination.clone());
+        }
+
+        let result = match &entry.kind {
+            EntryKind::Directory => {
+                let created = self
+                    .ensure_inside(&destination)
+                    .and_then(|()| Ok(std::fs::create_dir_all(&destination)?));
+                if created.is_ok() {
+                    self.directories.push((destination.clone(), entry.attrs));
+                }
+                created
+            }
+            EntryKind::Symlink(target) => self.extract_symlink(target, &destination),
+            EntryKind::Hardlink(_) | EntryKind::File => {
+                return self.extract_file(&source, &destination, &entry, reader);
+            }
+        };
+        if let Err(e) = result {
+            self.fail(&source, &destination, e);
+        }
+        Ok(())
+    }
+
+    fn extract_file(
+        &mut self,
+        source: &Path,
+        destination: &Path,
+        entry: &Entry,
+        reader: &mut dyn Read,
+    ) -> CopyResult<()> {
+        self.reporter.file_begin(source, destination, entry.size);
+        self.options.events.emit(|| CopyEvent::Started {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            size: entry.size,
+        });
+        self.options.control.start_file(source, entry.size);
+        let started = Instant::now();
+
+        let result = match &entry.kind {
+            EntryKind::Hardlink(target) => self.extract_hardlink(target, destination),
+            _ => self.write_file(source, destination, entry, reader),
+        };
+        match result {
+            Ok(true) => {
+                self.options.control.finish_file(None);
+                self.stats.record_copied();
+                self.stats.record_duration(source, started.elapsed());
+                self.options.events.emit(|| CopyEvent::Finished {
+                    source: source.to_path_buf(),
+                    destination: destination.to_path_buf(),
+                });
+                self.reporter.file_done(source, destination, None);
+                output::copied(self.options, self.reporter, source, destination);
+                Ok(())
+            }
+            Ok(false) => {
+                self.options.control.finish_file(None);
+                self.reporter.file_skipped(source, "not overwritten");
+                Ok(())
+            }
+            Err(CopyError::Skipped(_)) => {
+                self.options.control.finish_file(None);
+                self.stats.record_skipped(1);
+                self.options.events.emit(|| CopyEvent::Skipped {
+                    source: source.to_path_buf(),
+                    reason: "skipped by user".to_string(),
+                });
+                self.reporter.file_skipped(source, "skipped by user");
+                output::skipped(self.options, source, "skipped by user");
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                self.options.control.finish_file(None);
+                self.reporter.file_failed(source, destination, &e);
+                Err(e)
+            }
+            Err(e) => {
+                self.fail(source, destination, e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns false when --interactive declined the overwrite.
+    fn write_file(
+        &self,
+        source: &Path,
+        destination: &Path,
+        entry: &Entry,
+        reader: &mut dyn Read,
+    ) -> CopyResult<bool> {
+        self.ensure_inside(destination)?;
+        // never write through a link planted by an earlier entry
+        if std::fs::symlink_metadata(destination).is_ok_and(|meta| meta.is_symlink()) {
+            std::fs::remove_file(destination)?;
+        }
+        if !prepare_destination(destination, self.options, self.reporter)? {
+            return Ok(false);
+        }
+        let mut file = create_destination(None, destination, self.options)?;
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            let checkpoint = self.options.control.checkpoint();
+            if checkpoint != Checkpoint::Continue {
+                drop(file);
+                remove_incomplete(destination, self.options);
+                return Err(checkpoint_error(checkpoint, source));
+            }
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buffer[..read])?;
+            report_bytes(self.reporter, self.stats, source, read as u64, self.options);
+        }
+        file.flush()?;
+        if self.options.fsync.files() {
+            file.sync_all()?;
+        }
+        drop(file);
+        apply_archived_attrs(destination, &entry.attrs, self.options.preserve)?;
+        Ok(true)
+    }
+
+    fn extract_hardlink(&self, target: &Path, destination: &Path) -> CopyResult<bool> {
+        let target =
+            enclosed_path(&self.destination, target).ok_or_else(|| CopyError::HardlinkFailed {
+                source: target.to_path_buf(),
+                destination: destination.to_path_buf(),
+            })?;
+        self.ensure_inside(destination)?;
+        if std::fs::symlink_metadata(destination).is_ok() {
+            if !prepare_destination(destination, self.options, self.reporter)? {
+                return Ok(false);
+            }
+            let _ = std::fs::remove_file(destination);
+        }
+        std::fs::hard_link(&target, destination).map_err(|_e| CopyError::HardlinkFailed {
+            source: target,
+            destination: destination.to_path_buf(),
+        })?;
+        self.stats.record_hardlink();
+        Ok(true)
+    }
+
+    fn extract_symlink(&self, target: &Path, destination: &Path) -> CopyResult<()> {
+        self.ensure_inside(destination)?;
+        if std::fs::symlink_metadata(destination).is_ok_and(|meta| !meta.is_dir()) {
+            std::fs::remove_file(destination)?;
+        }
+        create_symlink(&SymlinkTask {
+            source: target.to_path_buf(),
+            destination: destination.to_path_buf(),
+            kind: SymlinkKind::PreserveExact,
+            origin: None,
+        })
+        .map_err(|_e| CopyError::SymlinkFailed {
+            source: target.to_path_buf(),
+            destination: destination.to_path_buf(),
+        })?;
+        self.stats.record_symlink();
+        Ok(())
+    }
+
+    /// Creates the parent of `path` and checks that symlinks unpacked earlier
+    /// don't redirect it outside the destination.
+    fn ensure_inside(&self, path: &Path) -> CopyResult<()> {
+        let parent = path.parent().unwrap_or(&self.destination);
+        std::fs::create_dir_all(parent)?;
+        if parent.canonicalize()?.starts_with(&self.root) {
+            Ok(())
+        } else {
+            Err(CopyError::CopyFailed {
+                source: path.to_path_buf(),
+                destination: self.destination.clone(),
+                reason: "entry path leaves the destination".to_string(),
+            })
+        }
+    }
+
+    fn fail(&mut self, source: &Path, destination: &Path, e: CopyError) {
+        self.options
+            .control
+            .finish_file(Some(format!("{}: {}", source.display(), e)));
+        self.reporter.file_failed(source, destination, &e);
+        self.stats.record_failed();
+        self.options.events.emit(|| CopyEvent::Failed {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            category: e.category(),
+            error: e.to_string(),
+        });
+        if let Some(logger) = &self.options.logger {
+            logger.failed(source, destination, &e.to_string());
+        }
+        if self
+            .options
+            .max_errors
+            .is_some_and(|limit| self.stats.files_failed() > limit)
+        {
+            self.options.control.abort();
+        }
+        self.failures.push(FailedFile::new(source, destination, e));
+    }
+
+    /// Directory modes and times are applied last, once nothing more is
+    /// written into them, innermost first.
+    fn finish_directories(&mut self) {
+        for (path, attrs) in self.directories.iter().rev() {
+            if let Err(e) = apply_archived_attrs(path, attrs, self.options.preserve)
+                && let Some(logger) = &self.options.logger
+            {
+                logger.warning(path, &e.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::exclude::{ExcludePattern, build_exclude_rules};
+    use crate::utility::output::Verbosity;
+    use crate::utility::preserve::PreserveAttr;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn options() -> CopyOptions {
+        let mut options = CopyOptions::none();
+        options.recursive = true;
+        options.verbosity = Verbosity::Silent;
+        options
+    }
+
+    fn write_tar(path: &Path, entries: &[(&str, &str)]) {
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o750);
+            header.set_mtime(1_000_000_000);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_detect_archive_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let tar = temp_dir.path().join("backup.tar.zst");
+        let zip = temp_dir.path().join("backup.ZIP");
+        fs::write(&tar, "").unwrap();
+        fs::write(&zip, "").unwrap();
+        assert_eq!(
+            ArchiveSource::detect(&tar),
+            Some(ArchiveSource::Tar(ArchiveFormat::TarZst))
+        );
+        assert_eq!(ArchiveSource::detect(&zip), Some(ArchiveSource::Zip));
+        // a directory named like an archive is copied as usual
+        let dir = temp_dir.path().join("dir.tar");
+        fs::create_dir(&dir).unwrap();
+        assert_eq!(ArchiveSource::detect(&dir), None);
+        assert!(!is_archive_source(&tar, &CopyOptions::none()));
+    }
+
+    #[test]
+    fn test_extract_tar_with_excludes_and_attributes() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("backup.tar");
+        write_tar(
+            &archive,
+            &[
+                ("./data/a.txt", "alpha"),
+                ("data/cache/big.bin", "skip"),
+                ("data/b.tmp", "tmp"),
+            ],
+        );
+        let destination = temp_dir.path().join("restore");
+        let mut options = options();
+        options.preserve = PreserveAttr::default();
+        options.exclude_rules = build_exclude_rules(vec![
+            ExcludePattern::from_string("cache"),
+            ExcludePattern::from_string("*.tmp"),
+        ])
+        .unwrap();
+        let stats = CopyStats::new();
+
+        extract_archives(
+            std::slice::from_ref(&archive),
+            &destination,
+            &options,
+            &stats,
+        )
+        .unwrap();
+
+        let extracted = destination.join("data/a.txt");
+        assert_eq!(fs::read_to_string(&extracted).unwrap(), "alpha");
+        assert!(!destination.join("data/cache").exists());
+        assert!(!destination.join("data/b.tmp").exists());
+        assert_eq!(stats.files_copied(), 1);
+        let metadata = fs::metadata(&extracted).unwrap();
+        assert_eq!(
+            filetime::FileTime::from_last_modification_time(&metadata).unix_seconds(),
+            1_000_000_000
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o750);
+        }
+
+        // unchanged files are skipped on a resumed run
+        let mut options = options;
+        options.resume = true;
+        let stats = CopyStats::new();
+        extract_archives(&[archive], &destination, &options, &stats).unwrap();
+        assert_eq!(stats.files_copied(), 0);
+        assert_eq!(stats.files_skipped(), 1);
+    }
+
+    #[test]
+    fn test_extract_rejects_escaping_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("evil.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let entry = zip::write::SimpleFileOptions::default();
+        writer.start_file("../outside.txt", entry).unwrap();
+        writer.write_all(b"x").unwrap();
+        writer.start_file("inside.txt", entry).unwrap();
+        writer.write_all(b"ok").unwrap();
+        writer.finish().unwrap();
+        let destination = temp_dir.path().join("restore");
+
+        let result = extract_archives(&[archive], &destination, &options(), &CopyStats::new());
+
+        assert!(matches!(result, Err(CopyError::PartialFailure(ref f)) if f.len() == 1));
+        assert!(!temp_dir.path().join("outside.txt").exists());
+        assert_eq!(
+            fs::read_to_string(destination.join("inside.txt")).unwrap(),
+            "ok"
+        );
+    }
+
+    #[test]
+    fn test_extract_refuses_mixed_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("backup.tar");
+        write_tar(&archive, &[("a.txt", "alpha")]);
+        let plain = temp_dir.path().join("plain.txt");
+        fs::write(&plain, "plain").unwrap();
+
+        let result = extract_archives(
+            &[archive, plain],
+            &temp_dir.path().join("restore"),
+            &options(),
+            &CopyStats::new(),
+        );
+
+        assert!(matches!(result, Err(CopyError::CopyFailed { .. })));
+    }
+}
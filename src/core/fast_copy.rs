@@ -1,25 +1,54 @@
-use crate::cli::args::CopyOptions;
+use crate::cli::args::{CopyBackend, CopyOptions};
+use crate::core::copy::{
+    abandon_incomplete, checkpoint_error, create_destination, report_bytes, trim_inplace,
+};
 use crate::error::{CopyError, CopyResult};
-use indicatif::ProgressBar;
-use nix::fcntl::copy_file_range;
+use crate::utility::control::Checkpoint;
+use crate::utility::helper::open_source;
+use crate::utility::reporter::ProgressReporter;
+use crate::utility::stats::CopyStats;
+use std::fs::File;
 use std::io;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
-use std::sync::atomic::Ordering;
 
+/// Files `--backend auto` maps: smaller ones don't repay setting up the
+/// mapping, and larger ones stream as fast through a buffer.
+const MAP_MIN: u64 = 1024 * 1024;
+const MAP_MAX: u64 = 64 * 1024 * 1024;
+/// Bytes written from a mapping between checkpoints.
+const MAP_CHUNK: usize = 4 * 1024 * 1024;
+
+/// Copies with the backend `--backend` picks. Ok(false) when none of them
+/// applies or works here, leaving the file to the buffered loop.
 pub fn fast_copy(
     source: &Path,
     destination: &Path,
     file_size: u64,
-    overall_pb: Option<&ProgressBar>,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
     options: &CopyOptions,
 ) -> CopyResult<bool> {
-    let src_file = std::fs::File::open(source).map_err(|e| CopyError::CopyFailed {
+    let (range, map) = match options.backend {
+        CopyBackend::Auto => (true, (MAP_MIN..=MAP_MAX).contains(&file_size)),
+        CopyBackend::CopyFileRange => (true, false),
+        CopyBackend::Mmap => (false, file_size > 0),
+        CopyBackend::Buffered => return Ok(false),
+    };
+    let range = range && cfg!(target_os = "linux");
+    if !range && !map {
+        return Ok(false);
+    }
+
+    let src_file = open_source(source).map_err(|e| CopyError::CopyFailed {
         source: source.to_path_buf(),
         destination: destination.to_path_buf(),
         reason: format!("Failed to open source file: {}", e),
     })?;
     if options.remove_destination {
-        let exists = std::fs::exists(destination).unwrap_or(false);
+        // a dangling symlink doesn't "exist", but is removed all the same
+        let exists = std::fs::symlink_metadata(destination).is_ok();
 
         if exists {
             std::fs::remove_file(destination).map_err(|e| CopyError::CopyFailed {
@@ -29,60 +58,264 @@ pub fn fast_copy(
             })?;
         }
     }
-    let dest_file = match std::fs::File::create(destination) {
-        Ok(file) => file,
-        Err(_e) if options.force => {
-            let _ = std::fs::remove_file(destination).map_err(|e| CopyError::CopyFailed {
-                source: source.to_path_buf(),
-                destination: destination.to_path_buf(),
-                reason: format!("Failed to remove destination: {}", e),
-            });
-            std::fs::File::create(destination).map_err(|e| CopyError::CopyFailed {
-                source: source.to_path_buf(),
-                destination: destination.to_path_buf(),
-                reason: format!("Failed to create destination: {}", e),
-            })?
-        }
-        Err(e) => return Err(CopyError::from(e)),
-    };
+    let dest_file = create_destination(Some(source), destination, options)?;
+
+    #[cfg(target_os = "linux")]
+    if range
+        && copy_range(
+            &src_file,
+            &dest_file,
+            source,
+            destination,
+            file_size,
+            reporter,
+            stats,
+            options,
+        )?
+    {
+        return Ok(true);
+    }
+    // whatever copy_file_range wrote before giving up is written over: the
+    // mapping is written out at its own offsets
+    if map && maps_safely(&src_file) {
+        return copy_mapped(
+            &src_file,
+            dest_file,
+            source,
+            destination,
+            file_size,
+            reporter,
+            stats,
+            options,
+        );
+    }
+    Ok(false)
+}
+
+#[cfg(target_os = "linux")]
+#[allow(clippy::too_many_arguments)]
+fn copy_range(
+    src_file: &File,
+    dest_file: &File,
+    source: &Path,
+    destination: &Path,
+    file_size: u64,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    options: &CopyOptions,
+) -> CopyResult<bool> {
+    use nix::fcntl::copy_file_range;
+
     const TARGET_UPDATES: u64 = 128;
     const MIN_CHUNK: usize = 4 * 1024 * 1024;
     let chunk_size = std::cmp::max(MIN_CHUNK, (file_size / TARGET_UPDATES) as usize);
     let mut total_copied = 0u64;
     loop {
-        if options.abort.load(Ordering::Relaxed) {
-            drop(dest_file); // Close file
-            if let Err(e) = std::fs::remove_file(destination) {
-                eprintln!(
-                    "Could not remove incomplete file {}: {}",
-                    destination.display(),
-                    e
-                );
-            } else {
-                eprintln!("Cleaned up incomplete file: {}", destination.display());
-            }
-            return Err(CopyError::Io(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "Operation aborted by user",
-            )));
+        let checkpoint = options.control.checkpoint();
+        if checkpoint != Checkpoint::Continue {
+            abandon_incomplete(destination, options);
+            return Err(checkpoint_error(checkpoint, source));
         }
 
         let to_copy = std::cmp::min(chunk_size, (file_size - total_copied) as usize);
         if to_copy == 0 {
             break;
         }
-        match copy_file_range(&src_file, None, &dest_file, None, to_copy) {
+        match copy_file_range(src_file, None, dest_file, None, to_copy) {
             Ok(0) => break,
             Ok(copied) => {
                 total_copied += copied as u64;
-                if let Some(pb) = overall_pb {
-                    pb.inc(copied as u64);
-                }
+                report_bytes(reporter, stats, source, copied as u64, options);
             }
             Err(_) => {
                 return Ok(false);
             }
         }
     }
+    trim_inplace(dest_file, file_size, options)?;
     Ok(true)
 }
+
+/// Writes the source out of a read-only mapping of it, so its pages go
+/// straight from the page cache to the destination without a copy into a
+/// buffer first.
+#[allow(clippy::too_many_arguments)]
+fn copy_mapped(
+    src_file: &File,
+    dest_file: File,
+    source: &Path,
+    destination: &Path,
+    file_size: u64,
+    reporter: &dyn ProgressReporter,
+    stats: &CopyStats,
+    options: &CopyOptions,
+) -> CopyResult<bool> {
+    use std::os::unix::fs::FileExt;
+
+    let Ok(len) = usize::try_from(file_size) else {
+        return Ok(false);
+    };
+    let Ok(mapping) = Mapping::new(src_file, len) else {
+        return Ok(false);
+    };
+    let mut offset = 0u64;
+    for chunk in mapping.chunks(MAP_CHUNK) {
+        let checkpoint = options.control.checkpoint();
+        if checkpoint != Checkpoint::Continue {
+            drop(dest_file);
+            abandon_incomplete(destination, options);
+            return Err(checkpoint_error(checkpoint, source));
+        }
+        if dest_file.write_all_at(chunk, offset).is_err() {
+            return Ok(false);
+        }
+        offset += chunk.len() as u64;
+        report_bytes(reporter, stats, source, chunk.len() as u64, options);
+    }
+    trim_inplace(&dest_file, file_size, options)?;
+    Ok(true)
+}
+
+/// A read-only shared mapping of the first `len` bytes of a file,
+/// unmapped on drop.
+pub(crate) struct Mapping {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapping {
+    pub(crate) fn new(file: &File, len: usize) -> io::Result<Self> {
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an empty file can't be mapped",
+            ));
+        }
+        let addr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // only a hint: readahead can be more aggressive
+        unsafe { libc::madvise(addr, len, libc::MADV_SEQUENTIAL) };
+        Ok(Self { addr, len })
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.addr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.len) };
+    }
+}
+
+/// Whether the file lives where reading it through a mapping is safe. On a
+/// network or FUSE filesystem a page can fail to load after the mapping
+/// succeeded, which raises SIGBUS and kills the process where a read would
+/// only have returned an error.
+#[cfg(target_os = "linux")]
+pub(crate) fn maps_safely(file: &File) -> bool {
+    const NFS: u32 = 0x6969;
+    const SMB: u32 = 0x517B;
+    const CIFS: u32 = 0xFF53_4D42;
+    const SMB2: u32 = 0xFE53_4D42;
+    const FUSE: u32 = 0x6573_5546;
+    const V9FS: u32 = 0x0102_1997;
+    const CEPH: u32 = 0x00C3_6400;
+    const AFS: u32 = 0x5346_414F;
+
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatfs(file.as_raw_fd(), &mut stat) } != 0 {
+        return false;
+    }
+    // the field's width varies by architecture; every magic fits 32 bits
+    let magic = stat.f_type as u32;
+    ![NFS, SMB, CIFS, SMB2, FUSE, V9FS, CEPH, AFS].contains(&magic)
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn maps_safely(file: &File) -> bool {
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatfs(file.as_raw_fd(), &mut stat) } != 0 {
+        return false;
+    }
+    stat.f_flags & libc::MNT_LOCAL as u32 != 0
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn maps_safely(_file: &File) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::args::CopyOptions;
+    use crate::utility::stats::CopyStats;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_mmap_backend_copies_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.bin");
+        let destination = temp_dir.path().join("destination.bin");
+        // spans more than one chunk, and ends partway through one
+        let data: Vec<u8> = (0..MAP_CHUNK * 2 + 1000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&source, &data).unwrap();
+
+        let mut options = CopyOptions::none();
+        options.backend = CopyBackend::Mmap;
+        let stats = CopyStats::new();
+        let copied = fast_copy(
+            &source,
+            &destination,
+            data.len() as u64,
+            &crate::utility::reporter::NoopReporter,
+            &stats,
+            &options,
+        )
+        .unwrap();
+        if maps_safely(&File::open(&source).unwrap()) {
+            assert!(copied);
+            assert_eq!(std::fs::read(&destination).unwrap(), data);
+        } else {
+            assert!(!copied);
+        }
+    }
+
+    #[test]
+    fn test_buffered_backend_leaves_the_copy_to_the_loop() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.bin");
+        std::fs::write(&source, vec![7u8; 4096]).unwrap();
+
+        let mut options = CopyOptions::none();
+        options.backend = CopyBackend::Buffered;
+        let copied = fast_copy(
+            &source,
+            &temp_dir.path().join("destination.bin"),
+            4096,
+            &crate::utility::reporter::NoopReporter,
+            &CopyStats::new(),
+            &options,
+        )
+        .unwrap();
+        assert!(!copied);
+        assert!(!temp_dir.path().join("destination.bin").exists());
+    }
+}
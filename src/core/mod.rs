@@ -1,2 +1,18 @@
+pub mod archive;
+pub mod bench;
+pub mod builder;
+pub mod check;
+pub mod compress;
 pub mod copy;
+pub mod device;
+pub mod diff;
+pub mod doctor;
+pub mod extract;
+#[cfg(unix)]
 pub mod fast_copy;
+pub mod plan;
+pub mod remote;
+#[cfg(feature = "remote")]
+pub mod sftp;
+pub mod verify;
+pub mod watch;
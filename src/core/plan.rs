@@ -0,0 +1,303 @@
+use crate::cli::args::CopyOptions;
+use crate::core::copy::{execute_planned, plan_sources};
+use crate::error::{CopyError, CopyResult};
+use crate::utility::events::CopyEvent;
+use crate::utility::preprocess::{
+    CopyPlan, DirectoryTask, DuplicateTask, FileTask, HardlinkTask, SymlinkKind, SymlinkTask,
+};
+use crate::utility::stats::CopyStats;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Bumped when the plan file layout changes incompatibly.
+pub const PLAN_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PlanFormat {
+    Json,
+    /// One row per action; for reading, `cpx apply` only takes json.
+    Csv,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedEntry {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// A [`CopyPlan`] as written by `cpx plan` and read back by `cpx apply`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlanFile {
+    pub version: u32,
+    /// The `cpx plan` arguments; `cpx apply` parses them again for its options.
+    pub args: Vec<String>,
+    pub total_files: usize,
+    pub total_bytes: u64,
+    pub directories: Vec<DirectoryTask>,
+    pub files: Vec<FileTask>,
+    pub symlinks: Vec<SymlinkTask>,
+    pub hardlinks: Vec<HardlinkTask>,
+    /// Files hard-linked from their unchanged copy under `--link-dest`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linked: Vec<DuplicateTask>,
+    /// Left out while planning: excluded, already up to date, or unreadable.
+    pub skipped: Vec<SkippedEntry>,
+}
+
+/// Just enough of a plan file to rebuild its options; the entries are
+/// skipped over.
+#[derive(Deserialize)]
+struct PlanHeader {
+    version: u32,
+    args: Vec<String>,
+}
+
+impl PlanFile {
+    /// Plans `sources` the way a copy would, recording why entries were
+    /// left out. Nothing is written.
+    pub fn build(
+        args: Vec<String>,
+        sources: &[PathBuf],
+        destination: &Path,
+        options: &CopyOptions,
+    ) -> CopyResult<Self> {
+        let (id, events) = options.events.subscribe();
+        let plan = plan_sources(sources, destination, options);
+        options.events.unsubscribe(id);
+        let plan = plan?;
+
+        let mut skipped: Vec<SkippedEntry> = events
+            .try_iter()
+            .filter_map(|event| match event {
+                CopyEvent::Skipped { source, reason } => Some(SkippedEntry {
+                    path: source,
+                    reason,
+                }),
+                _ => None,
+            })
+            .collect();
+        skipped.extend(plan.failures.iter().map(|failure| SkippedEntry {
+            path: failure.source.clone(),
+            reason: failure.error.to_string(),
+        }));
+
+        Ok(Self {
+            version: PLAN_VERSION,
+            args,
+            total_files: plan.total_files,
+            total_bytes: plan.total_size,
+            directories: plan.directories,
+            files: plan.files,
+            symlinks: plan.symlinks,
+            hardlinks: plan.hardlinks,
+            linked: plan.duplicates,
+            skipped,
+        })
+    }
+
+    pub fn read(path: &Path) -> CopyResult<Self> {
+        let plan: Self = read_json(path)?;
+        check_version(path, plan.version)?;
+        Ok(plan)
+    }
+
+    /// The arguments a plan was made with, without loading its entries.
+    pub fn read_args(path: &Path) -> CopyResult<Vec<String>> {
+        let header: PlanHeader = read_json(path)?;
+        check_version(path, header.version)?;
+        Ok(header.args)
+    }
+
+    pub fn into_plan(self) -> CopyPlan {
+        let mut plan = CopyPlan::new();
+        plan.total_size = self.files.iter().map(|file| file.size).sum();
+        plan.total_files = self.files.len() + self.linked.len();
+        plan.total_symlinks = self.symlinks.len();
+        plan.total_hardlinks = self.hardlinks.len();
+        plan.directories = self.directories;
+        plan.files = self.files;
+        plan.symlinks = self.symlinks;
+        plan.hardlinks = self.hardlinks;
+        plan.duplicates = self.linked;
+        plan
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// `action,source,destination,size,detail` rows. A symlink's source is
+    /// its target and its detail the link kind; a skip's detail is the reason.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("action,source,destination,size,detail\n");
+        let mut row =
+            |action: &str, source: &Path, destination: &Path, size: &str, detail: &str| {
+                out.push_str(&format!(
+                    "{},{},{},{},{}\n",
+                    action,
+                    csv_field(&source.to_string_lossy()),
+                    csv_field(&destination.to_string_lossy()),
+                    size,
+                    csv_field(detail)
+                ));
+            };
+        let none = Path::new("");
+        for dir in &self.directories {
+            row(
+                "mkdir",
+                dir.source.as_deref().unwrap_or(none),
+                &dir.destination,
+                "",
+                "",
+            );
+        }
+        for file in &self.files {
+            row(
+                "copy",
+                &file.source,
+                &file.destination,
+                &file.size.to_string(),
+                "",
+            );
+        }
+        for link in &self.symlinks {
+            let kind = match link.kind {
+                SymlinkKind::PreserveExact => "preserve_exact",
+                SymlinkKind::RelativeToSource => "relative_to_source",
+                SymlinkKind::AbsoluteToSource => "absolute_to_source",
+                SymlinkKind::Junction => "junction",
+            };
+            row("symlink", &link.source, &link.destination, "", kind);
+        }
+        for link in &self.hardlinks {
+            row("hardlink", &link.source, &link.destination, "", "");
+        }
+        for link in &self.linked {
+            row(
+                "link",
+                &link.file.source,
+                &link.file.destination,
+                &link.file.size.to_string(),
+                &link.original.to_string_lossy(),
+            );
+        }
+        for entry in &self.skipped {
+            row("skip", &entry.path, none, "", &entry.reason);
+        }
+        out
+    }
+}
+
+/// `cpx apply`: runs a plan file with the options it was made with. The
+/// sources are not walked again.
+pub fn apply_plan(path: &Path, options: &CopyOptions) -> CopyResult<()> {
+    let plan = PlanFile::read(path)?.into_plan();
+    execute_planned(plan, options, &CopyStats::new())
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> CopyResult<T> {
+    let file = File::open(path).map_err(|e| {
+        CopyError::Io(io::Error::new(
+            e.kind(),
+            format!("cannot open plan '{}': {}", path.display(), e),
+        ))
+    })?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+        CopyError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "'{}' is not a json plan from cpx plan: {}",
+                path.display(),
+                e
+            ),
+        ))
+    })
+}
+
+fn check_version(path: &Path, version: u32) -> CopyResult<()> {
+    if version == PLAN_VERSION {
+        return Ok(());
+    }
+    Err(CopyError::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "'{}' is a version {} plan, this cpx reads version {}",
+            path.display(),
+            version,
+            PLAN_VERSION
+        ),
+    )))
+}
+
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::exclude::{ExcludePattern, build_exclude_rules};
+    use crate::utility::output::Verbosity;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_plan_round_trip_and_apply() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("a.txt"), "alpha").unwrap();
+        fs::write(source.join("sub/b, \"c\".txt"), "beta").unwrap();
+        fs::write(source.join("skip.log"), "log").unwrap();
+        let destination = temp_dir.path().join("dst");
+
+        let mut options = CopyOptions::none();
+        options.recursive = true;
+        options.verbosity = Verbosity::Silent;
+        options.exclude_rules =
+            build_exclude_rules(vec![ExcludePattern::from_string("*.log")]).unwrap();
+
+        let args = vec!["-r".to_string()];
+        let plan = PlanFile::build(
+            args.clone(),
+            std::slice::from_ref(&source),
+            &destination,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(plan.total_files, 2);
+        assert_eq!(plan.total_bytes, 9);
+        assert_eq!(
+            plan.skipped,
+            vec![SkippedEntry {
+                path: source.join("skip.log"),
+                reason: "excluded".to_string(),
+            }]
+        );
+        assert!(!destination.exists());
+
+        let csv = plan.to_csv();
+        assert!(csv.contains("sub/b, \"\"c\"\".txt\","));
+        assert!(csv.contains(",excluded\n"));
+
+        let path = temp_dir.path().join("plan.json");
+        fs::write(&path, plan.to_json().unwrap()).unwrap();
+        assert_eq!(PlanFile::read_args(&path).unwrap(), args);
+
+        apply_plan(&path, &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(destination.join("src/a.txt")).unwrap(),
+            "alpha"
+        );
+        assert!(destination.join("src/sub/b, \"c\".txt").exists());
+        assert!(!destination.join("src/skip.log").exists());
+    }
+}
@@ -0,0 +1,686 @@
+// without the `remote` feature only the parsing and the generic engine (used by tests) remain
+#![cfg_attr(not(feature = "remote"), allow(dead_code))]
+
+use crate::cli::args::CopyOptions;
+use crate::core::copy::{
+    checkpoint_error, failures_to_result, finish_run, report_bytes, run_file_tasks,
+};
+use crate::error::{CopyError, CopyResult, FailedFile};
+use crate::utility::control::Checkpoint;
+use crate::utility::events::CopyEvent;
+use crate::utility::fd_budget;
+use crate::utility::helper::open_source;
+use crate::utility::output;
+use crate::utility::preprocess::{CopyPlan, FileTask, SymlinkKind, preprocess_tree};
+use crate::utility::reporter::{self, ProgressReporter};
+use crate::utility::stats::CopyStats;
+use std::fmt;
+use std::fs::Metadata;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+pub const DEFAULT_SSH_PORT: u16 = 22;
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// A destination on another machine, reached over SSH.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteTarget {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub path: PathBuf,
+}
+
+impl RemoteTarget {
+    /// Parses `[user@]host:path` (as scp does) or `ssh://[user@]host[:port]/path`.
+    /// Anything that looks like a local path yields `None`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        if let Some(rest) = spec.strip_prefix("ssh://") {
+            let (authority, path) = rest.split_once('/')?;
+            let (user, host_port) = split_user(authority);
+            let (host, port) = match host_port.rsplit_once(':') {
+                Some((host, port)) => (host, port.parse().ok()?),
+                None => (host_port, DEFAULT_SSH_PORT),
+            };
+            if host.is_empty() {
+                return None;
+            }
+            return Some(Self {
+                user,
+                host: host.to_string(),
+                port,
+                path: PathBuf::from(format!("/{}", path)),
+            });
+        }
+
+        let (authority, path) = spec.split_once(':')?;
+        // `./a:b` and `C:\dir` are local
+        if authority.contains('/') || authority.len() < 2 {
+            return None;
+        }
+        let (user, host) = split_user(authority);
+        if host.is_empty() {
+            return None;
+        }
+        Some(Self {
+            user,
+            host: host.to_string(),
+            port: DEFAULT_SSH_PORT,
+            path: PathBuf::from(if path.is_empty() { "." } else { path }),
+        })
+    }
+
+    /// The login name, falling back to the local user like `ssh` does.
+    pub fn user_name(&self) -> String {
+        self.user
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "root".to_string())
+    }
+}
+
+fn split_user(authority: &str) -> (Option<String>, &str) {
+    match authority.rsplit_once('@') {
+        Some((user, host)) => (Some(user.to_string()), host),
+        None => (None, authority),
+    }
+}
+
+impl fmt::Display for RemoteTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        write!(f, "{}:{}", self.host, self.path.display())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteMetadata {
+    pub size: u64,
+    /// Seconds since the epoch.
+    pub mtime: Option<u64>,
+    pub is_dir: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemoteAttributes {
+    pub mode: Option<u32>,
+    pub atime: Option<u64>,
+    pub mtime: Option<u64>,
+}
+
+/// The filesystem operations a remote destination has to support.
+pub trait RemoteFs {
+    /// `None` when nothing exists at `path`.
+    fn stat(&self, path: &Path) -> io::Result<Option<RemoteMetadata>>;
+
+    /// Creates one directory; succeeds if it already exists.
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Opens `path` for writing, truncating an existing file.
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + '_>>;
+
+    fn set_attributes(&self, path: &Path, attributes: RemoteAttributes) -> io::Result<()>;
+
+    /// Creates `link` pointing at `target`, in the argument order of
+    /// `std::os::unix::fs::symlink`.
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+}
+
+/// Opens connections to the remote side; each copy worker gets its own.
+pub trait Connector: Sync {
+    type Fs: RemoteFs;
+
+    fn connect(&self) -> io::Result<Self::Fs>;
+}
+
+/// Copies `sources` to `target` over SFTP.
+pub fn copy_to_remote(
+    sources: &[PathBuf],
+    target: &RemoteTarget,
+    options: &CopyOptions,
+    stats: &CopyStats,
+) -> CopyResult<()> {
+    #[cfg(feature = "remote")]
+    {
+        let connector = crate::core::sftp::SftpConnector::new(target.clone());
+        copy_with(&connector, sources, &target.path, options, stats)
+    }
+    #[cfg(not(feature = "remote"))]
+    {
+        let _ = (sources, options, stats);
+        Err(CopyError::CopyFailed {
+            source: PathBuf::new(),
+            destination: target.path.clone(),
+            reason: "cpx was built without remote support (feature `remote`)".to_string(),
+        })
+    }
+}
+
+/// Plans locally, then creates directories and links over one connection
+/// and uploads files in parallel, one connection per worker.
+pub(crate) fn copy_with<C: Connector>(
+    connector: &C,
+    sources: &[PathBuf],
+    destination: &Path,
+    options: &CopyOptions,
+    stats: &CopyStats,
+) -> CopyResult<()> {
+    let reporter = reporter::for_options(options);
+    reporter.scan_started(&sources.iter().map(PathBuf::as_path).collect::<Vec<_>>());
+
+    let remote = connector.connect()?;
+    let plan = plan_remote(&remote, sources, destination, options)?;
+    reporter.scan_finished(plan.total_files, plan.total_size, plan.skipped_files);
+    stats.record_unreadable(&plan.unreadable);
+    options.events.emit(|| CopyEvent::Scanned {
+        total_files: plan.total_files,
+        total_bytes: plan.total_size,
+        skipped_files: plan.skipped_files,
+    });
+
+    let result = upload_plan(connector, &remote, plan, options, stats, reporter.as_ref());
+    finish_run(result, stats, options, reporter.as_ref())
+}
+
+fn plan_remote(
+    remote: &impl RemoteFs,
+    sources: &[PathBuf],
+    destination: &Path,
+    options: &CopyOptions,
+) -> CopyResult<CopyPlan> {
+    let destination_is_dir = remote.stat(destination)?.is_some_and(|meta| meta.is_dir);
+    if sources.len() > 1 && !destination_is_dir {
+        return Err(CopyError::InvalidDestination(destination.to_path_buf()));
+    }
+
+    // --resume compares against the remote side while uploading, not against
+    // whatever happens to exist at the same path locally
+    let mut planning = options.clone();
+    planning.resume = false;
+
+    let mut plan = CopyPlan::new();
+    for source in sources {
+        let metadata =
+            std::fs::metadata(source).map_err(|_| CopyError::InvalidSource(source.clone()))?;
+        if metadata.is_dir() {
+            if !options.recursive {
+                return Err(CopyError::CopyFailed {
+                    source: source.clone(),
+                    destination: destination.to_path_buf(),
+                    reason: "'src' is a directory (not copied, use -r to copy recursively)"
+                        .to_string(),
+                });
+            }
+            // like `cp -r src newdir`, a missing destination becomes the directory itself
+            let root = match source.file_name() {
+                Some(name) if destination_is_dir => destination.join(name),
+                _ => destination.to_path_buf(),
+            };
+            let dir_plan = preprocess_tree(source, &root, &planning)?;
+            plan.merge(dir_plan);
+        } else {
+            let file_destination = match source.file_name() {
+                Some(name) if destination_is_dir => destination.join(name),
+                _ => destination.to_path_buf(),
+            };
+            plan.add_file(source.clone(), file_destination, metadata.len());
+        }
+    }
+    plan.sort_files(options.order);
+    Ok(plan)
+}
+
+fn upload_plan<C: Connector>(
+    connector: &C,
+    remote: &C::Fs,
+    mut plan: CopyPlan,
+    options: &CopyOptions,
+    stats: &CopyStats,
+    reporter: &dyn ProgressReporter,
+) -> CopyResult<()> {
+    let mut failures: Vec<FailedFile> = std::mem::take(&mut plan.failures);
+
+    for dir_task in &plan.directories {
+        if let Err(e) = remote.create_dir(&dir_task.destination) {
+            if !options.ignore_errors {
+                return Err(CopyError::Io(e));
+            }
+            let source = dir_task.source.as_deref().unwrap_or(&dir_task.destination);
+            failures.push(FailedFile::new(source, &dir_task.destination, e.into()));
+        }
+    }
+
+    for link in &plan.symlinks {
+        if !matches!(
+            link.kind,
+            SymlinkKind::PreserveExact | SymlinkKind::Junction
+        ) {
+            continue;
+        }
+        match remote.symlink(&link.source, &link.destination) {
+            Ok(()) => stats.record_symlink(),
+            Err(e) => failures.push(FailedFile::new(&link.source, &link.destination, e.into())),
+        }
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(fd_budget::worker_count(options.parallel, options))
+        .build()
+        .map_err(|e| CopyError::CopyFailed {
+            source: PathBuf::new(),
+            destination: PathBuf::new(),
+            reason: format!("Failed to create thread pool: {}", e),
+        })?;
+
+    reporter.begin(
+        plan.total_files,
+        plan.total_size,
+        plan.skipped_files,
+        plan.skipped_size,
+    );
+    options
+        .control
+        .set_totals(plan.total_files, plan.total_size);
+
+    let upload_failures = run_file_tasks(
+        &pool,
+        &plan.files,
+        options,
+        stats,
+        || connector.connect(),
+        |connection, task| {
+            let result = match connection {
+                Ok(remote) => run_upload(remote, task, options, stats, reporter),
+                Err(e) => Err(CopyError::Io(io::Error::new(e.kind(), e.to_string()))),
+            };
+            result.map_err(|e| FailedFile::new(&task.source, &task.destination, e))
+        },
+    );
+
+    let mut interrupted = false;
+    for failure in upload_failures {
+        if failure.error.kind() == io::ErrorKind::Interrupted {
+            interrupted = true;
+        } else {
+            failures.push(failure);
+        }
+    }
+
+    if interrupted {
+        if let Some(limit) = options
+            .max_errors
+            .filter(|limit| stats.files_failed() > *limit)
+        {
+            reporter.abandon("Stopped after too many errors");
+            return Err(CopyError::ErrorLimitExceeded { limit, failures });
+        }
+        if let Some(limit) = options
+            .timeout_total
+            .filter(|_| options.control.timed_out())
+        {
+            reporter.abandon("Stopped at the time limit");
+            return Err(CopyError::TimeLimitExceeded { limit, failures });
+        }
+        return Err(CopyError::Io(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Operation interrupted by user",
+        )));
+    }
+    if !failures.is_empty() {
+        reporter.abandon("Completed with errors");
+        return failures_to_result(failures);
+    }
+    reporter.finish("Done");
+    Ok(())
+}
+
+fn run_upload(
+    remote: &impl RemoteFs,
+    task: &FileTask,
+    options: &CopyOptions,
+    stats: &CopyStats,
+    reporter: &dyn ProgressReporter,
+) -> CopyResult<()> {
+    reporter.file_begin(&task.source, &task.destination, task.size);
+    options.events.emit(|| CopyEvent::Started {
+        source: task.source.clone(),
+        destination: task.destination.clone(),
+        size: task.size,
+    });
+    options.control.start_file(&task.source, task.size);
+    let started = Instant::now();
+
+    let result = upload_file(remote, task, options, stats, reporter);
+    options.control.finish_file(match &result {
+        Err(e) if e.kind() != io::ErrorKind::Interrupted && !matches!(e, CopyError::Skipped(_)) => {
+            Some(format!("{}: {}", task.source.display(), e))
+        }
+        _ => None,
+    });
+
+    match &result {
+        Ok(true) => {
+            stats.record_copied();
+            stats.record_duration(&task.source, started.elapsed());
+            reporter.file_done(&task.source, &task.destination, None);
+            options.events.emit(|| CopyEvent::Finished {
+                source: task.source.clone(),
+                destination: task.destination.clone(),
+            });
+            output::copied(options, reporter, &task.source, &task.destination);
+        }
+        Ok(false) => skip(task, "already up to date", options, stats, reporter),
+        Err(CopyError::Skipped(_)) => {
+            skip(task, "skipped by user", options, stats, reporter);
+            return Ok(());
+        }
+        Err(e) => {
+            reporter.file_failed(&task.source, &task.destination, e);
+            if e.kind() != io::ErrorKind::Interrupted {
+                stats.record_failed();
+                options.events.emit(|| CopyEvent::Failed {
+                    source: task.source.clone(),
+                    destination: task.destination.clone(),
+                    category: e.category(),
+                    error: e.to_string(),
+                });
+                if options
+                    .max_errors
+                    .is_some_and(|limit| stats.files_failed() > limit)
+                {
+                    options.control.abort();
+                }
+            }
+            if let Some(logger) = &options.logger {
+                logger.failed(&task.source, &task.destination, &e.to_string());
+            }
+        }
+    }
+    result.map(|_| ())
+}
+
+fn skip(
+    task: &FileTask,
+    reason: &str,
+    options: &CopyOptions,
+    stats: &CopyStats,
+    reporter: &dyn ProgressReporter,
+) {
+    stats.record_skipped(1);
+    reporter.file_skipped(&task.source, reason);
+    options.events.emit(|| CopyEvent::Skipped {
+        source: task.source.clone(),
+        reason: reason.to_string(),
+    });
+    output::skipped(options, &task.source, reason);
+}
+
+/// Returns `Ok(false)` when `--resume` found an up-to-date copy.
+fn upload_file(
+    remote: &impl RemoteFs,
+    task: &FileTask,
+    options: &CopyOptions,
+    stats: &CopyStats,
+    reporter: &dyn ProgressReporter,
+) -> CopyResult<bool> {
+    let metadata = std::fs::metadata(&task.source)?;
+    if options.resume
+        && let Some(existing) = remote.stat(&task.destination)?
+        && is_up_to_date(&metadata, &existing, options.modify_window)
+    {
+        return Ok(false);
+    }
+
+    let mut source = open_source(&task.source)?;
+    let mut writer = remote.create(&task.destination)?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let checkpoint = options.control.checkpoint();
+        if checkpoint != Checkpoint::Continue {
+            drop(writer);
+            if let Err(e) = remote.remove_file(&task.destination) {
+                output::run_error(
+                    options,
+                    format!(
+                        "Could not remove incomplete file {}: {}",
+                        task.destination.display(),
+                        e
+                    ),
+                );
+            }
+            return Err(checkpoint_error(checkpoint, &task.source));
+        }
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read])?;
+        report_bytes(reporter, stats, &task.source, read as u64, options);
+    }
+    writer.flush()?;
+    drop(writer);
+
+    let attributes = attributes_to_preserve(&metadata, options);
+    if attributes != RemoteAttributes::default() {
+        remote.set_attributes(&task.destination, attributes)?;
+    }
+    Ok(true)
+}
+
+// same test as the local --resume: equal size and a destination at least as new
+fn is_up_to_date(local: &Metadata, remote: &RemoteMetadata, modify_window: Duration) -> bool {
+    let local_mtime = local
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|age| age.as_secs());
+    !remote.is_dir
+        && remote.size == local.len()
+        && matches!(
+            (local_mtime, remote.mtime),
+            (Some(local), Some(remote)) if local <= remote.saturating_add(modify_window.as_secs())
+        )
+}
+
+fn attributes_to_preserve(metadata: &Metadata, options: &CopyOptions) -> RemoteAttributes {
+    let seconds = |time: io::Result<std::time::SystemTime>| {
+        time.ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|age| age.as_secs())
+    };
+    let mut attributes = RemoteAttributes::default();
+    #[cfg(unix)]
+    if options.preserve.mode {
+        use std::os::unix::fs::PermissionsExt;
+        attributes.mode = Some(metadata.permissions().mode() & 0o7777);
+    }
+    if options.preserve.timestamps {
+        attributes.atime = seconds(metadata.accessed());
+        attributes.mtime = seconds(metadata.modified());
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::output::Verbosity;
+    use std::fs::{self, File};
+    use tempfile::TempDir;
+
+    /// Stands in for an SFTP server by mapping remote paths under a local root.
+    struct LocalConnector(PathBuf);
+
+    struct LocalFs(PathBuf);
+
+    impl LocalFs {
+        fn local(&self, path: &Path) -> PathBuf {
+            self.0.join(path.strip_prefix("/").unwrap_or(path))
+        }
+    }
+
+    impl Connector for LocalConnector {
+        type Fs = LocalFs;
+
+        fn connect(&self) -> io::Result<LocalFs> {
+            Ok(LocalFs(self.0.clone()))
+        }
+    }
+
+    impl RemoteFs for LocalFs {
+        fn stat(&self, path: &Path) -> io::Result<Option<RemoteMetadata>> {
+            Ok(fs::metadata(self.local(path))
+                .ok()
+                .map(|meta| RemoteMetadata {
+                    size: meta.len(),
+                    mtime: meta
+                        .modified()
+                        .ok()
+                        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                        .map(|age| age.as_secs()),
+                    is_dir: meta.is_dir(),
+                }))
+        }
+
+        fn create_dir(&self, path: &Path) -> io::Result<()> {
+            fs::create_dir_all(self.local(path))
+        }
+
+        fn create(&self, path: &Path) -> io::Result<Box<dyn Write + '_>> {
+            Ok(Box::new(File::create(self.local(path))?))
+        }
+
+        fn set_attributes(&self, _path: &Path, _attributes: RemoteAttributes) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+            std::os::unix::fs::symlink(target, self.local(link))
+        }
+
+        fn remove_file(&self, path: &Path) -> io::Result<()> {
+            fs::remove_file(self.local(path))
+        }
+    }
+
+    fn options() -> CopyOptions {
+        let mut options = CopyOptions::none();
+        options.recursive = true;
+        options.verbosity = Verbosity::Silent;
+        options
+    }
+
+    #[test]
+    fn test_parse_remote_targets() {
+        assert_eq!(
+            RemoteTarget::parse("me@backup:/srv/data"),
+            Some(RemoteTarget {
+                user: Some("me".to_string()),
+                host: "backup".to_string(),
+                port: DEFAULT_SSH_PORT,
+                path: PathBuf::from("/srv/data"),
+            })
+        );
+        let url = RemoteTarget::parse("ssh://nas:2222/backups").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("nas", 2222));
+        assert_eq!(url.path, PathBuf::from("/backups"));
+        assert_eq!(
+            RemoteTarget::parse("host:").unwrap().path,
+            PathBuf::from(".")
+        );
+
+        assert_eq!(RemoteTarget::parse("./dir:name"), None);
+        assert_eq!(RemoteTarget::parse("C:\\data"), None);
+        assert_eq!(RemoteTarget::parse("plain"), None);
+    }
+
+    #[test]
+    fn test_upload_tree_and_resume() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("data");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("a.txt"), "alpha").unwrap();
+        fs::write(source.join("sub/b.txt"), "beta").unwrap();
+        let server = temp_dir.path().join("server");
+        fs::create_dir_all(server.join("backups")).unwrap();
+        let connector = LocalConnector(server.clone());
+
+        let stats = CopyStats::new();
+        copy_with(
+            &connector,
+            std::slice::from_ref(&source),
+            Path::new("/backups"),
+            &options(),
+            &stats,
+        )
+        .unwrap();
+        assert_eq!(stats.files_copied(), 2);
+        assert_eq!(
+            fs::read_to_string(server.join("backups/data/sub/b.txt")).unwrap(),
+            "beta"
+        );
+
+        let mut resume = options();
+        resume.resume = true;
+        let stats = CopyStats::new();
+        copy_with(
+            &connector,
+            &[source],
+            Path::new("/backups"),
+            &resume,
+            &stats,
+        )
+        .unwrap();
+        assert_eq!(stats.files_copied(), 0);
+        assert_eq!(stats.files_skipped(), 2);
+    }
+
+    #[test]
+    fn test_upload_symlink_points_at_its_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("data");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("a.txt"), "alpha").unwrap();
+        std::os::unix::fs::symlink("a.txt", source.join("link")).unwrap();
+        let server = temp_dir.path().join("server");
+        fs::create_dir_all(&server).unwrap();
+
+        copy_with(
+            &LocalConnector(server.clone()),
+            &[source],
+            Path::new("/backups"),
+            &options(),
+            &CopyStats::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_link(server.join("backups/link")).unwrap(),
+            Path::new("a.txt")
+        );
+    }
+
+    #[test]
+    fn test_upload_directory_to_new_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("data");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("a.txt"), "alpha").unwrap();
+        let server = temp_dir.path().join("server");
+        fs::create_dir_all(&server).unwrap();
+
+        copy_with(
+            &LocalConnector(server.clone()),
+            &[source],
+            Path::new("/renamed"),
+            &options(),
+            &CopyStats::new(),
+        )
+        .unwrap();
+        assert!(server.join("renamed/a.txt").exists());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::core::remote::{Connector, RemoteAttributes, RemoteFs, RemoteMetadata, RemoteTarget};
+use ssh2::{CheckResult, ErrorCode, FileStat, KnownHostFileKind, Session, Sftp};
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+// SSH_FX_NO_SUCH_FILE
+const NO_SUCH_FILE: i32 = 2;
+const IDENTITY_FILES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Connects with the same host keys and credentials `ssh` would use:
+/// `~/.ssh/known_hosts`, then ssh-agent or the default identity files.
+#[derive(Debug, Clone)]
+pub struct SftpConnector {
+    target: RemoteTarget,
+}
+
+pub struct SftpFs {
+    // the channel is only valid while its session is alive
+    _session: Session,
+    sftp: Sftp,
+}
+
+impl SftpConnector {
+    pub fn new(target: RemoteTarget) -> Self {
+        Self { target }
+    }
+
+    fn verify_host_key(&self, session: &Session) -> io::Result<()> {
+        let host = &self.target.host;
+        let (key, _) = session
+            .host_key()
+            .ok_or_else(|| io::Error::other(format!("{} sent no host key", host)))?;
+        let mut known_hosts = session.known_hosts()?;
+        if let Some(file) = ssh_dir().map(|dir| dir.join("known_hosts")) {
+            let _ = known_hosts.read_file(&file, KnownHostFileKind::OpenSSH);
+        }
+        match known_hosts.check_port(host, self.target.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::NotFound => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "host key for {} is not in ~/.ssh/known_hosts; connect once with ssh to add it",
+                    host
+                ),
+            )),
+            CheckResult::Mismatch => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("host key for {} does not match ~/.ssh/known_hosts", host),
+            )),
+            CheckResult::Failure => Err(io::Error::other(format!(
+                "could not check the host key for {}",
+                host
+            ))),
+        }
+    }
+
+    fn authenticate(&self, session: &Session) -> io::Result<()> {
+        let user = self.target.user_name();
+        if session.userauth_agent(&user).is_ok() && session.authenticated() {
+            return Ok(());
+        }
+        if let Some(dir) = ssh_dir() {
+            for name in IDENTITY_FILES {
+                let key = dir.join(name);
+                if key.exists()
+                    && session
+                        .userauth_pubkey_file(&user, None, &key, None)
+                        .is_ok()
+                    && session.authenticated()
+                {
+                    return Ok(());
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "authentication failed for {}@{} (tried ssh-agent and ~/.ssh keys)",
+                user, self.target.host
+            ),
+        ))
+    }
+}
+
+impl Connector for SftpConnector {
+    type Fs = SftpFs;
+
+    fn connect(&self) -> io::Result<SftpFs> {
+        let tcp = TcpStream::connect((self.target.host.as_str(), self.target.port))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+        self.verify_host_key(&session)?;
+        self.authenticate(&session)?;
+        let sftp = session.sftp()?;
+        Ok(SftpFs {
+            _session: session,
+            sftp,
+        })
+    }
+}
+
+impl RemoteFs for SftpFs {
+    fn stat(&self, path: &Path) -> io::Result<Option<RemoteMetadata>> {
+        match self.sftp.stat(path) {
+            Ok(stat) => Ok(Some(RemoteMetadata {
+                size: stat.size.unwrap_or(0),
+                mtime: stat.mtime,
+                is_dir: stat.is_dir(),
+            })),
+            Err(e) if e.code() == ErrorCode::SFTP(NO_SUCH_FILE) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        match self.sftp.mkdir(path, 0o755) {
+            Ok(()) => Ok(()),
+            Err(_) if self.stat(path)?.is_some_and(|meta| meta.is_dir) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + '_>> {
+        Ok(Box::new(self.sftp.create(path)?))
+    }
+
+    fn set_attributes(&self, path: &Path, attributes: RemoteAttributes) -> io::Result<()> {
+        let stat = FileStat {
+            size: None,
+            uid: None,
+            gid: None,
+            perm: attributes.mode,
+            atime: attributes.atime.or(attributes.mtime),
+            mtime: attributes.mtime.or(attributes.atime),
+        };
+        self.sftp.setstat(path, stat).map_err(Into::into)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        // same order as std: ssh2 creates its second argument, pointing at
+        // its first
+        self.sftp.symlink(target, link).map_err(Into::into)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.sftp.unlink(path).map_err(Into::into)
+    }
+}
+
+fn ssh_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".ssh"))
+}
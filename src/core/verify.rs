@@ -0,0 +1,370 @@
+use crate::cli::args::CopyOptions;
+use crate::error::{CopyError, CopyResult};
+use crate::utility::exclude::ExcludeRules;
+use crate::utility::hash::{HashAlgo, hash_file};
+use crate::utility::output::Verbosity;
+use crate::utility::preprocess::{is_newer_or_same, preprocess_tree};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How the contents of two files are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    /// Same size and the same digest.
+    Checksum(HashAlgo),
+    /// Same size and a destination at least as new as the source.
+    Quick,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mismatch {
+    /// A file on one side, a directory or symlink on the other.
+    Type,
+    Size,
+    Mtime,
+    Checksum,
+    /// Symlinks pointing somewhere else.
+    Target,
+}
+
+impl Mismatch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mismatch::Type => "type",
+            Mismatch::Size => "size",
+            Mismatch::Mtime => "mtime",
+            Mismatch::Checksum => "checksum",
+            Mismatch::Target => "target",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    pub path: PathBuf,
+    pub reason: Mismatch,
+}
+
+/// Result of `cpx verify`. Paths are relative to the two roots; a missing or
+/// extra directory is listed once rather than with everything inside it.
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub matched: usize,
+    pub missing: Vec<PathBuf>,
+    pub extra: Vec<PathBuf>,
+    pub differing: Vec<Difference>,
+}
+
+impl VerifyReport {
+    pub fn is_identical(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.differing.is_empty()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for path in &self.missing {
+            out.push_str(&format!("missing  {}\n", path.display()));
+        }
+        for path in &self.extra {
+            out.push_str(&format!("extra    {}\n", path.display()));
+        }
+        for difference in &self.differing {
+            out.push_str(&format!(
+                "differs  {} ({})\n",
+                difference.path.display(),
+                difference.reason.as_str()
+            ));
+        }
+        out.push_str(&format!(
+            "{} matched, {} missing, {} extra, {} differing\n",
+            self.matched,
+            self.missing.len(),
+            self.extra.len(),
+            self.differing.len()
+        ));
+        out
+    }
+}
+
+enum Status {
+    Matched,
+    Missing,
+    Differs(Mismatch),
+}
+
+/// Everything [`compare_trees`] found. Sizes are in bytes: missing and extra
+/// directories carry the total of the files inside them.
+#[derive(Debug, Default)]
+pub(crate) struct Comparison {
+    pub matched: usize,
+    pub missing: Vec<(PathBuf, u64)>,
+    pub extra: Vec<(PathBuf, u64)>,
+    pub differing: Vec<Changed>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Changed {
+    pub difference: Difference,
+    pub source_size: u64,
+    pub destination_size: u64,
+}
+
+impl Comparison {
+    fn record(&mut self, path: PathBuf, status: Status, source_size: u64, destination: &Path) {
+        match status {
+            Status::Matched => self.matched += 1,
+            Status::Missing => self.missing.push((path, source_size)),
+            Status::Differs(reason) => self.differing.push(Changed {
+                difference: Difference { path, reason },
+                source_size,
+                destination_size: fs::symlink_metadata(destination).map_or(0, |m| m.len()),
+            }),
+        }
+    }
+}
+
+/// Compares `destination` against `source`: walks both with the copy
+/// planner, so excludes and symlink handling match what a copy would do.
+pub fn verify_trees(
+    source: &Path,
+    destination: &Path,
+    compare: Compare,
+    exclude_rules: Option<ExcludeRules>,
+) -> CopyResult<VerifyReport> {
+    let comparison = compare_trees(source, destination, compare, exclude_rules)?;
+    let paths = |entries: Vec<(PathBuf, u64)>| entries.into_iter().map(|(path, _)| path).collect();
+    Ok(VerifyReport {
+        matched: comparison.matched,
+        missing: paths(comparison.missing),
+        extra: paths(comparison.extra),
+        differing: comparison
+            .differing
+            .into_iter()
+            .map(|changed| changed.difference)
+            .collect(),
+    })
+}
+
+/// Walks `source` and `destination` without writing anything; shared by
+/// `cpx verify` and `cpx diff`.
+pub(crate) fn compare_trees(
+    source: &Path,
+    destination: &Path,
+    compare: Compare,
+    exclude_rules: Option<ExcludeRules>,
+) -> CopyResult<Comparison> {
+    let source_metadata =
+        fs::symlink_metadata(source).map_err(|_| CopyError::InvalidSource(source.to_path_buf()))?;
+    let destination_metadata = fs::symlink_metadata(destination)
+        .map_err(|_| CopyError::InvalidDestination(destination.to_path_buf()))?;
+    let mut comparison = Comparison::default();
+
+    if !source_metadata.is_dir() || !destination_metadata.is_dir() {
+        let name = PathBuf::from(source.file_name().unwrap_or_default());
+        let status = if source_metadata.is_dir() || destination_metadata.is_dir() {
+            Status::Differs(Mismatch::Type)
+        } else {
+            compare_file(source, destination, source_metadata.len(), compare)?
+        };
+        comparison.record(name, status, source_metadata.len(), destination);
+        return Ok(comparison);
+    }
+
+    let options = walk_options(exclude_rules);
+    let forward = preprocess_tree(source, destination, &options)?;
+    let backward = preprocess_tree(destination, source, &options)?;
+    let relative = |path: &Path| path.strip_prefix(destination).unwrap_or(path).to_path_buf();
+
+    for dir in forward
+        .directories
+        .iter()
+        .filter(|dir| dir.destination != destination)
+    {
+        let status = match fs::symlink_metadata(&dir.destination) {
+            Err(_) => Status::Missing,
+            Ok(metadata) if !metadata.is_dir() => Status::Differs(Mismatch::Type),
+            Ok(_) => continue,
+        };
+        // a directory's size is the files inside it, recorded on their own
+        comparison.record(relative(&dir.destination), status, 0, &dir.destination);
+    }
+    // symlinks are planned with their own target as the source
+    for link in &forward.symlinks {
+        let status = match fs::symlink_metadata(&link.destination) {
+            Err(_) => Status::Missing,
+            Ok(metadata) if !metadata.file_type().is_symlink() => Status::Differs(Mismatch::Type),
+            Ok(_) if fs::read_link(&link.destination)? != link.source => {
+                Status::Differs(Mismatch::Target)
+            }
+            Ok(_) => Status::Matched,
+        };
+        comparison.record(relative(&link.destination), status, 0, &link.destination);
+    }
+    let files = forward
+        .files
+        .par_iter()
+        .map(|file| {
+            compare_file(&file.source, &file.destination, file.size, compare)
+                .map(|status| (file, status))
+        })
+        .collect::<CopyResult<Vec<_>>>()?;
+    for (file, status) in files {
+        comparison.record(
+            relative(&file.destination),
+            status,
+            file.size,
+            &file.destination,
+        );
+    }
+
+    let relative = |path: &Path| path.strip_prefix(source).unwrap_or(path).to_path_buf();
+    let extra_dirs = backward
+        .directories
+        .iter()
+        .filter(|dir| dir.destination != source)
+        .map(|dir| (&dir.destination, 0));
+    let extra_links = backward.symlinks.iter().map(|link| (&link.destination, 0));
+    let extra_files = backward
+        .files
+        .iter()
+        .map(|file| (&file.destination, file.size));
+    comparison.extra = extra_dirs
+        .chain(extra_links)
+        .chain(extra_files)
+        .filter(|(path, _)| fs::symlink_metadata(path).is_err())
+        .map(|(path, size)| (relative(path), size))
+        .collect();
+
+    comparison.missing = outermost(std::mem::take(&mut comparison.missing));
+    comparison.extra = outermost(std::mem::take(&mut comparison.extra));
+    comparison
+        .differing
+        .sort_by(|a, b| a.difference.path.cmp(&b.difference.path));
+    Ok(comparison)
+}
+
+fn walk_options(exclude_rules: Option<ExcludeRules>) -> CopyOptions {
+    let mut options = CopyOptions::none();
+    options.recursive = true;
+    options.verbosity = Verbosity::Silent;
+    options.exclude_rules = exclude_rules;
+    options
+}
+
+fn compare_file(
+    source: &Path,
+    destination: &Path,
+    size: u64,
+    compare: Compare,
+) -> CopyResult<Status> {
+    let metadata = match fs::symlink_metadata(destination) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(Status::Missing),
+    };
+    if !metadata.is_file() {
+        return Ok(Status::Differs(Mismatch::Type));
+    }
+    if metadata.len() != size {
+        return Ok(Status::Differs(Mismatch::Size));
+    }
+    let same = match compare {
+        Compare::Quick if is_newer_or_same(source, destination, Duration::ZERO) => {
+            return Ok(Status::Matched);
+        }
+        Compare::Quick => return Ok(Status::Differs(Mismatch::Mtime)),
+        Compare::Checksum(algo) => hash(source, algo)? == hash(destination, algo)?,
+    };
+    Ok(if same {
+        Status::Matched
+    } else {
+        Status::Differs(Mismatch::Checksum)
+    })
+}
+
+fn hash(path: &Path, algo: HashAlgo) -> CopyResult<String> {
+    hash_file(path, algo).map_err(|e| {
+        CopyError::Io(io::Error::new(
+            e.kind(),
+            format!("cannot read '{}': {}", path.display(), e),
+        ))
+    })
+}
+
+/// Folds entries inside another listed path into it, adding up their sizes;
+/// sorting puts each directory directly before its contents.
+fn outermost(mut entries: Vec<(PathBuf, u64)>) -> Vec<(PathBuf, u64)> {
+    entries.sort();
+    let mut kept: Vec<(PathBuf, u64)> = Vec::new();
+    for (path, size) in entries {
+        match kept.last_mut() {
+            Some((parent, total)) if path.starts_with(&*parent) => *total += size,
+            _ => kept.push((path, size)),
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_verify_reports_missing_extra_and_differing() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        let destination = temp_dir.path().join("dst");
+        for root in [&source, &destination] {
+            fs::create_dir_all(root.join("same")).unwrap();
+            fs::write(root.join("same/a.txt"), "alpha").unwrap();
+            fs::write(root.join("size.txt"), "short").unwrap();
+            symlink("same/a.txt", root.join("link")).unwrap();
+        }
+        fs::write(source.join("content.txt"), "one").unwrap();
+        fs::write(destination.join("content.txt"), "two").unwrap();
+        fs::write(destination.join("size.txt"), "longer").unwrap();
+        fs::create_dir_all(source.join("gone/deeper")).unwrap();
+        fs::write(source.join("gone/deeper/x.txt"), "x").unwrap();
+        fs::write(destination.join("stray.txt"), "stray").unwrap();
+
+        let report = verify_trees(
+            &source,
+            &destination,
+            Compare::Checksum(HashAlgo::Xxh3),
+            None,
+        )
+        .unwrap();
+        assert_eq!(report.matched, 2);
+        assert_eq!(report.missing, vec![PathBuf::from("gone")]);
+        assert_eq!(report.extra, vec![PathBuf::from("stray.txt")]);
+        assert_eq!(
+            report.differing,
+            vec![
+                Difference {
+                    path: PathBuf::from("content.txt"),
+                    reason: Mismatch::Checksum,
+                },
+                Difference {
+                    path: PathBuf::from("size.txt"),
+                    reason: Mismatch::Size,
+                },
+            ]
+        );
+        assert!(!report.is_identical());
+
+        // same size and a newer destination passes the quick check
+        let quick = verify_trees(&source, &destination, Compare::Quick, None).unwrap();
+        assert!(
+            !quick
+                .differing
+                .iter()
+                .any(|difference| difference.path == Path::new("content.txt"))
+        );
+    }
+}
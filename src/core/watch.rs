@@ -0,0 +1,246 @@
+use crate::cli::args::CopyOptions;
+use crate::core::copy::{copy_with_stats, multiple_copy_with_stats};
+use crate::error::{CopyError, CopyResult};
+use crate::utility::exclude::should_exclude;
+use crate::utility::output;
+use crate::utility::progress_bar::ProgressMode;
+use crate::utility::stats::CopyStats;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How often the event loop wakes up to notice Ctrl+C.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A watched source and where changes under it are copied to.
+struct WatchRoot {
+    source: PathBuf,
+    /// What exclude patterns are matched against, as in a normal copy.
+    exclude_root: PathBuf,
+    destination: PathBuf,
+    is_dir: bool,
+}
+
+impl WatchRoot {
+    fn new(source: &Path, destination: &Path) -> CopyResult<Self> {
+        let source = std::path::absolute(source)?;
+        let is_dir = source.is_dir();
+        let name = source
+            .file_name()
+            .ok_or_else(|| CopyError::InvalidSource(source.clone()))?;
+        let destination = if is_dir || destination.is_dir() {
+            destination.join(name)
+        } else {
+            destination.to_path_buf()
+        };
+        let exclude_root = if is_dir {
+            source.clone()
+        } else {
+            source.parent().unwrap_or(&source).to_path_buf()
+        };
+        Ok(Self {
+            source,
+            exclude_root,
+            destination,
+            is_dir,
+        })
+    }
+
+    /// Where a changed `path` is copied to, if it belongs to this source.
+    fn target(&self, path: &Path) -> Option<PathBuf> {
+        if !self.is_dir {
+            return (path == self.source).then(|| self.destination.clone());
+        }
+        let relative = path.strip_prefix(&self.source).ok()?;
+        (!relative.as_os_str().is_empty()).then(|| self.destination.join(relative))
+    }
+
+    fn is_excluded(&self, path: &Path, options: &CopyOptions) -> bool {
+        let Some(rules) = &options.exclude_rules else {
+            return false;
+        };
+        path.ancestors()
+            .take_while(|ancestor| *ancestor != self.exclude_root)
+            .any(|ancestor| should_exclude(ancestor, &self.exclude_root, rules))
+    }
+}
+
+/// `cpx watch`: copies `sources` once, then copies files and directories as
+/// they are created or modified until interrupted. Deletions are not mirrored.
+pub fn watch(
+    sources: &[PathBuf],
+    destination: &Path,
+    debounce: Duration,
+    options: &CopyOptions,
+) -> CopyResult<()> {
+    for source in sources {
+        std::fs::symlink_metadata(source).map_err(|_| CopyError::InvalidSource(source.clone()))?;
+    }
+
+    // subscribe first so changes made during the initial copy are not missed
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    for source in sources {
+        subscribe(&mut watcher, &std::path::absolute(source)?).map_err(watch_error)?;
+    }
+
+    let stats = CopyStats::new();
+    let result = if sources.len() == 1 {
+        copy_with_stats(&sources[0], destination, options, &stats)
+    } else {
+        multiple_copy_with_stats(sources, destination, options, &stats)
+    };
+    match result {
+        Err(e) if options.control.is_aborted() => return Err(e),
+        Err(CopyError::PartialFailure(failures)) => {
+            for failure in &failures {
+                output::run_error(options, format!("  {}", failure));
+            }
+        }
+        Err(e) => return Err(e),
+        Ok(()) => {}
+    }
+    // built after the copy: whether the destination is a directory decides
+    // where a single file source goes
+    let roots = sources
+        .iter()
+        .map(|source| WatchRoot::new(source, destination))
+        .collect::<CopyResult<Vec<_>>>()?;
+
+    let sync_options = sync_options(options);
+    output::info(options, "Watching for changes, press Ctrl+C to stop");
+    let mut pending = BTreeSet::new();
+    let mut last_event = Instant::now();
+    while !options.control.is_aborted() {
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) if !matches!(event.kind, EventKind::Access(_)) => {
+                pending.extend(event.paths);
+                last_event = Instant::now();
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => output::run_error(options, format!("Watch error: {}", e)),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if !pending.is_empty() && last_event.elapsed() >= debounce {
+            sync(&roots, std::mem::take(&mut pending), &sync_options);
+        }
+    }
+    Ok(())
+}
+
+fn subscribe(watcher: &mut impl Watcher, source: &Path) -> notify::Result<()> {
+    if source.is_dir() {
+        watcher.watch(source, RecursiveMode::Recursive)
+    } else {
+        // editors save by renaming over the file, which ends a watch on it
+        watcher.watch(
+            source.parent().unwrap_or(source),
+            RecursiveMode::NonRecursive,
+        )
+    }
+}
+
+/// Each change is a small run of its own; bars, summaries and notifications
+/// for every one of them would only be noise.
+fn sync_options(options: &CopyOptions) -> CopyOptions {
+    let mut options = options.clone();
+    options.progress_bar.mode = ProgressMode::Never;
+    options.stats = None;
+    options.notify = None;
+    options
+}
+
+fn sync(roots: &[WatchRoot], changed: BTreeSet<PathBuf>, options: &CopyOptions) {
+    let stats = CopyStats::new();
+    let mut new_dirs: Vec<PathBuf> = Vec::new();
+    for path in changed {
+        // sorted, so a new directory comes before the entries it brought along
+        if new_dirs.iter().any(|dir| path.starts_with(dir)) {
+            continue;
+        }
+        let Some((root, target)) = roots
+            .iter()
+            .find_map(|root| root.target(&path).map(|target| (root, target)))
+        else {
+            continue;
+        };
+        if root.is_excluded(&path, options) {
+            output::skipped(options, &path, "excluded");
+            continue;
+        }
+        let Ok(metadata) = path.symlink_metadata() else {
+            continue;
+        };
+        let Some(parent) = target.parent() else {
+            continue;
+        };
+
+        let result = if metadata.is_dir() {
+            // existing directories only change through their entries
+            if target.exists() {
+                continue;
+            }
+            new_dirs.push(path.clone());
+            // copied into the parent, where it keeps its name
+            copy_with_stats(&path, parent, options, &stats)
+        } else {
+            std::fs::create_dir_all(parent)
+                .map_err(CopyError::from)
+                .and_then(|()| copy_with_stats(&path, &target, options, &stats))
+        };
+        if let Err(e) = result {
+            if options.control.is_aborted() {
+                return;
+            }
+            output::run_error(options, format!("Error copying {}: {}", path.display(), e));
+        }
+    }
+    let copied = stats.files_copied();
+    if copied > 0 {
+        output::info(options, format!("Copied {} changed file(s)", copied));
+    }
+}
+
+fn watch_error(e: notify::Error) -> CopyError {
+    CopyError::Io(std::io::Error::other(format!(
+        "cannot watch for changes: {}",
+        e
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::exclude::{ExcludePattern, build_exclude_rules};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_watch_root_maps_changes_into_destination() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("src");
+        fs::create_dir_all(source.join("cache")).unwrap();
+        fs::write(source.join("notes.txt"), "notes").unwrap();
+        let destination = temp_dir.path().join("dst");
+
+        let root = WatchRoot::new(&source, &destination).unwrap();
+        assert_eq!(
+            root.target(&source.join("cache/page.html")),
+            Some(destination.join("src/cache/page.html"))
+        );
+        assert_eq!(root.target(&source), None);
+        assert_eq!(root.target(&temp_dir.path().join("other.txt")), None);
+
+        let mut options = CopyOptions::none();
+        options.exclude_rules =
+            build_exclude_rules(vec![ExcludePattern::from_string("cache")]).unwrap();
+        assert!(root.is_excluded(&source.join("cache/page.html"), &options));
+        assert!(!root.is_excluded(&source.join("notes.txt"), &options));
+
+        let file = WatchRoot::new(&source.join("notes.txt"), &destination).unwrap();
+        assert_eq!(file.target(&source.join("notes.txt")), Some(destination));
+        assert_eq!(file.target(&source.join("other.txt")), None);
+    }
+}
@@ -1,6 +1,9 @@
+use indicatif::BinaryBytes;
+use serde::Serialize;
 use std::fmt;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum CpxError {
@@ -45,7 +48,77 @@ pub enum CopyError {
         source: PathBuf,
         destination: PathBuf,
     },
+    VerifyFailed {
+        source: PathBuf,
+        destination: PathBuf,
+    },
     PreserveFailed(PreserveError),
+    PartialFailure(Vec<FailedFile>),
+    ErrorLimitExceeded {
+        limit: usize,
+        failures: Vec<FailedFile>,
+    },
+    /// A file still copying after `--timeout-per-file`.
+    TimedOut {
+        path: PathBuf,
+        limit: Duration,
+    },
+    /// The run stopped at `--timeout-total`.
+    TimeLimitExceeded {
+        limit: Duration,
+        failures: Vec<FailedFile>,
+    },
+    /// The run stopped when the destination filled up. `remaining` bytes
+    /// were still to be written, with `available` free at that point.
+    DestinationFull {
+        remaining: u64,
+        available: u64,
+        failures: Vec<FailedFile>,
+    },
+    /// Abandoned on request (TUI skip); not counted as a failure.
+    Skipped(PathBuf),
+    /// Turned down at the `--confirm` prompt, before anything was written.
+    Declined,
+}
+
+/// A file that could not be copied while the rest of the run continued.
+#[derive(Debug)]
+pub struct FailedFile {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub error: CopyError,
+}
+
+/// Coarse failure class used when reporting errors to users and scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    NoSpace,
+    Unsupported,
+    Integrity,
+    Interrupted,
+    Io,
+}
+
+/// Process exit status, matching cp where it has an equivalent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    /// Some or all files failed to copy.
+    Failure = 1,
+    /// Invalid arguments, options or configuration.
+    Usage = 2,
+    /// Stopped by SIGINT/SIGTERM.
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
 }
 
 #[derive(Debug)]
@@ -142,11 +215,86 @@ impl fmt::Display for CopyError {
                     destination.display()
                 )
             }
+            CopyError::VerifyFailed {
+                source,
+                destination,
+            } => {
+                write!(
+                    f,
+                    "Verification failed: '{}' does not match '{}'",
+                    destination.display(),
+                    source.display()
+                )
+            }
             CopyError::PreserveFailed(e) => write!(f, "Preserve failed: {}", e),
+            CopyError::PartialFailure(failures) => {
+                write!(f, "{} file(s) failed to copy", failures.len())
+            }
+            CopyError::ErrorLimitExceeded { limit, failures } => {
+                write!(
+                    f,
+                    "Stopped after {} failures (--max-errors {})",
+                    failures.len(),
+                    limit
+                )
+            }
+            CopyError::TimedOut { path, limit } => write!(
+                f,
+                "Timed out after {} (--timeout-per-file): '{}'",
+                humantime::format_duration(*limit),
+                path.display()
+            ),
+            CopyError::TimeLimitExceeded { limit, .. } => write!(
+                f,
+                "Stopped after {} (--timeout-total)",
+                humantime::format_duration(*limit)
+            ),
+            CopyError::DestinationFull {
+                remaining,
+                available,
+                ..
+            } => write!(
+                f,
+                "Destination is full: {} more space is needed to finish ({} left to copy, {} free)",
+                BinaryBytes(remaining.saturating_sub(*available)),
+                BinaryBytes(*remaining),
+                BinaryBytes(*available)
+            ),
+            CopyError::Skipped(path) => write!(f, "Skipped by user: {}", path.display()),
+            CopyError::Declined => write!(f, "Cancelled, nothing was copied"),
         }
     }
 }
 
+impl fmt::Display for FailedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] '{}' -> '{}': {}",
+            self.error.category(),
+            self.source.display(),
+            self.destination.display(),
+            self.error
+        )
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorCategory::NotFound => "not found",
+            ErrorCategory::PermissionDenied => "permission denied",
+            ErrorCategory::AlreadyExists => "already exists",
+            ErrorCategory::NoSpace => "no space",
+            ErrorCategory::Unsupported => "unsupported",
+            ErrorCategory::Integrity => "integrity",
+            ErrorCategory::Interrupted => "interrupted",
+            ErrorCategory::Io => "io",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl fmt::Display for ExcludeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -276,7 +424,141 @@ impl CopyError {
             CopyError::ReflinkFailed { .. } => io::ErrorKind::Unsupported,
             CopyError::HardlinkFailed { .. } => io::ErrorKind::Other,
             CopyError::SymlinkFailed { .. } => io::ErrorKind::Other,
+            CopyError::VerifyFailed { .. } => io::ErrorKind::InvalidData,
             CopyError::PreserveFailed(_) => io::ErrorKind::Other,
+            CopyError::PartialFailure(_) => io::ErrorKind::Other,
+            CopyError::ErrorLimitExceeded { .. } => io::ErrorKind::Other,
+            CopyError::TimedOut { .. } => io::ErrorKind::TimedOut,
+            CopyError::TimeLimitExceeded { .. } => io::ErrorKind::TimedOut,
+            CopyError::DestinationFull { .. } => io::ErrorKind::StorageFull,
+            CopyError::Skipped(_) => io::ErrorKind::Other,
+            CopyError::Declined => io::ErrorKind::Other,
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        if matches!(self, CopyError::VerifyFailed { .. }) {
+            return ErrorCategory::Integrity;
+        }
+        match self.kind() {
+            io::ErrorKind::NotFound => ErrorCategory::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorCategory::PermissionDenied,
+            io::ErrorKind::AlreadyExists => ErrorCategory::AlreadyExists,
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => ErrorCategory::NoSpace,
+            io::ErrorKind::Unsupported => ErrorCategory::Unsupported,
+            io::ErrorKind::InvalidData => ErrorCategory::Integrity,
+            io::ErrorKind::Interrupted => ErrorCategory::Interrupted,
+            _ => ErrorCategory::Io,
+        }
+    }
+
+    /// Per-file failures collected before the run ended.
+    pub fn failures(&self) -> &[FailedFile] {
+        match self {
+            CopyError::PartialFailure(failures)
+            | CopyError::ErrorLimitExceeded { failures, .. }
+            | CopyError::TimeLimitExceeded { failures, .. }
+            | CopyError::DestinationFull { failures, .. } => failures,
+            _ => &[],
+        }
+    }
+
+    /// The step that failed, for `--error-report`.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            CopyError::FileExists(_) | CopyError::InvalidDestination(_) => "create",
+            CopyError::InvalidSource(_) => "read",
+            CopyError::ReflinkFailed { .. } => "reflink",
+            CopyError::HardlinkFailed { .. } => "hardlink",
+            CopyError::SymlinkFailed { .. } => "symlink",
+            CopyError::VerifyFailed { .. } => "verify",
+            CopyError::PreserveFailed(_) => "preserve",
+            _ => "copy",
+        }
+    }
+
+    /// The OS error number behind the failure, when there is one.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            CopyError::Io(e) | CopyError::PreserveFailed(PreserveError::Io(e)) => e.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    /// Errors worth retrying, typically from network filesystems.
+    pub fn is_transient(&self) -> bool {
+        let CopyError::Io(e) = self else {
+            return false;
+        };
+        matches!(
+            e.kind(),
+            io::ErrorKind::TimedOut
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::StaleNetworkFileHandle
+                | io::ErrorKind::ResourceBusy
+        ) || e.raw_os_error() == Some(libc::EIO)
+    }
+
+    /// The destination filesystem is full, or the user's quota on it used up.
+    pub fn is_out_of_space(&self) -> bool {
+        let CopyError::Io(e) = self else {
+            return false;
+        };
+        matches!(
+            e.kind(),
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+        )
+    }
+
+    /// The process or system ran out of file descriptors. Other workers
+    /// close theirs as they finish, so waiting and trying again helps.
+    pub fn is_out_of_descriptors(&self) -> bool {
+        let CopyError::Io(e) = self else {
+            return false;
+        };
+        matches!(e.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+    }
+
+    /// Sharing and lock violations: another process holds the file open.
+    pub fn is_locked(&self) -> bool {
+        let CopyError::Io(e) = self else {
+            return false;
+        };
+        // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+        cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33))
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        match self.kind() {
+            io::ErrorKind::Interrupted => ExitCode::Interrupted,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+impl FailedFile {
+    pub fn new(source: &Path, destination: &Path, error: CopyError) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            error,
+        }
+    }
+}
+
+impl CpxError {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            CpxError::Copy(e) => e.exit_code(),
+            CpxError::Config(_)
+            | CpxError::Exclude(_)
+            | CpxError::Preserve(_)
+            | CpxError::Validation(_)
+            | CpxError::InvalidPath(_) => ExitCode::Usage,
+            CpxError::OperationCancelled => ExitCode::Interrupted,
+            CpxError::Io(_) => ExitCode::Failure,
         }
     }
 }
@@ -1,5 +1,18 @@
+//! cpx as a library: build a copy with [`CopyBuilder`], then [`Copier::run`]
+//! it to get a [`CopySummary`]. Nothing is printed to the terminal; subscribe
+//! to [`Copier::events`] to follow progress.
 pub mod cli;
 pub mod config;
 pub mod core;
 pub mod error;
 pub mod utility;
+
+pub use crate::cli::args::{ConflictPolicy, FollowSymlink, LockedFiles, ReflinkMode, VerifyMode};
+pub use crate::core::builder::{Copier, CopyBuilder, CopySummary};
+pub use crate::error::{CopyError, CpxError, FailedFile};
+pub use crate::utility::chmod::Chmod;
+pub use crate::utility::control::{CancellationToken, ControlCommand, CopyControl};
+pub use crate::utility::events::CopyEvent;
+pub use crate::utility::hash::HashAlgo;
+pub use crate::utility::preserve::{Chown, PreserveAttr};
+pub use crate::utility::stats::StatsSummary;
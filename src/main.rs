@@ -1,70 +1,245 @@
 use cpx::cli::args::CLIArgs;
 use cpx::core::copy::{copy, multiple_copy};
-use cpx::error::CpxError;
+use cpx::core::plan::apply_plan;
+use cpx::core::watch::watch;
+use cpx::error::{CopyError, CopyResult, CpxError, ExitCode};
+use cpx::utility::control::CopyControl;
+use cpx::utility::history::{self, RunRecord};
+use cpx::utility::pause::{self, KeyListener};
+use cpx::utility::status::StatusServer;
+use cpx::utility::{error_report, fd_budget, output, priority, tui};
 use signal_hook::consts::signal::*;
 use signal_hook::iterator::Signals;
+use std::path::Path;
 use std::process;
 
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::Ordering;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 fn main() {
     // custom parser
     let args = CLIArgs::parse();
+    // large plans at high -j need more than the default 1024 on Linux
+    fd_budget::raise_limit();
 
     let (sources, destination, mut options) = match args.validate() {
         Ok(validated) => validated,
         Err(e) => {
-            eprintln!("Error: {}", e);
-            process::exit(1);
+            output::error(format!("Error: {}", e));
+            process::exit(e.exit_code().code());
         }
     };
 
-    let abort = Arc::new(AtomicBool::new(false));
-    options.abort = abort.clone();
+    // before any thread starts, so every worker inherits it
+    if let Err(e) = priority::lower_priority(options.nice, options.io_priority) {
+        output::warn(&options, format!("Could not lower priority: {}", e));
+    }
+
+    // tracked for the TUI and for `cpx status`
+    let control = Arc::new(CopyControl::tracked());
+    options.control = control.clone();
+    control.set_timeouts(options.timeout_per_file, options.timeout_total);
+    control.set_bwlimit(options.bwlimit);
 
-    let mut signals = Signals::new([SIGINT, SIGTERM])
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGUSR1, SIGUSR2])
         .map_err(CpxError::Io)
         .unwrap_or_else(|e| {
-            eprintln!("Failed to setup signal handler: {}", e);
-            process::exit(1);
+            output::error(format!("Failed to setup signal handler: {}", e));
+            process::exit(ExitCode::Failure.code());
         });
 
     std::thread::spawn({
-        let abort = abort.clone();
+        let control = control.clone();
+        let verbosity = options.verbosity;
         move || {
             for sig in signals.forever() {
                 match sig {
                     SIGINT | SIGTERM => {
-                        abort.store(true, Ordering::Relaxed);
+                        pause::restore_terminal();
+                        control.abort();
                     }
+                    SIGUSR1 => pause::set_paused(&control, true, verbosity),
+                    SIGUSR2 => pause::set_paused(&control, false, verbosity),
                     _ => unreachable!(),
                 }
             }
         }
     });
 
-    let result = if sources.len() == 1 {
-        copy(&sources[0], &destination, &options)
+    let run = || {
+        if let Some(plan) = &options.apply {
+            apply_plan(plan, &options)
+        } else if let Some(debounce) = options.watch {
+            watch(&sources, &destination, debounce, &options)
+        } else if sources.len() == 1 {
+            copy(&sources[0], &destination, &options)
+        } else {
+            multiple_copy(sources, destination, &options)
+        }
+    };
+    let started = (SystemTime::now(), Instant::now());
+    let command = std::env::args().collect::<Vec<_>>().join(" ");
+    let status_server = options
+        .status_socket
+        .then(|| {
+            StatusServer::start(control.clone(), command, options.verbosity)
+                .inspect_err(|e| {
+                    if let Some(logger) = &options.logger {
+                        logger.debug(&format!("status socket unavailable: {}", e));
+                    }
+                })
+                .ok()
+        })
+        .flatten();
+    // prompts and the TUI read the terminal themselves
+    let keys = (options.shows_progress() && !options.confirm)
+        .then(|| KeyListener::start(control.clone(), options.verbosity))
+        .flatten();
+    let result = if options.tui {
+        tui::run(&control, run).unwrap_or_else(|e| {
+            output::error(format!("Failed to start the TUI: {}", e));
+            process::exit(ExitCode::Failure.code());
+        })
     } else {
-        multiple_copy(sources, destination, &options)
+        run()
     };
+    // restore the terminal and remove the socket before anything can exit
+    // the process
+    drop(keys);
+    drop(status_server);
 
-    match result {
-        Ok(_) => {
-            // normal
+    if let Some(path) = &options.error_report {
+        let failures = match &result {
+            Err(e) => e.failures(),
+            Ok(()) => &[],
+        };
+        let retries = |source: &Path| control.retries(source);
+        if let Err(e) = error_report::write(path, failures, options.error_report_format, retries) {
+            output::error(format!(
+                "Failed to write error report {}: {}",
+                path.display(),
+                e
+            ));
         }
-        Err(e) => {
-            // interrupt check
-            if abort.load(Ordering::Relaxed) {
-                eprintln!("\nOperation interrupted");
-                eprintln!("Resume with: cpx --resume [original command]");
-                eprintln!("Completed files will be skipped automatically");
-                process::exit(130); // SIGINT
-            } else {
-                eprintln!("Error copying file: {}", e);
-                process::exit(1);
+    }
+
+    let code = match &result {
+        Ok(()) => ExitCode::Success,
+        // interrupt check, --max-errors also raises the abort flag
+        Err(e)
+            if control.is_aborted()
+                && !matches!(
+                    e,
+                    CopyError::ErrorLimitExceeded { .. }
+                        | CopyError::TimeLimitExceeded { .. }
+                        | CopyError::DestinationFull { .. }
+                ) =>
+        {
+            ExitCode::Interrupted
+        }
+        Err(e) => e.exit_code(),
+    };
+
+    if options.history
+        && let Err(e) = history::record(&run_record(
+            &control,
+            started,
+            options.job.as_ref().map(|job| job.name()),
+            &result,
+            code,
+        ))
+        && let Some(logger) = &options.logger
+    {
+        logger.debug(&format!("could not record the run in the history: {}", e));
+    }
+    if code == ExitCode::Success
+        && let Some(job) = &options.job
+        && let Err(e) = job.finished()
+    {
+        output::warn(
+            &options,
+            format!("Could not mark job {} finished: {}", job.name(), e),
+        );
+    }
+
+    if let Err(e) = result {
+        match &e {
+            _ if code == ExitCode::Interrupted => {
+                output::error("\nOperation interrupted");
+                output::error("Resume with: cpx --resume [original command]");
+                output::error("Completed files will be skipped automatically");
             }
+            CopyError::PartialFailure(failures) => report_failures(failures),
+            CopyError::Declined => output::error(e.to_string()),
+            CopyError::ErrorLimitExceeded { failures, .. }
+            | CopyError::TimeLimitExceeded { failures, .. } => {
+                report_failures(failures);
+                output::error(format!("\n{}", e));
+            }
+            CopyError::DestinationFull { failures, .. } => {
+                // the files cut short by the full disk are simply not done yet
+                let others: Vec<_> = failures
+                    .iter()
+                    .filter(|failure| !failure.error.is_out_of_space())
+                    .collect();
+                report_failures(&others);
+                output::error(format!("\n{}", e));
+                output::error("Free up space, then resume with: cpx --resume [original command]");
+            }
+            _ => output::error(format!("Error copying file: {}", e)),
+        }
+        process::exit(code.code());
+    }
+}
+
+/// What `cpx history` keeps of this run.
+fn run_record(
+    control: &CopyControl,
+    (started, clock): (SystemTime, Instant),
+    job: Option<&str>,
+    result: &CopyResult<()>,
+    code: ExitCode,
+) -> RunRecord {
+    let mut record = RunRecord {
+        id: 0,
+        started: started
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs()),
+        job: job.map(String::from),
+        duration_seconds: clock.elapsed().as_secs_f64(),
+        cwd: std::env::current_dir().unwrap_or_default(),
+        args: std::env::args().collect(),
+        exit_code: code.code(),
+        total_files: 0,
+        files_done: 0,
+        total_bytes: 0,
+        bytes_done: 0,
+        failed: 0,
+        errors: Vec::new(),
+    };
+    if let Some(activity) = control.activity() {
+        record.total_files = activity.total_files.load(Ordering::Relaxed);
+        record.files_done = activity.files_done.load(Ordering::Relaxed);
+        record.total_bytes = activity.total_bytes.load(Ordering::Relaxed);
+        record.bytes_done = activity.bytes_done.load(Ordering::Relaxed);
+    }
+    match result {
+        Ok(()) => record,
+        Err(e) if e.failures().is_empty() => record.with_errors([e.to_string()]),
+        Err(e) => {
+            record.failed = e.failures().len();
+            record.with_errors(e.failures().iter().map(|failure| failure.to_string()))
         }
     }
 }
+
+fn report_failures(failures: &[impl std::fmt::Display]) {
+    if failures.is_empty() {
+        return;
+    }
+    output::error(format!("\nFailed to copy {} file(s):", failures.len()));
+    for failure in failures {
+        output::error(format!("  {}", failure));
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Workers `-j auto` starts with.
+pub const START_WORKERS: usize = 2;
+/// Most workers `-j auto` will run.
+pub const MAX_WORKERS: usize = 32;
+
+const TICK: Duration = Duration::from_millis(250);
+/// Ticks measured at one worker count before it is judged.
+const WINDOW_TICKS: usize = 4;
+/// Changes smaller than this are treated as noise.
+const THRESHOLD: f64 = 0.05;
+
+/// A semaphore whose size can change while workers wait on it.
+#[derive(Debug)]
+pub struct Limiter {
+    state: Mutex<LimiterState>,
+    freed: Condvar,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    limit: usize,
+    active: usize,
+}
+
+pub struct Permit<'a>(&'a Limiter);
+
+impl Limiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                limit: limit.max(1),
+                active: 0,
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().map(|s| s.limit).unwrap_or(1)
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        if let Ok(mut state) = self.state.lock() {
+            state.limit = limit.max(1);
+        }
+        self.freed.notify_all();
+    }
+
+    /// Blocks until fewer than `limit` permits are out.
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.active >= state.limit {
+            state = self.freed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.active += 1;
+        Permit(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.0.state.lock() {
+            state.active -= 1;
+        }
+        self.0.freed.notify_one();
+    }
+}
+
+/// Work finished during one tick: files or entries, and bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub items: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Measure {
+    throughput: f64,
+    latency: f64,
+}
+
+/// Hill-climbs the worker count: keeps stepping while throughput improves,
+/// turns around when it drops, and backs off when only latency grows.
+#[derive(Debug)]
+pub struct Tuner {
+    workers: usize,
+    max: usize,
+    rising: bool,
+    window: VecDeque<Sample>,
+    last: Option<Measure>,
+}
+
+impl Tuner {
+    pub fn new(start: usize, max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            workers: start.clamp(1, max),
+            max,
+            rising: true,
+            window: VecDeque::with_capacity(WINDOW_TICKS),
+            last: None,
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Records one tick and returns the worker count to run next.
+    pub fn observe(&mut self, sample: Sample) -> usize {
+        if self.window.len() == WINDOW_TICKS {
+            self.window.pop_front();
+        }
+        self.window.push_back(sample);
+        if self.window.len() < WINDOW_TICKS {
+            return self.workers;
+        }
+        let Some(current) = self.measure() else {
+            // nothing finished for a whole window, e.g. one huge file
+            return self.workers;
+        };
+
+        let step = match self.last {
+            None => true,
+            Some(last) => {
+                let gain = current.throughput / last.throughput.max(f64::MIN_POSITIVE);
+                if gain >= 1.0 + THRESHOLD {
+                    true
+                } else if gain <= 1.0 - THRESHOLD {
+                    self.rising = !self.rising;
+                    true
+                } else if current.latency / last.latency.max(f64::MIN_POSITIVE) >= 1.0 + THRESHOLD {
+                    // the same throughput for more waiting: fewer workers do
+                    self.rising = false;
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        self.last = Some(current);
+        if step {
+            let delta = (self.workers / 4).max(1);
+            let next = if self.rising {
+                (self.workers + delta).min(self.max)
+            } else {
+                self.workers.saturating_sub(delta).max(1)
+            };
+            if next != self.workers {
+                self.workers = next;
+                self.window.clear();
+            }
+        }
+        self.workers
+    }
+
+    /// Throughput in bytes per second (items when nothing carries bytes,
+    /// as in a scan) and, by Little's law, seconds per item.
+    fn measure(&self) -> Option<Measure> {
+        let items: u64 = self.window.iter().map(|s| s.items).sum();
+        let bytes: u64 = self.window.iter().map(|s| s.bytes).sum();
+        let seconds: f64 = self.window.iter().map(|s| s.elapsed.as_secs_f64()).sum();
+        if seconds <= 0.0 || (items == 0 && bytes == 0) {
+            return None;
+        }
+        let throughput = if bytes > 0 { bytes } else { items } as f64 / seconds;
+        let latency = if items > 0 {
+            self.workers as f64 * seconds / items as f64
+        } else {
+            f64::INFINITY
+        };
+        Some(Measure {
+            throughput,
+            latency,
+        })
+    }
+}
+
+/// Runs `work` while a background thread feeds `tuner` from `progress`,
+/// the (items, bytes) done so far, every tick and resizes `limiter`.
+pub fn with_tuning<R>(
+    limiter: &Limiter,
+    mut tuner: Tuner,
+    progress: impl Fn() -> (u64, u64) + Sync,
+    work: impl FnOnce() -> R,
+) -> R {
+    limiter.set_limit(tuner.workers());
+    let (done, ticks) = mpsc::channel::<()>();
+    let progress = &progress;
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            let mut last = progress();
+            let mut last_at = Instant::now();
+            while let Err(mpsc::RecvTimeoutError::Timeout) = ticks.recv_timeout(TICK) {
+                let now = progress();
+                let sample = Sample {
+                    items: now.0.saturating_sub(last.0),
+                    bytes: now.1.saturating_sub(last.1),
+                    elapsed: last_at.elapsed(),
+                };
+                last = now;
+                last_at = Instant::now();
+                limiter.set_limit(tuner.observe(sample));
+            }
+        });
+        let result = work();
+        drop(done);
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(items: u64, bytes: u64) -> Sample {
+        Sample {
+            items,
+            bytes,
+            elapsed: TICK,
+        }
+    }
+
+    /// Feeds a full window in which each worker adds `per_worker` bytes,
+    /// up to `ceiling` bytes per tick.
+    fn run_window(tuner: &mut Tuner, per_worker: u64, ceiling: u64) -> usize {
+        let mut workers = tuner.workers();
+        for _ in 0..WINDOW_TICKS {
+            let bytes = (workers as u64 * per_worker).min(ceiling);
+            workers = tuner.observe(tick(bytes / 1000, bytes));
+        }
+        workers
+    }
+
+    #[test]
+    fn test_tuner_climbs_to_the_throughput_ceiling() {
+        let mut tuner = Tuner::new(START_WORKERS, MAX_WORKERS);
+        let mut seen = Vec::new();
+        for _ in 0..40 {
+            seen.push(run_window(&mut tuner, 1_000_000, 8_000_000));
+        }
+        let settled = &seen[30..];
+        assert!(seen.iter().any(|&w| w >= 8), "{:?}", seen);
+        assert!(settled.iter().all(|&w| (4..=12).contains(&w)), "{:?}", seen);
+    }
+
+    #[test]
+    fn test_tuner_holds_without_progress_and_stays_in_bounds() {
+        let mut tuner = Tuner::new(10, 4);
+        assert_eq!(tuner.workers(), 4);
+        for _ in 0..WINDOW_TICKS * 3 {
+            assert_eq!(tuner.observe(tick(0, 0)), 4);
+        }
+        for _ in 0..20 {
+            run_window(&mut tuner, 1_000_000, u64::MAX);
+        }
+        assert_eq!(tuner.workers(), 4);
+    }
+
+    #[test]
+    fn test_limiter_resizes_while_in_use() {
+        let limiter = Limiter::new(1);
+        let first = limiter.acquire();
+        limiter.set_limit(2);
+        let second = limiter.acquire();
+        drop(first);
+        drop(second);
+        limiter.set_limit(0);
+        assert_eq!(limiter.limit(), 1);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::cli::args::BackupMode;
 use crate::error::{CopyError, CopyResult};
+use crate::utility::rename::rename_or_copy;
 use std::io;
 use std::path::{Path, PathBuf};
 
@@ -72,10 +73,16 @@ fn format_numbered_backup(path: &Path, number: u32) -> PathBuf {
     PathBuf::from(path_str)
 }
 
-pub fn create_backup(destination: &Path, backup_path: &PathBuf) -> CopyResult<()> {
-    std::fs::rename(destination, backup_path).map_err(|e| CopyError::CopyFailed {
+/// Moves `destination` aside to `backup_path`, copying it when the two are
+/// on different filesystems; `progress` follows such a copy.
+pub fn create_backup(
+    destination: &Path,
+    backup_path: &Path,
+    progress: impl FnMut(u64),
+) -> CopyResult<()> {
+    rename_or_copy(destination, backup_path, progress).map_err(|e| CopyError::CopyFailed {
         source: destination.to_path_buf(),
-        destination: backup_path.clone(),
+        destination: backup_path.to_path_buf(),
         reason: format!("Failed to create backup: {}", e),
     })
 }
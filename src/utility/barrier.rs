@@ -0,0 +1,173 @@
+use crate::core::bench::parse_size;
+use crate::utility::helper::{sync_parent_dirs, sync_path};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// `--write-barrier`: how much may be written between flushes, as a file
+/// count, a byte count, or both; whichever is reached first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierSpec {
+    pub files: Option<u64>,
+    pub bytes: Option<u64>,
+}
+
+impl FromStr for BarrierSpec {
+    type Err = String;
+
+    /// `1000` (files), `512M` (bytes, which need a K, M or G suffix) or
+    /// both, comma-separated.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = BarrierSpec {
+            files: None,
+            bytes: None,
+        };
+        for part in s.split(',').map(str::trim) {
+            if part.is_empty() {
+                return Err(format!("invalid write barrier '{}'", s));
+            }
+            if part.bytes().all(|b| b.is_ascii_digit()) {
+                let files = part
+                    .parse()
+                    .ok()
+                    .filter(|&files| files > 0)
+                    .ok_or_else(|| format!("invalid file count '{}'", part))?;
+                spec.files = Some(files);
+            } else {
+                spec.bytes = Some(parse_size(part)?);
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// Files written since the last flush.
+#[derive(Debug, Default)]
+struct Pending {
+    files: Vec<PathBuf>,
+    bytes: u64,
+}
+
+/// Defers fsyncing destination files until enough of them are written,
+/// then flushes the batch along with the directories holding them. The
+/// kernel writes the batch back as a whole instead of one file per sync.
+#[derive(Debug)]
+pub struct WriteBarrier {
+    spec: BarrierSpec,
+    pending: Mutex<Pending>,
+}
+
+impl WriteBarrier {
+    pub fn new(spec: BarrierSpec) -> Self {
+        Self {
+            spec,
+            pending: Mutex::new(Pending::default()),
+        }
+    }
+
+    /// Records a finished destination of `size` bytes, and flushes the batch
+    /// when it reaches the barrier. Other workers keep copying meanwhile.
+    pub fn written(&self, path: &Path, size: u64) -> io::Result<()> {
+        let batch = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            pending.files.push(path.to_path_buf());
+            pending.bytes += size;
+            let full = self
+                .spec
+                .files
+                .is_some_and(|files| pending.files.len() as u64 >= files)
+                || self.spec.bytes.is_some_and(|bytes| pending.bytes >= bytes);
+            if !full {
+                return Ok(());
+            }
+            std::mem::take(&mut *pending)
+        };
+        sync_batch(&batch.files)
+    }
+
+    /// Flushes whatever was written since the last barrier, at the end of a
+    /// run.
+    pub fn flush(&self) -> io::Result<()> {
+        let batch = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::take(&mut *pending)
+        };
+        sync_batch(&batch.files)
+    }
+}
+
+/// Reopens and fsyncs each file, then the directories they were created in.
+/// Ones removed or replaced by something else since are left alone.
+fn sync_batch(files: &[PathBuf]) -> io::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    for file in files {
+        match std::fs::symlink_metadata(file) {
+            Ok(metadata) if metadata.is_file() => sync_path(file).map_err(|e| {
+                io::Error::new(e.kind(), format!("cannot sync '{}': {}", file.display(), e))
+            })?,
+            _ => continue,
+        }
+    }
+    sync_parent_dirs(files.iter().map(PathBuf::as_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_barrier_spec() {
+        assert_eq!(
+            "1000".parse(),
+            Ok(BarrierSpec {
+                files: Some(1000),
+                bytes: None
+            })
+        );
+        assert_eq!(
+            "1000, 1G".parse(),
+            Ok(BarrierSpec {
+                files: Some(1000),
+                bytes: Some(1 << 30)
+            })
+        );
+        for spec in ["", "0", "1000,", "1T"] {
+            assert!(spec.parse::<BarrierSpec>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_barrier_flushes_in_batches() {
+        let temp_dir = TempDir::new().unwrap();
+        let barrier = WriteBarrier::new("3,1K".parse().unwrap());
+        let paths: Vec<_> = (0..4)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("file{}", i));
+                std::fs::write(&path, b"data").unwrap();
+                path
+            })
+            .collect();
+
+        let pending = |barrier: &WriteBarrier| barrier.pending.lock().unwrap().files.len();
+        barrier.written(&paths[0], 4).unwrap();
+        barrier.written(&paths[1], 4).unwrap();
+        assert_eq!(pending(&barrier), 2);
+        // the third file reaches the count
+        barrier.written(&paths[2], 4).unwrap();
+        assert_eq!(pending(&barrier), 0);
+        // a large file reaches the size on its own
+        barrier.written(&paths[3], 4096).unwrap();
+        assert_eq!(pending(&barrier), 0);
+
+        // a file gone by the time of the flush isn't an error
+        barrier
+            .written(&temp_dir.path().join("missing"), 4)
+            .unwrap();
+        barrier.flush().unwrap();
+        assert_eq!(pending(&barrier), 0);
+    }
+}
@@ -0,0 +1,133 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Buffers kept by [`shared`] until a run sizes it for its workers.
+const DEFAULT_CAPACITY: usize = 8;
+
+static SHARED: BufferPool = BufferPool::new(DEFAULT_CAPACITY);
+
+/// The pool the copy loop and checksums draw their buffers from.
+pub fn shared() -> &'static BufferPool {
+    &SHARED
+}
+
+/// I/O buffers handed from one file to the next, so that once every worker
+/// has copied a file of each size, copying another allocates nothing.
+pub struct BufferPool {
+    free: Mutex<Vec<Box<[u8]>>>,
+    /// Most buffers kept for reuse. More can be out at once; the extra ones
+    /// are freed as they come back.
+    capacity: AtomicUsize,
+}
+
+impl BufferPool {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+            capacity: AtomicUsize::new(capacity),
+        }
+    }
+
+    /// Keeps up to `capacity` buffers: two per worker covers a copy buffer
+    /// and a checksum buffer each.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        if free.len() > capacity {
+            free.sort_unstable_by_key(|buffer| std::cmp::Reverse(buffer.len()));
+            free.truncate(capacity);
+        }
+        // so that giving buffers back never grows the list
+        let spare = capacity.saturating_sub(free.len());
+        free.reserve(spare);
+    }
+
+    /// A buffer of `len` bytes: the smallest free one that is big enough,
+    /// or a new one when none is. Its contents are whatever was left in it.
+    pub fn take(&self, len: usize) -> PooledBuffer<'_> {
+        let reused = {
+            let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+            free.iter()
+                .enumerate()
+                .filter(|(_, buffer)| buffer.len() >= len)
+                .min_by_key(|(_, buffer)| buffer.len())
+                .map(|(index, _)| index)
+                .map(|index| free.swap_remove(index))
+        };
+        PooledBuffer {
+            buffer: reused.unwrap_or_else(|| vec![0u8; len].into_boxed_slice()),
+            len,
+            pool: self,
+        }
+    }
+
+    fn give_back(&self, buffer: Box<[u8]>) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        if free.len() < capacity {
+            free.push(buffer);
+        } else if let Some(smallest) = free.iter_mut().min_by_key(|kept| kept.len()) {
+            // a full pool keeps the larger buffers, which serve any request
+            if smallest.len() < buffer.len() {
+                *smallest = buffer;
+            }
+        }
+    }
+}
+
+/// A buffer on loan from a [`BufferPool`], returned to it when dropped.
+pub struct PooledBuffer<'a> {
+    buffer: Box<[u8]>,
+    len: usize,
+    pool: &'a BufferPool,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer[..self.len]
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buffer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_reuses_returned_buffers() {
+        let pool = BufferPool::new(2);
+        let first = pool.take(64 * 1024).as_ptr();
+        // a smaller request is served from the same allocation
+        let second = pool.take(1024);
+        assert_eq!(second.as_ptr(), first);
+        assert_eq!(second.len(), 1024);
+        drop(second);
+        // a larger one can't be
+        assert_ne!(pool.take(128 * 1024).as_ptr(), first);
+    }
+
+    #[test]
+    fn test_pool_keeps_at_most_capacity() {
+        let pool = BufferPool::new(2);
+        let buffers: Vec<_> = [1, 2, 3].iter().map(|&len| pool.take(len)).collect();
+        drop(buffers);
+        let free = pool.free.lock().unwrap();
+        let mut kept: Vec<_> = free.iter().map(|buffer| buffer.len()).collect();
+        kept.sort_unstable();
+        assert_eq!(kept, [2, 3]);
+    }
+}
@@ -0,0 +1,378 @@
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+const FS_COMPR_FL: u32 = 0x0000_0004;
+const FS_SYNC_FL: u32 = 0x0000_0008;
+const FS_IMMUTABLE_FL: u32 = 0x0000_0010;
+const FS_APPEND_FL: u32 = 0x0000_0020;
+const FS_NODUMP_FL: u32 = 0x0000_0040;
+const FS_NOATIME_FL: u32 = 0x0000_0080;
+const FS_NOCOMP_FL: u32 = 0x0000_0400;
+const FS_DIRSYNC_FL: u32 = 0x0001_0000;
+const FS_TOPDIR_FL: u32 = 0x0002_0000;
+const FS_NOCOW_FL: u32 = 0x0080_0000;
+
+/// The flags cpx copies and `--set-attr` can change, from `linux/fs.h`,
+/// with the letters `chattr` and `lsattr` use.
+const FLAGS: &[(char, u32)] = &[
+    ('c', FS_COMPR_FL),
+    ('S', FS_SYNC_FL),
+    ('i', FS_IMMUTABLE_FL),
+    ('a', FS_APPEND_FL),
+    ('d', FS_NODUMP_FL),
+    ('A', FS_NOATIME_FL),
+    ('m', FS_NOCOMP_FL),
+    ('D', FS_DIRSYNC_FL),
+    ('T', FS_TOPDIR_FL),
+    ('C', FS_NOCOW_FL),
+];
+
+/// Flags that only take proper effect on an empty file: btrfs ignores
+/// `C` once a file has data, and `c`/`m` only govern data written later.
+#[cfg(target_os = "linux")]
+const CONTENT: u32 = FS_COMPR_FL | FS_NOCOMP_FL | FS_NOCOW_FL;
+/// Flags only a process with `CAP_LINUX_IMMUTABLE` may set.
+#[cfg(target_os = "linux")]
+const PRIVILEGED: u32 = FS_IMMUTABLE_FL | FS_APPEND_FL;
+
+/// `--set-attr`: file attribute flags set on or cleared from every copy,
+/// written as `chattr` takes them, e.g. `+C` or `+c,-d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Chattr {
+    set: u32,
+    clear: u32,
+}
+
+impl Chattr {
+    /// `flags` with this change made.
+    pub fn apply(&self, flags: u32) -> u32 {
+        (flags & !self.clear) | self.set
+    }
+}
+
+impl FromStr for Chattr {
+    type Err = String;
+
+    /// One or more `[+-=]` runs of flag letters, optionally separated by
+    /// commas. `=` clears every flag cpx knows before setting its own.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid --set-attr value '{}'", s);
+        let mut chattr = Chattr::default();
+        let mut op = None;
+        for c in s.chars().filter(|c| !c.is_whitespace()) {
+            match c {
+                '+' | '-' | '=' => {
+                    op = Some(c);
+                    if c == '=' {
+                        chattr = Chattr {
+                            set: 0,
+                            clear: all_flags(),
+                        };
+                    }
+                }
+                ',' => op = None,
+                letter => {
+                    let flag = FLAGS
+                        .iter()
+                        .find(|(l, _)| *l == letter)
+                        .map(|(_, flag)| *flag)
+                        .ok_or_else(invalid)?;
+                    match op.ok_or_else(invalid)? {
+                        '-' => {
+                            chattr.set &= !flag;
+                            chattr.clear |= flag;
+                        }
+                        _ => {
+                            chattr.set |= flag;
+                            chattr.clear &= !flag;
+                        }
+                    }
+                }
+            }
+        }
+        if chattr == Chattr::default() {
+            return Err(invalid());
+        }
+        Ok(chattr)
+    }
+}
+
+fn all_flags() -> u32 {
+    FLAGS.iter().fold(0, |all, (_, flag)| all | flag)
+}
+
+/// Sets the flags that must be in place before any data is written on
+/// `file`, the copy of `source` just created: those `source` has when
+/// `preserve`, changed by `change`. Best effort; [`apply`] reports what
+/// couldn't be set once the copy is done.
+#[cfg(target_os = "linux")]
+pub fn prepare(file: &std::fs::File, source: Option<&Path>, change: Option<Chattr>) {
+    let from_source = source.and_then(|source| flags_of(source).ok()).unwrap_or(0);
+    let wanted = change.map_or(from_source, |change| change.apply(from_source)) & CONTENT;
+    // a device being written to would take the ioctl as its own
+    if wanted != 0
+        && file.metadata().is_ok_and(|metadata| metadata.is_file())
+        && let Ok(current) = get_flags(file)
+    {
+        let _ = set_flags(file, current | wanted);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn prepare(_file: &std::fs::File, _source: Option<&Path>, _change: Option<Chattr>) {}
+
+/// Gives `destination` the flags `source` has, when there is one to
+/// preserve them from, changed by `change`. Flags the destination's
+/// filesystem has no room for, and `i` and `a` without the privilege to
+/// set them, are only copied where permitted; a `change` asking for them
+/// is an error.
+#[cfg(target_os = "linux")]
+pub fn apply(source: Option<&Path>, destination: &Path, change: Option<Chattr>) -> io::Result<()> {
+    let from_source = match source.map(flags_of) {
+        Some(Ok(flags)) => Some(flags),
+        // nothing to copy from a filesystem without flags
+        Some(Err(e)) if unsupported(&e) => None,
+        Some(Err(e)) => return Err(e),
+        None => None,
+    };
+    if from_source.is_none() && change.is_none() {
+        return Ok(());
+    }
+    // device nodes would take the ioctl as their own, and symlinks have
+    // no flags of their own
+    let metadata = std::fs::symlink_metadata(destination)?;
+    if !metadata.is_file() && !metadata.is_dir() {
+        return Ok(());
+    }
+    let file = match open(destination) {
+        Ok(file) => file,
+        Err(e) if change.is_none() && unsupported(&e) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let current = match get_flags(&file) {
+        Ok(flags) => flags,
+        Err(e) if change.is_none() && unsupported(&e) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let copied = all_flags();
+    let preserved = from_source.map_or(current, |flags| (current & !copied) | (flags & copied));
+    let wanted = change.map_or(preserved, |change| change.apply(preserved));
+    if wanted == current {
+        return Ok(());
+    }
+    match set_flags(&file, wanted) {
+        Err(e) if change.is_none() && unsupported(&e) => Ok(()),
+        // without CAP_LINUX_IMMUTABLE, copy what may be copied; what
+        // --set-attr asks for explicitly stays an error
+        Err(e)
+            if e.kind() == io::ErrorKind::PermissionDenied
+                && change.is_none_or(|change| change.set & PRIVILEGED == 0)
+                && (wanted ^ current) & PRIVILEGED != 0 =>
+        {
+            let wanted = (wanted & !PRIVILEGED) | (current & PRIVILEGED);
+            if wanted == current {
+                return Ok(());
+            }
+            set_flags(&file, wanted)
+        }
+        result => result,
+    }
+}
+
+/// Gives `destination` the BSD file flags (`uchg`, `hidden`, `nodump`,
+/// ...) `source` has, as `cp -p` does. The system flags only the superuser
+/// may change are copied where permitted.
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub fn apply(source: Option<&Path>, destination: &Path, change: Option<Chattr>) -> io::Result<()> {
+    /// The system flags (`schg`, `sappnd`, ...), `SF_SETTABLE`.
+    const SYSTEM: u32 = 0xffff_0000;
+    /// `UF_COMPRESSED`: the copy's data was written uncompressed.
+    #[cfg(target_os = "macos")]
+    const UNCOPIED: u32 = 0x0000_0020;
+    #[cfg(not(target_os = "macos"))]
+    const UNCOPIED: u32 = 0;
+
+    if change.is_some() {
+        return Err(set_attr_unsupported());
+    }
+    let Some(source) = source else {
+        return Ok(());
+    };
+    let current = st_flags(&std::fs::symlink_metadata(destination)?);
+    let wanted = (st_flags(&std::fs::symlink_metadata(source)?) & !UNCOPIED) | (current & UNCOPIED);
+    if wanted == current {
+        return Ok(());
+    }
+    match lchflags(destination, wanted) {
+        // a filesystem without flags has nothing to lose when there are none
+        Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) && wanted & !UNCOPIED == 0 => Ok(()),
+        Err(e)
+            if e.kind() == io::ErrorKind::PermissionDenied && (wanted ^ current) & SYSTEM != 0 =>
+        {
+            let wanted = (wanted & !SYSTEM) | (current & SYSTEM);
+            if wanted == current {
+                return Ok(());
+            }
+            lchflags(destination, wanted)
+        }
+        result => result,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn apply(
+    _source: Option<&Path>,
+    _destination: &Path,
+    change: Option<Chattr>,
+) -> io::Result<()> {
+    match change {
+        Some(_) => Err(set_attr_unsupported()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_attr_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--set-attr is only supported on Linux",
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn st_flags(metadata: &std::fs::Metadata) -> u32 {
+    std::os::macos::fs::MetadataExt::st_flags(metadata)
+}
+
+#[cfg(target_os = "freebsd")]
+fn st_flags(metadata: &std::fs::Metadata) -> u32 {
+    std::os::freebsd::fs::MetadataExt::st_flags(metadata)
+}
+
+/// `chflags` without following a symlink, which has flags of its own.
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+fn lchflags(path: &Path, flags: u32) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if unsafe { libc::lchflags(path.as_ptr(), flags as _) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The flags of a regular file or directory; none for anything else.
+#[cfg(target_os = "linux")]
+fn flags_of(path: &Path) -> io::Result<u32> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_file() && !metadata.is_dir() {
+        return Ok(0);
+    }
+    get_flags(&open(path)?)
+}
+
+#[cfg(target_os = "linux")]
+fn open(path: &Path) -> io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::File::options()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+}
+
+/// Errors from a filesystem that keeps no flags, or not these.
+#[cfg(target_os = "linux")]
+fn unsupported(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::ENOTTY | libc::EOPNOTSUPP | libc::EINVAL)
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn get_flags(file: &std::fs::File) -> io::Result<u32> {
+    use std::os::unix::io::AsRawFd;
+
+    // the kernel reads and writes an int, whatever the ioctl number says
+    let mut flags: libc::c_int = 0;
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(flags as u32)
+}
+
+#[cfg(target_os = "linux")]
+fn set_flags(file: &std::fs::File, flags: u32) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let flags = flags as libc::c_int;
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_set_attr() {
+        let chattr: Chattr = "+C".parse().unwrap();
+        assert_eq!(chattr.apply(0), FS_NOCOW_FL);
+
+        let chattr: Chattr = "+c,-d".parse().unwrap();
+        assert_eq!(chattr.apply(FS_NODUMP_FL), FS_COMPR_FL);
+        // flags cpx doesn't know are left alone
+        assert_eq!(chattr.apply(0x0008_0000), 0x0008_0000 | FS_COMPR_FL);
+
+        let chattr: Chattr = "=i".parse().unwrap();
+        assert_eq!(chattr.apply(FS_COMPR_FL | FS_APPEND_FL), FS_IMMUTABLE_FL);
+
+        for spec in ["", "c", "+z", "+", ","] {
+            assert!(spec.parse::<Chattr>().is_err(), "{}", spec);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_apply_copies_flags_where_supported() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let destination = temp_dir.path().join("destination");
+        std::fs::write(&source, b"data").unwrap();
+        std::fs::write(&destination, b"data").unwrap();
+
+        // nodump is unprivileged and kept by ext4, xfs and btrfs alike;
+        // elsewhere both sides simply have no flags
+        let nodump: Chattr = "+d".parse().unwrap();
+        if apply(None, &source, Some(nodump)).is_err() {
+            apply(Some(&source), &destination, None).unwrap();
+            return;
+        }
+        apply(Some(&source), &destination, None).unwrap();
+        assert_eq!(flags_of(&destination).unwrap() & FS_NODUMP_FL, FS_NODUMP_FL);
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+    #[test]
+    fn test_apply_copies_bsd_flags() {
+        const UF_NODUMP: u32 = 0x0000_0001;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let destination = temp_dir.path().join("destination");
+        std::fs::write(&source, b"data").unwrap();
+        std::fs::write(&destination, b"data").unwrap();
+        lchflags(&source, UF_NODUMP).unwrap();
+
+        apply(Some(&source), &destination, None).unwrap();
+        let metadata = std::fs::symlink_metadata(&destination).unwrap();
+        assert_eq!(st_flags(&metadata) & UF_NODUMP, UF_NODUMP);
+        assert!(apply(None, &destination, Some("+d".parse().unwrap())).is_err());
+    }
+}
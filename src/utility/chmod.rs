@@ -0,0 +1,201 @@
+use std::str::FromStr;
+
+/// `--chmod`: permission changes applied to every copied directory and
+/// file, e.g. `D755,F644` or `Dg+s,ug+w,o-rwx,+X`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Chmod {
+    dirs: ModeChange,
+    files: ModeChange,
+}
+
+/// A run of clauses folded into one step: `(mode & keep) | set`, plus
+/// `set_if_exec` when the mode the run starts from has any execute bit
+/// (the `X` permission).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ModeChange {
+    keep: u32,
+    set: u32,
+    set_if_exec: u32,
+}
+
+impl Default for ModeChange {
+    fn default() -> Self {
+        Self {
+            keep: 0o7777,
+            set: 0,
+            set_if_exec: 0,
+        }
+    }
+}
+
+impl ModeChange {
+    fn then(self, next: ModeChange) -> ModeChange {
+        ModeChange {
+            keep: self.keep & next.keep,
+            set: (self.set & next.keep) | next.set,
+            set_if_exec: (self.set_if_exec & next.keep) | next.set_if_exec,
+        }
+    }
+
+    fn apply(self, mode: u32) -> u32 {
+        let mut new = (mode & self.keep) | self.set;
+        if mode & 0o111 != 0 {
+            new |= self.set_if_exec;
+        }
+        (mode & !0o7777) | new
+    }
+}
+
+impl Chmod {
+    /// The permission bits `mode` ends up with, for a directory or a file.
+    pub fn apply(&self, mode: u32, is_dir: bool) -> u32 {
+        if is_dir {
+            self.dirs.apply(mode)
+        } else {
+            self.files.apply(mode)
+        }
+    }
+}
+
+impl FromStr for Chmod {
+    type Err = String;
+
+    /// Comma-separated clauses, each for directories only with a `D`
+    /// prefix, files only with `F`, or both. A clause is an octal mode or
+    /// `[ugoa]*` followed by one or more `[+-=][rwxXst]*`; without `ugoa`
+    /// it applies to all.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chmod = Chmod::default();
+        for clause in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            let (dirs, files, spec) = match clause.as_bytes()[0] {
+                b'D' => (true, false, &clause[1..]),
+                b'F' => (false, true, &clause[1..]),
+                _ => (true, true, clause),
+            };
+            let [for_dirs, for_files] = [true, false].map(|is_dir| parse_clause(spec, is_dir));
+            if dirs {
+                chmod.dirs = chmod.dirs.then(for_dirs?);
+            }
+            if files {
+                chmod.files = chmod.files.then(for_files?);
+            }
+        }
+        Ok(chmod)
+    }
+}
+
+fn parse_clause(spec: &str, is_dir: bool) -> Result<ModeChange, String> {
+    let invalid = || format!("invalid --chmod clause '{}'", spec);
+    if !spec.is_empty() && spec.bytes().all(|b| b.is_ascii_digit()) {
+        let mode = u32::from_str_radix(spec, 8).map_err(|_| invalid())?;
+        if mode > 0o7777 {
+            return Err(invalid());
+        }
+        return Ok(ModeChange {
+            keep: 0,
+            set: mode,
+            set_if_exec: 0,
+        });
+    }
+
+    let ops_at = spec.find(['+', '-', '=']).ok_or_else(invalid)?;
+    let (who, mut rest) = spec.split_at(ops_at);
+    let mut who_mask = 0;
+    for c in who.chars() {
+        who_mask |= match c {
+            'u' => 0o4700,
+            'g' => 0o2070,
+            'o' => 0o1007,
+            'a' => 0o7777,
+            _ => return Err(invalid()),
+        };
+    }
+    if who_mask == 0 {
+        who_mask = 0o7777;
+    }
+
+    let mut change = ModeChange::default();
+    while let Some(op) = rest.chars().next() {
+        let perms_end = rest[1..]
+            .find(['+', '-', '='])
+            .map_or(rest.len(), |i| i + 1);
+        let (mut bits, mut exec_bits) = (0, 0);
+        for c in rest[1..perms_end].chars() {
+            match c {
+                'r' => bits |= 0o444,
+                'w' => bits |= 0o222,
+                'x' => bits |= 0o111,
+                'X' if is_dir => bits |= 0o111,
+                'X' => exec_bits |= 0o111,
+                's' => bits |= 0o6000,
+                't' => bits |= 0o1000,
+                _ => return Err(invalid()),
+            }
+        }
+        let (bits, exec_bits) = (bits & who_mask, exec_bits & who_mask);
+        let step = match op {
+            '+' => ModeChange {
+                keep: 0o7777,
+                set: bits,
+                set_if_exec: exec_bits,
+            },
+            '-' => ModeChange {
+                keep: 0o7777 & !(bits | exec_bits),
+                set: 0,
+                set_if_exec: 0,
+            },
+            '=' => ModeChange {
+                keep: 0o7777 & !who_mask,
+                set: bits,
+                set_if_exec: exec_bits,
+            },
+            _ => return Err(invalid()),
+        };
+        change = change.then(step);
+        rest = &rest[perms_end..];
+    }
+    Ok(change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chmod(spec: &str) -> Chmod {
+        spec.parse().unwrap()
+    }
+
+    #[test]
+    fn test_octal_clauses_by_type() {
+        let chmod = chmod("D755,F644");
+        assert_eq!(chmod.apply(0o700, true), 0o755);
+        assert_eq!(chmod.apply(0o777, false), 0o644);
+        // the file type bits are left alone
+        assert_eq!(chmod.apply(0o100600, false), 0o100644);
+    }
+
+    #[test]
+    fn test_symbolic_clauses() {
+        assert_eq!(chmod("go-w").apply(0o777, false), 0o755);
+        assert_eq!(chmod("u=rw,go=r").apply(0o777, false), 0o644);
+        assert_eq!(chmod("a+r,u+w-x").apply(0o100, false), 0o644);
+        assert_eq!(chmod("Dg+s").apply(0o755, true), 0o2755);
+        assert_eq!(chmod("Dg+s").apply(0o755, false), 0o755);
+        assert_eq!(chmod("+t").apply(0o777, true), 0o1777);
+    }
+
+    #[test]
+    fn test_capital_x_only_for_dirs_and_executables() {
+        let chmod = chmod("go+X");
+        assert_eq!(chmod.apply(0o700, true), 0o711);
+        assert_eq!(chmod.apply(0o700, false), 0o711);
+        assert_eq!(chmod.apply(0o600, false), 0o600);
+    }
+
+    #[test]
+    fn test_invalid_clauses() {
+        for spec in ["u", "q+r", "u+z", "D8", "77777"] {
+            assert!(spec.parse::<Chmod>().is_err(), "{}", spec);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::cli::args::{ConflictPolicy, CopyOptions};
+use crate::error::{CopyError, CopyResult};
+use crate::utility::helper::prompt_overwrite;
+use crate::utility::output;
+use crate::utility::preprocess::is_newer_or_same;
+use crate::utility::preserve;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What happens to a destination that may already exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Nothing is there, or it is to be replaced.
+    Write,
+    /// Left alone, for this reason.
+    Keep(&'static str),
+    /// Left alone; the copy goes to this free name next to it.
+    Rename(PathBuf),
+}
+
+/// Applies `policy` to `destination` before `source` is written there.
+/// Names in `planned` count as taken when renaming.
+pub fn resolve(
+    source: &Path,
+    destination: &Path,
+    policy: ConflictPolicy,
+    modify_window: Duration,
+    planned: &HashSet<PathBuf>,
+) -> CopyResult<Resolution> {
+    let Ok(existing) = std::fs::symlink_metadata(destination) else {
+        return Ok(Resolution::Write);
+    };
+    Ok(match policy {
+        ConflictPolicy::Newer if is_newer_or_same(source, destination, modify_window) => {
+            Resolution::Keep("destination is not older")
+        }
+        ConflictPolicy::Larger
+            if std::fs::metadata(source).is_ok_and(|meta| meta.len() <= existing.len()) =>
+        {
+            Resolution::Keep("destination is not smaller")
+        }
+        ConflictPolicy::Newer | ConflictPolicy::Larger => Resolution::Write,
+        ConflictPolicy::Rename => Resolution::Rename(free_name(destination, planned)),
+        _ => resolve_existing(destination, policy)?,
+    })
+}
+
+/// The part of [`resolve`] that needs no source: newer, larger and rename
+/// are settled when the copy is planned and write here.
+pub fn resolve_existing(destination: &Path, policy: ConflictPolicy) -> CopyResult<Resolution> {
+    if std::fs::symlink_metadata(destination).is_err() {
+        return Ok(Resolution::Write);
+    }
+    match policy {
+        ConflictPolicy::Skip => Ok(Resolution::Keep("already exists")),
+        ConflictPolicy::Prompt if !prompt_overwrite(destination)? => {
+            Ok(Resolution::Keep("not overwritten"))
+        }
+        ConflictPolicy::Error => Err(CopyError::FileExists(destination.to_path_buf())),
+        _ => Ok(Resolution::Write),
+    }
+}
+
+/// Makes way for a hard or symbolic link at `destination`, removing what
+/// is there when the policy replaces it. Returns false when it is kept.
+pub fn prepare_link(destination: &Path, options: &CopyOptions) -> CopyResult<bool> {
+    match resolve_existing(destination, options.conflict_policy(true))? {
+        Resolution::Keep(reason) => {
+            output::skipped(options, destination, reason);
+            Ok(false)
+        }
+        _ => {
+            // a directory in the way stays, and creating the link fails
+            if std::fs::symlink_metadata(destination).is_ok_and(|meta| !meta.is_dir()) {
+                preserve::remove_forced(destination)?;
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// `name (1).ext`, `name (2).ext`, ...: the first that neither exists nor
+/// is in `planned`.
+pub fn free_name(destination: &Path, planned: &HashSet<PathBuf>) -> PathBuf {
+    let stem = destination.file_stem().unwrap_or_default();
+    let extension = destination.extension();
+    (1u64..)
+        .map(|n| {
+            let mut name = OsString::from(stem);
+            name.push(format!(" ({})", n));
+            if let Some(extension) = extension {
+                name.push(".");
+                name.push(extension);
+            }
+            destination.with_file_name(name)
+        })
+        .find(|candidate| {
+            !planned.contains(candidate) && std::fs::symlink_metadata(candidate).is_err()
+        })
+        .unwrap_or_else(|| destination.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_free_name_skips_taken_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let destination = temp_dir.path().join("report.txt");
+        fs::write(&destination, "old").unwrap();
+        fs::write(temp_dir.path().join("report (1).txt"), "older").unwrap();
+        let planned = HashSet::from([temp_dir.path().join("report (2).txt")]);
+
+        assert_eq!(
+            free_name(&destination, &planned),
+            temp_dir.path().join("report (3).txt")
+        );
+        assert_eq!(
+            free_name(&temp_dir.path().join(".profile"), &HashSet::new()),
+            temp_dir.path().join(".profile (1)")
+        );
+    }
+
+    #[test]
+    fn test_resolve_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.txt");
+        let destination = temp_dir.path().join("dest.txt");
+        fs::write(&source, "new contents").unwrap();
+        let none = HashSet::new();
+        let resolve = |policy| resolve(&source, &destination, policy, Duration::ZERO, &none);
+
+        // nothing there yet: every policy writes
+        assert_eq!(resolve(ConflictPolicy::Error).unwrap(), Resolution::Write);
+
+        fs::write(&destination, "old").unwrap();
+        assert_eq!(
+            resolve(ConflictPolicy::Overwrite).unwrap(),
+            Resolution::Write
+        );
+        assert_eq!(
+            resolve(ConflictPolicy::Skip).unwrap(),
+            Resolution::Keep("already exists")
+        );
+        assert_eq!(resolve(ConflictPolicy::Larger).unwrap(), Resolution::Write);
+        // written after the source, so it is newer
+        assert_eq!(
+            resolve(ConflictPolicy::Newer).unwrap(),
+            Resolution::Keep("destination is not older")
+        );
+        assert_eq!(
+            resolve(ConflictPolicy::Rename).unwrap(),
+            Resolution::Rename(temp_dir.path().join("dest (1).txt"))
+        );
+        assert!(matches!(
+            resolve(ConflictPolicy::Error),
+            Err(CopyError::FileExists(_))
+        ));
+
+        fs::write(&destination, "much longer old contents").unwrap();
+        assert_eq!(
+            resolve(ConflictPolicy::Larger).unwrap(),
+            Resolution::Keep("destination is not smaller")
+        );
+    }
+}
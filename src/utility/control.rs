@@ -0,0 +1,519 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most recent errors kept for display.
+const MAX_RECENT_ERRORS: usize = 100;
+
+/// Requests sent to running copy workers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+    /// Abandon the file currently being copied by the given worker.
+    Skip(usize),
+    /// Stop the run, cleaning up partially written files (Ctrl+C, --max-errors).
+    Abort,
+}
+
+/// Cheap, cloneable handle that stops a run. Workers notice it between
+/// chunks, remove the file they were writing and return `Interrupted`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// What a worker should do at its next checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checkpoint {
+    Continue,
+    Skip,
+    Abort,
+    /// The current file ran past `--timeout-per-file`.
+    TimedOut(Duration),
+}
+
+/// The file a worker is copying.
+#[derive(Debug, Clone)]
+pub struct WorkerStatus {
+    pub source: PathBuf,
+    pub size: u64,
+    pub copied: u64,
+}
+
+/// Live counters for the TUI and `cpx status`. Only kept when tracking is
+/// enabled so library runs don't pay for the extra locking.
+#[derive(Debug, Default)]
+pub struct Activity {
+    pub total_files: AtomicUsize,
+    pub total_bytes: AtomicU64,
+    pub files_done: AtomicUsize,
+    pub bytes_done: AtomicU64,
+    workers: Mutex<BTreeMap<usize, WorkerStatus>>,
+    errors: Mutex<Vec<String>>,
+}
+
+impl Activity {
+    pub fn workers(&self) -> Vec<(usize, WorkerStatus)> {
+        self.workers
+            .lock()
+            .map(|workers| workers.iter().map(|(id, w)| (*id, w.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn errors(&self) -> Vec<String> {
+        self.errors
+            .lock()
+            .map(|errors| errors.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Control channel shared between the copy workers and whoever drives them
+/// (signal handler, `--max-errors`, the TUI).
+#[derive(Debug, Default)]
+pub struct CopyControl {
+    token: CancellationToken,
+    paused: Mutex<bool>,
+    resumed: Condvar,
+    resumes: AtomicUsize,
+    skips: Mutex<HashSet<usize>>,
+    activity: Option<Activity>,
+    timeouts: Mutex<Timeouts>,
+    timed_out: AtomicBool,
+    out_of_space: AtomicBool,
+    /// Retries made for each source that needed any, for `--error-report`.
+    retries: Mutex<HashMap<PathBuf, u32>>,
+    bandwidth: Mutex<Bandwidth>,
+    /// Bumped whenever the limit changes, so a worker waiting out the old
+    /// one stops.
+    bandwidth_changes: AtomicUsize,
+}
+
+/// `--bwlimit`, shared by every worker.
+#[derive(Debug, Default)]
+struct Bandwidth {
+    /// Bytes per second; `None` copies at full speed.
+    rate: Option<u64>,
+    /// When the bytes let through so far have been paid for.
+    free_at: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Timeouts {
+    per_file: Option<Duration>,
+    deadline: Option<Instant>,
+    /// When each worker started its current file, kept with `per_file`.
+    started: HashMap<usize, Instant>,
+}
+
+impl CopyControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A control that stops when `token` is cancelled.
+    pub fn with_token(token: CancellationToken) -> Self {
+        Self {
+            token,
+            ..Self::default()
+        }
+    }
+
+    /// A control that also records per-worker activity.
+    pub fn tracked() -> Self {
+        Self {
+            activity: Some(Activity::default()),
+            ..Self::default()
+        }
+    }
+
+    /// Arms `--timeout-per-file` and `--timeout-total`; the total counts
+    /// from now. Both are checked at checkpoints, between chunks.
+    pub fn set_timeouts(&self, per_file: Option<Duration>, total: Option<Duration>) {
+        if let Ok(mut timeouts) = self.timeouts.lock() {
+            timeouts.per_file = per_file;
+            timeouts.deadline = total.map(|total| Instant::now() + total);
+        }
+    }
+
+    /// Sets `--bwlimit` for every worker, or lifts it with `None`. It can
+    /// change while the run goes on, from `cpx status --bwlimit`.
+    pub fn set_bwlimit(&self, rate: Option<u64>) {
+        if let Ok(mut bandwidth) = self.bandwidth.lock() {
+            bandwidth.rate = rate.filter(|&rate| rate > 0);
+            bandwidth.free_at = None;
+            self.bandwidth_changes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn bwlimit(&self) -> Option<u64> {
+        self.bandwidth
+            .lock()
+            .ok()
+            .and_then(|bandwidth| bandwidth.rate)
+    }
+
+    /// Holds the calling worker back until `bytes` more fit in the limit,
+    /// counting what every worker has copied. Returns early when the run is
+    /// aborted or the limit changes.
+    pub fn throttle(&self, bytes: u64) {
+        let (until, changes) = {
+            let Ok(mut bandwidth) = self.bandwidth.lock() else {
+                return;
+            };
+            let Some(rate) = bandwidth.rate else {
+                return;
+            };
+            let now = Instant::now();
+            let from = bandwidth.free_at.map_or(now, |free_at| free_at.max(now));
+            let until = from + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            bandwidth.free_at = Some(until);
+            (until, self.bandwidth_changes.load(Ordering::Relaxed))
+        };
+        while !self.is_aborted() && self.bandwidth_changes.load(Ordering::Relaxed) == changes {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            std::thread::sleep(left.min(Duration::from_millis(100)));
+        }
+    }
+
+    /// Whether the run was aborted for passing `--timeout-total`.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Stops the run because the destination is full: files already being
+    /// copied are abandoned and no new ones start.
+    pub fn stop_out_of_space(&self) {
+        self.out_of_space.store(true, Ordering::Relaxed);
+        self.abort();
+    }
+
+    /// Whether the run was stopped by [`stop_out_of_space`](Self::stop_out_of_space).
+    pub fn out_of_space(&self) -> bool {
+        self.out_of_space.load(Ordering::Relaxed)
+    }
+
+    pub fn send(&self, command: ControlCommand) {
+        match command {
+            ControlCommand::Pause => self.set_paused(true),
+            ControlCommand::Resume => self.set_paused(false),
+            ControlCommand::Skip(worker) => {
+                if let Ok(mut skips) = self.skips.lock() {
+                    skips.insert(worker);
+                }
+                // a paused worker wakes up to drop its file
+                self.resumed.notify_all();
+            }
+            ControlCommand::Abort => {
+                self.token.cancel();
+                self.resumed.notify_all();
+            }
+        }
+    }
+
+    pub fn abort(&self) {
+        self.send(ControlCommand::Abort);
+    }
+
+    /// Also true when the token was cancelled directly, without going
+    /// through [`abort`](Self::abort).
+    pub fn is_aborted(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.lock().map(|paused| *paused).unwrap_or(false)
+    }
+
+    pub fn record_retry(&self, source: &Path) {
+        if let Ok(mut retries) = self.retries.lock() {
+            *retries.entry(source.to_path_buf()).or_default() += 1;
+        }
+    }
+
+    /// Retries made for `source` so far.
+    pub fn retries(&self, source: &Path) -> u32 {
+        self.retries
+            .lock()
+            .ok()
+            .and_then(|retries| retries.get(source).copied())
+            .unwrap_or(0)
+    }
+
+    pub fn activity(&self) -> Option<&Activity> {
+        self.activity.as_ref()
+    }
+
+    /// How many times a pause has ended, so progress displays can restart
+    /// their rate estimate instead of averaging the pause in.
+    pub fn resumes(&self) -> usize {
+        self.resumes.load(Ordering::Relaxed)
+    }
+
+    fn set_paused(&self, value: bool) {
+        if let Ok(mut paused) = self.paused.lock() {
+            if *paused && !value {
+                self.resumes.fetch_add(1, Ordering::Relaxed);
+            }
+            *paused = value;
+        }
+        self.resumed.notify_all();
+    }
+
+    /// Blocks while the run is paused, then reports whether the calling
+    /// worker should keep going, drop its current file, or stop.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let worker = worker_id();
+        if let Ok(mut paused) = self.paused.lock() {
+            while *paused && !self.is_aborted() && !self.skip_pending(worker) {
+                paused = match self
+                    .resumed
+                    .wait_timeout(paused, Duration::from_millis(200))
+                {
+                    Ok((guard, _)) => guard,
+                    Err(_) => break,
+                };
+            }
+        }
+        let overdue = self.overdue(worker);
+        if overdue == Some(Checkpoint::Abort) {
+            self.timed_out.store(true, Ordering::Relaxed);
+            self.abort();
+        }
+        if self.is_aborted() {
+            return Checkpoint::Abort;
+        }
+        let skipped = self
+            .skips
+            .lock()
+            .map(|mut skips| skips.remove(&worker))
+            .unwrap_or(false);
+        if skipped {
+            Checkpoint::Skip
+        } else {
+            overdue.unwrap_or(Checkpoint::Continue)
+        }
+    }
+
+    /// `Abort` past the run's deadline, `TimedOut` past the file's.
+    fn overdue(&self, worker: usize) -> Option<Checkpoint> {
+        let timeouts = self.timeouts.lock().ok()?;
+        let now = Instant::now();
+        if timeouts.deadline.is_some_and(|deadline| now >= deadline) {
+            return Some(Checkpoint::Abort);
+        }
+        let limit = timeouts.per_file?;
+        let started = timeouts.started.get(&worker)?;
+        (now.duration_since(*started) >= limit).then_some(Checkpoint::TimedOut(limit))
+    }
+
+    fn skip_pending(&self, worker: usize) -> bool {
+        self.skips
+            .lock()
+            .map(|skips| skips.contains(&worker))
+            .unwrap_or(false)
+    }
+
+    pub fn set_totals(&self, files: usize, bytes: u64) {
+        if let Some(activity) = &self.activity {
+            activity.total_files.store(files, Ordering::Relaxed);
+            activity.total_bytes.store(bytes, Ordering::Relaxed);
+        }
+    }
+
+    /// Marks `source` as the calling worker's current file. A skip requested
+    /// for an earlier file does not carry over.
+    pub fn start_file(&self, source: &Path, size: u64) {
+        let worker = worker_id();
+        if let Ok(mut skips) = self.skips.lock() {
+            skips.remove(&worker);
+        }
+        if let Ok(mut timeouts) = self.timeouts.lock()
+            && timeouts.per_file.is_some()
+        {
+            timeouts.started.insert(worker, Instant::now());
+        }
+        if let Some(activity) = &self.activity
+            && let Ok(mut workers) = activity.workers.lock()
+        {
+            workers.insert(
+                worker,
+                WorkerStatus {
+                    source: source.to_path_buf(),
+                    size,
+                    copied: 0,
+                },
+            );
+        }
+    }
+
+    pub fn add_bytes(&self, delta: u64) {
+        if let Some(activity) = &self.activity {
+            activity.bytes_done.fetch_add(delta, Ordering::Relaxed);
+            if let Ok(mut workers) = activity.workers.lock()
+                && let Some(worker) = workers.get_mut(&worker_id())
+            {
+                worker.copied += delta;
+            }
+        }
+    }
+
+    /// Clears the calling worker's slot, recording `error` if the file failed.
+    pub fn finish_file(&self, error: Option<String>) {
+        if let Some(activity) = &self.activity {
+            if let Ok(mut workers) = activity.workers.lock() {
+                workers.remove(&worker_id());
+            }
+            match error {
+                Some(error) => {
+                    if let Ok(mut errors) = activity.errors.lock() {
+                        if errors.len() == MAX_RECENT_ERRORS {
+                            errors.remove(0);
+                        }
+                        errors.push(error);
+                    }
+                }
+                None => {
+                    activity.files_done.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+/// Index of the rayon worker running the caller; sequential runs use 0.
+fn worker_id() -> usize {
+    rayon::current_thread_index().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_skip_targets_worker_once() {
+        let control = CopyControl::new();
+        control.send(ControlCommand::Skip(worker_id()));
+        assert_eq!(control.checkpoint(), Checkpoint::Skip);
+        assert_eq!(control.checkpoint(), Checkpoint::Continue);
+    }
+
+    #[test]
+    fn test_abort_releases_paused_workers() {
+        let control = Arc::new(CopyControl::new());
+        control.send(ControlCommand::Pause);
+
+        let worker = std::thread::spawn({
+            let control = control.clone();
+            move || control.checkpoint()
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        control.abort();
+
+        assert_eq!(worker.join().unwrap(), Checkpoint::Abort);
+    }
+
+    #[test]
+    fn test_cancelled_token_aborts_workers() {
+        let token = CancellationToken::new();
+        let control = CopyControl::with_token(token.clone());
+        assert_eq!(control.checkpoint(), Checkpoint::Continue);
+
+        token.cancel();
+        assert!(control.is_aborted());
+        assert_eq!(control.checkpoint(), Checkpoint::Abort);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let control = CopyControl::new();
+        control.set_timeouts(Some(Duration::from_millis(20)), None);
+        control.start_file(Path::new("a.txt"), 10);
+        assert_eq!(control.checkpoint(), Checkpoint::Continue);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(
+            control.checkpoint(),
+            Checkpoint::TimedOut(Duration::from_millis(20))
+        );
+        control.start_file(Path::new("b.txt"), 10);
+        assert_eq!(control.checkpoint(), Checkpoint::Continue);
+
+        control.set_timeouts(None, Some(Duration::ZERO));
+        assert_eq!(control.checkpoint(), Checkpoint::Abort);
+        assert!(control.timed_out());
+        assert!(control.is_aborted());
+    }
+
+    #[test]
+    fn test_bwlimit_paces_the_run() {
+        let control = Arc::new(CopyControl::new());
+        control.set_bwlimit(Some(1000));
+        let started = Instant::now();
+        for _ in 0..3 {
+            control.throttle(100);
+        }
+        assert!(started.elapsed() >= Duration::from_millis(250));
+
+        // a worker waiting out the old limit is let go when it changes
+        let worker = std::thread::spawn({
+            let control = control.clone();
+            move || control.throttle(1_000_000)
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        control.set_bwlimit(None);
+        worker.join().unwrap();
+        assert_eq!(control.bwlimit(), None);
+
+        let started = Instant::now();
+        control.throttle(1 << 30);
+        assert!(started.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_out_of_space_stops_the_run() {
+        let control = CopyControl::new();
+        assert!(!control.out_of_space());
+        control.stop_out_of_space();
+        assert!(control.out_of_space());
+        assert_eq!(control.checkpoint(), Checkpoint::Abort);
+    }
+
+    #[test]
+    fn test_tracked_activity() {
+        let control = CopyControl::tracked();
+        control.set_totals(2, 10);
+        control.start_file(Path::new("a.txt"), 10);
+        control.add_bytes(4);
+
+        let activity = control.activity().unwrap();
+        assert_eq!(activity.workers()[0].1.copied, 4);
+
+        control.finish_file(Some("boom".to_string()));
+        assert!(activity.workers().is_empty());
+        assert_eq!(activity.errors(), vec!["boom".to_string()]);
+        assert_eq!(activity.files_done.load(Ordering::Relaxed), 0);
+    }
+}
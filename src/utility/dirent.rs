@@ -0,0 +1,252 @@
+use crate::utility::buffers;
+use std::ffi::OsStr;
+use std::fs::{File, Metadata};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+
+/// Bytes of entries one `getdents64` call fills: a few thousand names,
+/// where `readdir` asks for 32 KiB at a time.
+const BATCH_SIZE: usize = 1024 * 1024;
+
+/// Offsets into `struct linux_dirent64`.
+const D_RECLEN: usize = 16;
+const D_TYPE: usize = 18;
+const D_NAME: usize = 19;
+
+/// What an entry is, as the directory records it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    Directory,
+    Symlink,
+    Other,
+}
+
+/// A directory entry. Only the walk's callback stats it: its type is known
+/// from the directory alone, except on filesystems that don't record one.
+#[derive(Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub entry_type: EntryType,
+    pub metadata: Option<io::Result<Metadata>>,
+}
+
+impl Entry {
+    pub fn name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or_default()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.entry_type == EntryType::Directory
+    }
+}
+
+/// A directory that couldn't be read.
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl From<WalkError> for io::Error {
+    fn from(e: WalkError) -> Self {
+        io::Error::new(e.error.kind(), format!("{}: {}", e.path.display(), e.error))
+    }
+}
+
+/// Reads every entry of the directory `dir` but `.` and `..`, in batches
+/// of [`BATCH_SIZE`] bytes.
+pub fn read_dir(dir: &Path) -> io::Result<Vec<Entry>> {
+    let file = File::options()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(dir)?;
+    let mut buffer = buffers::shared().take(BATCH_SIZE);
+    let mut entries = Vec::new();
+    loop {
+        let filled = unsafe {
+            libc::syscall(
+                libc::SYS_getdents64,
+                file.as_raw_fd(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+            )
+        };
+        if filled < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+        if filled == 0 {
+            return Ok(entries);
+        }
+        parse_batch(&buffer[..filled as usize], dir, &mut entries);
+    }
+}
+
+/// Appends the entries packed in `batch` to `entries`. An entry whose type
+/// the filesystem didn't record is stat'ed to learn it.
+fn parse_batch(batch: &[u8], dir: &Path, entries: &mut Vec<Entry>) {
+    let mut offset = 0;
+    while offset + D_NAME < batch.len() {
+        let record = &batch[offset..];
+        let reclen = u16::from_ne_bytes([record[D_RECLEN], record[D_RECLEN + 1]]) as usize;
+        if reclen <= D_NAME || reclen > record.len() {
+            break;
+        }
+        let d_type = record[D_TYPE];
+        let name = &record[D_NAME..reclen];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+        offset += reclen;
+        if name == b"." || name == b".." {
+            continue;
+        }
+
+        let path = dir.join(OsStr::from_bytes(name));
+        let (entry_type, metadata) = match d_type {
+            libc::DT_DIR => (EntryType::Directory, None),
+            libc::DT_LNK => (EntryType::Symlink, None),
+            libc::DT_UNKNOWN => {
+                let metadata = std::fs::symlink_metadata(&path);
+                let entry_type = match &metadata {
+                    Ok(meta) if meta.is_dir() => EntryType::Directory,
+                    Ok(meta) if meta.file_type().is_symlink() => EntryType::Symlink,
+                    _ => EntryType::Other,
+                };
+                (entry_type, Some(metadata))
+            }
+            _ => (EntryType::Other, None),
+        };
+        entries.push(Entry {
+            path,
+            entry_type,
+            metadata,
+        });
+    }
+}
+
+/// Walks the tree under `root` without following symlinks, reading
+/// directories on `threads` threads. `process` sees each directory's
+/// entries before they are yielded, on the thread that read them; it can
+/// stat them there, or drop them so the walk neither yields nor descends
+/// into them. A directory's entries come before any found beneath it.
+pub fn walk<F>(
+    root: &Path,
+    threads: usize,
+    process: F,
+) -> io::Result<impl Iterator<Item = Result<Entry, WalkError>>>
+where
+    F: Fn(&Path, &mut Vec<Entry>) + Send + Sync + 'static,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(io::Error::other)?;
+    let (sender, receiver) = mpsc::channel();
+    let root = root.to_path_buf();
+    std::thread::spawn(move || {
+        let process = &process;
+        pool.scope(|scope| read_tree(scope, root, process, sender));
+    });
+    Ok(receiver.into_iter().flat_map(|batch| {
+        let (entries, error) = match batch {
+            Ok(entries) => (entries, None),
+            Err(error) => (Vec::new(), Some(error)),
+        };
+        entries.into_iter().map(Ok).chain(error.map(Err))
+    }))
+}
+
+type Batch = Result<Vec<Entry>, WalkError>;
+
+fn read_tree<'s, F>(scope: &rayon::Scope<'s>, dir: PathBuf, process: &'s F, sender: Sender<Batch>)
+where
+    F: Fn(&Path, &mut Vec<Entry>) + Sync,
+{
+    let mut entries = match read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            let _ = sender.send(Err(WalkError { path: dir, error }));
+            return;
+        }
+    };
+    process(&dir, &mut entries);
+    let subdirs: Vec<PathBuf> = entries
+        .iter()
+        .filter(|entry| entry.is_dir())
+        .map(|entry| entry.path.clone())
+        .collect();
+    // the caller stopped listening: nothing below is wanted either
+    if sender.send(Ok(entries)).is_err() {
+        return;
+    }
+    for subdir in subdirs {
+        let sender = sender.clone();
+        scope.spawn(move |scope| read_tree(scope, subdir, process, sender));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_dir_matches_std() {
+        let temp_dir = TempDir::new().unwrap();
+        // long names, so they take more than one batch
+        let padding = "x".repeat(200);
+        for i in 0..6000 {
+            let name = format!("file-{:04}-{}", i, padding);
+            fs::write(temp_dir.path().join(name), b"").unwrap();
+        }
+        fs::create_dir(temp_dir.path().join("dir")).unwrap();
+        std::os::unix::fs::symlink("dir", temp_dir.path().join("link")).unwrap();
+
+        let entries = read_dir(temp_dir.path()).unwrap();
+        let ours: BTreeSet<_> = entries.iter().map(|e| e.path.clone()).collect();
+        let std: BTreeSet<_> = fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(ours, std);
+
+        let kind = |name: &str| {
+            let entry = entries.iter().find(|e| e.name() == name).unwrap();
+            entry.entry_type
+        };
+        assert_eq!(kind("dir"), EntryType::Directory);
+        assert_eq!(kind("link"), EntryType::Symlink);
+        assert_eq!(kind(&format!("file-0000-{}", padding)), EntryType::Other);
+    }
+
+    #[test]
+    fn test_walk_yields_parents_first_and_prunes() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::create_dir_all(root.join("skip/inner")).unwrap();
+        fs::write(root.join("a/b/c/file.txt"), b"data").unwrap();
+
+        let walk = walk(root, 2, |_, entries: &mut Vec<Entry>| {
+            entries.retain(|entry| entry.name() != "skip");
+        })
+        .unwrap();
+        let paths: Vec<PathBuf> = walk.map(|entry| entry.unwrap().path).collect();
+        let relative: Vec<_> = paths
+            .iter()
+            .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            relative,
+            ["a", "a/b", "a/b/c", "a/b/c/file.txt"].map(PathBuf::from)
+        );
+    }
+}
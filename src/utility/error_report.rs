@@ -0,0 +1,169 @@
+use crate::error::{ErrorCategory, FailedFile};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// What `--error-report` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ErrorReportFormat {
+    /// the failure lines printed at the end of the run
+    #[default]
+    Text,
+    /// an array of objects with every field
+    Json,
+    /// one line per failure: source, destination, operation, category,
+    /// errno, retries, error
+    Tsv,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportEntry<'a> {
+    source: &'a Path,
+    destination: &'a Path,
+    operation: &'static str,
+    category: ErrorCategory,
+    errno: Option<i32>,
+    retries: u32,
+    error: String,
+}
+
+impl<'a> ReportEntry<'a> {
+    fn new(failure: &'a FailedFile, retries: &impl Fn(&Path) -> u32) -> Self {
+        Self {
+            source: &failure.source,
+            destination: &failure.destination,
+            operation: failure.error.operation(),
+            category: failure.error.category(),
+            errno: failure.error.raw_os_error(),
+            retries: retries(&failure.source),
+            error: failure.error.to_string(),
+        }
+    }
+}
+
+/// Writes `failures` to `path`, replacing it, with the number of times
+/// each was retried from `retries`. The file is written even when nothing
+/// failed, so a job can tell a clean run from one that didn't get that
+/// far.
+pub fn write(
+    path: &Path,
+    failures: &[FailedFile],
+    format: ErrorReportFormat,
+    retries: impl Fn(&Path) -> u32,
+) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_to(&mut out, failures, format, &retries)?;
+    out.flush()
+}
+
+fn write_to(
+    out: &mut impl Write,
+    failures: &[FailedFile],
+    format: ErrorReportFormat,
+    retries: &impl Fn(&Path) -> u32,
+) -> io::Result<()> {
+    match format {
+        ErrorReportFormat::Text => {
+            for failure in failures {
+                writeln!(out, "{}", failure)?;
+            }
+        }
+        ErrorReportFormat::Json => {
+            let entries: Vec<ReportEntry> = failures
+                .iter()
+                .map(|failure| ReportEntry::new(failure, retries))
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &entries).map_err(io::Error::other)?;
+            writeln!(out)?;
+        }
+        ErrorReportFormat::Tsv => {
+            for failure in failures {
+                let entry = ReportEntry::new(failure, retries);
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    tsv_field(&entry.source.to_string_lossy()),
+                    tsv_field(&entry.destination.to_string_lossy()),
+                    entry.operation,
+                    entry.category,
+                    entry.errno.map_or(String::new(), |errno| errno.to_string()),
+                    entry.retries,
+                    tsv_field(&entry.error)
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Escapes the characters that would split a field or a line, the way
+/// `\t` and `\n` are written in TSV.
+fn tsv_field(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CopyError;
+
+    fn failures() -> Vec<FailedFile> {
+        vec![
+            FailedFile::new(
+                Path::new("/src/a\tb.txt"),
+                Path::new("/dst/a\tb.txt"),
+                CopyError::Io(io::Error::from_raw_os_error(13)),
+            ),
+            FailedFile::new(
+                Path::new("/src/c.txt"),
+                Path::new("/dst/c.txt"),
+                CopyError::VerifyFailed {
+                    source: "/src/c.txt".into(),
+                    destination: "/dst/c.txt".into(),
+                },
+            ),
+        ]
+    }
+
+    fn report(format: ErrorReportFormat) -> String {
+        let mut out = Vec::new();
+        let retries = |source: &Path| if source.ends_with("c.txt") { 0 } else { 2 };
+        write_to(&mut out, &failures(), format, &retries).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_error_report_formats() {
+        let tsv = report(ErrorReportFormat::Tsv);
+        let lines: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0][..6],
+            [
+                "/src/a\\tb.txt",
+                "/dst/a\\tb.txt",
+                "copy",
+                "permission denied",
+                "13",
+                "2"
+            ]
+        );
+        assert_eq!(lines[1][2..6], ["verify", "integrity", "", "0"]);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report(ErrorReportFormat::Json)).unwrap();
+        assert_eq!(json[0]["source"], "/src/a\tb.txt");
+        assert_eq!(json[0]["errno"], 13);
+        assert_eq!(json[0]["retries"], 2);
+        assert_eq!(json[1]["operation"], "verify");
+        assert_eq!(json[1]["errno"], serde_json::Value::Null);
+
+        assert!(report(ErrorReportFormat::Text).starts_with("[permission denied] '/src/a"));
+    }
+}
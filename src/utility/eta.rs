@@ -0,0 +1,213 @@
+use indicatif::{FormattedDuration, ProgressState};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Files that must finish before the model is trusted over indicatif's
+/// byte-rate ETA.
+const MIN_SAMPLES: u64 = 3;
+
+/// Time left for the whole run, from what finished files cost: a fixed
+/// time per file (open, create, metadata, fsync) plus a time per byte,
+/// fitted to each file's size and duration. indicatif's ETA only divides
+/// the bytes left by the byte rate, so a tree of small files that moves
+/// few bytes a second shows hours for minutes of work.
+#[derive(Debug)]
+pub struct Eta {
+    state: Mutex<Model>,
+}
+
+#[derive(Debug)]
+struct Model {
+    started: Instant,
+    /// Files not yet finished, in flight or queued.
+    remaining_files: usize,
+    /// Start time and size of each file in flight.
+    in_flight: HashMap<PathBuf, (Instant, u64)>,
+    fit: Fit,
+}
+
+/// Running sums for a least-squares line through (size, seconds) of the
+/// finished files.
+#[derive(Debug, Default, Clone, Copy)]
+struct Fit {
+    n: u64,
+    x: f64,
+    y: f64,
+    xx: f64,
+    xy: f64,
+}
+
+impl Default for Eta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Eta {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(Model {
+                started: Instant::now(),
+                remaining_files: 0,
+                in_flight: HashMap::new(),
+                fit: Fit::default(),
+            }),
+        }
+    }
+
+    /// Copying starts with `total_files` to go.
+    pub fn begin(&self, total_files: usize) {
+        if let Ok(mut model) = self.state.lock() {
+            model.started = Instant::now();
+            model.remaining_files = total_files;
+        }
+    }
+
+    pub fn file_started(&self, source: &Path, size: u64) {
+        if let Ok(mut model) = self.state.lock() {
+            model
+                .in_flight
+                .insert(source.to_path_buf(), (Instant::now(), size));
+        }
+    }
+
+    /// `source` is done with. Only files that were copied are `sampled`:
+    /// a skipped or failed one says nothing about what the rest cost.
+    pub fn file_finished(&self, source: &Path, sampled: bool) {
+        if let Ok(mut model) = self.state.lock() {
+            model.remaining_files = model.remaining_files.saturating_sub(1);
+            if let Some((started, size)) = model.in_flight.remove(source)
+                && sampled
+            {
+                model.fit.add(size as f64, started.elapsed().as_secs_f64());
+            }
+        }
+    }
+
+    /// Starts the estimate over, after a pause that would otherwise count
+    /// as time the files took.
+    pub fn reset(&self) {
+        if let Ok(mut model) = self.state.lock() {
+            let now = Instant::now();
+            model.started = now;
+            model.fit = Fit::default();
+            for (started, _) in model.in_flight.values_mut() {
+                *started = now;
+            }
+        }
+    }
+
+    /// Time left, given the bytes left in `state`; indicatif's own ETA
+    /// until enough files have finished to fit the model.
+    pub fn estimate(&self, state: &ProgressState) -> Duration {
+        let bytes_left = state.len().unwrap_or(0).saturating_sub(state.pos());
+        self.state
+            .lock()
+            .ok()
+            .and_then(|model| model.estimate(bytes_left))
+            .unwrap_or_else(|| state.eta())
+    }
+
+    /// For the `{eta}` key of the overall bar, formatted like indicatif's
+    /// `{eta_precise}`.
+    pub fn write(&self, state: &ProgressState, w: &mut dyn fmt::Write) {
+        let _ = write!(w, "{}", FormattedDuration(self.estimate(state)));
+    }
+}
+
+impl Model {
+    fn estimate(&self, bytes_left: u64) -> Option<Duration> {
+        if self.remaining_files == 0 {
+            return Some(Duration::ZERO);
+        }
+        if self.fit.n < MIN_SAMPLES || self.fit.y <= 0.0 {
+            return None;
+        }
+        let (per_file, per_byte) = self.fit.line();
+        let work = per_file * self.remaining_files as f64 + per_byte * bytes_left as f64;
+        // file-seconds done per second of the run: above 1 with parallel
+        // workers, below it when time goes on things outside the files
+        let rate = self.fit.y / self.started.elapsed().as_secs_f64().max(1e-3);
+        Some(Duration::from_secs_f64((work / rate).clamp(0.0, 1e9)))
+    }
+}
+
+impl Fit {
+    fn add(&mut self, size: f64, seconds: f64) {
+        self.n += 1;
+        self.x += size;
+        self.y += seconds;
+        self.xx += size * size;
+        self.xy += size * seconds;
+    }
+
+    /// Seconds per file and per byte. Files all of one size can't tell the
+    /// two apart, so their time is put down to bytes, or to the files
+    /// themselves when they are empty.
+    fn line(&self) -> (f64, f64) {
+        let n = self.n as f64;
+        let (mean_x, mean_y) = (self.x / n, self.y / n);
+        let variance = self.xx / n - mean_x * mean_x;
+        if variance <= mean_x * mean_x * 1e-6 {
+            return if mean_x >= 1.0 {
+                (0.0, mean_y / mean_x)
+            } else {
+                (mean_y, 0.0)
+            };
+        }
+        let slope = (self.xy / n - mean_x * mean_y) / variance;
+        let per_byte = slope.max(0.0);
+        let per_file = (mean_y - per_byte * mean_x).max(0.0);
+        (per_file, per_byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(samples: &[(f64, f64)]) -> Fit {
+        let mut fit = Fit::default();
+        for &(size, seconds) in samples {
+            fit.add(size, seconds);
+        }
+        fit
+    }
+
+    #[test]
+    fn test_fit_separates_per_file_and_per_byte_cost() {
+        // 10ms a file plus 1s per MB
+        let (per_file, per_byte) =
+            fit(&[(0.0, 0.01), (1e6, 1.01), (2e6, 2.01), (5e5, 0.51)]).line();
+        assert!((per_file - 0.01).abs() < 1e-9, "{}", per_file);
+        assert!((per_byte - 1e-6).abs() < 1e-12, "{}", per_byte);
+
+        assert_eq!(fit(&[(0.0, 0.02), (0.0, 0.04)]).line(), (0.03, 0.0));
+        assert_eq!(fit(&[(100.0, 1.0), (100.0, 3.0)]).line(), (0.0, 0.02));
+    }
+
+    #[test]
+    fn test_estimate_counts_files_left() {
+        let mut model = Model {
+            started: Instant::now() - Duration::from_secs(10),
+            remaining_files: 1000,
+            in_flight: HashMap::new(),
+            fit: fit(&[(10.0, 0.0), (10.0, 0.0)]),
+        };
+        assert_eq!(model.estimate(0), None);
+
+        // one worker busy the whole 10s on 100 tiny files, 0.1s each
+        // whatever their size: the 1000 left take 100s, not the bytes'
+        // worth
+        let tiny: Vec<_> = (0..100).map(|i| ((i % 2 * 20) as f64, 0.1)).collect();
+        model.fit = fit(&tiny);
+        let estimate = model.estimate(10_000).unwrap().as_secs_f64();
+        assert!((99.0..101.0).contains(&estimate), "{}", estimate);
+
+        model.remaining_files = 0;
+        assert_eq!(model.estimate(10_000), Some(Duration::ZERO));
+    }
+}
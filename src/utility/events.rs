@@ -0,0 +1,126 @@
+use crate::error::ErrorCategory;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// What happened during a copy, in the order workers report it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopyEvent {
+    /// Planning finished; `skipped_files` already exist and won't be copied.
+    Scanned {
+        total_files: usize,
+        total_bytes: u64,
+        skipped_files: usize,
+    },
+    Started {
+        source: PathBuf,
+        destination: PathBuf,
+        size: u64,
+    },
+    /// `bytes` more of `source` were written.
+    Progress {
+        source: PathBuf,
+        bytes: u64,
+    },
+    Retrying {
+        source: PathBuf,
+        attempt: u32,
+        max_attempts: u32,
+    },
+    Finished {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    Skipped {
+        source: PathBuf,
+        reason: String,
+    },
+    Failed {
+        source: PathBuf,
+        destination: PathBuf,
+        category: ErrorCategory,
+        error: String,
+    },
+}
+
+/// Fans copy events out to every subscribed channel. Events are only built
+/// when someone is listening.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    next_id: AtomicUsize,
+    subscribers: RwLock<Vec<(usize, Sender<CopyEvent>)>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts receiving events. The channel closes once the bus is dropped
+    /// or [`unsubscribe`](Self::unsubscribe) is called with the returned id.
+    pub fn subscribe(&self) -> (usize, Receiver<CopyEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut subscribers) = self.subscribers.write() {
+            subscribers.push((id, sender));
+        }
+        (id, receiver)
+    }
+
+    pub fn unsubscribe(&self, id: usize) {
+        if let Ok(mut subscribers) = self.subscribers.write() {
+            subscribers.retain(|(subscriber, _)| *subscriber != id);
+        }
+    }
+
+    pub fn emit(&self, event: impl FnOnce() -> CopyEvent) {
+        let Ok(subscribers) = self.subscribers.read() else {
+            return;
+        };
+        match subscribers.as_slice() {
+            [] => {}
+            [(_, only)] => {
+                let _ = only.send(event());
+            }
+            all => {
+                let event = event();
+                for (_, sender) in all {
+                    let _ = sender.send(event.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_reach_every_subscriber() {
+        let bus = EventBus::new();
+        let (_, first) = bus.subscribe();
+        let (second_id, second) = bus.subscribe();
+
+        bus.emit(|| CopyEvent::Progress {
+            source: PathBuf::from("a"),
+            bytes: 3,
+        });
+        bus.unsubscribe(second_id);
+        bus.emit(|| CopyEvent::Skipped {
+            source: PathBuf::from("b"),
+            reason: "exists".to_string(),
+        });
+        drop(bus);
+
+        assert_eq!(first.iter().count(), 2);
+        assert_eq!(second.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_no_event_built_without_subscribers() {
+        let bus = EventBus::new();
+        bus.emit(|| unreachable!("nobody is listening"));
+    }
+}
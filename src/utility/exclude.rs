@@ -1,9 +1,12 @@
 use crate::error::{ExcludeError, ExcludeResult};
 use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::io::Read;
 use std::path::Component;
 use std::{
     borrow::Cow,
     collections::HashSet,
+    ffi::OsStr,
+    fs::Metadata,
     path::{Path, PathBuf},
 };
 
@@ -14,6 +17,83 @@ pub struct ExcludeRules {
     pub glob_set: Option<GlobSet>,
 }
 
+/// `--hidden=skip`: dotfiles on Unix, entries with the hidden attribute on
+/// Windows.
+pub fn is_hidden(name: &OsStr, metadata: Option<&Metadata>) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        use windows_sys::Win32::Storage::FileSystem::FILE_ATTRIBUTE_HIDDEN;
+        let _ = name;
+        metadata.is_some_and(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = metadata;
+        name.as_encoded_bytes().starts_with(b".")
+    }
+}
+
+/// Version control metadata, left out by `--exclude-vcs`.
+pub const VCS_PATTERNS: &[&str] = &[".git", ".hg", ".svn", ".bzr", "_darcs", "CVS"];
+
+/// Dependency, build and OS clutter, left out with the VCS patterns by
+/// `--exclude-common`.
+pub const COMMON_PATTERNS: &[&str] = &["node_modules", "target", "__pycache__", ".DS_Store"];
+
+/// The built-in patterns `--exclude-vcs` and `--exclude-common` turn on.
+pub fn builtin_patterns(vcs: bool, common: bool) -> Vec<ExcludePattern> {
+    let mut names = Vec::new();
+    if vcs || common {
+        names.extend_from_slice(VCS_PATTERNS);
+    }
+    if common {
+        names.extend_from_slice(COMMON_PATTERNS);
+    }
+    names.into_iter().map(ExcludePattern::from_string).collect()
+}
+
+/// Directories left out for what they hold: a file named by
+/// `--exclude-if-present`, or with `--exclude-caches` a `CACHEDIR.TAG`
+/// carrying the cache directory signature.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExcludeMarkers {
+    pub files: Vec<String>,
+    pub caches: bool,
+}
+
+/// First bytes of a valid `CACHEDIR.TAG` (<https://bford.info/cachedir/>).
+const CACHEDIR_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+impl ExcludeMarkers {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && !self.caches
+    }
+
+    /// The marker that leaves `dir` out, if it holds one.
+    pub fn find(&self, dir: &Path) -> Option<&str> {
+        if let Some(name) = self
+            .files
+            .iter()
+            .find(|name| std::fs::symlink_metadata(dir.join(name)).is_ok())
+        {
+            return Some(name);
+        }
+        if self.caches && is_cachedir_tag(&dir.join("CACHEDIR.TAG")) {
+            return Some("CACHEDIR.TAG");
+        }
+        None
+    }
+}
+
+fn is_cachedir_tag(path: &Path) -> bool {
+    let mut signature = [0; CACHEDIR_SIGNATURE.len()];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|()| signature == CACHEDIR_SIGNATURE)
+}
+
+#[derive(Debug, Clone)]
 pub enum ExcludePattern {
     AbsolutePath(PathBuf),
     BaseName(String),
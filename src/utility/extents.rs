@@ -0,0 +1,246 @@
+use crate::utility::preprocess::FileTask;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Part of a source file stored in the same blocks as part of another,
+/// left behind by an earlier reflink copy or deduplication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    pub logical: u64,
+    pub physical: u64,
+    pub length: u64,
+}
+
+/// A range of `destination` to clone from an earlier copied file so the
+/// two share their blocks again, as their sources did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedRange {
+    pub original: PathBuf,
+    pub original_offset: u64,
+    pub destination: PathBuf,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Pairs up the shared extents of `files`: an extent also found in an
+/// earlier file becomes a [`SharedRange`] cloned from that file's copy.
+/// Extents are matched by where they start on disk, which is how reflinked
+/// files share them; sharing with files outside the copy is ignored.
+pub fn find_shared(files: &[FileTask]) -> Vec<SharedRange> {
+    let mut first: HashMap<u64, (&Path, Extent)> = HashMap::new();
+    let mut ranges = Vec::new();
+    for task in files {
+        let Ok(extents) = shared_extents(&task.source) else {
+            continue;
+        };
+        for extent in extents {
+            match first.get(&extent.physical) {
+                Some((original, earlier)) if *original != task.destination.as_path() => {
+                    ranges.push(SharedRange {
+                        original: original.to_path_buf(),
+                        original_offset: earlier.logical,
+                        destination: task.destination.clone(),
+                        offset: extent.logical,
+                        length: extent.length.min(earlier.length),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    first.insert(extent.physical, (&task.destination, extent));
+                }
+            }
+        }
+    }
+    ranges
+}
+
+#[cfg(target_os = "linux")]
+mod fiemap {
+    /// `struct fiemap` from `linux/fiemap.h`, followed by its extents.
+    #[repr(C)]
+    pub struct Header {
+        pub start: u64,
+        pub length: u64,
+        pub flags: u32,
+        pub mapped_extents: u32,
+        pub extent_count: u32,
+        pub reserved: u32,
+    }
+
+    /// `struct fiemap_extent`.
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Extent {
+        pub logical: u64,
+        pub physical: u64,
+        pub length: u64,
+        pub reserved64: [u64; 2],
+        pub flags: u32,
+        pub reserved: [u32; 3],
+    }
+
+    /// `_IOWR('f', 11, struct fiemap)`
+    pub const FS_IOC_FIEMAP: libc::Ioctl = 0xC020_660B_u32 as libc::Ioctl;
+    pub const FLAG_SYNC: u32 = 0x1;
+    pub const EXTENT_LAST: u32 = 0x1;
+    pub const EXTENT_SHARED: u32 = 0x2000;
+    /// Extents whose blocks can't be cloned as they are: not yet allocated,
+    /// compressed or encrypted, or packed in with other data.
+    pub const EXTENT_UNCLONABLE: u32 = 0x2 | 0x4 | 0x8 | 0x80 | 0x100 | 0x200 | 0x400;
+    pub const BATCH: usize = 256;
+}
+
+/// The extents of `path` the filesystem reports as shared with another
+/// file, from `FS_IOC_FIEMAP`.
+#[cfg(target_os = "linux")]
+pub fn shared_extents(path: &Path) -> io::Result<Vec<Extent>> {
+    use std::mem::size_of;
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+    let header_words = size_of::<fiemap::Header>() / size_of::<u64>();
+    let extent_words = size_of::<fiemap::Extent>() / size_of::<u64>();
+    // u64 words keep the buffer aligned for both structs
+    let mut buffer = vec![0u64; header_words + extent_words * fiemap::BATCH];
+    let mut shared = Vec::new();
+    let mut start = 0u64;
+    loop {
+        buffer.fill(0);
+        let header = buffer.as_mut_ptr().cast::<fiemap::Header>();
+        unsafe {
+            (*header).start = start;
+            (*header).length = u64::MAX - start;
+            (*header).flags = fiemap::FLAG_SYNC;
+            (*header).extent_count = fiemap::BATCH as u32;
+        }
+        if unsafe { libc::ioctl(file.as_raw_fd(), fiemap::FS_IOC_FIEMAP, header) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mapped = unsafe { (*header).mapped_extents } as usize;
+        if mapped == 0 {
+            break;
+        }
+        let extents = unsafe {
+            std::slice::from_raw_parts(
+                buffer.as_ptr().add(header_words).cast::<fiemap::Extent>(),
+                mapped,
+            )
+        };
+        for extent in extents {
+            if extent.flags & fiemap::EXTENT_SHARED != 0
+                && extent.flags & fiemap::EXTENT_UNCLONABLE == 0
+            {
+                shared.push(Extent {
+                    logical: extent.logical,
+                    physical: extent.physical,
+                    length: extent.length,
+                });
+            }
+        }
+        let last = extents[mapped - 1];
+        if last.flags & fiemap::EXTENT_LAST != 0 {
+            break;
+        }
+        start = last.logical + last.length;
+    }
+    Ok(shared)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn shared_extents(_path: &Path) -> io::Result<Vec<Extent>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "shared extents can only be read on Linux",
+    ))
+}
+
+/// Clones `range` with `FICLONERANGE`, replacing that part of the
+/// destination with the original's blocks. Returns the bytes now shared.
+/// The range is trimmed to both files and to whole blocks, except where it
+/// runs to the end of both.
+#[cfg(target_os = "linux")]
+pub fn clone_range(range: &SharedRange) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+
+    let original = std::fs::File::open(&range.original)?;
+    let destination = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&range.destination)?;
+    let original_size = original.metadata()?.len();
+    let metadata = destination.metadata()?;
+    let destination_size = metadata.len();
+
+    let mut length = range
+        .length
+        .min(original_size.saturating_sub(range.original_offset))
+        .min(destination_size.saturating_sub(range.offset));
+    let to_both_ends = range.original_offset + length == original_size
+        && range.offset + length == destination_size;
+    let block = metadata.blksize().max(1);
+    if !to_both_ends {
+        length -= length % block;
+    }
+    if length == 0 {
+        return Ok(0);
+    }
+
+    let args = libc::file_clone_range {
+        src_fd: original.as_raw_fd() as i64,
+        src_offset: range.original_offset,
+        src_length: length,
+        dest_offset: range.offset,
+    };
+    if unsafe { libc::ioctl(destination.as_raw_fd(), libc::FICLONERANGE, &args) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(length)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn clone_range(_range: &SharedRange) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extents can only be cloned on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_unshared_files_have_nothing_to_clone() {
+        let temp_dir = TempDir::new().unwrap();
+        let files: Vec<FileTask> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let source = temp_dir.path().join(name);
+                fs::write(&source, vec![7u8; 64 * 1024]).unwrap();
+                FileTask {
+                    destination: temp_dir.path().join(format!("{}.copy", name)),
+                    source,
+                    size: 64 * 1024,
+                    inode_group: None,
+                }
+            })
+            .collect();
+        assert!(find_shared(&files).is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_fiemap_reads_a_plain_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("plain");
+        fs::write(&path, vec![1u8; 256 * 1024]).unwrap();
+        match shared_extents(&path) {
+            Ok(extents) => assert!(extents.is_empty()),
+            // tmpfs and some network filesystems have no FIEMAP
+            Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EOPNOTSUPP)),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::cli::args::CopyOptions;
+use crate::utility::output;
+
+/// Descriptors one copy worker may hold at once: the source, the
+/// destination, and a read-back or data stream alongside them.
+pub const FDS_PER_WORKER: u64 = 4;
+/// Kept back for everything else: standard streams, the log, journal and
+/// manifest, the status socket and directory walks.
+const RESERVED: u64 = 64;
+
+/// macOS refuses a soft limit above `OPEN_MAX`, even when the hard limit
+/// is unlimited.
+#[cfg(target_os = "macos")]
+const OPEN_MAX: u64 = 10240;
+
+/// The soft `RLIMIT_NOFILE` in force, or None where there is no such limit.
+#[cfg(unix)]
+pub fn current_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)] // rlim_t is u64 on most targets
+    Some(limit.rlim_cur as u64)
+}
+
+#[cfg(not(unix))]
+pub fn current_limit() -> Option<u64> {
+    None
+}
+
+/// Raises the soft `RLIMIT_NOFILE` as far as the hard limit allows, once at
+/// startup, and returns the limit now in force.
+#[cfg(unix)]
+pub fn raise_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    let target = limit.rlim_max;
+    #[cfg(target_os = "macos")]
+    let target = target.min(OPEN_MAX as libc::rlim_t);
+    if limit.rlim_cur < target {
+        let raised = libc::rlimit {
+            rlim_cur: target,
+            rlim_max: limit.rlim_max,
+        };
+        // keeps the old limit if refused
+        unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) };
+    }
+    current_limit()
+}
+
+#[cfg(not(unix))]
+pub fn raise_limit() -> Option<u64> {
+    None
+}
+
+/// Workers `limit` descriptors can keep busy, at least one.
+pub fn workers_within(limit: u64) -> usize {
+    let workers = limit.saturating_sub(RESERVED) / FDS_PER_WORKER;
+    usize::try_from(workers).unwrap_or(usize::MAX).max(1)
+}
+
+/// `requested` workers, or fewer when the descriptor limit can't serve
+/// them all. The remaining tasks wait their turn instead of failing with
+/// "too many open files".
+pub fn worker_count(requested: usize, options: &CopyOptions) -> usize {
+    let Some(limit) = current_limit() else {
+        return requested;
+    };
+    let workers = workers_within(limit);
+    if workers >= requested {
+        return requested;
+    }
+    output::warn(
+        options,
+        format!(
+            "Running {} workers instead of {}: the open file limit is {} (ulimit -n)",
+            workers, requested, limit
+        ),
+    );
+    workers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workers_within_limit() {
+        assert_eq!(workers_within(1024), 240);
+        assert_eq!(workers_within(256), 48);
+        // too low to budget for: one worker still runs
+        assert_eq!(workers_within(32), 1);
+    }
+}
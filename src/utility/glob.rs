@@ -0,0 +1,130 @@
+use globset::{GlobBuilder, GlobMatcher};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Characters that make a source argument a pattern.
+const GLOB_CHARS: &[char] = &['*', '?', '[', '{'];
+
+/// Whether `source` holds wildcards a shell would have expanded.
+pub fn has_glob(source: &Path) -> bool {
+    source.components().any(|component| is_pattern(&component))
+}
+
+fn is_pattern(component: &Component) -> bool {
+    match component {
+        Component::Normal(name) => name.to_str().is_some_and(|name| name.contains(GLOB_CHARS)),
+        _ => false,
+    }
+}
+
+/// Expands source arguments the way a Unix shell would, for Windows shells
+/// that pass `*.log` on as it is (and `--glob` elsewhere). A source that
+/// exists as written is kept, and a pattern that matches nothing is an
+/// error.
+pub fn expand_sources(sources: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    let mut expanded = Vec::with_capacity(sources.len());
+    for source in sources {
+        if !has_glob(&source) || std::fs::symlink_metadata(&source).is_ok() {
+            expanded.push(source);
+            continue;
+        }
+        let matches = expand(&source)?;
+        if matches.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no files match '{}'", source.display()),
+            ));
+        }
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// The paths matching `pattern`, sorted. Wildcards never cross a `/`, and,
+/// outside Windows, only match a leading dot when the pattern starts with
+/// one.
+pub fn expand(pattern: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![PathBuf::new()];
+    for component in pattern.components() {
+        if !is_pattern(&component) {
+            for path in &mut paths {
+                path.push(component);
+            }
+            continue;
+        }
+        let name = component.as_os_str().to_string_lossy();
+        let matcher = matcher(&name)?;
+        let dotted = cfg!(windows) || name.starts_with('.');
+        let mut next = Vec::new();
+        for path in &paths {
+            let dir = if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path.as_path()
+            };
+            // like a shell, a directory that can't be read matches nothing
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let entry_name = entry.file_name();
+                if !dotted && entry_name.as_encoded_bytes().starts_with(b".") {
+                    continue;
+                }
+                if matcher.is_match(&entry_name) {
+                    next.push(path.join(entry_name));
+                }
+            }
+        }
+        next.sort();
+        paths = next;
+    }
+    // literal components after the last wildcard may not exist
+    paths.retain(|path| std::fs::symlink_metadata(path).is_ok());
+    Ok(paths)
+}
+
+fn matcher(name: &str) -> io::Result<GlobMatcher> {
+    GlobBuilder::new(name)
+        .literal_separator(true)
+        .case_insensitive(cfg!(windows))
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_expand_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for file in ["a.log", "b.log", "c.txt", ".hidden.log", "logs/1/app.log"] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        std::fs::create_dir_all(root.join("logs/2")).unwrap();
+
+        let expanded = expand_sources(vec![
+            root.join("*.log"),
+            root.join("logs/*/app.log"),
+            root.join("c.txt"),
+        ])
+        .unwrap();
+        let mut expected = vec![root.join("a.log"), root.join("b.log")];
+        if cfg!(windows) {
+            expected.insert(0, root.join(".hidden.log"));
+        }
+        expected.extend([root.join("logs/1/app.log"), root.join("c.txt")]);
+        assert_eq!(expanded, expected);
+
+        assert!(has_glob(Path::new("src/*.rs")));
+        assert!(!has_glob(Path::new("src/main.rs")));
+        let e = expand_sources(vec![root.join("*.csv")]).unwrap_err();
+        assert!(e.to_string().contains("no files match"));
+    }
+}
@@ -0,0 +1,275 @@
+use super::buffers;
+use super::helper::open_source;
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+use std::path::Path;
+use xxhash_rust::xxh3::Xxh3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum HashAlgo {
+    #[default]
+    Sha256,
+    Xxh3,
+    /// CRC-32 (IEEE), as used by zip and gzip; catches corruption, not tampering.
+    Crc32,
+    /// BLAKE3, cryptographic like SHA-256 but faster, and hashed on several
+    /// threads for large buffers.
+    Blake3,
+}
+
+impl HashAlgo {
+    /// Conventional manifest file name for this algorithm.
+    pub fn manifest_name(&self) -> &'static str {
+        match self {
+            HashAlgo::Sha256 => "SHA256SUMS",
+            HashAlgo::Xxh3 => "XXH3SUMS",
+            HashAlgo::Crc32 => "CRC32SUMS",
+            HashAlgo::Blake3 => "B3SUMS",
+        }
+    }
+}
+
+/// Incremental hasher fed from the copy loop.
+pub enum FileHasher {
+    Sha256(Box<Sha256>),
+    Xxh3(Box<Xxh3>),
+    Crc32(crc32fast::Hasher),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl FileHasher {
+    pub fn new(algo: HashAlgo) -> Self {
+        match algo {
+            HashAlgo::Sha256 => FileHasher::Sha256(Box::new(Sha256::new())),
+            HashAlgo::Xxh3 => FileHasher::Xxh3(Box::new(Xxh3::new())),
+            HashAlgo::Crc32 => FileHasher::Crc32(crc32fast::Hasher::new()),
+            HashAlgo::Blake3 => FileHasher::Blake3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            FileHasher::Sha256(h) => h.update(data),
+            FileHasher::Xxh3(h) => h.update(data),
+            FileHasher::Crc32(h) => h.update(data),
+            FileHasher::Blake3(h) => {
+                h.update_rayon(data);
+            }
+        }
+    }
+
+    /// Lowercase hex digest, as printed by `sha256sum`.
+    pub fn finish(self) -> String {
+        match self {
+            FileHasher::Sha256(h) => to_hex(&h.finalize()),
+            FileHasher::Xxh3(h) => format!("{:016x}", h.digest()),
+            FileHasher::Crc32(h) => format!("{:08x}", h.finalize()),
+            FileHasher::Blake3(h) => to_hex(h.finalize().as_bytes()),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hash_file(path: &Path, algo: HashAlgo) -> io::Result<String> {
+    hash_reader(open_source(path)?, algo)
+}
+
+/// Hashes `path` as it reads back from the disk rather than from the page
+/// cache: the file is flushed and its cached pages dropped before reading.
+/// Where the platform has no way to drop them this is a plain re-read.
+pub fn hash_file_uncached(path: &Path, algo: HashAlgo) -> io::Result<String> {
+    let file = std::fs::File::open(path)?;
+    // dirty pages can't be dropped, so they go to disk first
+    file.sync_all()?;
+    drop_cached_pages(&file)?;
+    hash_reader(file, algo)
+}
+
+/// Hashes the first `len` bytes of `path`, such as what was written to a
+/// block device larger than the data; `uncached` as [`hash_file_uncached`].
+pub fn hash_prefix(path: &Path, len: u64, algo: HashAlgo, uncached: bool) -> io::Result<String> {
+    let file = std::fs::File::open(path)?;
+    if uncached {
+        file.sync_all()?;
+        drop_cached_pages(&file)?;
+    }
+    hash_reader(file.take(len), algo)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn drop_cached_pages(file: &std::fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn drop_cached_pages(file: &std::fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+)))]
+fn drop_cached_pages(_file: &std::fs::File) -> io::Result<()> {
+    Ok(())
+}
+
+fn hash_reader(mut file: impl Read, algo: HashAlgo) -> io::Result<String> {
+    let mut hasher = FileHasher::new(algo);
+    let mut buffer = buffers::shared().take(128 * 1024);
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Extended attribute `--store-checksum` records a copy's digest in.
+pub const DIGEST_XATTR: &str = "user.cpx.checksum";
+
+/// Records `digest` of `path`'s contents on the file itself, with the size
+/// and mtime it has now, so a later run can take it as the digest for as
+/// long as neither changes.
+pub fn store_digest(path: &Path, algo: HashAlgo, digest: &str) -> io::Result<()> {
+    if !xattr::SUPPORTED_PLATFORM {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "extended attributes are not supported on this platform",
+        ));
+    }
+    let metadata = std::fs::metadata(path)?;
+    let value = format!(
+        "{} {} {} {}",
+        algo_name(algo),
+        digest,
+        metadata.len(),
+        mtime_nanos(&metadata)?
+    );
+    xattr::set(path, DIGEST_XATTR, value.as_bytes())
+}
+
+/// The `algo` digest [`store_digest`] recorded on `path`, if the file still
+/// has the size and mtime it had then.
+pub fn stored_digest(path: &Path, algo: HashAlgo) -> Option<String> {
+    if !xattr::SUPPORTED_PLATFORM {
+        return None;
+    }
+    let value = xattr::get(path, DIGEST_XATTR).ok()??;
+    let value = String::from_utf8(value).ok()?;
+    let mut fields = value.split(' ');
+    let (name, digest, len, mtime) = (
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+    );
+    let metadata = std::fs::metadata(path).ok()?;
+    let current = (metadata.len(), mtime_nanos(&metadata).ok()?);
+    (name == algo_name(algo) && (len.parse().ok()?, mtime.parse().ok()?) == current)
+        .then(|| digest.to_string())
+}
+
+fn algo_name(algo: HashAlgo) -> String {
+    algo.to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+fn mtime_nanos(metadata: &std::fs::Metadata) -> io::Result<u128> {
+    let since_epoch = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(since_epoch.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sha256_known_digest() {
+        let mut hasher = FileHasher::new(HashAlgo::Sha256);
+        hasher.update(b"abc");
+        assert_eq!(
+            hasher.finish(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_crc32_known_digest() {
+        let mut hasher = FileHasher::new(HashAlgo::Crc32);
+        hasher.update(b"123456789");
+        assert_eq!(hasher.finish(), "cbf43926");
+    }
+
+    #[test]
+    fn test_blake3_known_digest() {
+        let mut hasher = FileHasher::new(HashAlgo::Blake3);
+        hasher.update(b"abc");
+        assert_eq!(
+            hasher.finish(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    #[test]
+    fn test_incremental_matches_file_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.bin");
+        fs::write(&path, b"hello world").unwrap();
+
+        for algo in [
+            HashAlgo::Sha256,
+            HashAlgo::Xxh3,
+            HashAlgo::Crc32,
+            HashAlgo::Blake3,
+        ] {
+            let mut hasher = FileHasher::new(algo);
+            hasher.update(b"hello ");
+            hasher.update(b"world");
+            assert_eq!(hasher.finish(), hash_file(&path, algo).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_stored_digest_holds_until_the_file_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.bin");
+        fs::write(&path, b"hello world").unwrap();
+        let digest = hash_file(&path, HashAlgo::Xxh3).unwrap();
+        // tmpfs without user xattrs, or a platform without any
+        if store_digest(&path, HashAlgo::Xxh3, &digest).is_err() {
+            return;
+        }
+
+        assert_eq!(stored_digest(&path, HashAlgo::Xxh3), Some(digest));
+        assert_eq!(stored_digest(&path, HashAlgo::Sha256), None);
+        // touched, even with the same size
+        filetime::set_file_mtime(&path, filetime::FileTime::from_unix_time(1, 0)).unwrap();
+        assert_eq!(stored_digest(&path, HashAlgo::Xxh3), None);
+    }
+}
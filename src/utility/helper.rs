@@ -1,23 +1,48 @@
+use super::barrier::BarrierSpec;
+use super::notify::DEFAULT_NOTIFY_AFTER;
 use super::preprocess::{SymlinkKind, SymlinkTask};
-use super::progress_bar::{ProgressBarStyle, ProgressOptions};
-use crate::cli::args::{BackupMode, CopyOptions, FollowSymlink, ReflinkMode, SymlinkMode};
+use super::progress_bar::{ProgressBarStyle, ProgressMode, ProgressOptions, STATUS_INTERVAL};
+use crate::cli::args::{
+    BackupMode, ConflictPolicy, CopyBackend, CopyOptions, DestSymlinks, FileOrder, FollowSymlink,
+    FsyncMode, HiddenMode, IoPriority, JunctionMode, ReflinkMode, SanitizeMode, SymlinkFallback,
+    SymlinkMode,
+};
 use crate::config::schema::Config;
 use crate::error::{CopyError, CopyResult};
 use crate::utility::preprocess::HardlinkTask;
+use std::collections::BTreeSet;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 
 pub fn create_directories(dirs: &[crate::utility::preprocess::DirectoryTask]) -> io::Result<()> {
+    create_directories_with(dirs, |_| Ok(()))
+}
+
+/// [`create_directories`], calling `created` for each directory that did not
+/// exist yet, parents first.
+pub fn create_directories_with(
+    dirs: &[crate::utility::preprocess::DirectoryTask],
+    mut created: impl FnMut(&Path) -> io::Result<()>,
+) -> io::Result<()> {
     let mut dirs: Vec<_> = dirs.iter().collect();
     dirs.sort_unstable_by_key(|d| d.destination.components().count());
     dirs.dedup_by_key(|d| &d.destination);
 
     for dir in &dirs {
         match std::fs::create_dir(&dir.destination) {
-            Ok(()) => {}
+            Ok(()) => created(&dir.destination)?,
             Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
             Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let missing: Vec<&Path> = dir
+                    .destination
+                    .ancestors()
+                    .take_while(|path| std::fs::symlink_metadata(path).is_err())
+                    .collect();
                 std::fs::create_dir_all(&dir.destination)?;
+                for path in missing.into_iter().rev() {
+                    created(path)?;
+                }
             }
             Err(e) => return Err(e),
         }
@@ -27,7 +52,7 @@ pub fn create_directories(dirs: &[crate::utility::preprocess::DirectoryTask]) ->
 
 pub fn create_symlink(task: &SymlinkTask) -> io::Result<()> {
     let target = match task.kind {
-        SymlinkKind::PreserveExact => task.source.clone(),
+        SymlinkKind::PreserveExact | SymlinkKind::Junction => task.source.clone(),
         SymlinkKind::AbsoluteToSource => task.source.canonicalize()?,
         SymlinkKind::RelativeToSource => {
             let dest_parent = task.destination.parent().ok_or_else(|| {
@@ -49,6 +74,9 @@ pub fn create_symlink(task: &SymlinkTask) -> io::Result<()> {
 
     #[cfg(windows)]
     {
+        if task.kind == SymlinkKind::Junction {
+            return super::junction::create(&target, &task.destination);
+        }
         let meta = std::fs::metadata(&target).ok();
         if meta.as_ref().map_or(false, |m| m.is_dir()) {
             std::os::windows::fs::symlink_dir(&target, &task.destination)?;
@@ -60,22 +88,35 @@ pub fn create_symlink(task: &SymlinkTask) -> io::Result<()> {
     Ok(())
 }
 
-pub fn create_hardlink(task: &HardlinkTask, options: &CopyOptions) -> CopyResult<()> {
-    if task.destination.try_exists()? {
-        if options.interactive && !prompt_overwrite(&task.destination)? {
-            return Ok(());
-        }
+/// Whether `e` is the OS refusing symlinks themselves: a missing privilege on
+/// Windows, a filesystem without symlinks (FAT) on Unix.
+pub fn symlink_not_permitted(e: &io::Error) -> bool {
+    #[cfg(windows)]
+    const NOT_PERMITTED: i32 = 1314; // ERROR_PRIVILEGE_NOT_HELD
+    #[cfg(not(windows))]
+    const NOT_PERMITTED: i32 = libc::EPERM;
+    e.raw_os_error() == Some(NOT_PERMITTED)
+}
 
-        if options.force || options.remove_destination {
-            if let Err(_e) = std::fs::remove_file(&task.destination) {
-                return Err(CopyError::HardlinkFailed {
-                    source: task.source.clone(),
-                    destination: task.destination.clone(),
-                });
-            }
-        } else {
-            return Err(CopyError::FileExists(task.destination.clone()));
-        }
+/// What `--symlink-fallback=copy` puts in place of `task`: the file or
+/// directory the link resolves to.
+pub fn symlink_fallback_source(task: &SymlinkTask) -> io::Result<PathBuf> {
+    match &task.origin {
+        Some(origin) => std::fs::canonicalize(origin),
+        None => std::fs::canonicalize(&task.source),
+    }
+}
+
+/// Returns false when `--on-conflict` keeps an existing destination.
+pub fn create_hardlink(task: &HardlinkTask, options: &CopyOptions) -> CopyResult<bool> {
+    if !super::conflict::prepare_link(&task.destination, options).map_err(|e| match e {
+        CopyError::Io(_) => CopyError::HardlinkFailed {
+            source: task.source.clone(),
+            destination: task.destination.clone(),
+        },
+        e => e,
+    })? {
+        return Ok(false);
     }
 
     std::fs::hard_link(&task.source, &task.destination).map_err(|_e| {
@@ -85,6 +126,62 @@ pub fn create_hardlink(task: &HardlinkTask, options: &CopyOptions) -> CopyResult
         }
     })?;
 
+    Ok(true)
+}
+
+/// Opens a source file for reading without updating its access time.
+/// `O_NOATIME` is only allowed on files the caller owns (or with
+/// `CAP_FOWNER`); anything else is opened normally.
+#[cfg(target_os = "linux")]
+pub fn open_source(path: &Path) -> io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOATIME)
+        .open(path)
+    {
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) => std::fs::File::open(path),
+        result => result,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_source(path: &Path) -> io::Result<std::fs::File> {
+    std::fs::File::open(path)
+}
+
+/// Flushes `path` to disk. On a directory this makes the entries created or
+/// renamed in it durable.
+pub fn sync_path(path: &Path) -> io::Result<()> {
+    std::fs::File::open(path)?.sync_all()
+}
+
+/// Syncs the directory holding each of `paths`, once per directory. Ones
+/// that were never created are skipped.
+pub fn sync_parent_dirs<'a>(paths: impl IntoIterator<Item = &'a Path>) -> io::Result<()> {
+    let parents: BTreeSet<&Path> = paths
+        .into_iter()
+        .filter_map(Path::parent)
+        .map(|parent| {
+            if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            }
+        })
+        .collect();
+    for parent in parents {
+        match sync_path(parent) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("cannot sync directory '{}': {}", parent.display(), e),
+                ));
+            }
+            _ => {}
+        }
+    }
     Ok(())
 }
 
@@ -100,6 +197,20 @@ pub fn prompt_overwrite(path: &Path) -> io::Result<bool> {
     Ok(input.trim().eq_ignore_ascii_case("y"))
 }
 
+/// Asks `question` on stderr; anything but y or yes is a no.
+pub fn prompt_proceed(question: &str) -> io::Result<bool> {
+    use std::io::{Write, stderr, stdin};
+
+    eprint!("{} [y/N] ", question);
+    stderr().flush()?;
+
+    let mut input = String::new();
+    stdin().read_line(&mut input)?;
+
+    let answer = input.trim().to_ascii_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
 pub fn with_parents(dest: &Path, source: &Path) -> PathBuf {
     let skip_count = if source.is_absolute() { 1 } else { 0 };
     let components = source.components().skip(skip_count);
@@ -112,12 +223,43 @@ pub fn with_parents(dest: &Path, source: &Path) -> PathBuf {
     dest.join(relative)
 }
 
+/// Canonicalizes a path that may not exist yet by resolving its deepest
+/// existing ancestor and applying the remaining components lexically.
+pub fn resolve_path(path: &Path) -> io::Result<PathBuf> {
+    let absolute = std::path::absolute(path)?;
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::new();
+    let mut resolved = loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            break resolved;
+        }
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(component)) => {
+                rest.push(component);
+                existing = parent;
+            }
+            _ => return Ok(absolute),
+        }
+    };
+    for component in rest.into_iter().rev() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            other => resolved.push(other),
+        }
+    }
+    Ok(resolved)
+}
+
+/// Cuts `filename` to `max_len` characters, ending in `...` when shortened.
 pub fn truncate_filename(filename: &str, max_len: usize) -> String {
-    if filename.len() <= max_len {
+    if filename.chars().count() <= max_len {
         filename.to_string()
     } else {
-        let truncate_at = max_len.saturating_sub(3);
-        format!("{}...", &filename[..truncate_at])
+        let kept: String = filename.chars().take(max_len.saturating_sub(3)).collect();
+        format!("{}...", kept)
     }
 }
 
@@ -139,24 +281,90 @@ pub fn parse_follow_symlink(s: &str) -> FollowSymlink {
     }
 }
 
+pub fn parse_junction_mode(s: &str) -> JunctionMode {
+    match s {
+        "skip" => JunctionMode::Skip,
+        "follow" => JunctionMode::Follow,
+        _ => JunctionMode::Recreate,
+    }
+}
+
+pub fn parse_hidden_mode(s: &str) -> HiddenMode {
+    match s {
+        "skip" => HiddenMode::Skip,
+        _ => HiddenMode::Include,
+    }
+}
+
+/// `""` leaves names alone.
+pub fn parse_sanitize_mode(s: &str) -> Option<SanitizeMode> {
+    match s {
+        "replace" => Some(SanitizeMode::Replace),
+        "percent" => Some(SanitizeMode::Percent),
+        _ => None,
+    }
+}
+
+pub fn parse_dest_symlinks(s: &str) -> DestSymlinks {
+    match s {
+        "replace" => DestSymlinks::Replace,
+        "keep-dirs" => DestSymlinks::KeepDirs,
+        _ => DestSymlinks::Follow,
+    }
+}
+
+pub fn parse_symlink_fallback(s: &str) -> SymlinkFallback {
+    match s {
+        "copy" => SymlinkFallback::Copy,
+        "skip" => SymlinkFallback::Skip,
+        _ => SymlinkFallback::Error,
+    }
+}
+
 pub fn parse_progress_style(s: &str) -> ProgressBarStyle {
     match s {
         "detailed" => ProgressBarStyle::Detailed,
+        "json" => ProgressBarStyle::Json,
         _ => ProgressBarStyle::Default,
     }
 }
 
+pub fn parse_progress_mode(s: &str) -> ProgressMode {
+    match s {
+        "always" => ProgressMode::Always,
+        "plain" => ProgressMode::Plain,
+        "never" => ProgressMode::Never,
+        _ => ProgressMode::Auto,
+    }
+}
+
 pub fn parse_progress_bar(cfg: &Config) -> ProgressOptions {
     ProgressOptions {
         style: parse_progress_style(&cfg.progress.style),
+        mode: parse_progress_mode(&cfg.progress.mode),
         filled: cfg.progress.bar.filled.clone(),
         empty: cfg.progress.bar.empty.clone(),
         head: cfg.progress.bar.head.clone(),
         bar_color: cfg.progress.color.bar.clone(),
         message_color: cfg.progress.color.message.clone(),
+        file_bars: cfg.progress.file_bars,
+        refresh_hz: cfg.progress.refresh_hz.max(1),
+        status_interval: humantime::parse_duration(&cfg.progress.status_interval)
+            .unwrap_or(STATUS_INTERVAL),
     }
 }
 
+/// `[notify]` threshold, or `None` when notifications are off.
+pub fn parse_notify(cfg: &Config) -> Option<Duration> {
+    cfg.notify.enabled.then(|| {
+        humantime::parse_duration(&cfg.notify.min_duration).unwrap_or(DEFAULT_NOTIFY_AFTER)
+    })
+}
+
+pub fn parse_modify_window(s: &str) -> Duration {
+    humantime::parse_duration(s).unwrap_or(Duration::ZERO)
+}
+
 pub fn parse_backup_mode(s: &str) -> Option<BackupMode> {
     match s {
         "none" => Some(BackupMode::None),
@@ -167,6 +375,58 @@ pub fn parse_backup_mode(s: &str) -> Option<BackupMode> {
     }
 }
 
+pub fn parse_conflict_policy(s: &str) -> Option<ConflictPolicy> {
+    match s {
+        "overwrite" => Some(ConflictPolicy::Overwrite),
+        "skip" => Some(ConflictPolicy::Skip),
+        "newer" => Some(ConflictPolicy::Newer),
+        "larger" => Some(ConflictPolicy::Larger),
+        "rename" => Some(ConflictPolicy::Rename),
+        "prompt" => Some(ConflictPolicy::Prompt),
+        "error" => Some(ConflictPolicy::Error),
+        _ => None,
+    }
+}
+
+pub fn parse_fsync_mode(s: &str) -> FsyncMode {
+    match s {
+        "file" => FsyncMode::File,
+        "dir" => FsyncMode::Dir,
+        "all" => FsyncMode::All,
+        _ => FsyncMode::None,
+    }
+}
+
+pub fn parse_write_barrier(s: &str) -> Option<BarrierSpec> {
+    s.parse().ok()
+}
+
+pub fn parse_io_priority(s: &str) -> IoPriority {
+    match s {
+        "low" => IoPriority::Low,
+        "idle" => IoPriority::Idle,
+        _ => IoPriority::Normal,
+    }
+}
+
+pub fn parse_copy_backend(s: &str) -> CopyBackend {
+    match s {
+        "copy-file-range" => CopyBackend::CopyFileRange,
+        "mmap" => CopyBackend::Mmap,
+        "buffered" => CopyBackend::Buffered,
+        _ => CopyBackend::Auto,
+    }
+}
+
+pub fn parse_file_order(s: &str) -> FileOrder {
+    match s {
+        "size-asc" => FileOrder::SizeAsc,
+        "path" => FileOrder::Path,
+        "none" => FileOrder::None,
+        _ => FileOrder::SizeDesc,
+    }
+}
+
 pub fn parse_reflink_mode(s: &str) -> Option<ReflinkMode> {
     match s {
         "auto" => Some(ReflinkMode::Auto),
@@ -183,6 +443,76 @@ mod tests {
     use std::path::PathBuf;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_parse_notify() {
+        let mut cfg = Config::default();
+        assert_eq!(parse_notify(&cfg), None);
+
+        cfg.notify.enabled = true;
+        cfg.notify.min_duration = "5m".to_string();
+        assert_eq!(parse_notify(&cfg), Some(Duration::from_secs(300)));
+
+        cfg.notify.min_duration = "soon".to_string();
+        assert_eq!(parse_notify(&cfg), Some(DEFAULT_NOTIFY_AFTER));
+    }
+
+    #[test]
+    fn test_sync_parent_dirs_skips_missing() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("a.txt");
+        fs::write(&file, "a").unwrap();
+        let missing = temp_dir.path().join("gone/b.txt");
+
+        sync_path(&file).unwrap();
+        sync_parent_dirs([file.as_path(), missing.as_path(), Path::new("relative")]).unwrap();
+        assert_eq!(parse_fsync_mode("dir"), FsyncMode::Dir);
+        assert_eq!(parse_fsync_mode(""), FsyncMode::None);
+        assert_eq!(parse_file_order("path"), FileOrder::Path);
+        assert_eq!(parse_file_order(""), FileOrder::SizeDesc);
+        assert_eq!(parse_io_priority("idle"), IoPriority::Idle);
+        assert_eq!(parse_io_priority(""), IoPriority::Normal);
+        assert_eq!(parse_copy_backend("mmap"), CopyBackend::Mmap);
+        assert_eq!(parse_copy_backend(""), CopyBackend::Auto);
+        assert_eq!(
+            parse_write_barrier("2G").and_then(|spec| spec.bytes),
+            Some(2 << 30)
+        );
+        assert_eq!(parse_write_barrier(""), None);
+        assert_eq!(parse_modify_window("2s"), Duration::from_secs(2));
+        assert_eq!(parse_modify_window("two"), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_open_source_reads_and_reports_missing() {
+        use std::io::Read;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("a.txt");
+        fs::write(&file, "content").unwrap();
+
+        let mut content = String::new();
+        open_source(&file)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "content");
+        assert_eq!(
+            open_source(&temp_dir.path().join("missing"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_resolve_path_missing_tail() {
+        let temp_dir = TempDir::new().unwrap();
+        let real = temp_dir.path().canonicalize().unwrap();
+
+        let resolved = resolve_path(&temp_dir.path().join("a/../b/new.txt")).unwrap();
+        assert_eq!(resolved, real.join("b/new.txt"));
+    }
+
     #[test]
     fn test_with_parents_relative_path() {
         let dest = Path::new("/dest");
@@ -290,6 +620,12 @@ mod tests {
         assert_eq!(result, "...");
     }
 
+    #[test]
+    fn test_truncate_filename_multibyte() {
+        let result = truncate_filename("Über_große_Datei.txt", 8);
+        assert_eq!(result, "Über_...");
+    }
+
     #[test]
     #[cfg(unix)]
     fn test_create_symlink_absolute() {
@@ -303,6 +639,7 @@ mod tests {
             source: source.clone(),
             destination: dest.clone(),
             kind: SymlinkKind::AbsoluteToSource,
+            origin: None,
         };
 
         create_symlink(&task).unwrap();
@@ -329,6 +666,7 @@ mod tests {
             source: source.clone(),
             destination: dest.clone(),
             kind: SymlinkKind::RelativeToSource,
+            origin: None,
         };
 
         create_symlink(&task).unwrap();
@@ -355,6 +693,7 @@ mod tests {
             source: source_dir.clone(),
             destination: dest_link.clone(),
             kind: SymlinkKind::AbsoluteToSource,
+            origin: None,
         };
 
         create_symlink(&task).unwrap();
@@ -379,6 +718,7 @@ mod tests {
             source: source.clone(),
             destination: dest.clone(),
             kind: SymlinkKind::RelativeToSource,
+            origin: None,
         };
 
         create_symlink(&task).unwrap();
@@ -400,6 +740,7 @@ mod tests {
             source: source.clone(),
             destination: dest.clone(),
             kind: SymlinkKind::AbsoluteToSource,
+            origin: None,
         };
 
         let result = create_symlink(&task);
@@ -417,10 +758,47 @@ mod tests {
             source: source.clone(),
             destination: dest.clone(),
             kind: SymlinkKind::RelativeToSource,
+            origin: None,
         };
 
         create_symlink(&task).unwrap();
         assert!(dest.symlink_metadata().unwrap().is_symlink());
         assert!(dest.metadata().is_err());
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_symlink_fallback_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = temp_dir.path().join("data");
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("file.txt"), b"x").unwrap();
+        let link = data.join("link.txt");
+        std::os::unix::fs::symlink("file.txt", &link).unwrap();
+
+        // an exact copy keeps the relative target, resolved from the link
+        let task = SymlinkTask {
+            source: PathBuf::from("file.txt"),
+            destination: temp_dir.path().join("out.txt"),
+            kind: SymlinkKind::PreserveExact,
+            origin: Some(link),
+        };
+        let expected = data.join("file.txt").canonicalize().unwrap();
+        assert_eq!(symlink_fallback_source(&task).unwrap(), expected);
+
+        let task = SymlinkTask {
+            source: data.join("file.txt"),
+            origin: None,
+            kind: SymlinkKind::AbsoluteToSource,
+            ..task
+        };
+        assert_eq!(symlink_fallback_source(&task).unwrap(), expected);
+
+        assert!(symlink_not_permitted(&io::Error::from_raw_os_error(
+            libc::EPERM
+        )));
+        assert!(!symlink_not_permitted(&io::Error::from_raw_os_error(
+            libc::EACCES
+        )));
+    }
 }
@@ -0,0 +1,262 @@
+use indicatif::{BinaryBytes, FormattedDuration};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Failures kept per run; the rest are only counted.
+const MAX_ERRORS: usize = 20;
+/// Runs kept in the history file; older ones are dropped as new ones are
+/// recorded.
+const MAX_RUNS: usize = 1000;
+
+/// One finished run, as `cpx history` lists it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// Position in the history file, from 1; assigned when read back, so
+    /// never written to it.
+    #[serde(skip_deserializing, skip_serializing_if = "unassigned")]
+    pub id: usize,
+    /// Seconds since the Unix epoch.
+    pub started: u64,
+    /// `--job` the run was started with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+    pub duration_seconds: f64,
+    pub cwd: PathBuf,
+    pub args: Vec<String>,
+    pub exit_code: i32,
+    pub total_files: usize,
+    pub files_done: usize,
+    pub total_bytes: u64,
+    pub bytes_done: u64,
+    pub failed: usize,
+    /// The first failures, or the error that ended the run.
+    pub errors: Vec<String>,
+}
+
+impl RunRecord {
+    /// Keeps the first [`MAX_ERRORS`] of `errors`; `failed` still counts
+    /// them all.
+    pub fn with_errors(mut self, errors: impl IntoIterator<Item = String>) -> Self {
+        self.errors = errors.into_iter().take(MAX_ERRORS).collect();
+        self
+    }
+
+    fn started_at(&self) -> String {
+        humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(self.started))
+            .to_string()
+    }
+
+    fn outcome(&self) -> String {
+        match self.exit_code {
+            0 => "ok".to_string(),
+            130 => "interrupted".to_string(),
+            _ if self.failed > 0 => format!("{} failed", self.failed),
+            _ => "failed".to_string(),
+        }
+    }
+
+    /// One line for the `cpx history` listing.
+    pub fn to_line(&self) -> String {
+        format!(
+            "{:>4}  {}  {:>8}  {}/{} files  {:>10}  {:<11}  {}\n",
+            self.id,
+            self.started_at(),
+            FormattedDuration(Duration::from_secs_f64(self.duration_seconds)),
+            self.files_done,
+            self.total_files,
+            BinaryBytes(self.bytes_done).to_string(),
+            self.outcome(),
+            self.args.join(" ")
+        )
+    }
+
+    /// Everything recorded, for `cpx history show`.
+    pub fn to_text(&self) -> String {
+        let mut out = format!("Run {}\n", self.id);
+        if let Some(job) = &self.job {
+            out.push_str(&format!("  Job:       {}\n", job));
+        }
+        out.push_str(&format!("  Command:   {}\n", self.args.join(" ")));
+        out.push_str(&format!("  Directory: {}\n", self.cwd.display()));
+        out.push_str(&format!("  Started:   {}\n", self.started_at()));
+        out.push_str(&format!(
+            "  Duration:  {}\n",
+            FormattedDuration(Duration::from_secs_f64(self.duration_seconds))
+        ));
+        out.push_str(&format!(
+            "  Files:     {} of {}\n",
+            self.files_done, self.total_files
+        ));
+        out.push_str(&format!(
+            "  Bytes:     {} of {}\n",
+            BinaryBytes(self.bytes_done),
+            BinaryBytes(self.total_bytes)
+        ));
+        out.push_str(&format!(
+            "  Result:    {} (exit {})\n",
+            self.outcome(),
+            self.exit_code
+        ));
+        if !self.errors.is_empty() {
+            out.push_str("  Errors:\n");
+            for error in &self.errors {
+                out.push_str(&format!("    {}\n", error));
+            }
+            if self.failed > self.errors.len() {
+                out.push_str(&format!(
+                    "    ... and {} more\n",
+                    self.failed - self.errors.len()
+                ));
+            }
+        }
+        out
+    }
+}
+
+fn unassigned(id: &usize) -> bool {
+    *id == 0
+}
+
+/// Where runs are recorded, one JSON object per line.
+pub fn history_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("cpx").join("history.jsonl"))
+}
+
+/// Appends `record` to the history file, dropping the oldest runs past
+/// [`MAX_RUNS`].
+pub fn record(record: &RunRecord) -> io::Result<()> {
+    let path = history_file()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+    append(&path, record)?;
+    trim(&path, MAX_RUNS)
+}
+
+/// Every recorded run, oldest first.
+pub fn load() -> io::Result<Vec<RunRecord>> {
+    match history_file() {
+        Some(path) => read(&path),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn append(path: &Path, record: &RunRecord) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(record).map_err(io::Error::other)?;
+    line.push('\n');
+    // a single append, so runs finishing together don't interleave
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Keeps the last `max` lines of the history file. The trimmed file
+/// replaces it in one rename, so a run reading it sees one or the other.
+/// Ids count from the oldest run kept.
+fn trim(path: &Path, max: usize) -> io::Result<()> {
+    let contents = fs::read_to_string(path)?;
+    let lines = contents.lines().count();
+    if lines <= max {
+        return Ok(());
+    }
+    let kept: String = contents
+        .lines()
+        .skip(lines - max)
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    let temp = path.with_extension("jsonl.tmp");
+    fs::write(&temp, kept)?;
+    fs::rename(&temp, path)
+}
+
+/// Lines that don't parse (a run cut off mid-write, a newer format) keep
+/// their number, so the ids of the others don't shift.
+fn read(path: &Path) -> io::Result<Vec<RunRecord>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(contents
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let mut record: RunRecord = serde_json::from_str(line).ok()?;
+            record.id = index + 1;
+            Some(record)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn run(exit_code: i32, failed: usize) -> RunRecord {
+        RunRecord {
+            id: 0,
+            started: 1_700_000_000,
+            job: None,
+            duration_seconds: 12.5,
+            cwd: PathBuf::from("/home/user"),
+            args: vec!["cpx".into(), "-r".into(), "src".into(), "dst".into()],
+            exit_code,
+            total_files: 10 + failed,
+            files_done: 10,
+            total_bytes: 4096,
+            bytes_done: 4096,
+            failed,
+            errors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_history_round_trip_keeps_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("cpx").join("history.jsonl");
+        assert!(read(&path).unwrap().is_empty());
+
+        append(&path, &run(0, 0)).unwrap();
+        // a truncated line still takes up an id
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"started\":\n")
+            .unwrap();
+        let failures = (0..30).map(|i| format!("file{}: denied", i));
+        append(&path, &run(1, 30).with_errors(failures)).unwrap();
+
+        let runs = read(&path).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].id, 1);
+        assert_eq!(runs[1].id, 3);
+        assert_eq!(runs[1].errors.len(), MAX_ERRORS);
+        assert!(runs[0].to_line().contains("ok"));
+        assert!(runs[0].to_line().contains("cpx -r src dst"));
+        let text = runs[1].to_text();
+        assert!(text.contains("30 failed (exit 1)"));
+        assert!(text.contains("file0: denied"));
+        assert!(text.contains("... and 10 more"));
+    }
+
+    #[test]
+    fn test_history_keeps_the_latest_runs() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("history.jsonl");
+        for failed in 0..5 {
+            append(&path, &run(1, failed)).unwrap();
+            trim(&path, 3).unwrap();
+        }
+        let runs = read(&path).unwrap();
+        let failed: Vec<_> = runs.iter().map(|run| (run.id, run.failed)).collect();
+        assert_eq!(failed, [(1, 2), (2, 3), (3, 4)]);
+    }
+}
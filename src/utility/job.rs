@@ -0,0 +1,168 @@
+use crate::utility::journal::Journal;
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::PathBuf;
+
+/// Left in the job directory while a run is going, and by runs that failed
+/// or were interrupted.
+const UNFINISHED: &str = "unfinished";
+
+/// A named copy job (`--job`): its journal lives in its own directory, its
+/// runs are tagged with its name in the history, and only one run of it
+/// goes at a time.
+#[derive(Debug)]
+pub struct Job {
+    name: String,
+    dir: PathBuf,
+    /// Held until the run ends; the OS releases it if cpx dies.
+    _lock: File,
+}
+
+impl Job {
+    /// Creates the job's directory if needed and takes its lock, failing
+    /// when another run of the same job holds it.
+    pub fn start(name: &str) -> io::Result<Self> {
+        let dir = jobs_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?
+            .join(name);
+        Self::start_in(name, dir)
+    }
+
+    fn start_in(name: &str, dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join("lock"))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ResourceBusy,
+                    format!("job '{}' is already running", name),
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        Ok(Self {
+            name: name.to_string(),
+            dir,
+            _lock: lock,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The journal of the job's latest run, for `cpx undo --job`.
+    pub fn journal_path(&self) -> PathBuf {
+        self.dir.join("journal.jsonl")
+    }
+
+    /// Opens the journal for this run. It starts over unless the previous
+    /// run didn't finish, in which case this run adds to what it recorded.
+    pub fn open_journal(&self) -> io::Result<Journal> {
+        let unfinished = self.dir.join(UNFINISHED);
+        let journal = if unfinished.exists() {
+            Journal::open(&self.journal_path())?
+        } else {
+            Journal::create(&self.journal_path())?
+        };
+        File::create(unfinished)?;
+        Ok(journal)
+    }
+
+    /// Marks the run as finished, so the next one starts a new journal.
+    pub fn finished(&self) -> io::Result<()> {
+        match fs::remove_file(self.dir.join(UNFINISHED)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Where each job keeps its state, in a directory named after it.
+pub fn jobs_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("cpx").join("jobs"))
+}
+
+/// The journal `--job NAME` writes, whether or not the job is running.
+pub fn journal_path(name: &str) -> Option<PathBuf> {
+    jobs_dir().map(|dir| dir.join(name).join("journal.jsonl"))
+}
+
+/// Job names become directory names, so they are kept to letters, digits,
+/// `-`, `_` and `.`, and can't start with a dot.
+pub fn parse_job_name(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(format!(
+            "invalid job name '{}': use letters, digits, '-', '_' and '.'",
+            name
+        ));
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_job_name() {
+        assert_eq!(
+            parse_job_name("nightly-2.db_backup"),
+            Ok("nightly-2.db_backup".into())
+        );
+        for name in ["", ".hidden", "a/b", "..", "night ly"] {
+            assert!(parse_job_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_one_run_of_a_job_at_a_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("nightly");
+        let job = Job::start_in("nightly", dir.clone()).unwrap();
+        assert_eq!(job.journal_path(), dir.join("journal.jsonl"));
+
+        let e = Job::start_in("nightly", dir.clone()).unwrap_err();
+        assert!(e.to_string().contains("already running"));
+        drop(job);
+        assert!(Job::start_in("nightly", dir).is_ok());
+    }
+
+    #[test]
+    fn test_journal_continues_an_unfinished_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let job = Job::start_in("nightly", temp_dir.path().join("nightly")).unwrap();
+        let entries = || crate::utility::journal::read_journal(&job.journal_path()).unwrap();
+
+        // interrupted: the next run adds to its journal
+        job.open_journal()
+            .unwrap()
+            .created(Path::new("/a"))
+            .unwrap();
+        job.open_journal()
+            .unwrap()
+            .created(Path::new("/b"))
+            .unwrap();
+        assert_eq!(entries().len(), 2);
+
+        // finished: the next run starts over
+        job.finished().unwrap();
+        job.open_journal()
+            .unwrap()
+            .created(Path::new("/c"))
+            .unwrap();
+        assert_eq!(entries().len(), 1);
+    }
+}
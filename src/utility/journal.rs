@@ -0,0 +1,321 @@
+use crate::utility::rename::rename_or_copy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One change cpx made to the destination, as written by `--journal`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// A directory that did not exist before.
+    CreateDir { path: PathBuf },
+    /// A file or link written where there was nothing.
+    Create { path: PathBuf },
+    /// An existing file replaced; `backup` is where `--backup` moved it.
+    Overwrite {
+        path: PathBuf,
+        backup: Option<PathBuf>,
+    },
+}
+
+impl JournalEntry {
+    pub fn path(&self) -> &Path {
+        match self {
+            JournalEntry::CreateDir { path }
+            | JournalEntry::Create { path }
+            | JournalEntry::Overwrite { path, .. } => path,
+        }
+    }
+}
+
+/// Appends a JSON line per change as it happens, so an interrupted run
+/// still leaves a usable journal. Paths are absolute.
+#[derive(Debug)]
+pub struct Journal {
+    file: Mutex<File>,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Like [`Journal::open`], but drops whatever an earlier run recorded.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn created_dir(&self, path: &Path) -> io::Result<()> {
+        self.record(JournalEntry::CreateDir {
+            path: std::path::absolute(path)?,
+        })
+    }
+
+    pub fn created(&self, path: &Path) -> io::Result<()> {
+        self.record(JournalEntry::Create {
+            path: std::path::absolute(path)?,
+        })
+    }
+
+    pub fn overwritten(&self, path: &Path, backup: Option<&Path>) -> io::Result<()> {
+        self.record(JournalEntry::Overwrite {
+            path: std::path::absolute(path)?,
+            backup: backup.map(std::path::absolute).transpose()?,
+        })
+    }
+
+    fn record(&self, entry: JournalEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(&entry).map_err(io::Error::other)?;
+        line.push('\n');
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("Failed to acquire journal lock"))?;
+        file.write_all(line.as_bytes())
+    }
+}
+
+/// Reads every entry of a journal, oldest first.
+pub fn read_journal(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let file = File::open(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("cannot open journal '{}': {}", path.display(), e),
+        )
+    })?;
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), number + 1, e),
+            )
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// What undoing one entry did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum UndoOutcome {
+    Removed {
+        path: PathBuf,
+    },
+    Restored {
+        path: PathBuf,
+        backup: PathBuf,
+    },
+    /// Already back to how it was, e.g. a created file that is gone.
+    Unchanged {
+        path: PathBuf,
+    },
+    Kept {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct UndoReport {
+    pub outcomes: Vec<UndoOutcome>,
+}
+
+impl UndoReport {
+    /// True when nothing had to be kept.
+    pub fn is_complete(&self) -> bool {
+        !self
+            .outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, UndoOutcome::Kept { .. }))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let mut undone = 0;
+        for outcome in &self.outcomes {
+            match outcome {
+                UndoOutcome::Removed { path } => {
+                    undone += 1;
+                    out.push_str(&format!("removed   {}\n", path.display()));
+                }
+                UndoOutcome::Restored { path, backup } => {
+                    undone += 1;
+                    out.push_str(&format!(
+                        "restored  {} from {}\n",
+                        path.display(),
+                        backup.display()
+                    ));
+                }
+                UndoOutcome::Unchanged { .. } => undone += 1,
+                UndoOutcome::Kept { path, reason } => {
+                    out.push_str(&format!("kept      {} ({})\n", path.display(), reason));
+                }
+            }
+        }
+        out.push_str(&format!(
+            "{} undone, {} could not be undone\n",
+            undone,
+            self.outcomes.len() - undone
+        ));
+        out
+    }
+}
+
+/// Reverts `entries`, newest first. With `dry_run` nothing is touched and
+/// the report says what would happen.
+pub fn undo(entries: &[JournalEntry], dry_run: bool) -> UndoReport {
+    let mut report = UndoReport::default();
+    // lets a dry run see directories emptied by the entries before them
+    let mut removed = HashSet::new();
+    for entry in entries.iter().rev() {
+        let outcome = match undo_entry(entry, dry_run, &removed) {
+            Ok(outcome) => outcome,
+            Err(e) => UndoOutcome::Kept {
+                path: entry.path().to_path_buf(),
+                reason: e.to_string(),
+            },
+        };
+        if let UndoOutcome::Removed { path } = &outcome {
+            removed.insert(path.clone());
+        }
+        report.outcomes.push(outcome);
+    }
+    report
+}
+
+fn undo_entry(
+    entry: &JournalEntry,
+    dry_run: bool,
+    removed: &HashSet<PathBuf>,
+) -> io::Result<UndoOutcome> {
+    let kept = |path: &Path, reason: &str| UndoOutcome::Kept {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    Ok(match entry {
+        JournalEntry::CreateDir { path } => match fs::symlink_metadata(path) {
+            Err(_) => UndoOutcome::Unchanged { path: path.clone() },
+            Ok(metadata) if !metadata.is_dir() => kept(path, "no longer a directory"),
+            Ok(_) if !is_empty_dir(path, removed)? => kept(path, "not empty"),
+            Ok(_) => {
+                if !dry_run {
+                    fs::remove_dir(path)?;
+                }
+                UndoOutcome::Removed { path: path.clone() }
+            }
+        },
+        JournalEntry::Create { path } => match fs::symlink_metadata(path) {
+            Err(_) => UndoOutcome::Unchanged { path: path.clone() },
+            Ok(metadata) if metadata.is_dir() => kept(path, "now a directory"),
+            Ok(_) => {
+                if !dry_run {
+                    fs::remove_file(path)?;
+                }
+                UndoOutcome::Removed { path: path.clone() }
+            }
+        },
+        JournalEntry::Overwrite { path, backup: None } => {
+            kept(path, "overwritten without --backup")
+        }
+        JournalEntry::Overwrite {
+            path,
+            backup: Some(backup),
+        } => {
+            if fs::symlink_metadata(backup).is_err() {
+                kept(path, "backup is gone")
+            } else {
+                if !dry_run {
+                    rename_or_copy(backup, path, |_| {})?;
+                }
+                UndoOutcome::Restored {
+                    path: path.clone(),
+                    backup: backup.clone(),
+                }
+            }
+        }
+    })
+}
+
+fn is_empty_dir(path: &Path, removed: &HashSet<PathBuf>) -> io::Result<bool> {
+    for child in fs::read_dir(path)? {
+        if !removed.contains(&child?.path()) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_undo_reverts_newest_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let journal_path = root.join("journal.jsonl");
+        let journal = Journal::open(&journal_path).unwrap();
+
+        fs::create_dir(root.join("new")).unwrap();
+        journal.created_dir(&root.join("new")).unwrap();
+        fs::write(root.join("new/file.txt"), "new").unwrap();
+        journal.created(&root.join("new/file.txt")).unwrap();
+        fs::write(root.join("kept.txt~"), "old").unwrap();
+        fs::write(root.join("kept.txt"), "new").unwrap();
+        journal
+            .overwritten(&root.join("kept.txt"), Some(&root.join("kept.txt~")))
+            .unwrap();
+        fs::write(root.join("lost.txt"), "new").unwrap();
+        journal.overwritten(&root.join("lost.txt"), None).unwrap();
+        drop(journal);
+
+        let entries = read_journal(&journal_path).unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let preview = undo(&entries, true);
+        assert!(root.join("new/file.txt").exists());
+        assert!(!preview.is_complete());
+        assert!(
+            preview
+                .to_text()
+                .contains(&format!("removed   {}\n", root.join("new").display()))
+        );
+
+        let report = undo(&entries, false);
+        assert_eq!(
+            report.outcomes[0],
+            UndoOutcome::Kept {
+                path: root.join("lost.txt"),
+                reason: "overwritten without --backup".to_string(),
+            }
+        );
+        assert!(!root.join("new").exists());
+        assert_eq!(fs::read_to_string(root.join("kept.txt")).unwrap(), "old");
+        assert!(!root.join("kept.txt~").exists());
+        assert!(
+            report
+                .to_text()
+                .ends_with("3 undone, 1 could not be undone\n")
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::error::ErrorCategory;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    ScanStarted {
+        sources: Vec<&'a Path>,
+    },
+    ScanFinished {
+        total_files: usize,
+        total_bytes: u64,
+        skipped_files: usize,
+    },
+    FileStarted {
+        source: &'a Path,
+        destination: &'a Path,
+        size: u64,
+    },
+    Bytes {
+        source: &'a Path,
+        delta: u64,
+    },
+    FileDone {
+        source: &'a Path,
+        destination: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        digest: Option<&'a str>,
+    },
+    FileFailed {
+        source: &'a Path,
+        destination: &'a Path,
+        category: ErrorCategory,
+        error: String,
+    },
+    Summary {
+        files_copied: usize,
+        files_failed: usize,
+        files_skipped: usize,
+        total_bytes: u64,
+        elapsed_ms: u128,
+    },
+}
+
+/// Writes a single event as one JSON line on stdout.
+pub fn emit(event: &ProgressEvent) {
+    let Ok(line) = serde_json::to_string(event) else {
+        return;
+    };
+    let mut out = std::io::stdout().lock();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_is_tagged() {
+        let event = ProgressEvent::Bytes {
+            source: Path::new("a.txt"),
+            delta: 42,
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(json, r#"{"event":"bytes","source":"a.txt","delta":42}"#);
+    }
+
+    #[test]
+    fn test_summary_fields() {
+        let event = ProgressEvent::Summary {
+            files_copied: 3,
+            files_failed: 1,
+            files_skipped: 2,
+            total_bytes: 1024,
+            elapsed_ms: 7,
+        };
+        let value: serde_json::Value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["event"], "summary");
+        assert_eq!(value["files_copied"], 3);
+        assert_eq!(value["files_failed"], 1);
+        assert_eq!(value["files_skipped"], 2);
+    }
+}
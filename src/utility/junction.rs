@@ -0,0 +1,177 @@
+use std::io;
+use std::path::Path;
+
+/// Reparse tag shared by junctions and volume mount points.
+pub const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+
+/// Whether `path` itself (not what it points to) is a junction or a volume
+/// mount point. `std` reports both as symlinks.
+#[cfg(windows)]
+pub fn is_junction(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::fs::MetadataExt;
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_ATTRIBUTE_REPARSE_POINT, FindClose, FindFirstFileW, WIN32_FIND_DATAW,
+    };
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return false;
+    };
+    if metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT == 0 {
+        return false;
+    }
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut data = WIN32_FIND_DATAW::default();
+    let handle = unsafe { FindFirstFileW(wide.as_ptr(), &mut data) };
+    if handle == INVALID_HANDLE_VALUE {
+        return false;
+    }
+    unsafe { FindClose(handle) };
+    // for reparse points, dwReserved0 holds the tag
+    data.dwReserved0 == IO_REPARSE_TAG_MOUNT_POINT
+}
+
+#[cfg(not(windows))]
+pub fn is_junction(_path: &Path) -> bool {
+    false
+}
+
+/// Creates `junction` as a directory junction to `target`, which should be
+/// absolute (a volume GUID path makes it a mount point).
+#[cfg(windows)]
+pub fn create(target: &Path, junction: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{CloseHandle, GENERIC_WRITE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_REPARSE_POINT;
+
+    let buffer = mount_point_buffer(target)?;
+    std::fs::create_dir(junction)?;
+    let wide: Vec<u16> = junction.as_os_str().encode_wide().chain(Some(0)).collect();
+    let handle = unsafe {
+        CreateFileW(
+            wide.as_ptr(),
+            GENERIC_WRITE,
+            0,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            FILE_FLAG_OPEN_REPARSE_POINT | FILE_FLAG_BACKUP_SEMANTICS,
+            std::ptr::null_mut(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        let e = io::Error::last_os_error();
+        let _ = std::fs::remove_dir(junction);
+        return Err(e);
+    }
+    let mut returned = 0u32;
+    let ok = unsafe {
+        DeviceIoControl(
+            handle,
+            FSCTL_SET_REPARSE_POINT,
+            buffer.as_ptr().cast(),
+            buffer.len() as u32,
+            std::ptr::null_mut(),
+            0,
+            &mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    let result = if ok == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    };
+    unsafe { CloseHandle(handle) };
+    if result.is_err() {
+        let _ = std::fs::remove_dir(junction);
+    }
+    result
+}
+
+#[cfg(not(windows))]
+pub fn create(_target: &Path, _junction: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "junctions only exist on Windows",
+    ))
+}
+
+/// The `REPARSE_DATA_BUFFER` for a mount point to `target`: the NT
+/// (`\??\`) path as substitute name, the plain path as print name.
+pub fn mount_point_buffer(target: &Path) -> io::Result<Vec<u8>> {
+    let plain = target.to_string_lossy();
+    let plain = plain.strip_prefix(r"\\?\").unwrap_or(&plain);
+    let bytes = plain.as_bytes();
+    let drive_absolute = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && matches!(bytes[2], b'\\' | b'/');
+    if !drive_absolute && !plain.starts_with("Volume{") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("junction target '{}' is not absolute", target.display()),
+        ));
+    }
+    let print: Vec<u16> = plain.encode_utf16().collect();
+    let substitute: Vec<u16> = format!(r"\??\{}", plain).encode_utf16().collect();
+
+    let substitute_len = (substitute.len() * 2) as u16;
+    let print_len = (print.len() * 2) as u16;
+    // both names are followed by a NUL
+    let path_buffer_len = substitute_len + 2 + print_len + 2;
+    let data_len = 8 + path_buffer_len;
+
+    let mut buffer = Vec::with_capacity(8 + data_len as usize);
+    buffer.extend_from_slice(&IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+    buffer.extend_from_slice(&data_len.to_le_bytes());
+    buffer.extend_from_slice(&0u16.to_le_bytes());
+    buffer.extend_from_slice(&0u16.to_le_bytes());
+    buffer.extend_from_slice(&substitute_len.to_le_bytes());
+    buffer.extend_from_slice(&(substitute_len + 2).to_le_bytes());
+    buffer.extend_from_slice(&print_len.to_le_bytes());
+    for unit in substitute.iter().chain(&[0]).chain(&print).chain(&[0]) {
+        buffer.extend_from_slice(&unit.to_le_bytes());
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(buffer: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+    }
+
+    #[test]
+    fn test_mount_point_buffer_layout() {
+        let buffer = mount_point_buffer(Path::new(r"C:\target")).unwrap();
+        assert_eq!(&buffer[..4], &IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+        assert_eq!(u16_at(&buffer, 4) as usize, buffer.len() - 8);
+
+        // "\??\C:\target" is 13 units, "C:\target" is 9
+        assert_eq!(u16_at(&buffer, 8), 0);
+        assert_eq!(u16_at(&buffer, 10), 26);
+        assert_eq!(u16_at(&buffer, 12), 28);
+        assert_eq!(u16_at(&buffer, 14), 18);
+        assert_eq!(buffer.len(), 16 + 28 + 20);
+
+        let substitute: Vec<u16> = (0..13).map(|i| u16_at(&buffer, 16 + i * 2)).collect();
+        assert_eq!(String::from_utf16(&substitute).unwrap(), r"\??\C:\target");
+        assert_eq!(
+            mount_point_buffer(Path::new(r"\\?\C:\target")).unwrap(),
+            buffer
+        );
+    }
+
+    #[test]
+    fn test_mount_point_buffer_rejects_relative_targets() {
+        assert!(mount_point_buffer(Path::new("relative")).is_err());
+        assert!(!is_junction(Path::new(".")));
+    }
+}
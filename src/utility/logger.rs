@@ -0,0 +1,212 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct LogRecord<'a> {
+    ts: String,
+    level: LogLevel,
+    event: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    destination: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub struct Logger {
+    file: Mutex<File>,
+    level: LogLevel,
+    format: LogFormat,
+    /// Stamped on every record instead of the current time.
+    fixed_time: Option<SystemTime>,
+}
+
+impl Logger {
+    pub fn open(path: &Path, level: LogLevel, format: LogFormat) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            level,
+            format,
+            fixed_time: None,
+        })
+    }
+
+    /// Stamps every record with `time`, so reruns write the same log.
+    pub fn at_fixed_time(mut self, time: SystemTime) -> Self {
+        self.fixed_time = Some(time);
+        self
+    }
+
+    pub fn copied(&self, source: &Path, destination: &Path) {
+        self.write(
+            LogLevel::Info,
+            "copied",
+            Some(source),
+            Some(destination),
+            None,
+        );
+    }
+
+    pub fn skipped(&self, path: &Path, reason: &str) {
+        self.write(LogLevel::Info, "skipped", Some(path), None, Some(reason));
+    }
+
+    pub fn failed(&self, source: &Path, destination: &Path, reason: &str) {
+        self.write(
+            LogLevel::Error,
+            "failed",
+            Some(source),
+            Some(destination),
+            Some(reason),
+        );
+    }
+
+    pub fn warning(&self, path: &Path, message: &str) {
+        self.write(LogLevel::Warn, "warning", Some(path), None, Some(message));
+    }
+
+    pub fn debug(&self, message: &str) {
+        self.write(LogLevel::Debug, "debug", None, None, Some(message));
+    }
+
+    fn write(
+        &self,
+        level: LogLevel,
+        event: &str,
+        source: Option<&Path>,
+        destination: Option<&Path>,
+        message: Option<&str>,
+    ) {
+        if level > self.level {
+            return;
+        }
+        let record = LogRecord {
+            ts: humantime::format_rfc3339_millis(self.fixed_time.unwrap_or_else(SystemTime::now))
+                .to_string(),
+            level,
+            event,
+            source,
+            destination,
+            message,
+        };
+        let line = match self.format {
+            LogFormat::Json => match serde_json::to_string(&record) {
+                Ok(line) => line,
+                Err(_) => return,
+            },
+            LogFormat::Text => format_text(&record),
+        };
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
+
+/// `SOURCE_DATE_EPOCH` from the reproducible-builds convention, or the
+/// epoch itself when it is unset or not a number of seconds.
+pub fn source_date_epoch() -> SystemTime {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn format_text(record: &LogRecord) -> String {
+    let level = match record.level {
+        LogLevel::Error => "ERROR",
+        LogLevel::Warn => "WARN",
+        LogLevel::Info => "INFO",
+        LogLevel::Debug => "DEBUG",
+    };
+    let mut line = format!("{} {:<5} {}", record.ts, level, record.event);
+    if let Some(source) = record.source {
+        line.push_str(&format!(" '{}'", source.display()));
+    }
+    if let Some(destination) = record.destination {
+        line.push_str(&format!(" -> '{}'", destination.display()));
+    }
+    if let Some(message) = record.message {
+        line.push_str(&format!(": {}", message));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_text_log_respects_level() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("cpx.log");
+
+        let logger = Logger::open(&log_path, LogLevel::Warn, LogFormat::Text).unwrap();
+        logger.copied(Path::new("a"), Path::new("b"));
+        logger.failed(Path::new("c"), Path::new("d"), "disk full");
+
+        let contents = fs::read_to_string(&log_path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        assert!(contents.contains("ERROR failed 'c' -> 'd': disk full"));
+    }
+
+    #[test]
+    fn test_json_log_record() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("cpx.jsonl");
+
+        let logger = Logger::open(&log_path, LogLevel::Info, LogFormat::Json).unwrap();
+        logger.skipped(Path::new("x.tmp"), "excluded");
+
+        let contents = fs::read_to_string(&log_path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+        assert_eq!(value["level"], "info");
+        assert_eq!(value["event"], "skipped");
+        assert_eq!(value["source"], "x.tmp");
+        assert_eq!(value["message"], "excluded");
+        assert!(value.get("destination").is_none());
+    }
+
+    #[test]
+    fn test_fixed_time_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("cpx.log");
+
+        let logger = Logger::open(&log_path, LogLevel::Info, LogFormat::Text)
+            .unwrap()
+            .at_fixed_time(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        logger.copied(Path::new("a"), Path::new("b"));
+
+        let contents = fs::read_to_string(&log_path).unwrap();
+        assert_eq!(
+            contents,
+            "2023-11-14T22:13:20.000Z INFO  copied 'a' -> 'b'\n"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use crate::utility::hash::HashAlgo;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Digests of every destination file written during a run, in the
+/// `sha256sum`-compatible `<digest>  <path>` layout. Compressed files are
+/// listed as `<digest>  <size>  <path>` with the digest and size of the
+/// original contents.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    entries: Mutex<Vec<(PathBuf, String, Option<u64>)>>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, destination: &Path, digest: &str) {
+        self.push(destination, digest, None);
+    }
+
+    /// Records a file written in transformed form, such as `name.zst`.
+    pub fn add_original(&self, destination: &Path, digest: &str, original_size: u64) {
+        self.push(destination, digest, Some(original_size));
+    }
+
+    fn push(&self, destination: &Path, digest: &str, original_size: Option<u64>) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push((destination.to_path_buf(), digest.to_string(), original_size));
+        }
+    }
+
+    /// Writes the manifest to `path`, or to the algorithm's conventional file
+    /// name inside it when `path` is a directory. Entries are relative to the
+    /// manifest's own directory so `sha256sum -c` can run from there.
+    pub fn write(&self, path: &Path, algo: HashAlgo) -> io::Result<PathBuf> {
+        let target = if path.is_dir() {
+            path.join(algo.manifest_name())
+        } else {
+            path.to_path_buf()
+        };
+        let base = std::path::absolute(&target)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| io::Error::other("Failed to acquire manifest lock"))?
+            .clone();
+        entries.sort();
+
+        let mut out = io::BufWriter::new(fs::File::create(&target)?);
+        for (destination, digest, original_size) in entries {
+            let absolute = std::path::absolute(&destination)?;
+            let relative = pathdiff::diff_paths(&absolute, &base).unwrap_or(absolute);
+            match original_size {
+                Some(size) => writeln!(out, "{}  {}  {}", digest, size, relative.display())?,
+                None => writeln!(out, "{}  {}", digest, relative.display())?,
+            }
+        }
+        out.flush()?;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_sorted_relative_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = Manifest::new();
+        manifest.add(&temp_dir.path().join("dst/b.txt"), "bbbb");
+        manifest.add(&temp_dir.path().join("dst/a.txt"), "aaaa");
+
+        let written = manifest.write(temp_dir.path(), HashAlgo::Sha256).unwrap();
+        assert_eq!(written, temp_dir.path().join("SHA256SUMS"));
+
+        let contents = fs::read_to_string(written).unwrap();
+        assert_eq!(contents, "aaaa  dst/a.txt\nbbbb  dst/b.txt\n");
+    }
+}
@@ -0,0 +1,106 @@
+use crate::utility::stats::StatsSummary;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The run as Prometheus text-format gauges, for node_exporter's textfile
+/// collector. `succeeded` is false when the run ended with an error.
+pub fn to_prometheus(summary: &StatsSummary, succeeded: bool, finished: SystemTime) -> String {
+    let finished = finished
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let metrics: [(&str, &str, f64); 10] = [
+        (
+            "cpx_last_run_success",
+            "Whether the last run finished without errors.",
+            u8::from(succeeded).into(),
+        ),
+        (
+            "cpx_last_run_timestamp_seconds",
+            "When the last run finished, in seconds since the epoch.",
+            finished as f64,
+        ),
+        (
+            "cpx_duration_seconds",
+            "How long the last run took.",
+            summary.elapsed_seconds,
+        ),
+        (
+            "cpx_bytes_copied",
+            "Bytes written by the last run.",
+            summary.bytes_copied as f64,
+        ),
+        (
+            "cpx_files_copied",
+            "Files copied by the last run.",
+            summary.files_copied as f64,
+        ),
+        (
+            "cpx_files_skipped",
+            "Files the last run skipped.",
+            summary.files_skipped as f64,
+        ),
+        (
+            "cpx_files_excluded",
+            "Entries the last run left out by excludes.",
+            summary.files_excluded as f64,
+        ),
+        (
+            "cpx_files_failed",
+            "Files the last run failed to copy.",
+            summary.files_failed as f64,
+        ),
+        (
+            "cpx_throughput_bytes_per_second",
+            "Average throughput of the last run.",
+            summary.average_bytes_per_sec,
+        ),
+        (
+            "cpx_peak_throughput_bytes_per_second",
+            "Peak one-second throughput of the last run.",
+            summary.peak_bytes_per_sec,
+        ),
+    ];
+
+    let mut out = String::new();
+    for (name, help, value) in metrics {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+    out
+}
+
+/// Replaces `path` with the run's metrics. Written next to it and renamed
+/// into place, so the collector never reads a partial file.
+pub fn write_metrics(path: &Path, summary: &StatsSummary, succeeded: bool) -> io::Result<()> {
+    let text = to_prometheus(summary, succeeded, SystemTime::now());
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+    fs::write(&partial, text)?;
+    fs::rename(&partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::stats::CopyStats;
+    use std::time::Duration;
+
+    #[test]
+    fn test_prometheus_text() {
+        let stats = CopyStats::new();
+        stats.record_copied();
+        stats.record_failed();
+        stats.add_bytes(4096);
+
+        let finished = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let text = to_prometheus(&stats.summary(), false, finished);
+        assert!(text.contains("# TYPE cpx_bytes_copied gauge\ncpx_bytes_copied 4096\n"));
+        assert!(text.contains("\ncpx_files_failed 1\n"));
+        assert!(text.contains("\ncpx_last_run_success 0\n"));
+        assert!(text.contains("\ncpx_last_run_timestamp_seconds 1700000000\n"));
+    }
+}
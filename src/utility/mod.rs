@@ -1,6 +1,45 @@
+pub mod autotune;
 pub mod backup;
+pub mod barrier;
+pub mod buffers;
+pub mod chattr;
+pub mod chmod;
+pub mod conflict;
+pub mod control;
+#[cfg(target_os = "linux")]
+pub mod dirent;
+pub mod error_report;
+pub mod eta;
+pub mod events;
 pub mod exclude;
+pub mod extents;
+pub mod fd_budget;
+pub mod glob;
+pub mod hash;
 pub mod helper;
+pub mod history;
+pub mod job;
+pub mod journal;
+pub mod json_progress;
+pub mod junction;
+pub mod logger;
+pub mod manifest;
+pub mod metrics;
+pub mod notify;
+pub mod output;
+pub mod pause;
 pub mod preprocess;
 pub mod preserve;
+pub mod priority;
 pub mod progress_bar;
+pub mod readahead;
+pub mod rename;
+pub mod reporter;
+pub mod sanitize;
+pub mod space;
+pub mod stats;
+pub mod status;
+pub mod statx;
+pub mod streams;
+pub mod throughput;
+pub mod tui;
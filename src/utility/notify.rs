@@ -0,0 +1,93 @@
+use crate::error::{CopyError, CopyResult};
+use crate::utility::stats::StatsSummary;
+use indicatif::BinaryBytes;
+use std::io;
+use std::time::Duration;
+
+/// Runs shorter than this finish without a notification unless configured.
+pub const DEFAULT_NOTIFY_AFTER: Duration = Duration::from_secs(30);
+
+/// Title and body of the end-of-run notification.
+pub fn summary_message(summary: &StatsSummary, result: &CopyResult<()>) -> (String, String) {
+    let title = match result {
+        Ok(()) => "cpx: copy finished",
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => "cpx: copy interrupted",
+        Err(
+            CopyError::ErrorLimitExceeded { .. }
+            | CopyError::TimeLimitExceeded { .. }
+            | CopyError::DestinationFull { .. },
+        ) => "cpx: copy stopped",
+        Err(_) => "cpx: copy finished with errors",
+    };
+    let mut body = format!(
+        "{} files, {} in {}",
+        summary.files_copied,
+        BinaryBytes(summary.bytes_copied),
+        humantime::format_duration(Duration::from_secs(summary.elapsed_seconds as u64))
+    );
+    if summary.files_failed > 0 {
+        body.push_str(&format!(", {} failed", summary.files_failed));
+    }
+    if summary.files_skipped > 0 {
+        body.push_str(&format!(", {} skipped", summary.files_skipped));
+    }
+    (title.to_string(), body)
+}
+
+/// Shows a desktop notification. Failures (no notification daemon, headless
+/// session) are returned for logging and otherwise ignored.
+pub fn send(summary: &StatsSummary, result: &CopyResult<()>) -> Result<(), String> {
+    let (title, body) = summary_message(summary, result);
+    notify_rust::Notification::new()
+        .appname("cpx")
+        .summary(&title)
+        .body(&body)
+        .show()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::FailedFile;
+    use std::path::Path;
+
+    fn summary(failed: usize) -> StatsSummary {
+        StatsSummary {
+            files_copied: 12,
+            files_skipped: 0,
+            files_excluded: 0,
+            files_failed: failed,
+            bytes_copied: 2048,
+            elapsed_seconds: 95.4,
+            average_bytes_per_sec: 0.0,
+            peak_bytes_per_sec: 0.0,
+            throughput_history: Vec::new(),
+            reflinks: 0,
+            hardlinks: 0,
+            symlinks: 0,
+            deduplicated: 0,
+            bytes_deduplicated: 0,
+            slowest_files: Vec::new(),
+            unreadable: Vec::new(),
+            renamed: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_summary_message() {
+        let (title, body) = summary_message(&summary(0), &Ok(()));
+        assert_eq!(title, "cpx: copy finished");
+        assert_eq!(body, "12 files, 2.00 KiB in 1m 35s");
+
+        let failed = Err(CopyError::PartialFailure(vec![FailedFile::new(
+            Path::new("a"),
+            Path::new("b"),
+            CopyError::InvalidSource(Path::new("a").to_path_buf()),
+        )]));
+        let (title, body) = summary_message(&summary(1), &failed);
+        assert_eq!(title, "cpx: copy finished with errors");
+        assert!(body.ends_with(", 1 failed"));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::cli::args::CopyOptions;
+use crate::utility::reporter::ProgressReporter;
+use std::fmt::Display;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    /// Nothing on the terminal at all; for embedding cpx as a library.
+    Silent,
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+    Debug,
+}
+
+impl Verbosity {
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        if quiet {
+            return Verbosity::Quiet;
+        }
+        match verbose {
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            _ => Verbosity::Debug,
+        }
+    }
+}
+
+// stdout belongs to the event stream in json mode
+fn human_stdout(options: &CopyOptions) -> bool {
+    !options.progress_bar.is_json()
+}
+
+pub fn format_copied(source: &Path, destination: &Path) -> String {
+    format!("'{}' -> '{}'", source.display(), destination.display())
+}
+
+pub fn format_skipped(path: &Path, reason: &str) -> String {
+    format!("skipped '{}' ({})", path.display(), reason)
+}
+
+/// Regular informational message, hidden by `--quiet`.
+pub fn info(options: &CopyOptions, msg: impl Display) {
+    if options.verbosity >= Verbosity::Normal && human_stdout(options) {
+        println!("{}", msg);
+    }
+}
+
+/// Non-fatal notice on stderr, hidden by `--quiet`.
+pub fn warn(options: &CopyOptions, msg: impl Display) {
+    if options.verbosity >= Verbosity::Normal {
+        eprintln!("{}", msg);
+    }
+}
+
+/// Output the user explicitly asked for, such as `--stats`. Not hidden by
+/// `--quiet`, and moved to stderr when stdout carries json progress events.
+pub fn report(options: &CopyOptions, msg: impl Display) {
+    if human_stdout(options) {
+        println!("{}", msg);
+    } else {
+        eprintln!("{}", msg);
+    }
+}
+
+/// Errors are always shown.
+pub fn error(msg: impl Display) {
+    eprintln!("{}", msg);
+}
+
+/// Errors raised in the middle of a run; shown at every level but `Silent`.
+pub fn run_error(options: &CopyOptions, msg: impl Display) {
+    if options.verbosity > Verbosity::Silent {
+        eprintln!("{}", msg);
+    }
+}
+
+/// `-v`: one line per completed file or link.
+pub fn copied(
+    options: &CopyOptions,
+    reporter: &dyn ProgressReporter,
+    source: &Path,
+    destination: &Path,
+) {
+    if let Some(logger) = &options.logger {
+        logger.copied(source, destination);
+    }
+    if options.verbosity >= Verbosity::Verbose && human_stdout(options) {
+        reporter.println(&format_copied(source, destination));
+    }
+}
+
+/// `-vv`: why an entry was left out of the copy.
+pub fn skipped(options: &CopyOptions, path: &Path, reason: &str) {
+    if let Some(logger) = &options.logger {
+        logger.skipped(path, reason);
+    }
+    if options.verbosity >= Verbosity::Debug && human_stdout(options) {
+        println!("{}", format_skipped(path, reason));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_from_flags() {
+        assert_eq!(Verbosity::from_flags(0, false), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(1, false), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(3, false), Verbosity::Debug);
+        assert_eq!(Verbosity::from_flags(2, true), Verbosity::Quiet);
+    }
+
+    #[test]
+    fn test_format_lines() {
+        assert_eq!(
+            format_copied(Path::new("a/b.txt"), Path::new("c/b.txt")),
+            "'a/b.txt' -> 'c/b.txt'"
+        );
+        assert_eq!(
+            format_skipped(Path::new("x.tmp"), "excluded"),
+            "skipped 'x.tmp' (excluded)"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::utility::control::{ControlCommand, CopyControl};
+use crate::utility::output::Verbosity;
+use std::sync::Arc;
+
+#[cfg(unix)]
+use std::io::{self, IsTerminal, Read};
+#[cfg(unix)]
+use std::sync::Mutex;
+
+/// The terminal settings a [`KeyListener`] replaced, until put back.
+#[cfg(unix)]
+static SAVED: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// Pauses or resumes the run and says so on stderr, unless `--quiet`.
+/// Workers stop at their next chunk and pick up again as soon as the run
+/// is resumed.
+pub fn set_paused(control: &CopyControl, paused: bool, verbosity: Verbosity) {
+    if control.is_paused() == paused {
+        return;
+    }
+    control.send(if paused {
+        ControlCommand::Pause
+    } else {
+        ControlCommand::Resume
+    });
+    if verbosity >= Verbosity::Normal {
+        if paused {
+            eprintln!("\nPaused (press p or send SIGUSR2 to resume)");
+        } else {
+            eprintln!("Resumed");
+        }
+    }
+}
+
+/// While alive, pressing `p` on the terminal pauses or resumes the run.
+/// Stdin is read unbuffered and without echo until it is dropped or
+/// [`restore_terminal`] is called; Ctrl+C still interrupts as usual.
+#[cfg(unix)]
+pub struct KeyListener;
+
+#[cfg(unix)]
+impl KeyListener {
+    /// `None` unless stdin and stderr are both terminals.
+    pub fn start(control: Arc<CopyControl>, verbosity: Verbosity) -> Option<Self> {
+        if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+            return None;
+        }
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return None;
+        }
+        let mut keys = saved;
+        keys.c_lflag &= !(libc::ICANON | libc::ECHO);
+        keys.c_cc[libc::VMIN] = 1;
+        keys.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys) } != 0 {
+            return None;
+        }
+        *SAVED.lock().unwrap() = Some(saved);
+
+        std::thread::spawn(move || {
+            for key in io::stdin().lock().bytes() {
+                match key {
+                    Ok(b'p' | b'P') => set_paused(&control, !control.is_paused(), verbosity),
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+        });
+        Some(Self)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for KeyListener {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Puts back the terminal settings a [`KeyListener`] changed, so an
+/// interrupted run leaves echo on even if it never gets to drop it.
+#[cfg(unix)]
+pub fn restore_terminal() {
+    if let Some(saved) = SAVED.lock().unwrap().take() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &saved) };
+    }
+}
+
+/// Nothing to restore outside Unix.
+#[cfg(not(unix))]
+pub fn restore_terminal() {}
+
+/// The terminal isn't put into raw mode outside Unix, so there is no key
+/// to press; `cpx status --pause` still pauses the run.
+#[cfg(not(unix))]
+pub struct KeyListener;
+
+#[cfg(not(unix))]
+impl KeyListener {
+    /// Always `None`.
+    pub fn start(_control: Arc<CopyControl>, _verbosity: Verbosity) -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_paused_toggles_the_control() {
+        let control = CopyControl::new();
+        set_paused(&control, true, Verbosity::Quiet);
+        assert!(control.is_paused());
+        assert_eq!(control.resumes(), 0);
+        set_paused(&control, false, Verbosity::Quiet);
+        set_paused(&control, false, Verbosity::Quiet);
+        assert!(!control.is_paused());
+        assert_eq!(control.resumes(), 1);
+    }
+}
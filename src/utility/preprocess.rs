@@ -1,22 +1,41 @@
-use super::exclude::should_exclude;
-use super::helper::with_parents;
-use crate::cli::args::{CopyOptions, FollowSymlink, SymlinkMode};
-use crate::error::{CopyError, CopyResult};
-use jwalk::WalkDir;
-use std::collections::HashMap;
+use super::autotune::{self, Limiter, Tuner};
+use super::conflict::{self, Resolution};
+#[cfg(target_os = "linux")]
+use super::dirent;
+use super::events::CopyEvent;
+use super::exclude::{ExcludeMarkers, is_hidden, should_exclude};
+use super::hash::{HashAlgo, hash_file, stored_digest};
+use super::helper::{resolve_path, with_parents};
+use super::junction;
+use super::output;
+use crate::cli::args::{
+    ConflictPolicy, CopyOptions, FileOrder, FollowSymlink, HiddenMode, JunctionMode, SymlinkMode,
+};
+use crate::core::{compress, device};
+use crate::error::{CopyError, CopyResult, FailedFile};
+use jwalk::WalkDirGeneric;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
 use std::fs::Metadata;
 use std::io;
 use std::path::{Path, PathBuf};
-use xxhash_rust::xxh3::Xxh3;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SymlinkKind {
     PreserveExact,
     RelativeToSource,
     AbsoluteToSource,
+    /// A Windows junction to the exact target.
+    Junction,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileTask {
     pub source: PathBuf,
     pub destination: PathBuf,
@@ -24,20 +43,38 @@ pub struct FileTask {
     pub inode_group: Option<u64>, // For tracking hard link groups
 }
 
-#[derive(Debug, Clone)]
+/// A file with the same content as an earlier planned file (`--dedupe`)
+/// or as its copy under `--link-dest`. It is hard-linked to that copy once
+/// all copies are done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateTask {
+    pub file: FileTask,
+    /// Destination of the copy it links to.
+    pub original: PathBuf,
+    /// Copied from `original` rather than linked to it: another name of a
+    /// hard-linked source, when links aren't preserved.
+    #[serde(default)]
+    pub separate: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DirectoryTask {
     pub source: Option<PathBuf>,
     pub destination: PathBuf,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SymlinkTask {
     pub source: PathBuf,
     pub destination: PathBuf,
     pub kind: SymlinkKind,
+    /// The source symlink an exact copy was read from; `source` is then its
+    /// target, possibly relative to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HardlinkTask {
     pub source: PathBuf,
     pub destination: PathBuf,
@@ -49,12 +86,21 @@ pub struct CopyPlan {
     pub directories: Vec<DirectoryTask>,
     pub symlinks: Vec<SymlinkTask>,
     pub hardlinks: Vec<HardlinkTask>,
+    pub duplicates: Vec<DuplicateTask>,
     pub total_size: u64,
     pub total_files: usize,
     pub total_symlinks: usize,
     pub total_hardlinks: usize,
     pub skipped_files: usize,
     pub skipped_size: u64,
+    /// Entries left out by excludes, marker files or `--hidden=skip`.
+    pub excluded: usize,
+    /// Sources that could not be planned under `--ignore-errors`.
+    pub failures: Vec<FailedFile>,
+    /// Entries the walk was denied access to and left out, unless `--strict`.
+    pub unreadable: Vec<PathBuf>,
+    /// Destinations `--sanitize-names` renamed, as (planned, renamed).
+    pub renamed: Vec<(PathBuf, PathBuf)>,
 }
 
 impl Default for CopyPlan {
@@ -70,12 +116,17 @@ impl CopyPlan {
             directories: Vec::new(),
             symlinks: Vec::new(),
             hardlinks: Vec::new(),
+            duplicates: Vec::new(),
             total_size: 0,
             total_files: 0,
             total_symlinks: 0,
             total_hardlinks: 0,
             skipped_files: 0,
             skipped_size: 0,
+            excluded: 0,
+            failures: Vec::new(),
+            unreadable: Vec::new(),
+            renamed: Vec::new(),
         }
     }
 
@@ -83,6 +134,29 @@ impl CopyPlan {
         self.add_file_with_inode(source, destination, size, None);
     }
 
+    /// Plans `source` as a file of its own whose data is copied from
+    /// `original`, the copy of another name of the same hard-linked file.
+    pub fn add_copy_of(
+        &mut self,
+        original: PathBuf,
+        source: PathBuf,
+        destination: PathBuf,
+        size: u64,
+    ) {
+        self.remove_existing_task(&destination);
+        self.duplicates.push(DuplicateTask {
+            file: FileTask {
+                source,
+                destination,
+                size,
+                inode_group: None,
+            },
+            original,
+            separate: true,
+        });
+        self.total_files += 1;
+    }
+
     // last source wins, if multiple sources collide prevents symlink write-through
     fn remove_existing_task(&mut self, dest: &Path) {
         self.symlinks.retain(|t| t.destination != dest);
@@ -125,10 +199,26 @@ impl CopyPlan {
             source,
             destination,
             kind,
+            origin: None,
         });
         self.total_symlinks += 1;
     }
 
+    /// [`add_symlink`](Self::add_symlink) for the link `origin` itself,
+    /// recreated with its `target`.
+    pub fn add_symlink_copy(
+        &mut self,
+        origin: &Path,
+        target: PathBuf,
+        destination: PathBuf,
+        kind: SymlinkKind,
+    ) {
+        self.add_symlink(target, destination, kind);
+        if let Some(task) = self.symlinks.last_mut() {
+            task.origin = Some(origin.to_path_buf());
+        }
+    }
+
     pub fn add_hardlink(&mut self, source: PathBuf, destination: PathBuf) {
         self.remove_existing_task(&destination);
         self.hardlinks.push(HardlinkTask {
@@ -143,8 +233,19 @@ impl CopyPlan {
         self.skipped_size += size;
     }
 
-    pub fn sort_files_descending(&mut self) {
-        self.files.sort_by(|a, b| b.size.cmp(&a.size));
+    pub fn sort_files(&mut self, order: FileOrder) {
+        match order {
+            FileOrder::SizeDesc => self.files.sort_by_key(|f| std::cmp::Reverse(f.size)),
+            FileOrder::SizeAsc => self.files.sort_by_key(|f| f.size),
+            FileOrder::Path => {
+                self.files.sort_by(|a, b| a.destination.cmp(&b.destination));
+                self.symlinks
+                    .sort_by(|a, b| a.destination.cmp(&b.destination));
+                self.hardlinks
+                    .sort_by(|a, b| a.destination.cmp(&b.destination));
+            }
+            FileOrder::None => {}
+        }
     }
 
     pub fn merge(&mut self, other: CopyPlan) {
@@ -152,15 +253,359 @@ impl CopyPlan {
         self.directories.extend(other.directories);
         self.symlinks.extend(other.symlinks);
         self.hardlinks.extend(other.hardlinks);
+        self.duplicates.extend(other.duplicates);
         self.total_size += other.total_size;
         self.total_files += other.total_files;
         self.total_symlinks += other.total_symlinks;
         self.total_hardlinks += other.total_hardlinks;
         self.skipped_files += other.skipped_files;
         self.skipped_size += other.skipped_size;
+        self.excluded += other.excluded;
+        self.failures.extend(other.failures);
+        self.unreadable.extend(other.unreadable);
+        self.renamed.extend(other.renamed);
+    }
+
+    /// Drops earlier tasks that write the same destination as a later one
+    /// (last source wins, as in `remove_existing_task`). Returns how many
+    /// tasks were dropped.
+    pub fn dedupe_destinations(&mut self) -> usize {
+        let removed_files = keep_last_by_destination(&mut self.files, |t| &t.destination);
+        let removed_symlinks = keep_last_by_destination(&mut self.symlinks, |t| &t.destination);
+        let removed_hardlinks = keep_last_by_destination(&mut self.hardlinks, |t| &t.destination);
+
+        self.total_files -= removed_files.len();
+        self.total_size -= removed_files.iter().map(|t| t.size).sum::<u64>();
+        self.total_symlinks -= removed_symlinks.len();
+        self.total_hardlinks -= removed_hardlinks.len();
+
+        removed_files.len() + removed_symlinks.len() + removed_hardlinks.len()
+    }
+
+    /// Turns files whose content matches an earlier planned file (same size
+    /// and xxh3) into [`DuplicateTask`]s. Only same-sized files are hashed.
+    /// They stay in `total_files`, but their bytes leave `total_size`.
+    /// Returns how many files were turned into links.
+    pub fn dedupe_content(&mut self, algo: HashAlgo) -> usize {
+        let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (index, task) in self.files.iter().enumerate() {
+            // hard link groups from --preserve=links are linked already
+            if task.size > 0 && task.inode_group.is_none() {
+                by_size.entry(task.size).or_default().push(index);
+            }
+        }
+        let mut candidates: Vec<usize> = by_size
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .flatten()
+            .collect();
+        candidates.sort_unstable();
+
+        let digests: Vec<Option<String>> = candidates
+            .par_iter()
+            .map(|&index| hash_file(&self.files[index].source, algo).ok())
+            .collect();
+
+        let mut first_copy: HashMap<(u64, String), usize> = HashMap::new();
+        let mut original_of: HashMap<usize, PathBuf> = HashMap::new();
+        for (&index, digest) in candidates.iter().zip(digests) {
+            let Some(digest) = digest else { continue };
+            match first_copy.entry((self.files[index].size, digest)) {
+                std::collections::hash_map::Entry::Occupied(first) => {
+                    original_of.insert(index, self.files[*first.get()].destination.clone());
+                }
+                std::collections::hash_map::Entry::Vacant(slot) => {
+                    slot.insert(index);
+                }
+            }
+        }
+        if original_of.is_empty() {
+            return 0;
+        }
+
+        let before = self.duplicates.len();
+        let files = std::mem::take(&mut self.files);
+        for (index, file) in files.into_iter().enumerate() {
+            match original_of.remove(&index) {
+                Some(original) => {
+                    self.total_size -= file.size;
+                    self.duplicates.push(DuplicateTask {
+                        file,
+                        original,
+                        separate: false,
+                    });
+                }
+                None => self.files.push(file),
+            }
+        }
+        self.duplicates.len() - before
+    }
+
+    /// Drops the directories below `root` that no planned file or link ends
+    /// up in (`--prune-empty-dirs`); `root` itself is always kept. Returns
+    /// how many directories were dropped.
+    pub fn prune_empty_dirs(&mut self, root: &Path) -> usize {
+        let mut needed: HashSet<&Path> = HashSet::new();
+        let leaves = self
+            .files
+            .iter()
+            .map(|t| &t.destination)
+            .chain(self.symlinks.iter().map(|t| &t.destination))
+            .chain(self.hardlinks.iter().map(|t| &t.destination))
+            .chain(self.duplicates.iter().map(|t| &t.file.destination));
+        for leaf in leaves {
+            // stop at the first ancestor already marked: the rest are too
+            for dir in leaf.ancestors().skip(1) {
+                if !needed.insert(dir) {
+                    break;
+                }
+            }
+        }
+        let before = self.directories.len();
+        self.directories
+            .retain(|d| d.destination == root || needed.contains(d.destination.as_path()));
+        before - self.directories.len()
+    }
+
+    /// Planned files and links whose destination already holds something
+    /// they would replace.
+    pub fn conflicts(&self) -> Vec<PathBuf> {
+        let mut destinations: Vec<&PathBuf> = self
+            .files
+            .iter()
+            .map(|task| &task.destination)
+            .chain(self.duplicates.iter().map(|task| &task.file.destination))
+            .chain(self.symlinks.iter().map(|task| &task.destination))
+            .chain(self.hardlinks.iter().map(|task| &task.destination))
+            .collect();
+        destinations.sort_unstable();
+        destinations
+            .into_par_iter()
+            .filter(|path| std::fs::symlink_metadata(path).is_ok())
+            .cloned()
+            .collect()
+    }
+
+    /// Applies the skip, newer, larger and rename `--on-conflict` policies
+    /// to planned files and links whose destination already exists. Kept
+    /// ones leave the plan as skipped and renamed ones get a free name; the
+    /// other policies are applied as each destination is written.
+    pub fn resolve_conflicts(&mut self, options: &CopyOptions) -> CopyResult<()> {
+        let file_policy = options.conflict_policy(false);
+        let link_policy = options.conflict_policy(true);
+        let planned_ahead = |policy| {
+            matches!(
+                policy,
+                ConflictPolicy::Skip
+                    | ConflictPolicy::Newer
+                    | ConflictPolicy::Larger
+                    | ConflictPolicy::Rename
+            )
+        };
+        if !planned_ahead(file_policy) && !planned_ahead(link_policy) {
+            return Ok(());
+        }
+
+        let mut taken: HashSet<PathBuf> = self
+            .files
+            .iter()
+            .map(|task| task.destination.clone())
+            .chain(
+                self.duplicates
+                    .iter()
+                    .map(|task| task.file.destination.clone()),
+            )
+            .chain(self.symlinks.iter().map(|task| task.destination.clone()))
+            .chain(self.hardlinks.iter().map(|task| task.destination.clone()))
+            .collect();
+        // false when the destination is kept
+        let mut settle = |source: &Path, destination: &mut PathBuf, policy| -> CopyResult<bool> {
+            if !planned_ahead(policy) {
+                return Ok(true);
+            }
+            match conflict::resolve(source, destination, policy, options.modify_window, &taken)? {
+                Resolution::Write => Ok(true),
+                Resolution::Keep(reason) => {
+                    skip(options, source, reason);
+                    Ok(false)
+                }
+                Resolution::Rename(name) => {
+                    taken.insert(name.clone());
+                    *destination = name;
+                    Ok(true)
+                }
+            }
+        };
+
+        // copies separate duplicates are taken from: where they went, or
+        // None if they were kept
+        let mut moved: HashMap<PathBuf, Option<PathBuf>> = HashMap::new();
+        for mut task in std::mem::take(&mut self.files) {
+            let planned = task.destination.clone();
+            if settle(&task.source, &mut task.destination, file_policy)? {
+                if task.destination != planned {
+                    moved.insert(planned, Some(task.destination.clone()));
+                }
+                self.files.push(task);
+            } else {
+                moved.insert(planned, None);
+                self.total_files -= 1;
+                self.total_size -= task.size;
+                self.mark_skipped(task.size);
+            }
+        }
+        for mut task in std::mem::take(&mut self.duplicates) {
+            if !settle(&task.file.source, &mut task.file.destination, file_policy)? {
+                self.total_files -= 1;
+                self.mark_skipped(task.file.size);
+                continue;
+            }
+            if !task.separate {
+                self.duplicates.push(task);
+                continue;
+            }
+            match moved.get(&task.original) {
+                Some(Some(renamed)) => {
+                    task.original = renamed.clone();
+                    self.duplicates.push(task);
+                }
+                // nothing to copy from: the first name still written takes
+                // its place
+                Some(None) => {
+                    moved.insert(task.original, Some(task.file.destination.clone()));
+                    self.total_size += task.file.size;
+                    self.files.push(task.file);
+                }
+                None => self.duplicates.push(task),
+            }
+        }
+        for mut task in std::mem::take(&mut self.symlinks) {
+            let source = task.origin.clone().unwrap_or_else(|| task.source.clone());
+            if settle(&source, &mut task.destination, link_policy)? {
+                self.symlinks.push(task);
+            } else {
+                self.total_symlinks -= 1;
+                self.mark_skipped(0);
+            }
+        }
+        for mut task in std::mem::take(&mut self.hardlinks) {
+            if settle(&task.source, &mut task.destination, link_policy)? {
+                self.hardlinks.push(task);
+            } else {
+                self.total_hardlinks -= 1;
+                self.mark_skipped(0);
+            }
+        }
+        Ok(())
+    }
+
+    /// `--link-dest`: files whose copy under `link_dest`, laid out like
+    /// `destination`, is unchanged are hard-linked to it instead of copied.
+    /// A relative `link_dest` is taken from `destination`.
+    /// Returns how many files were turned into links.
+    pub fn link_unchanged(
+        &mut self,
+        link_dest: &Path,
+        destination: &Path,
+        options: &CopyOptions,
+    ) -> usize {
+        // the destination may not exist yet for `..` to step out of
+        let link_dest = destination.join(link_dest);
+        let link_dest = resolve_path(&link_dest).unwrap_or(link_dest);
+        let originals: Vec<Option<PathBuf>> = self
+            .files
+            .par_iter()
+            .map(|task| {
+                // hard link groups from --preserve=links are linked already
+                if task.inode_group.is_some() {
+                    return None;
+                }
+                let relative = task.destination.strip_prefix(destination).ok()?;
+                let original = link_dest.join(relative);
+                is_unchanged(&task.source, &original, options).then_some(original)
+            })
+            .collect();
+
+        let before = self.duplicates.len();
+        let files = std::mem::take(&mut self.files);
+        for (file, original) in files.into_iter().zip(originals) {
+            match original {
+                Some(original) => {
+                    self.total_size -= file.size;
+                    self.duplicates.push(DuplicateTask {
+                        file,
+                        original,
+                        separate: false,
+                    });
+                }
+                None => self.files.push(file),
+            }
+        }
+        self.duplicates.len() - before
     }
 }
 
+/// Whether `original`, an earlier copy of `source`, can stand in for a new
+/// one: a regular file holding the same data and, where they are preserved,
+/// the same mode and owner.
+fn is_unchanged(source: &Path, original: &Path, options: &CopyOptions) -> bool {
+    let (Ok(src_metadata), Ok(metadata)) = (
+        std::fs::metadata(source),
+        std::fs::symlink_metadata(original),
+    ) else {
+        return false;
+    };
+    if !metadata.is_file() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if options.preserve.mode && src_metadata.mode() != metadata.mode() {
+            return false;
+        }
+        if options.preserve.ownership
+            && (src_metadata.uid(), src_metadata.gid()) != (metadata.uid(), metadata.gid())
+        {
+            return false;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = src_metadata;
+    should_skip_file(
+        source,
+        original,
+        options.modify_window,
+        options.checksum_algo,
+    )
+    .unwrap_or(false)
+}
+
+fn keep_last_by_destination<T>(tasks: &mut Vec<T>, destination: impl Fn(&T) -> &Path) -> Vec<T> {
+    let mut seen = HashSet::new();
+    let mut kept = Vec::with_capacity(tasks.len());
+    let mut removed = Vec::new();
+    for task in tasks.drain(..).rev() {
+        if seen.insert(destination(&task).to_path_buf()) {
+            kept.push(task);
+        } else {
+            removed.push(task);
+        }
+    }
+    kept.reverse();
+    *tasks = kept;
+    removed
+}
+
+// Resolves the parent only, so distinct symlinks to one target stay distinct
+fn resolve_source(source: &Path) -> PathBuf {
+    let resolved = match (source.parent(), source.file_name()) {
+        (Some(parent), Some(name)) => resolve_path(parent).map(|p| p.join(name)),
+        _ => resolve_path(source),
+    };
+    resolved.unwrap_or_else(|_| source.to_path_buf())
+}
+
 fn symlink_kind_from_mode(source: &Path, mode: SymlinkMode) -> SymlinkKind {
     match mode {
         SymlinkMode::Absolute => SymlinkKind::AbsoluteToSource,
@@ -175,24 +620,31 @@ fn symlink_kind_from_mode(source: &Path, mode: SymlinkMode) -> SymlinkKind {
     }
 }
 
-fn calculate_checksum(path: &Path) -> io::Result<u64> {
-    use std::io::Read;
-    let mut file = std::fs::File::open(path)?;
-    let mut hasher = Xxh3::new();
-    let mut buffer = vec![0u8; 128 * 1024];
-
-    loop {
-        let bytes_read = file.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        hasher.update(&buffer[..bytes_read]);
-    }
+/// Leaves `path` out of the plan, telling the log and event subscribers why.
+fn skip(options: &CopyOptions, path: &Path, reason: &str) {
+    output::skipped(options, path, reason);
+    options.events.emit(|| CopyEvent::Skipped {
+        source: path.to_path_buf(),
+        reason: reason.to_string(),
+    });
+}
 
-    Ok(hasher.digest())
+/// [`skip`] for entries left out by excludes, marker files or `--hidden`,
+/// which the summary counts apart from files already up to date.
+fn exclude(plan: &mut CopyPlan, options: &CopyOptions, path: &Path, reason: &str) {
+    skip(options, path, reason);
+    plan.excluded += 1;
 }
 
-pub fn should_skip_file(source: &Path, destination: &Path) -> io::Result<bool> {
+/// Whether `source` and `destination` already hold the same data: equal
+/// sizes and a destination no older than the source, give or take
+/// `modify_window`, or else equal `algo` checksums.
+pub fn should_skip_file(
+    source: &Path,
+    destination: &Path,
+    modify_window: Duration,
+    algo: HashAlgo,
+) -> io::Result<bool> {
     let dest_metadata = match std::fs::metadata(destination) {
         Ok(meta) => meta,
         Err(_) => return Ok(false),
@@ -206,17 +658,41 @@ pub fn should_skip_file(source: &Path, destination: &Path) -> io::Result<bool> {
 
     if let (Ok(src_modified), Ok(dest_modified)) =
         (src_metadata.modified(), dest_metadata.modified())
-        && src_modified <= dest_modified
+        && within_window(src_modified, dest_modified, modify_window)
     {
         return Ok(true);
     }
 
-    let src_checksum = calculate_checksum(source)?;
-    let dest_checksum = calculate_checksum(destination)?;
+    let src_checksum = hash_file(source, algo)?;
+    // recorded by --store-checksum, and good while the copy is untouched
+    let dest_checksum = match stored_digest(destination, algo) {
+        Some(digest) => digest,
+        None => hash_file(destination, algo)?,
+    };
 
     Ok(src_checksum == dest_checksum)
 }
 
+pub(crate) fn is_newer_or_same(source: &Path, destination: &Path, modify_window: Duration) -> bool {
+    match (std::fs::metadata(source), std::fs::metadata(destination)) {
+        (Ok(src), Ok(dest)) => matches!(
+            (src.modified(), dest.modified()),
+            (Ok(src_modified), Ok(dest_modified))
+                if within_window(src_modified, dest_modified, modify_window)
+        ),
+        _ => false,
+    }
+}
+
+/// `source <= destination`, allowing the destination to be up to `window`
+/// older: FAT and some SMB servers round mtimes to 2 seconds.
+pub(crate) fn within_window(source: SystemTime, destination: SystemTime, window: Duration) -> bool {
+    source <= destination
+        || source
+            .duration_since(destination)
+            .is_ok_and(|lag| lag <= window)
+}
+
 fn process_entry(
     plan: &mut CopyPlan,
     source: &Path,
@@ -229,6 +705,7 @@ fn process_entry(
     if let Some(exclude_rules) = &options.exclude_rules
         && should_exclude(source, source_root, exclude_rules)
     {
+        exclude(plan, options, source, "excluded");
         return Ok(());
     }
 
@@ -265,14 +742,29 @@ fn process_entry(
         None
     };
 
-    if metadata.file_type().is_symlink() {
+    if metadata.file_type().is_symlink() && junction::is_junction(source) {
+        match options.junctions {
+            JunctionMode::Skip => skip(options, source, "junction"),
+            // followed junctions are walked as directories; without -r
+            // there is nothing to follow into
+            JunctionMode::Recreate | JunctionMode::Follow => {
+                let target = std::fs::read_link(source)?;
+                plan.add_symlink_copy(source, target, dest_path, SymlinkKind::Junction);
+            }
+        }
+    } else if metadata.file_type().is_symlink() {
         if !matches!(options.follow_symlink, FollowSymlink::Dereference) {
             if let Some(mode) = options.symbolic_link {
                 let kind = symlink_kind_from_mode(source, mode);
                 plan.add_symlink(source.to_path_buf(), dest_path, kind);
             } else {
                 let original_target = std::fs::read_link(source)?;
-                plan.add_symlink(original_target, dest_path, SymlinkKind::PreserveExact);
+                plan.add_symlink_copy(
+                    source,
+                    original_target,
+                    dest_path,
+                    SymlinkKind::PreserveExact,
+                );
             }
         }
     } else if options.hard_link {
@@ -280,14 +772,118 @@ fn process_entry(
     } else if let Some(mode) = options.symbolic_link {
         let kind = symlink_kind_from_mode(source, mode);
         plan.add_symlink(source.to_path_buf(), dest_path, kind);
-    } else if options.resume && should_skip_file(source, &dest_path)? {
+    } else if compress::transcodes(source, options) {
+        let dest_path = compress::transcoded_path(dest_path, options);
+        // sizes differ by design, so only the timestamps can tell
+        if options.resume && is_newer_or_same(source, &dest_path, options.modify_window) {
+            skip(options, source, "already up to date");
+            plan.mark_skipped(metadata.len());
+        } else {
+            plan_file(
+                plan,
+                options,
+                source,
+                dest_path,
+                metadata,
+                inode_group,
+                inode_groups,
+            );
+        }
+    } else if options.resume
+        && should_skip_file(
+            source,
+            &dest_path,
+            options.modify_window,
+            options.checksum_algo,
+        )?
+    {
+        skip(options, source, "already up to date");
         plan.mark_skipped(metadata.len());
     } else {
-        plan.add_file_with_inode(source.to_path_buf(), dest_path, metadata.len(), inode_group);
+        plan_file(
+            plan,
+            options,
+            source,
+            dest_path,
+            metadata,
+            inode_group,
+            inode_groups,
+        );
     }
     Ok(())
 }
 
+/// Plans a source named on the command line. A block device there is
+/// copied as its contents, sized from the device. Devices found while
+/// walking a directory aren't read: their metadata gives them no size.
+fn process_source_entry(
+    plan: &mut CopyPlan,
+    source: &Path,
+    source_root: &Path,
+    dest_path: PathBuf,
+    metadata: &Metadata,
+    options: &CopyOptions,
+) -> io::Result<()> {
+    let excluded = options
+        .exclude_rules
+        .as_ref()
+        .is_some_and(|rules| should_exclude(source, source_root, rules));
+    if device::is_block_device(metadata)
+        && !excluded
+        && !options.hard_link
+        && options.symbolic_link.is_none()
+    {
+        let size = device::device_size(&std::fs::File::open(source)?)?;
+        plan.add_file(source.to_path_buf(), dest_path, size);
+        return Ok(());
+    }
+    process_entry(
+        plan,
+        source,
+        source_root,
+        dest_path,
+        metadata,
+        options,
+        &mut None,
+    )
+}
+
+/// Plans a regular file. Without `--preserve=links`, a further name of a
+/// hard-linked file still becomes a file of its own, but one copied from
+/// the first name's copy, so the data is read from the source only once.
+fn plan_file(
+    plan: &mut CopyPlan,
+    options: &CopyOptions,
+    source: &Path,
+    dest_path: PathBuf,
+    metadata: &Metadata,
+    inode_group: Option<u64>,
+    inode_groups: &mut Option<HashMap<u64, Vec<PathBuf>>>,
+) {
+    #[cfg(unix)]
+    if !options.preserve.links {
+        use std::os::unix::fs::MetadataExt;
+
+        if metadata.nlink() > 1 {
+            let names = inode_groups
+                .get_or_insert_with(HashMap::new)
+                .entry(metadata.ino())
+                .or_default();
+            if let Some(first) = names.first() {
+                plan.add_copy_of(
+                    first.clone(),
+                    source.to_path_buf(),
+                    dest_path,
+                    metadata.len(),
+                );
+                return;
+            }
+            names.push(dest_path.clone());
+        }
+    }
+    plan.add_file_with_inode(source.to_path_buf(), dest_path, metadata.len(), inode_group);
+}
+
 pub fn preprocess_file(
     source: &Path,
     source_root: &Path,
@@ -343,6 +939,7 @@ pub fn preprocess_file(
     if let Some(exclude_rules) = &options.exclude_rules
         && should_exclude(source, source_root, exclude_rules)
     {
+        exclude(&mut plan, options, source, "excluded");
         return Ok(plan);
     }
     if options.parents
@@ -351,15 +948,13 @@ pub fn preprocess_file(
         plan.add_directory(None, parent.to_path_buf());
     }
 
-    let mut inode_groups = None;
-    process_entry(
+    process_source_entry(
         &mut plan,
         source,
         source_root,
         dest_path.clone(),
         &source_metadata,
         options,
-        &mut inode_groups,
     )
     .map_err(|e| CopyError::CopyFailed {
         source: source.to_path_buf(),
@@ -369,17 +964,41 @@ pub fn preprocess_file(
     Ok(plan)
 }
 
+// cpx -r dir dir/backup would otherwise copy the tree into itself
+fn ensure_not_nested(source: &Path, destination: &Path) -> CopyResult<()> {
+    let resolved_source = resolve_path(source)?;
+    let resolved_destination = resolve_path(destination)?;
+    if resolved_destination.starts_with(&resolved_source) {
+        return Err(CopyError::CopyFailed {
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            reason: format!(
+                "cannot copy a directory, '{}', into itself, '{}'",
+                source.display(),
+                destination.display()
+            ),
+        });
+    }
+    Ok(())
+}
+
 pub fn preprocess_directory(
     source: &Path,
     source_root: &Path,
     destination: &Path,
     options: &CopyOptions,
 ) -> CopyResult<CopyPlan> {
-    let mut plan = CopyPlan::new();
     if source != source_root
         && let Some(exclude_rules) = &options.exclude_rules
         && should_exclude(source, source_root, exclude_rules)
     {
+        let mut plan = CopyPlan::new();
+        exclude(&mut plan, options, source, "excluded");
+        return Ok(plan);
+    }
+    if let Some(marker) = options.exclude_markers.find(source) {
+        let mut plan = CopyPlan::new();
+        exclude(&mut plan, options, source, &format!("contains {}", marker));
         return Ok(plan);
     }
 
@@ -392,9 +1011,37 @@ pub fn preprocess_directory(
             })?)
         };
 
-    plan.add_directory(Some(source.into()), root_destination.clone());
+    ensure_not_nested(source, &root_destination)?;
+    preprocess_tree(source, &root_destination, options)
+}
+
+/// Plans `source` as `root_destination`, without the copy-into-itself check
+/// that only applies to local destinations (not remote hosts or archives).
+pub fn preprocess_tree(
+    source: &Path,
+    root_destination: &Path,
+    options: &CopyOptions,
+) -> CopyResult<CopyPlan> {
+    let followed = if options.junctions == JunctionMode::Follow {
+        vec![resolve_path(source)?]
+    } else {
+        Vec::new()
+    };
+    walk_tree(source, root_destination, options, &followed)
+}
+
+/// Plans one tree. `followed` holds the resolved roots of the trees being
+/// walked, outermost first, so `--junctions=follow` can refuse loops.
+fn walk_tree(
+    source: &Path,
+    root_destination: &Path,
+    options: &CopyOptions,
+    followed: &[PathBuf],
+) -> CopyResult<CopyPlan> {
+    let destination = root_destination;
+    let mut plan = CopyPlan::new();
+    plan.add_directory(Some(source.into()), root_destination.to_path_buf());
 
-    let num_threads = num_cpus::get().min(8);
     let follow_symlink = match options.follow_symlink {
         FollowSymlink::NoDereference | FollowSymlink::CommandLineSymlink => false,
         FollowSymlink::Dereference => true,
@@ -418,67 +1065,395 @@ pub fn preprocess_directory(
 
     let mut inode_groups = None;
 
-    for entry in WalkDir::new(&walk_root)
-        .skip_hidden(false)
-        .parallelism(jwalk::Parallelism::RayonNewPool(num_threads))
-        .follow_links(follow_symlink)
-    {
-        let entry = entry.map_err(|e| CopyError::CopyFailed {
-            source: source.to_path_buf(),
-            destination: destination.to_path_buf(),
-            reason: format!("Failed to read directory entry: {}", e),
-        })?;
-        let src_path = entry.path();
-        if src_path == walk_root {
-            continue;
-        }
+    let loops = Arc::new(Mutex::new(Vec::new()));
+    let pruned = Arc::new(Mutex::new(Vec::new()));
+    let markers = Arc::new(options.exclude_markers.clone());
+    let hidden = options.hidden;
+    // `-j auto` sizes the scan on its own, from entries read per second
+    let limiter = options
+        .auto_jobs
+        .then(|| Arc::new(Limiter::new(autotune::START_WORKERS)));
+    let scanned = Arc::new(AtomicU64::new(0));
+    let num_threads = if options.auto_jobs {
+        autotune::MAX_WORKERS
+    } else {
+        num_cpus::get().min(8)
+    };
+    // on Linux the tree is read in large batches and only what needs its
+    // metadata is stat'ed; jwalk keeps the order --deterministic asks for
+    // and the loop checks following symlinks needs
+    #[cfg(target_os = "linux")]
+    let batched = (!follow_symlink && !options.deterministic)
+        .then(|| {
+            let pruned = pruned.clone();
+            let markers = markers.clone();
+            let limiter = limiter.clone();
+            let scanned = scanned.clone();
+            dirent::walk(&walk_root, num_threads, move |_, entries| {
+                let _permit = limiter.as_deref().map(Limiter::acquire);
+                if hidden == HiddenMode::Skip || !markers.is_empty() {
+                    entries.retain(|entry| {
+                        let metadata = entry.metadata.as_ref().and_then(|m| m.as_ref().ok());
+                        let reason = prune_reason(
+                            entry.name(),
+                            entry.is_dir(),
+                            metadata,
+                            || entry.path.clone(),
+                            &markers,
+                            hidden,
+                        );
+                        keep_unpruned(reason, || entry.path.clone(), &pruned)
+                    });
+                }
+                stat_files(entries);
+                scanned.fetch_add(entries.len() as u64, Ordering::Relaxed);
+            })
+        })
+        .transpose()?
+        .map(|walk| {
+            walk.map(|entry| entry.map(|entry| (entry.path, entry.metadata)))
+                .map(|entry| entry.map_err(|e| (Some(e.path.clone()), io::Error::from(e))))
+        });
+    #[cfg(not(target_os = "linux"))]
+    let batched: Option<std::iter::Empty<Result<Found, WalkFailure>>> = None;
+
+    let entries: Box<dyn Iterator<Item = Result<Found, WalkFailure>>> = match batched {
+        Some(walk) => Box::new(walk),
+        None => Box::new(
+            WalkDirGeneric::<(Vec<DirId>, Option<Metadata>)>::new(&walk_root)
+                .skip_hidden(false)
+                .sort(options.deterministic)
+                .parallelism(jwalk::Parallelism::RayonNewPool(num_threads))
+                .follow_links(follow_symlink)
+                .process_read_dir({
+                    let loops = loops.clone();
+                    let pruned = pruned.clone();
+                    let limiter = limiter.clone();
+                    let scanned = scanned.clone();
+                    move |_, path, ancestors, children| {
+                        let _permit = limiter.as_deref().map(Limiter::acquire);
+                        if follow_symlink {
+                            prune_symlink_loops(path, ancestors, children, &loops);
+                        }
+                        prefetch_metadata(children);
+                        if hidden == HiddenMode::Skip || !markers.is_empty() {
+                            prune_excluded(children, &markers, hidden, &pruned);
+                        }
+                        scanned.fetch_add(children.len() as u64, Ordering::Relaxed);
+                    }
+                })
+                .into_iter()
+                .map(|entry| match entry {
+                    Ok(mut entry) => {
+                        let metadata = entry
+                            .client_state
+                            .take()
+                            .map_or_else(|| entry.metadata(), Ok)
+                            .map_err(io::Error::from);
+                        Ok((entry.path(), Some(metadata)))
+                    }
+                    Err(e) => Err((e.path().map(Path::to_path_buf), io::Error::from(e))),
+                }),
+        ),
+    };
 
-        let relative = src_path
-            .strip_prefix(&walk_root)
-            .map_err(|_| CopyError::CopyFailed {
-                source: source.to_path_buf(),
-                destination: destination.to_path_buf(),
-                reason: "Failed to calculate relative path".to_string(),
-            })?;
+    let walk = || -> CopyResult<()> {
+        for entry in entries {
+            let (src_path, metadata) = match entry {
+                Ok(found) => found,
+                Err((path, e)) if !options.strict && is_permission_denied(Some(&e)) => {
+                    let path = path.as_deref().unwrap_or(source);
+                    skip(options, path, "permission denied");
+                    plan.unreadable.push(path.to_path_buf());
+                    continue;
+                }
+                Err((_, e)) => {
+                    return Err(CopyError::CopyFailed {
+                        source: source.to_path_buf(),
+                        destination: destination.to_path_buf(),
+                        reason: format!("Failed to read directory entry: {}", e),
+                    });
+                }
+            };
+            if src_path == walk_root {
+                continue;
+            }
 
-        let full_source_path = if walk_root != source {
-            source.join(relative)
-        } else {
-            src_path.to_path_buf()
-        };
+            let relative =
+                src_path
+                    .strip_prefix(&walk_root)
+                    .map_err(|_| CopyError::CopyFailed {
+                        source: source.to_path_buf(),
+                        destination: destination.to_path_buf(),
+                        reason: "Failed to calculate relative path".to_string(),
+                    })?;
 
-        if let Some(exclude_rules) = &options.exclude_rules
-            && should_exclude(&full_source_path, source, exclude_rules)
-        {
-            continue;
+            let full_source_path = if walk_root != source {
+                source.join(relative)
+            } else {
+                src_path.to_path_buf()
+            };
+
+            if let Some(exclude_rules) = &options.exclude_rules
+                && should_exclude(&full_source_path, source, exclude_rules)
+            {
+                exclude(&mut plan, options, &full_source_path, "excluded");
+                continue;
+            }
+
+            let dest_path = root_destination.join(relative);
+            // None for a directory known by its type alone
+            let metadata = match metadata.transpose() {
+                Ok(metadata) => metadata,
+                Err(e) if !options.strict && is_permission_denied(Some(&e)) => {
+                    skip(options, &src_path, "permission denied");
+                    plan.unreadable.push(src_path.to_path_buf());
+                    continue;
+                }
+                Err(e) => {
+                    return Err(CopyError::CopyFailed {
+                        source: src_path.to_path_buf(),
+                        destination: destination.to_path_buf(),
+                        reason: format!("Failed to get metadata: {}", e),
+                    });
+                }
+            };
+
+            if options.junctions == JunctionMode::Follow
+                && metadata
+                    .as_ref()
+                    .is_some_and(|metadata| metadata.file_type().is_symlink())
+                && junction::is_junction(&src_path)
+            {
+                if let Some(chain) = follow_junction(&src_path, followed)? {
+                    plan.merge(walk_tree(&src_path, &dest_path, options, &chain)?);
+                } else {
+                    output::warn(
+                        options,
+                        format!("Skipping junction loop: '{}'", src_path.display()),
+                    );
+                    if let Some(logger) = &options.logger {
+                        logger.warning(&src_path, "junction loop, not descending");
+                    }
+                }
+                continue;
+            }
+
+            match metadata {
+                Some(metadata) if !metadata.is_dir() => {
+                    options.scan_progress.file(metadata.len());
+                    process_entry(
+                        &mut plan,
+                        &src_path,
+                        &walk_root,
+                        dest_path,
+                        &metadata,
+                        options,
+                        &mut inode_groups,
+                    )?;
+                }
+                _ => {
+                    options.scan_progress.dir(&src_path);
+                    plan.add_directory(Some(src_path.to_path_buf()), dest_path);
+                }
+            }
         }
+        Ok(())
+    };
+    match &limiter {
+        Some(limiter) => {
+            let tuner = Tuner::new(autotune::START_WORKERS, autotune::MAX_WORKERS);
+            let progress = || (scanned.load(Ordering::Relaxed), 0);
+            autotune::with_tuning(limiter, tuner, progress, walk)?;
+        }
+        None => walk()?,
+    }
 
-        let dest_path = root_destination.join(relative);
-        let metadata = entry.metadata().map_err(|e| CopyError::CopyFailed {
-            source: src_path.to_path_buf(),
-            destination: destination.to_path_buf(),
-            reason: format!("Failed to get metadata: {}", e),
-        })?;
+    for path in loops.lock().map(|l| l.clone()).unwrap_or_default() {
+        output::warn(
+            options,
+            format!("Skipping symlink loop: '{}'", path.display()),
+        );
+        if let Some(logger) = &options.logger {
+            logger.warning(&path, "symlink loop, not descending");
+        }
+    }
 
-        if metadata.is_dir() {
-            plan.add_directory(Some(src_path.to_path_buf()), dest_path);
-        } else {
-            process_entry(
-                &mut plan,
-                &src_path,
-                &walk_root,
-                dest_path,
-                &metadata,
-                options,
-                &mut inode_groups,
-            )?;
+    for (path, reason) in pruned.lock().map(|p| p.clone()).unwrap_or_default() {
+        exclude(&mut plan, options, &path, &reason);
+    }
+
+    if options.prune_empty_dirs {
+        let pruned = plan.prune_empty_dirs(root_destination);
+        if let Some(logger) = &options.logger {
+            logger.debug(&format!(
+                "{} empty directories left out of {}",
+                pruned,
+                root_destination.display()
+            ));
         }
     }
 
-    plan.sort_files_descending();
+    plan.sort_files(options.order);
     Ok(plan)
 }
 
+fn is_permission_denied(error: Option<&io::Error>) -> bool {
+    error.is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+}
+
+/// `followed` extended with the junction's target, or `None` when the target
+/// holds the junction itself or a tree already being walked.
+fn follow_junction(path: &Path, followed: &[PathBuf]) -> io::Result<Option<Vec<PathBuf>>> {
+    let target = resolve_path(path)?;
+    let parent = resolve_path(path.parent().unwrap_or(path))?;
+    if followed
+        .iter()
+        .chain(std::iter::once(&parent))
+        .any(|dir| dir.starts_with(&target))
+    {
+        return Ok(None);
+    }
+    let mut chain = followed.to_vec();
+    chain.push(target);
+    Ok(Some(chain))
+}
+
+/// An entry a walk found, with its metadata; None for a directory known by
+/// its type alone.
+type Found = (PathBuf, Option<io::Result<Metadata>>);
+/// A failed read, with the path being read when it is known.
+type WalkFailure = (Option<PathBuf>, io::Error);
+type DirId = (u64, u64);
+type WalkEntry = jwalk::DirEntry<(Vec<DirId>, Option<Metadata>)>;
+
+#[cfg(unix)]
+fn dir_id(metadata: &Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(_metadata: &Metadata) -> Option<DirId> {
+    None
+}
+
+/// Stats the entries of a directory on the walk's worker threads rather
+/// than one by one as they are planned.
+fn prefetch_metadata(children: &mut [jwalk::Result<WalkEntry>]) {
+    for entry in children.iter_mut().flatten() {
+        entry.client_state = entry.metadata().ok();
+    }
+}
+
+/// Stats what the batched walk can't plan from its type alone: everything
+/// but directories, which only need creating.
+#[cfg(target_os = "linux")]
+fn stat_files(entries: &mut [dirent::Entry]) {
+    for entry in entries
+        .iter_mut()
+        .filter(|entry| !entry.is_dir() && entry.metadata.is_none())
+    {
+        entry.metadata = Some(std::fs::symlink_metadata(&entry.path));
+    }
+}
+
+// `ancestors` is jwalk's per-directory state, inherited by each child read,
+// so it holds the (dev, inode) of every directory above the one being read.
+fn prune_symlink_loops(
+    path: &Path,
+    ancestors: &mut Vec<DirId>,
+    children: &mut Vec<jwalk::Result<WalkEntry>>,
+    loops: &Mutex<Vec<PathBuf>>,
+) {
+    if let Some(id) = std::fs::metadata(path).ok().as_ref().and_then(dir_id) {
+        ancestors.push(id);
+    }
+    children.retain(|child| {
+        let looped = match child {
+            Ok(entry) if entry.file_type.is_dir() => entry
+                .metadata()
+                .ok()
+                .as_ref()
+                .and_then(dir_id)
+                .is_some_and(|id| ancestors.contains(&id)),
+            Err(e) => e.loop_ancestor().is_some(),
+            Ok(_) => false,
+        };
+        if looped {
+            let path = match child {
+                Ok(entry) => Some(entry.path()),
+                Err(e) => e.path().map(Path::to_path_buf),
+            };
+            if let (Some(path), Ok(mut loops)) = (path, loops.lock()) {
+                loops.push(path);
+            }
+        }
+        !looped
+    });
+}
+
+/// Leaves out hidden entries under `--hidden=skip` and directories
+/// holding an exclude marker before the walk reads them, recording each
+/// with the reason.
+fn prune_excluded(
+    children: &mut Vec<jwalk::Result<WalkEntry>>,
+    markers: &ExcludeMarkers,
+    hidden: HiddenMode,
+    pruned: &Mutex<Vec<(PathBuf, String)>>,
+) {
+    children.retain(|child| {
+        let Ok(entry) = child else { return true };
+        let reason = prune_reason(
+            &entry.file_name,
+            entry.file_type.is_dir(),
+            entry.client_state.as_ref(),
+            || entry.path(),
+            markers,
+            hidden,
+        );
+        keep_unpruned(reason, || entry.path(), pruned)
+    });
+}
+
+/// Why an entry is left out before the walk reads it, if it is.
+fn prune_reason(
+    name: &OsStr,
+    is_dir: bool,
+    metadata: Option<&Metadata>,
+    path: impl FnOnce() -> PathBuf,
+    markers: &ExcludeMarkers,
+    hidden: HiddenMode,
+) -> Option<String> {
+    if hidden == HiddenMode::Skip && is_hidden(name, metadata) {
+        Some("hidden".to_string())
+    } else if is_dir {
+        markers
+            .find(&path())
+            .map(|marker| format!("contains {}", marker))
+    } else {
+        None
+    }
+}
+
+/// Whether an entry stays in the walk; one with a `reason` to leave it out
+/// is recorded in `pruned` instead.
+fn keep_unpruned(
+    reason: Option<String>,
+    path: impl FnOnce() -> PathBuf,
+    pruned: &Mutex<Vec<(PathBuf, String)>>,
+) -> bool {
+    match reason {
+        Some(reason) => {
+            if let Ok(mut pruned) = pruned.lock() {
+                pruned.push((path(), reason));
+            }
+            false
+        }
+        None => true,
+    }
+}
+
 pub fn preprocess_multiple(
     sources: &[PathBuf],
     destination: &Path,
@@ -495,66 +1470,114 @@ pub fn preprocess_multiple(
     }
 
     let mut plan = CopyPlan::new();
+    let mut seen: Vec<PathBuf> = Vec::new();
 
     for source in sources {
-        let metadata = match options.follow_symlink {
-            FollowSymlink::Dereference | FollowSymlink::CommandLineSymlink => {
-                std::fs::metadata(source)
-                    .map_err(|_e| CopyError::InvalidSource(source.to_path_buf()))?
+        let resolved = resolve_source(source);
+        if seen.contains(&resolved) {
+            output::warn(
+                options,
+                format!("Skipping duplicate source '{}'", source.display()),
+            );
+            continue;
+        }
+        if let Some(other) = seen
+            .iter()
+            .find(|s| resolved.starts_with(s) || s.starts_with(&resolved))
+        {
+            output::warn(
+                options,
+                format!(
+                    "Source '{}' overlaps with '{}'; shared files will be copied more than once",
+                    source.display(),
+                    other.display()
+                ),
+            );
+        }
+        seen.push(resolved);
+
+        if let Err(e) = preprocess_source(&mut plan, source, destination, options) {
+            if !options.ignore_errors {
+                return Err(e);
             }
-            FollowSymlink::NoDereference => std::fs::symlink_metadata(source)
-                .map_err(|_e| CopyError::InvalidSource(source.to_path_buf()))?,
-        };
+            plan.failures.push(FailedFile::new(source, destination, e));
+        }
+    }
 
-        if metadata.is_dir() {
-            let dir_plan =
-                preprocess_directory(source, source, destination, options).map_err(|e| {
-                    CopyError::CopyFailed {
-                        source: source.to_path_buf(),
-                        destination: destination.to_path_buf(),
-                        reason: e.to_string(),
-                    }
-                })?;
-            plan.merge(dir_plan);
-        } else {
-            let _source_root = source.parent().unwrap_or_else(|| Path::new("."));
+    let duplicates = plan.dedupe_destinations();
+    if duplicates > 0 {
+        output::warn(
+            options,
+            format!(
+                "Dropped {} tasks that targeted the same destination as another source",
+                duplicates
+            ),
+        );
+    }
 
-            let dest_path = if options.parents {
-                with_parents(destination, source)
-            } else {
-                destination.join(source.file_name().ok_or_else(|| CopyError::CopyFailed {
-                    source: source.to_path_buf(),
-                    destination: destination.to_path_buf(),
-                    reason: "Invalid source path".to_string(),
-                })?)
-            };
+    plan.sort_files(options.order);
+    Ok(plan)
+}
 
-            if options.parents
-                && let Some(parent) = dest_path.parent()
-            {
-                plan.add_directory(None, parent.to_path_buf());
+fn preprocess_source(
+    plan: &mut CopyPlan,
+    source: &Path,
+    destination: &Path,
+    options: &CopyOptions,
+) -> CopyResult<()> {
+    let metadata = match options.follow_symlink {
+        FollowSymlink::Dereference | FollowSymlink::CommandLineSymlink => std::fs::metadata(source)
+            .map_err(|_e| CopyError::InvalidSource(source.to_path_buf()))?,
+        FollowSymlink::NoDereference => std::fs::symlink_metadata(source)
+            .map_err(|_e| CopyError::InvalidSource(source.to_path_buf()))?,
+    };
+    let metadata = if options.junctions == JunctionMode::Follow
+        && metadata.file_type().is_symlink()
+        && junction::is_junction(source)
+    {
+        std::fs::metadata(source).map_err(|_e| CopyError::InvalidSource(source.to_path_buf()))?
+    } else {
+        metadata
+    };
+
+    if metadata.is_dir() {
+        let dir_plan = preprocess_directory(source, source, destination, options).map_err(|e| {
+            CopyError::CopyFailed {
+                source: source.to_path_buf(),
+                destination: destination.to_path_buf(),
+                reason: e.to_string(),
             }
+        })?;
+        plan.merge(dir_plan);
+    } else {
+        let _source_root = source.parent().unwrap_or_else(|| Path::new("."));
 
-            let mut inode_groups = None;
-            process_entry(
-                &mut plan,
-                source,
-                source,
-                dest_path.clone(),
-                &metadata,
-                options,
-                &mut inode_groups,
-            )
-            .map_err(|e| CopyError::CopyFailed {
+        let dest_path = if options.parents {
+            with_parents(destination, source)
+        } else {
+            destination.join(source.file_name().ok_or_else(|| CopyError::CopyFailed {
+                source: source.to_path_buf(),
+                destination: destination.to_path_buf(),
+                reason: "Invalid source path".to_string(),
+            })?)
+        };
+
+        if options.parents
+            && let Some(parent) = dest_path.parent()
+        {
+            plan.add_directory(None, parent.to_path_buf());
+        }
+
+        process_source_entry(plan, source, source, dest_path.clone(), &metadata, options).map_err(
+            |e| CopyError::CopyFailed {
                 source: source.to_path_buf(),
                 destination: dest_path.clone(),
                 reason: e.to_string(),
-            })?;
-        }
+            },
+        )?;
     }
 
-    plan.sort_files_descending();
-    Ok(plan)
+    Ok(())
 }
 
 #[cfg(test)]
@@ -581,12 +1604,40 @@ mod tests {
         create_test_file(&file1, content).unwrap();
         create_test_file(&file2, content).unwrap();
 
-        let hash1 = calculate_checksum(&file1).unwrap();
-        let hash2 = calculate_checksum(&file2).unwrap();
+        let hash1 = hash_file(&file1, HashAlgo::Xxh3).unwrap();
+        let hash2 = hash_file(&file2, HashAlgo::Xxh3).unwrap();
 
         assert_eq!(hash1, hash2);
     }
 
+    #[test]
+    fn test_dedupe_content_links_identical_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut plan = CopyPlan::new();
+        for (name, content) in [
+            ("a.jpg", "same bytes"),
+            ("b.jpg", "same bytes"),
+            ("c.jpg", "diff bytes"),
+            ("d.jpg", "short"),
+        ] {
+            let source = temp_dir.path().join(name);
+            create_test_file(&source, content.as_bytes()).unwrap();
+            plan.add_file(
+                source,
+                PathBuf::from("dst").join(name),
+                content.len() as u64,
+            );
+        }
+
+        assert_eq!(plan.dedupe_content(HashAlgo::Xxh3), 1);
+        assert_eq!(plan.files.len(), 3);
+        assert_eq!(plan.total_files, 4);
+        assert_eq!(plan.total_size, 25);
+        let duplicate = &plan.duplicates[0];
+        assert_eq!(duplicate.file.destination, PathBuf::from("dst/b.jpg"));
+        assert_eq!(duplicate.original, PathBuf::from("dst/a.jpg"));
+    }
+
     #[test]
     fn test_preprocess_directory() {
         let temp_dir = TempDir::new().unwrap();
@@ -607,6 +1658,240 @@ mod tests {
         assert!(!plan.directories.is_empty());
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn test_preprocess_directory_reads_hard_links_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let dest_dir = temp_dir.path().join("dest");
+        std_fs::create_dir_all(&source_dir).unwrap();
+        create_test_file(&source_dir.join("a.bin"), b"linked content").unwrap();
+        std_fs::hard_link(source_dir.join("a.bin"), source_dir.join("b.bin")).unwrap();
+
+        let options = CopyOptions::none();
+        let plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &options).unwrap();
+
+        assert_eq!(plan.total_files, 2);
+        assert_eq!(plan.total_size, 14);
+        assert_eq!(plan.files.len(), 1);
+        let duplicate = &plan.duplicates[0];
+        assert!(duplicate.separate);
+        assert_eq!(duplicate.original, plan.files[0].destination);
+    }
+
+    #[test]
+    fn test_preprocess_directory_skips_marked_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let dest_dir = temp_dir.path().join("dest");
+        for dir in ["kept", "private", "cache", "not-a-cache"] {
+            std_fs::create_dir_all(source_dir.join(dir)).unwrap();
+            create_test_file(&source_dir.join(dir).join("file.txt"), b"content").unwrap();
+        }
+        create_test_file(&source_dir.join("private/.nobackup"), b"").unwrap();
+        create_test_file(
+            &source_dir.join("cache/CACHEDIR.TAG"),
+            b"Signature: 8a477f597d28d172789f06886806bc55\n# a cache\n",
+        )
+        .unwrap();
+        create_test_file(&source_dir.join("not-a-cache/CACHEDIR.TAG"), b"").unwrap();
+
+        let mut options = CopyOptions::none();
+        options.exclude_markers = ExcludeMarkers {
+            files: vec![".nobackup".to_string()],
+            caches: true,
+        };
+        let plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &options).unwrap();
+        let mut planned: Vec<_> = plan
+            .files
+            .iter()
+            .map(|task| task.source.strip_prefix(&source_dir).unwrap().to_path_buf())
+            .collect();
+        planned.sort();
+        assert_eq!(
+            planned,
+            [
+                Path::new("kept/file.txt"),
+                Path::new("not-a-cache/CACHEDIR.TAG"),
+                Path::new("not-a-cache/file.txt"),
+            ]
+        );
+
+        // a marked source is left out as a whole
+        let plan = preprocess_directory(
+            &source_dir.join("private"),
+            &source_dir,
+            &dest_dir,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(plan.total_files, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preprocess_directory_skips_hidden_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let dest_dir = temp_dir.path().join("dest");
+        std_fs::create_dir_all(source_dir.join(".config")).unwrap();
+        create_test_file(&source_dir.join(".config/settings"), b"hidden").unwrap();
+        create_test_file(&source_dir.join(".env"), b"hidden").unwrap();
+        create_test_file(&source_dir.join("visible.txt"), b"shown").unwrap();
+
+        let mut options = CopyOptions::none();
+        let plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &options).unwrap();
+        assert_eq!(plan.total_files, 3);
+
+        options.hidden = HiddenMode::Skip;
+        let plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &options).unwrap();
+        assert_eq!(plan.total_files, 1);
+        assert_eq!(plan.files[0].source, source_dir.join("visible.txt"));
+        assert_eq!(plan.excluded, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preprocess_directory_prunes_empty_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let dest_dir = temp_dir.path().join("dest");
+        std_fs::create_dir_all(source_dir.join("empty/nested")).unwrap();
+        std_fs::create_dir_all(source_dir.join("only-hidden")).unwrap();
+        std_fs::create_dir_all(source_dir.join("kept/deep")).unwrap();
+        create_test_file(&source_dir.join("only-hidden/.env"), b"hidden").unwrap();
+        create_test_file(&source_dir.join("kept/deep/file.txt"), b"shown").unwrap();
+
+        let mut options = CopyOptions::none();
+        options.hidden = HiddenMode::Skip;
+        let plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &options).unwrap();
+        assert_eq!(plan.directories.len(), 6);
+
+        options.prune_empty_dirs = true;
+        let plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &options).unwrap();
+        let mut planned: Vec<_> = plan
+            .directories
+            .iter()
+            .map(|task| {
+                task.destination
+                    .strip_prefix(&dest_dir)
+                    .unwrap()
+                    .to_path_buf()
+            })
+            .collect();
+        planned.sort();
+        assert_eq!(
+            planned,
+            [
+                Path::new("source"),
+                Path::new("source/kept"),
+                Path::new("source/kept/deep"),
+            ]
+        );
+        assert_eq!(plan.total_files, 1);
+    }
+
+    #[test]
+    fn test_modify_window_tolerates_coarse_mtimes() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.txt");
+        let dest = temp_dir.path().join("dest.txt");
+        std_fs::write(&source, "content").unwrap();
+        std_fs::write(&dest, "CONTENT").unwrap();
+        let now = SystemTime::now();
+        // a destination rounded down by a FAT-like filesystem
+        filetime::set_file_mtime(&source, filetime::FileTime::from_system_time(now)).unwrap();
+        let rounded = now - Duration::from_millis(1500);
+        filetime::set_file_mtime(&dest, filetime::FileTime::from_system_time(rounded)).unwrap();
+
+        assert!(!is_newer_or_same(&source, &dest, Duration::ZERO));
+        assert!(!should_skip_file(&source, &dest, Duration::ZERO, HashAlgo::Xxh3).unwrap());
+        assert!(is_newer_or_same(&source, &dest, Duration::from_secs(2)));
+        assert!(should_skip_file(&source, &dest, Duration::from_secs(2), HashAlgo::Xxh3).unwrap());
+        assert!(!within_window(
+            now,
+            now - Duration::from_secs(3),
+            Duration::from_secs(2)
+        ));
+    }
+
+    #[test]
+    fn test_sort_files_orders() {
+        let mut plan = CopyPlan::new();
+        plan.add_file("b".into(), "dest/b".into(), 30);
+        plan.add_file("c".into(), "dest/c".into(), 10);
+        plan.add_file("a".into(), "dest/a".into(), 20);
+        let order = |plan: &CopyPlan| {
+            plan.files
+                .iter()
+                .map(|f| f.source.to_string_lossy().into_owned())
+                .collect::<String>()
+        };
+
+        plan.sort_files(FileOrder::None);
+        assert_eq!(order(&plan), "bca");
+        plan.sort_files(FileOrder::SizeAsc);
+        assert_eq!(order(&plan), "cab");
+        plan.sort_files(FileOrder::Path);
+        assert_eq!(order(&plan), "abc");
+        plan.sort_files(FileOrder::SizeDesc);
+        assert_eq!(order(&plan), "bac");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_preprocess_directory_leaves_out_unreadable_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let dest_dir = temp_dir.path().join("dest");
+
+        create_test_file(&source_dir.join("file.txt"), b"content").unwrap();
+        let locked = source_dir.join("locked");
+        create_test_file(&locked.join("secret.txt"), b"secret").unwrap();
+        std_fs::set_permissions(&locked, std_fs::Permissions::from_mode(0o000)).unwrap();
+        // root reads the directory regardless
+        let readable = std_fs::read_dir(&locked).is_ok();
+
+        let options = CopyOptions::none();
+        let plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &options);
+        let mut strict = CopyOptions::none();
+        strict.strict = true;
+        let strict_plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &strict);
+        std_fs::set_permissions(&locked, std_fs::Permissions::from_mode(0o755)).unwrap();
+        if readable {
+            return;
+        }
+
+        let plan = plan.unwrap();
+        assert_eq!(plan.total_files, 1);
+        assert_eq!(plan.unreadable, vec![locked]);
+        assert!(strict_plan.is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_preprocess_directory_stops_at_symlink_loop() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let dest_dir = temp_dir.path().join("dest");
+
+        create_test_file(&source_dir.join("a/file.txt"), b"content").unwrap();
+        std::os::unix::fs::symlink("..", source_dir.join("a/up")).unwrap();
+
+        let mut options = CopyOptions::none();
+        options.follow_symlink = FollowSymlink::Dereference;
+        let plan = preprocess_directory(&source_dir, &source_dir, &dest_dir, &options).unwrap();
+
+        assert_eq!(plan.total_files, 1);
+        assert!(
+            plan.directories
+                .iter()
+                .all(|d| !d.destination.ends_with("up"))
+        );
+    }
+
     #[test]
     fn test_preprocess_file_with_symlink_auto() {
         let temp_dir = TempDir::new().unwrap();
@@ -746,6 +2031,57 @@ mod tests {
         assert_eq!(plan.symlinks.len(), 2);
     }
 
+    #[test]
+    fn test_preprocess_multiple_skips_duplicate_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let dest_dir = temp_dir.path().join("dest");
+        std_fs::create_dir(&dest_dir).unwrap();
+
+        let source_dir = temp_dir.path().join("tree");
+        create_test_file(&source_dir.join("a.txt"), b"aaaa").unwrap();
+        create_test_file(&source_dir.join("sub/b.txt"), b"bb").unwrap();
+        let file = temp_dir.path().join("c.txt");
+        create_test_file(&file, b"c").unwrap();
+
+        let sources = vec![
+            source_dir.clone(),
+            temp_dir.path().join("tree/"),
+            file.clone(),
+            temp_dir.path().join("./c.txt"),
+        ];
+
+        let mut options = CopyOptions::none();
+        options.recursive = true;
+        let plan = preprocess_multiple(&sources, &dest_dir, &options).unwrap();
+
+        assert_eq!(plan.total_files, 3);
+        assert_eq!(plan.total_size, 7);
+    }
+
+    #[test]
+    fn test_dedupe_destinations_keeps_last() {
+        let mut plan = CopyPlan::new();
+        plan.files.push(FileTask {
+            source: PathBuf::from("first/x"),
+            destination: PathBuf::from("dest/x"),
+            size: 10,
+            inode_group: None,
+        });
+        plan.files.push(FileTask {
+            source: PathBuf::from("second/x"),
+            destination: PathBuf::from("dest/x"),
+            size: 3,
+            inode_group: None,
+        });
+        plan.total_files = 2;
+        plan.total_size = 13;
+
+        assert_eq!(plan.dedupe_destinations(), 1);
+        assert_eq!(plan.total_files, 1);
+        assert_eq!(plan.total_size, 3);
+        assert_eq!(plan.files[0].source, PathBuf::from("second/x"));
+    }
+
     #[test]
     fn test_preprocess_file_normal_copy_mode() {
         let temp_dir = TempDir::new().unwrap();
@@ -788,4 +2124,35 @@ mod tests {
         assert_eq!(plan.symlinks[0].source, source);
         assert_eq!(plan.symlinks[0].destination, dest);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_junction_refuses_loops() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("root");
+        let outside = temp_dir.path().join("outside");
+        std_fs::create_dir_all(root.join("a")).unwrap();
+        std_fs::create_dir_all(&outside).unwrap();
+        // symlinks resolve like junctions do
+        std::os::unix::fs::symlink(&root, root.join("a/up")).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("out")).unwrap();
+        std::os::unix::fs::symlink(&root, outside.join("back")).unwrap();
+
+        let followed = vec![resolve_path(&root).unwrap()];
+        assert!(
+            follow_junction(&root.join("a/up"), &followed)
+                .unwrap()
+                .is_none()
+        );
+
+        let chain = follow_junction(&root.join("out"), &followed)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chain.len(), 2);
+        assert!(
+            follow_junction(&root.join("out/back"), &chain)
+                .unwrap()
+                .is_none()
+        );
+    }
 }
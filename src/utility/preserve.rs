@@ -1,8 +1,12 @@
 use crate::error::{PreserveError, PreserveResult};
+use crate::utility::chattr::Chattr;
+use crate::utility::chmod::Chmod;
 use std::collections::HashMap;
 use std::io;
 use std::path::{Path, PathBuf};
 
+#[cfg(unix)]
+use crate::utility::chattr;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
@@ -14,6 +18,86 @@ pub struct PreserveAttr {
     pub links: bool,
     pub context: bool,
     pub xattr: bool,
+    /// Windows attribute bits: read-only, hidden, system and archive.
+    pub attrs: bool,
+    /// Creation (birth) time, where the destination's filesystem lets it be set.
+    pub crtimes: bool,
+    /// Blocks shared between source files (reflinks), cloned again between
+    /// their copies where the destination supports it. Linux only.
+    pub extents: bool,
+    /// File attribute flags: on Linux those `lsattr` lists, such as `C` (no
+    /// copy-on-write), `c` (compress), `a` (append-only) and `i`
+    /// (immutable); on macOS and FreeBSD the `chflags` flags, such as `uchg`,
+    /// `hidden` and `nodump`, kept by `-p` as `cp -p` keeps them. Flags only
+    /// a privileged process may set are copied where permitted.
+    pub flags: bool,
+    /// Owner forced onto every copy (`--chown`), whatever the source's.
+    pub chown: Option<Chown>,
+    /// Permission changes applied on top of the mode (`--chmod`).
+    pub chmod: Option<Chmod>,
+    /// Attribute flags set on or cleared from every copy (`--set-attr`).
+    pub set_attr: Option<Chattr>,
+}
+
+/// `--chown USER:GROUP`: either half may be left out to keep that id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chown {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl std::str::FromStr for Chown {
+    type Err = String;
+
+    /// Accepts `USER`, `USER:GROUP`, `USER:` and `:GROUP`, by name or
+    /// numeric id.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user, group) = s.split_once(':').unwrap_or((s, ""));
+        let chown = Chown {
+            uid: (!user.is_empty()).then(|| lookup_user(user)).transpose()?,
+            gid: (!group.is_empty())
+                .then(|| lookup_group(group))
+                .transpose()?,
+        };
+        if chown.uid.is_none() && chown.gid.is_none() {
+            return Err("expected USER, USER:GROUP or :GROUP".to_string());
+        }
+        Ok(chown)
+    }
+}
+
+#[cfg(unix)]
+fn lookup_user(name: &str) -> Result<u32, String> {
+    if let Ok(uid) = name.parse() {
+        return Ok(uid);
+    }
+    match nix::unistd::User::from_name(name) {
+        Ok(Some(user)) => Ok(user.uid.as_raw()),
+        Ok(None) => Err(format!("no such user '{}'", name)),
+        Err(e) => Err(format!("cannot look up user '{}': {}", name, e)),
+    }
+}
+
+#[cfg(unix)]
+fn lookup_group(name: &str) -> Result<u32, String> {
+    if let Ok(gid) = name.parse() {
+        return Ok(gid);
+    }
+    match nix::unistd::Group::from_name(name) {
+        Ok(Some(group)) => Ok(group.gid.as_raw()),
+        Ok(None) => Err(format!("no such group '{}'", name)),
+        Err(e) => Err(format!("cannot look up group '{}': {}", name, e)),
+    }
+}
+
+#[cfg(not(unix))]
+fn lookup_user(_name: &str) -> Result<u32, String> {
+    Err("--chown is only supported on Unix".to_string())
+}
+
+#[cfg(not(unix))]
+fn lookup_group(_name: &str) -> Result<u32, String> {
+    Err("--chown is only supported on Unix".to_string())
 }
 
 impl Default for PreserveAttr {
@@ -25,6 +109,13 @@ impl Default for PreserveAttr {
             links: false,
             context: false,
             xattr: false,
+            attrs: true,
+            crtimes: false,
+            extents: false,
+            flags: cfg!(any(target_os = "macos", target_os = "freebsd")),
+            chown: None,
+            chmod: None,
+            set_attr: None,
         }
     }
 }
@@ -38,6 +129,22 @@ impl PreserveAttr {
             links: false,
             context: false,
             xattr: false,
+            attrs: false,
+            crtimes: false,
+            extents: false,
+            flags: false,
+            chown: None,
+            chmod: None,
+            set_attr: None,
+        }
+    }
+
+    /// What is kept without `-p`: the Windows attribute bits, unless
+    /// `--no-preserve=attrs` turns them off.
+    pub fn implicit() -> Self {
+        Self {
+            attrs: cfg!(windows),
+            ..Self::none()
         }
     }
 
@@ -49,6 +156,13 @@ impl PreserveAttr {
             links: true,
             context: true,
             xattr: true,
+            attrs: true,
+            crtimes: true,
+            extents: true,
+            flags: true,
+            chown: None,
+            chmod: None,
+            set_attr: None,
         }
     }
 
@@ -61,8 +175,30 @@ impl PreserveAttr {
             return Ok(Self::all());
         }
 
-        let mut attr = Self::none();
+        Self::parse_list(Self::implicit(), s)
+    }
+
+    /// `--no-preserve`: clears the attributes named in `s`.
+    pub fn without(self, s: &str) -> PreserveResult<Self> {
+        let dropped = Self::parse_list(Self::none(), s)?;
+        Ok(Self {
+            mode: self.mode && !dropped.mode,
+            ownership: self.ownership && !dropped.ownership,
+            timestamps: self.timestamps && !dropped.timestamps,
+            links: self.links && !dropped.links,
+            context: self.context && !dropped.context,
+            xattr: self.xattr && !dropped.xattr,
+            attrs: self.attrs && !dropped.attrs,
+            crtimes: self.crtimes && !dropped.crtimes,
+            extents: self.extents && !dropped.extents,
+            flags: self.flags && !dropped.flags,
+            chown: self.chown,
+            chmod: self.chmod,
+            set_attr: self.set_attr,
+        })
+    }
 
+    fn parse_list(mut attr: Self, s: &str) -> PreserveResult<Self> {
         for cur in s.split(',') {
             match cur.trim() {
                 "" => continue,
@@ -72,6 +208,10 @@ impl PreserveAttr {
                 "xattr" => attr.xattr = true,
                 "context" => attr.context = true,
                 "links" => attr.links = true,
+                "attrs" => attr.attrs = true,
+                "crtimes" => attr.crtimes = true,
+                "extents" => attr.extents = true,
+                "flags" => attr.flags = true,
                 "all" => return Ok(Self::all()),
                 other => {
                     return Err(PreserveError::UnsupportedAttribute(format!(
@@ -103,6 +243,12 @@ pub fn apply_preserve_attrs(
             }
         })?;
     }
+    if attrs.crtimes {
+        preserve_birth_time(source, destination).map_err(|_e| PreserveError::FailedToPreserve {
+            path: destination.to_path_buf(),
+            attribute: "crtimes".to_string(),
+        })?;
+    }
     #[cfg(unix)]
     if attrs.mode {
         preserve_mode(destination, &src_metadata).map_err(|_e| {
@@ -123,6 +269,22 @@ pub fn apply_preserve_attrs(
         })?;
     }
 
+    #[cfg(unix)]
+    if let Some(owner) = attrs.chown {
+        force_owner(destination, owner).map_err(|_e| PreserveError::FailedToPreserve {
+            path: destination.to_path_buf(),
+            attribute: "chown".to_string(),
+        })?;
+    }
+
+    #[cfg(unix)]
+    if let Some(chmod) = attrs.chmod {
+        change_mode(destination, chmod).map_err(|_e| PreserveError::FailedToPreserve {
+            path: destination.to_path_buf(),
+            attribute: "chmod".to_string(),
+        })?;
+    }
+
     #[cfg(unix)]
     if attrs.xattr {
         preserve_xattr(source, destination).map_err(|_e| PreserveError::FailedToPreserve {
@@ -139,6 +301,83 @@ pub fn apply_preserve_attrs(
         })?;
     }
 
+    // after everything else: an immutable or append-only file refuses
+    // further changes
+    #[cfg(unix)]
+    if attrs.flags || attrs.set_attr.is_some() {
+        chattr::apply(attrs.flags.then_some(source), destination, attrs.set_attr).map_err(
+            |_e| PreserveError::FailedToPreserve {
+                path: destination.to_path_buf(),
+                attribute: "flags".to_string(),
+            },
+        )?;
+    }
+
+    // last: a read-only bit would refuse the writes above
+    #[cfg(windows)]
+    if attrs.attrs {
+        preserve_file_attributes(destination, &src_metadata).map_err(|_e| {
+            PreserveError::FailedToPreserve {
+                path: destination.to_path_buf(),
+                attribute: "attrs".to_string(),
+            }
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Attributes recorded in an archive entry instead of on a source file.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ArchivedAttrs {
+    pub mode: Option<u32>,
+    /// Seconds since the Unix epoch.
+    pub mtime: Option<i64>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// [`apply_preserve_attrs`] for files unpacked from an archive.
+pub fn apply_archived_attrs(
+    destination: &Path,
+    archived: &ArchivedAttrs,
+    attrs: PreserveAttr,
+) -> PreserveResult<()> {
+    let failed = |attribute: &str| PreserveError::FailedToPreserve {
+        path: destination.to_path_buf(),
+        attribute: attribute.to_string(),
+    };
+    if attrs.timestamps
+        && let Some(mtime) = archived.mtime
+    {
+        filetime::set_file_mtime(destination, filetime::FileTime::from_unix_time(mtime, 0))
+            .map_err(|_e| failed("timestamps"))?;
+    }
+    #[cfg(unix)]
+    if attrs.ownership
+        && let (Some(uid), Some(gid)) = (archived.uid, archived.gid)
+    {
+        chown(destination, uid, gid).map_err(|_e| failed("ownership"))?;
+    }
+    #[cfg(unix)]
+    if let Some(owner) = attrs.chown {
+        force_owner(destination, owner).map_err(|_e| failed("chown"))?;
+    }
+    #[cfg(unix)]
+    if attrs.mode
+        && let Some(mode) = archived.mode
+    {
+        std::fs::set_permissions(destination, std::fs::Permissions::from_mode(mode & 0o7777))
+            .map_err(|_e| failed("mode"))?;
+    }
+    #[cfg(unix)]
+    if let Some(chmod) = attrs.chmod {
+        change_mode(destination, chmod).map_err(|_e| failed("chmod"))?;
+    }
+    #[cfg(unix)]
+    if attrs.set_attr.is_some() {
+        chattr::apply(None, destination, attrs.set_attr).map_err(|_e| failed("flags"))?;
+    }
     Ok(())
 }
 
@@ -154,6 +393,91 @@ fn preserve_timestamps(destination: &Path, src_metadata: &std::fs::Metadata) ->
     Ok(())
 }
 
+/// Sets the destination's creation time to the source's birth time. A
+/// source without one (e.g. on ext4 before statx) leaves it alone.
+#[cfg(any(windows, target_os = "macos"))]
+fn preserve_birth_time(source: &Path, destination: &Path) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    use std::os::macos::fs::FileTimesExt;
+    #[cfg(windows)]
+    use std::os::windows::fs::{FileTimesExt, OpenOptionsExt};
+
+    let Some(birth_time) = super::statx::statx(source, true)?.birth_time else {
+        return Ok(());
+    };
+    // setattrlist on macOS doesn't need a writable descriptor; SetFileTime
+    // needs FILE_WRITE_ATTRIBUTES, and directories BACKUP_SEMANTICS
+    #[cfg(target_os = "macos")]
+    let file = std::fs::File::open(destination)?;
+    #[cfg(windows)]
+    let file = {
+        use windows_sys::Win32::Storage::FileSystem::{
+            FILE_FLAG_BACKUP_SEMANTICS, FILE_WRITE_ATTRIBUTES,
+        };
+        std::fs::OpenOptions::new()
+            .access_mode(FILE_WRITE_ATTRIBUTES)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(destination)?
+    };
+    file.set_times(std::fs::FileTimes::new().set_created(birth_time))
+}
+
+/// Linux can read a birth time through statx but has no call that sets
+/// one, so there is nothing to do.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn preserve_birth_time(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(windows)]
+fn preserve_file_attributes(
+    destination: &Path,
+    src_metadata: &std::fs::Metadata,
+) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::fs::MetadataExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_ATTRIBUTE_ARCHIVE, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_NORMAL,
+        FILE_ATTRIBUTE_READONLY, FILE_ATTRIBUTE_SYSTEM, SetFileAttributesW,
+    };
+
+    const COPIED: u32 = FILE_ATTRIBUTE_READONLY
+        | FILE_ATTRIBUTE_HIDDEN
+        | FILE_ATTRIBUTE_SYSTEM
+        | FILE_ATTRIBUTE_ARCHIVE;
+    // bits such as NOT_CONTENT_INDEXED stay as the destination has them;
+    // ones SetFileAttributesW can't set (compressed, sparse, ...) are ignored
+    let current = std::fs::symlink_metadata(destination)?.file_attributes();
+    let mut wanted = (current & !COPIED) | (src_metadata.file_attributes() & COPIED);
+    if wanted == 0 {
+        wanted = FILE_ATTRIBUTE_NORMAL;
+    }
+    let wide: Vec<u16> = destination
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    if unsafe { SetFileAttributesW(wide.as_ptr(), wanted) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Removes `path` for `--force`, clearing the read-only attribute first:
+/// Windows refuses to delete read-only files.
+pub fn remove_forced(path: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        let mut permissions = metadata.permissions();
+        if permissions.readonly() {
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            let _ = std::fs::set_permissions(path, permissions);
+        }
+    }
+    std::fs::remove_file(path)
+}
+
 #[cfg(unix)]
 fn preserve_mode(destination: &Path, src_metadata: &std::fs::Metadata) -> io::Result<()> {
     use std::fs::Permissions;
@@ -170,9 +494,29 @@ fn preserve_mode(destination: &Path, src_metadata: &std::fs::Metadata) -> io::Re
 fn preserve_ownership(destination: &Path, src_metadata: &std::fs::Metadata) -> io::Result<()> {
     use std::os::unix::fs::MetadataExt;
 
-    let uid = src_metadata.uid();
-    let gid = src_metadata.gid();
+    chown(destination, src_metadata.uid(), src_metadata.gid())
+}
+
+/// `--chown`: unlike preserving ownership, not being allowed to is an error.
+#[cfg(unix)]
+fn force_owner(destination: &Path, owner: Chown) -> io::Result<()> {
+    std::os::unix::fs::lchown(destination, owner.uid, owner.gid)
+}
+
+/// `--chmod`, on whatever mode the copy has by now. Symlinks have no
+/// mode of their own and are left alone.
+#[cfg(unix)]
+fn change_mode(destination: &Path, chmod: Chmod) -> io::Result<()> {
+    let metadata = std::fs::symlink_metadata(destination)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    let mode = chmod.apply(metadata.permissions().mode(), metadata.is_dir());
+    std::fs::set_permissions(destination, std::fs::Permissions::from_mode(mode & 0o7777))
+}
 
+#[cfg(unix)]
+fn chown(destination: &Path, uid: u32, gid: u32) -> io::Result<()> {
     // Note: This requires elevated privileges (root) to work in most cases
     // We'll attempt it but won't fail if it doesn't work
     let dest_cstring = std::ffi::CString::new(destination.to_string_lossy().as_bytes())
@@ -323,6 +667,85 @@ mod tests {
         assert!(attr.links);
         assert!(attr.context);
         assert!(attr.xattr);
+        assert!(attr.crtimes);
+        assert!(attr.flags);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_chown_parses_names_and_ids() {
+        assert_eq!(
+            "0:0".parse::<Chown>().unwrap(),
+            Chown {
+                uid: Some(0),
+                gid: Some(0)
+            }
+        );
+        assert_eq!(
+            "root:".parse::<Chown>().unwrap(),
+            Chown {
+                uid: Some(0),
+                gid: None
+            }
+        );
+        assert_eq!(":0".parse::<Chown>().unwrap().uid, None);
+        assert!(":".parse::<Chown>().is_err());
+        assert!("no-such-user-here".parse::<Chown>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_chown_is_applied_after_copying() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.txt");
+        let dest = temp_dir.path().join("dest.txt");
+        fs::write(&source, b"test").unwrap();
+        fs::write(&dest, b"test").unwrap();
+
+        // the current group is one any user may set
+        let gid = fs::metadata(&dest).unwrap().gid();
+        let attrs = PreserveAttr {
+            chown: Some(Chown {
+                uid: None,
+                gid: Some(gid),
+            }),
+            ..PreserveAttr::none()
+        };
+        apply_preserve_attrs(&source, &dest, attrs).unwrap();
+        assert_eq!(fs::metadata(&dest).unwrap().gid(), gid);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_chmod_is_applied_on_top_of_the_mode() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.txt");
+        let dest = temp_dir.path().join("dest.txt");
+        fs::write(&source, b"test").unwrap();
+        fs::write(&dest, b"test").unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o777)).unwrap();
+
+        let attrs = PreserveAttr {
+            mode: true,
+            chmod: Some("Dg+s,Fgo-w".parse().unwrap()),
+            ..PreserveAttr::none()
+        };
+        apply_preserve_attrs(&source, &dest, attrs).unwrap();
+        assert_eq!(
+            fs::metadata(&dest).unwrap().permissions().mode() & 0o7777,
+            0o755
+        );
+    }
+
+    #[test]
+    fn test_preserve_attr_crtimes() {
+        let attr = PreserveAttr::from_string("timestamps,crtimes").unwrap();
+        assert!(attr.timestamps);
+        assert!(attr.crtimes);
+        assert!(!PreserveAttr::default().crtimes);
+        assert!(!PreserveAttr::all().without("crtimes").unwrap().crtimes);
     }
 
     #[test]
@@ -356,6 +779,33 @@ mod tests {
         assert!(!attr.ownership);
     }
 
+    #[test]
+    fn test_preserve_attr_windows_attrs() {
+        assert!(PreserveAttr::from_string("attrs").unwrap().attrs);
+        assert!(PreserveAttr::all().attrs);
+        assert!(!PreserveAttr::none().attrs);
+        assert_eq!(PreserveAttr::implicit().attrs, cfg!(windows));
+        assert_eq!(
+            PreserveAttr::from_string("mode").unwrap().attrs,
+            cfg!(windows)
+        );
+    }
+
+    #[test]
+    fn test_preserve_attr_without() {
+        let attr = PreserveAttr::all().without("mode,attrs").unwrap();
+        assert!(!attr.mode);
+        assert!(!attr.attrs);
+        assert!(attr.timestamps);
+        assert!(attr.xattr);
+
+        assert_eq!(
+            PreserveAttr::default().without("all").unwrap(),
+            PreserveAttr::none()
+        );
+        assert!(PreserveAttr::default().without("bogus").is_err());
+    }
+
     #[test]
     fn test_preserve_attr_from_string_invalid() {
         let result = PreserveAttr::from_string("mode,invalid_attr");
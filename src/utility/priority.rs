@@ -0,0 +1,125 @@
+use crate::cli::args::IoPriority;
+use std::io;
+
+/// Lowers the process's CPU priority to niceness `nice` and its disk
+/// priority to `io_priority`. Called before any threads start: Linux keeps
+/// both per thread, and new threads take them from the one that made them.
+pub fn lower_priority(nice: i32, io_priority: IoPriority) -> io::Result<()> {
+    if nice > 0 {
+        set_nice(nice)?;
+    }
+    if io_priority != IoPriority::Normal {
+        set_io_priority(io_priority)?;
+    }
+    Ok(())
+}
+
+/// Never raises the priority: a process already niced further is left so.
+#[cfg(unix)]
+fn set_nice(nice: i32) -> io::Result<()> {
+    use nix::errno::Errno;
+
+    // -1 is a valid niceness, so only errno tells a failure apart
+    Errno::clear();
+    let current = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+    if current == -1 && Errno::last_raw() != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if nice > current && unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Windows has priority classes rather than nice levels.
+#[cfg(windows)]
+fn set_nice(nice: i32) -> io::Result<()> {
+    use windows_sys::Win32::System::Threading::{
+        BELOW_NORMAL_PRIORITY_CLASS, GetCurrentProcess, IDLE_PRIORITY_CLASS, SetPriorityClass,
+    };
+
+    let class = if nice >= 10 {
+        IDLE_PRIORITY_CLASS
+    } else {
+        BELOW_NORMAL_PRIORITY_CLASS
+    };
+    if unsafe { SetPriorityClass(GetCurrentProcess(), class) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn set_nice(_nice: i32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--nice is not supported on this platform",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn set_io_priority(io_priority: IoPriority) -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    /// Lowest of the best-effort levels 0-7.
+    const IOPRIO_BE_LOWEST: libc::c_int = 7;
+
+    let value = match io_priority {
+        IoPriority::Normal => return Ok(()),
+        IoPriority::Low => (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | IOPRIO_BE_LOWEST,
+        IoPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+    };
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, value) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn set_io_priority(io_priority: IoPriority) -> io::Result<()> {
+    const IOPOL_TYPE_DISK: libc::c_int = 0;
+    const IOPOL_SCOPE_PROCESS: libc::c_int = 0;
+    const IOPOL_THROTTLE: libc::c_int = 3;
+    const IOPOL_UTILITY: libc::c_int = 4;
+    unsafe extern "C" {
+        fn setiopolicy_np(
+            iotype: libc::c_int,
+            scope: libc::c_int,
+            policy: libc::c_int,
+        ) -> libc::c_int;
+    }
+
+    let policy = match io_priority {
+        IoPriority::Normal => return Ok(()),
+        IoPriority::Low => IOPOL_UTILITY,
+        IoPriority::Idle => IOPOL_THROTTLE,
+    };
+    if unsafe { setiopolicy_np(IOPOL_TYPE_DISK, IOPOL_SCOPE_PROCESS, policy) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Background mode lowers disk and memory priority together, and the CPU
+/// priority with them; Windows has no finer-grained switch for a process.
+#[cfg(windows)]
+fn set_io_priority(_io_priority: IoPriority) -> io::Result<()> {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, PROCESS_MODE_BACKGROUND_BEGIN, SetPriorityClass,
+    };
+
+    if unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn set_io_priority(_io_priority: IoPriority) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--ionice is not supported on this platform",
+    ))
+}
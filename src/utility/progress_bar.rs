@@ -1,5 +1,21 @@
+use crate::utility::eta::Eta;
+use crate::utility::throughput::{self, Throughput};
 use clap::ValueEnum;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{
+    HumanBytes, HumanCount, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressFinish,
+    ProgressState, ProgressStyle, TermLike,
+};
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often a plain status line is printed, unless configured.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+/// Bar redraws per second, unless configured; indicatif's own default.
+pub const REFRESH_HZ: u8 = 20;
 
 fn colorize(token: &str, color: &str) -> String {
     match color {
@@ -13,60 +29,550 @@ fn colorize(token: &str, color: &str) -> String {
 #[derive(Debug, Clone)]
 pub struct ProgressOptions {
     pub style: ProgressBarStyle,
+    pub mode: ProgressMode,
     pub filled: String,
     pub empty: String,
     pub head: String,
     pub bar_color: String,
     pub message_color: String,
+    /// Bars shown under the overall bar, one per worker up to this many,
+    /// with the file each is copying; 0 shows none.
+    pub file_bars: usize,
+    /// Most bar redraws per second.
+    pub refresh_hz: u8,
+    /// Least time between plain status lines.
+    pub status_interval: Duration,
 }
 impl ProgressOptions {
-    pub fn apply(&self, pb: &ProgressBar, total_files: usize) {
+    pub fn is_json(&self) -> bool {
+        matches!(self.style, ProgressBarStyle::Json)
+    }
+
+    /// Builds the overall progress bar for the configured mode, or `None`
+    /// when progress output is disabled.
+    /// The detailed style adds a line with the `throughput` history. The
+    /// ETA comes from `eta`, which counts files left as well as bytes.
+    pub fn create_bar(
+        &self,
+        total_bytes: u64,
+        total_files: usize,
+        throughput: Arc<Throughput>,
+        eta: Arc<Eta>,
+    ) -> Option<ProgressBar> {
+        let (target, plain) = self.draw_target()?;
+        // styled while hidden so the first line drawn is already complete
+        let pb = ProgressBar::hidden();
+        pb.set_length(total_bytes);
+        if plain {
+            self.apply_plain(&pb, total_files, eta);
+        } else {
+            self.apply(&pb, total_files, Some(throughput), eta);
+        }
+        pb.set_draw_target(target);
+        Some(pb)
+    }
+
+    /// The display worker bars are added to alongside the overall bar, or
+    /// `None` when they are off or output is plain status lines.
+    pub fn create_multi(&self) -> Option<MultiProgress> {
+        if self.file_bars == 0 {
+            return None;
+        }
+        match self.draw_target()? {
+            (_, true) => None,
+            (target, false) => Some(MultiProgress::with_draw_target(target)),
+        }
+    }
+
+    /// A worker's bar: bytes written with the file name after them.
+    pub fn file_bar_style(&self) -> ProgressStyle {
+        ProgressStyle::default_bar()
+            .template(&self.file_bar_template())
+            .unwrap()
+            .progress_chars(&format!("{}{}{}", self.filled, self.head, self.empty))
+    }
+
+    /// The detailed style adds the file's speed, time so far and ETA.
+    fn file_bar_template(&self) -> String {
+        let stats = match self.style {
+            ProgressBarStyle::Detailed => {
+                " {binary_bytes_per_sec:>12} {elapsed_precise} ETA {eta_precise}"
+            }
+            _ => "",
+        };
+        format!(
+            "  {{bar:20}} {{binary_bytes:>10}}/{{binary_total_bytes:<10}}{} {}",
+            stats,
+            colorize("wide_msg", &self.message_color)
+        )
+    }
+
+    /// Builds the spinner shown while the sources are walked, redrawn from
+    /// `scan` until it is cleared, or `None` when progress output is disabled.
+    pub fn create_spinner(&self, scan: Arc<ScanProgress>) -> Option<ProgressBar> {
+        let (target, plain) = self.draw_target()?;
+        let template = if plain {
+            // the key writes the whole line, so none is printed before the
+            // walk has counted something
+            "{scan}".to_string()
+        } else {
+            format!(
+                "{{spinner}} {} {{scan}}",
+                colorize("msg", &self.message_color)
+            )
+        };
+        let style = ProgressStyle::default_spinner()
+            .template(&template)
+            .unwrap()
+            .with_key("scan", move |_: &ProgressState, w: &mut dyn fmt::Write| {
+                if !plain {
+                    let _ = w.write_str(&scan.status());
+                } else if !scan.is_empty() {
+                    let _ = write!(w, "Scanning {}", scan.status());
+                }
+            });
+        let pb = ProgressBar::hidden()
+            .with_style(style)
+            .with_message("Scanning")
+            .with_finish(ProgressFinish::AndClear);
+        pb.set_draw_target(target);
+        // the spinner ticks no faster than bars may redraw
+        let tick = Duration::from_secs(1) / u32::from(self.refresh_hz.max(1));
+        pb.enable_steady_tick(tick.max(Duration::from_millis(100)));
+        Some(pb)
+    }
+
+    /// Where bars go in the configured mode, and whether that target takes
+    /// plain status lines rather than a live bar.
+    fn draw_target(&self) -> Option<(ProgressDrawTarget, bool)> {
+        let interactive = io::stderr().is_terminal();
+        let plain = match self.mode {
+            ProgressMode::Never => return None,
+            ProgressMode::Auto => !interactive,
+            ProgressMode::Always => false,
+            ProgressMode::Plain => true,
+        };
+        if plain {
+            let status = PlainStatus::new(self.status_interval);
+            return Some((
+                ProgressDrawTarget::term_like_with_hz(Box::new(status), 1),
+                true,
+            ));
+        }
+        let hz = self.refresh_hz.max(1);
+        let target = if interactive {
+            ProgressDrawTarget::stderr_with_hz(hz)
+        } else {
+            ProgressDrawTarget::term_like_with_hz(Box::new(console::Term::stderr()), hz)
+        };
+        Some((target, false))
+    }
+
+    /// Uncoloured, bar-less template for log files and CI output.
+    fn apply_plain(&self, pb: &ProgressBar, total_files: usize, eta: Arc<Eta>) {
+        let template = match self.style {
+            ProgressBarStyle::Detailed => {
+                "{msg} {prefix} {percent}% {binary_bytes}/{binary_total_bytes} \
+                 {binary_bytes_per_sec} elapsed {elapsed_precise} ETA {eta}"
+            }
+            _ => "{msg} {prefix} {percent}% {binary_bytes}/{binary_total_bytes} ETA {eta}",
+        };
+        pb.set_style(with_eta(
+            ProgressStyle::default_bar().template(template).unwrap(),
+            eta,
+        ));
+        self.set_initial_message(pb, total_files);
+    }
+
+    pub fn apply(
+        &self,
+        pb: &ProgressBar,
+        total_files: usize,
+        throughput: Option<Arc<Throughput>>,
+        eta: Arc<Eta>,
+    ) {
         let bar = colorize("wide_bar", &self.bar_color);
         let msg = colorize("msg", &self.message_color);
 
         let template = match self.style {
-            ProgressBarStyle::Default => {
-                format!("{} {{percent}}% {} ETA:{{eta_precise}}", msg, bar)
+            ProgressBarStyle::Default | ProgressBarStyle::Json => {
+                format!("{} {{prefix}} {{percent}}% {} ETA:{{eta}}", msg, bar)
             }
             ProgressBarStyle::Detailed => format!(
-                "{} {} {{percent:>3}}% • {{binary_bytes}}/{{binary_total_bytes}} • \
-                 {{binary_bytes_per_sec}} • Elapsed: {{elapsed_precise}} • ETA:{{eta_precise}}",
+                "{} {{prefix}} {} {{percent:>3}}% • {{binary_bytes}}/{{binary_total_bytes}} • \
+                 {{binary_bytes_per_sec}} • Elapsed: {{elapsed_precise}} • ETA:{{eta}}",
                 msg, bar
             ),
         };
 
+        let throughput = throughput.filter(|_| self.style == ProgressBarStyle::Detailed);
+        let template = match throughput {
+            Some(_) => format!("{}\n{{throughput}}", template),
+            None => template,
+        };
         let chars = format!("{}{}{}", self.filled, self.head, self.empty);
 
-        let style = ProgressStyle::default_bar()
-            .template(&template)
-            .unwrap()
-            .progress_chars(&chars);
+        let mut style = with_eta(
+            ProgressStyle::default_bar()
+                .template(&template)
+                .unwrap()
+                .progress_chars(&chars),
+            eta,
+        );
+        if let Some(throughput) = throughput {
+            style = style.with_key(
+                "throughput",
+                move |state: &ProgressState, w: &mut dyn fmt::Write| {
+                    let average = state.pos() as f64 / state.elapsed().as_secs_f64().max(1e-3);
+                    let line =
+                        throughput::format_line(&throughput.history(), average, throughput.peak());
+                    let _ = w.write_str(&line);
+                },
+            );
+        }
 
         pb.set_style(style);
+        self.set_initial_message(pb, total_files);
+    }
 
-        pb.set_message(match self.style {
-            ProgressBarStyle::Detailed => format!("Copying: 0/{} files", total_files),
-            _ => "Copying".to_string(),
-        });
+    fn set_initial_message(&self, pb: &ProgressBar, total_files: usize) {
+        pb.set_prefix(format_file_count(0, total_files));
+        pb.set_message("Copying");
     }
 }
 
-#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+/// Adds the `{eta}` key the overall bar templates use in place of
+/// indicatif's byte-rate `{eta_precise}`.
+fn with_eta(style: ProgressStyle, eta: Arc<Eta>) -> ProgressStyle {
+    style.with_key(
+        "eta",
+        move |state: &ProgressState, w: &mut dyn fmt::Write| eta.write(state, w),
+    )
+}
+
+/// The `N/M files` counter shown next to the byte progress.
+pub fn format_file_count(finished: usize, total_files: usize) -> String {
+    format!("{}/{} files", finished, total_files)
+}
+
+/// Live counters from walking the sources, updated by the traversal and
+/// read by the scan spinner.
+#[derive(Debug, Default)]
+pub struct ScanProgress {
+    dirs: AtomicU64,
+    files: AtomicU64,
+    bytes: AtomicU64,
+    current: Mutex<PathBuf>,
+}
+
+impl ScanProgress {
+    /// The walk entered `path`.
+    pub fn dir(&self, path: &Path) {
+        self.dirs.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut current) = self.current.lock() {
+            current.clear();
+            current.push(path);
+        }
+    }
+
+    pub fn file(&self, size: u64) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+    }
+
+    /// Nothing counted since the last [`reset`](Self::reset).
+    pub fn is_empty(&self) -> bool {
+        self.dirs.load(Ordering::Relaxed) == 0 && self.files.load(Ordering::Relaxed) == 0
+    }
+
+    pub fn reset(&self) {
+        self.dirs.store(0, Ordering::Relaxed);
+        self.files.store(0, Ordering::Relaxed);
+        self.bytes.store(0, Ordering::Relaxed);
+        if let Ok(mut current) = self.current.lock() {
+            current.clear();
+        }
+    }
+
+    /// e.g. `1,204 dirs, 38,112 files, 4.20 GiB  src/assets`
+    pub fn status(&self) -> String {
+        let mut status = format!(
+            "{} dirs, {} files, {}",
+            HumanCount(self.dirs.load(Ordering::Relaxed)),
+            HumanCount(self.files.load(Ordering::Relaxed)),
+            HumanBytes(self.bytes.load(Ordering::Relaxed)),
+        );
+        if let Ok(current) = self.current.lock()
+            && !current.as_os_str().is_empty()
+        {
+            status.push_str(&format!("  {}", current.display()));
+        }
+        status
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
 pub enum ProgressBarStyle {
     #[default]
     Default,
     Detailed,
+    /// One JSON object per progress event on stdout instead of bars
+    Json,
+}
+
+/// When to draw progress output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// Bars on a terminal, periodic status lines otherwise
+    #[default]
+    Auto,
+    /// Bars even when stderr is not a terminal
+    Always,
+    /// Periodic status lines even on a terminal
+    Plain,
+    /// No progress output
+    Never,
+}
+
+/// A `--progress` value: either a style or a mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressSetting {
+    Style(ProgressBarStyle),
+    Mode(ProgressMode),
+}
+
+impl std::str::FromStr for ProgressSetting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(mode) = ProgressMode::from_str(s, true) {
+            return Ok(ProgressSetting::Mode(mode));
+        }
+        ProgressBarStyle::from_str(s, true)
+            .map(ProgressSetting::Style)
+            .map_err(|_| {
+                format!(
+                    "invalid value '{}' (expected default, detailed, json, auto, always, plain or never)",
+                    s
+                )
+            })
+    }
+}
+
+/// Draw target that turns redraws into plain status lines, at most one per
+/// `interval`. The last unprinted line is flushed on drop so the final state
+/// always reaches the log.
+#[derive(Debug)]
+struct PlainStatus {
+    interval: Duration,
+    state: Mutex<PlainState>,
+}
+
+#[derive(Debug, Default)]
+struct PlainState {
+    pending: String,
+    unprinted: Option<String>,
+    last_emit: Option<Instant>,
+}
+
+impl PlainStatus {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            state: Mutex::default(),
+        }
+    }
+
+    fn emit(line: &str) {
+        let _ = writeln!(io::stderr(), "{}", line);
+    }
+}
+
+impl TermLike for PlainStatus {
+    fn width(&self) -> u16 {
+        u16::MAX
+    }
+
+    fn move_cursor_up(&self, _n: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_down(&self, _n: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_right(&self, _n: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_left(&self, _n: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_line(&self, s: &str) -> io::Result<()> {
+        self.write_str(s)
+    }
+
+    fn write_str(&self, s: &str) -> io::Result<()> {
+        if let Ok(mut state) = self.state.lock() {
+            state
+                .pending
+                .push_str(s.trim_matches(|c: char| c == '\r' || c == '\n'));
+        }
+        Ok(())
+    }
+
+    fn clear_line(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let Ok(mut state) = self.state.lock() else {
+            return Ok(());
+        };
+        let line = std::mem::take(&mut state.pending).trim_end().to_string();
+        if line.is_empty() {
+            return Ok(());
+        }
+        let due = state
+            .last_emit
+            .is_none_or(|last| last.elapsed() >= self.interval);
+        if due {
+            Self::emit(&line);
+            state.last_emit = Some(Instant::now());
+            state.unprinted = None;
+        } else {
+            state.unprinted = Some(line);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PlainStatus {
+    fn drop(&mut self) {
+        if let Ok(state) = self.state.get_mut()
+            && let Some(line) = state.unprinted.take()
+        {
+            Self::emit(&line);
+        }
+    }
 }
 
 impl Default for ProgressOptions {
     fn default() -> Self {
         ProgressOptions {
             style: ProgressBarStyle::Default,
+            mode: ProgressMode::Auto,
             filled: String::from("█"),
             empty: String::from("░"),
             head: String::from("░"),
             bar_color: String::from("white"),
             message_color: String::from("white"),
+            file_bars: 0,
+            refresh_hz: REFRESH_HZ,
+            status_interval: STATUS_INTERVAL,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_setting_parses_styles_and_modes() {
+        assert_eq!(
+            "detailed".parse::<ProgressSetting>(),
+            Ok(ProgressSetting::Style(ProgressBarStyle::Detailed))
+        );
+        assert_eq!(
+            "never".parse::<ProgressSetting>(),
+            Ok(ProgressSetting::Mode(ProgressMode::Never))
+        );
+        assert!("sometimes".parse::<ProgressSetting>().is_err());
+    }
+
+    #[test]
+    fn test_bar_starts_with_file_count() {
+        let options = ProgressOptions::default();
+        let pb = ProgressBar::hidden();
+        options.apply(&pb, 42, None, Arc::default());
+        assert_eq!(pb.prefix(), "0/42 files");
+        assert_eq!(pb.message(), "Copying");
+    }
+
+    #[test]
+    fn test_detailed_file_bars_show_speed_and_eta() {
+        let mut options = ProgressOptions::default();
+        assert!(!options.file_bar_template().contains("eta_precise"));
+        options.style = ProgressBarStyle::Detailed;
+        let template = options.file_bar_template();
+        assert!(template.contains("{binary_bytes_per_sec:>12}"));
+        assert!(template.contains("{elapsed_precise} ETA {eta_precise}"));
+    }
+
+    #[test]
+    fn test_never_mode_creates_no_bar() {
+        let options = ProgressOptions {
+            mode: ProgressMode::Never,
+            ..ProgressOptions::default()
+        };
+        assert!(
+            options
+                .create_bar(100, 1, Arc::default(), Arc::default())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_scan_progress_status() {
+        let scan = ScanProgress::default();
+        assert!(scan.is_empty());
+        assert_eq!(scan.status(), "0 dirs, 0 files, 0 B");
+        scan.dir(Path::new("src/assets"));
+        assert!(!scan.is_empty());
+        scan.file(1024);
+        scan.file(1024);
+        assert_eq!(scan.status(), "1 dirs, 2 files, 2.00 KiB  src/assets");
+        scan.reset();
+        assert!(scan.is_empty());
+    }
+
+    #[test]
+    fn test_plain_status_throttles_lines() {
+        let status = PlainStatus::new(STATUS_INTERVAL);
+        status.write_str("\rCopying 10%").unwrap();
+        status.flush().unwrap();
+        status.write_str("Copying 20%").unwrap();
+        status.flush().unwrap();
+
+        let state = status.state.lock().unwrap();
+        assert!(state.last_emit.is_some());
+        assert_eq!(state.unprinted.as_deref(), Some("Copying 20%"));
+    }
+
+    #[test]
+    fn test_plain_status_prints_every_interval() {
+        let status = PlainStatus::new(Duration::ZERO);
+        status.write_str("Copying 10%").unwrap();
+        status.flush().unwrap();
+        status.write_str("Copying 20%").unwrap();
+        status.flush().unwrap();
+        assert_eq!(status.state.lock().unwrap().unprinted, None);
+    }
+
+    #[test]
+    fn test_plain_mode_draws_status_lines() {
+        let options = ProgressOptions {
+            mode: ProgressMode::Plain,
+            file_bars: 4,
+            ..ProgressOptions::default()
+        };
+        assert!(options.draw_target().is_some_and(|(_, plain)| plain));
+        assert!(options.create_multi().is_none());
+        assert_eq!(
+            "plain".parse::<ProgressSetting>(),
+            Ok(ProgressSetting::Mode(ProgressMode::Plain))
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs::File;
+use std::path::Path;
+
+/// Bytes hinted per file: enough to keep a disk busy until the worker's own
+/// reads take over, without pulling a large file into the page cache long
+/// before it is needed.
+const WINDOW: u64 = 8 * 1024 * 1024;
+
+/// Asks the kernel to start reading the beginning of `path`, `size` bytes
+/// long, into the page cache, so a worker that opens it later finds the
+/// data there instead of waiting on the disk. Only a hint: a file that
+/// can't be opened is left for the copy itself to report.
+pub fn prefetch(path: &Path, size: u64) {
+    if size == 0 {
+        return;
+    }
+    if let Ok(file) = File::open(path) {
+        advise_will_need(&file, size.min(WINDOW));
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn advise_will_need(file: &File, len: u64) {
+    use std::os::unix::io::AsRawFd;
+
+    // readahead continues in the background after the file is closed
+    unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            0,
+            len as libc::off_t,
+            libc::POSIX_FADV_WILLNEED,
+        )
+    };
+}
+
+#[cfg(target_os = "macos")]
+fn advise_will_need(file: &File, len: u64) {
+    use std::os::unix::io::AsRawFd;
+
+    let advisory = libc::radvisory {
+        ra_offset: 0,
+        ra_count: len as libc::c_int,
+    };
+    unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDADVISE, &advisory) };
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+)))]
+fn advise_will_need(_file: &File, _len: u64) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_prefetch_is_only_a_hint() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.bin");
+        std::fs::write(&path, vec![1u8; 64 * 1024]).unwrap();
+        prefetch(&path, 64 * 1024);
+        // neither a missing file nor an empty one is an error
+        prefetch(&temp_dir.path().join("missing"), 1024);
+        prefetch(&path, 0);
+        assert_eq!(std::fs::read(&path).unwrap().len(), 64 * 1024);
+    }
+}
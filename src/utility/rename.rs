@@ -0,0 +1,137 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Moves `from` to `to`. Within a filesystem this is a rename; across
+/// filesystems (EXDEV) `from` is copied next to `to` under a temporary
+/// name, renamed into place and then removed. `progress` is told how many
+/// more bytes the copy wrote; a plain rename reports nothing.
+pub fn rename_or_copy(from: &Path, to: &Path, mut progress: impl FnMut(u64)) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let temp = temp_path(to);
+            if let Err(e) =
+                copy_entry(from, &temp, &mut progress).and_then(|()| fs::rename(&temp, to))
+            {
+                let _ = remove_entry(&temp);
+                return Err(e);
+            }
+            remove_entry(from)
+        }
+        result => result,
+    }
+}
+
+/// `.name.cpx-tmp`, beside `path`: on its filesystem, and out of `ls`.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.cpx-tmp", name))
+}
+
+/// Copies a file, symlink or directory tree with its permissions and mtime.
+fn copy_entry(from: &Path, to: &Path, progress: &mut dyn FnMut(u64)) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.file_type().is_symlink() {
+        copy_symlink(from, to)?;
+        return Ok(());
+    }
+    if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &to.join(entry.file_name()), progress)?;
+        }
+    } else {
+        let mut reader = fs::File::open(from)?;
+        let mut writer = fs::File::create(to)?;
+        let mut buffer = vec![0u8; 1024 * 1024];
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..n])?;
+            progress(n as u64);
+        }
+        writer.flush()?;
+    }
+    fs::set_permissions(to, metadata.permissions())?;
+    if let Ok(modified) = metadata.modified() {
+        filetime::set_file_mtime(to, filetime::FileTime::from_system_time(modified))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(windows)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    if fs::metadata(from).is_ok_and(|meta| meta.is_dir()) {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
+
+fn remove_entry(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rename_or_copy_renames_within_a_filesystem() {
+        let temp_dir = TempDir::new().unwrap();
+        let from = temp_dir.path().join("a.txt");
+        let to = temp_dir.path().join("b.txt");
+        fs::write(&from, b"content").unwrap();
+
+        let mut copied = 0;
+        rename_or_copy(&from, &to, |n| copied += n).unwrap();
+
+        assert!(!from.exists());
+        assert_eq!(fs::read(&to).unwrap(), b"content");
+        assert_eq!(copied, 0);
+    }
+
+    #[test]
+    fn test_copy_entry_copies_trees_with_progress() {
+        let temp_dir = TempDir::new().unwrap();
+        let from = temp_dir.path().join("tree");
+        fs::create_dir_all(from.join("sub")).unwrap();
+        fs::write(from.join("a.txt"), b"12345").unwrap();
+        fs::write(from.join("sub/b.txt"), b"678").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a.txt", from.join("link")).unwrap();
+
+        let temp = temp_path(&temp_dir.path().join("copy"));
+        assert_eq!(temp, temp_dir.path().join(".copy.cpx-tmp"));
+        let mut copied = 0;
+        copy_entry(&from, &temp, &mut |n| copied += n).unwrap();
+        remove_entry(&from).unwrap();
+
+        assert_eq!(copied, 8);
+        assert!(!from.exists());
+        assert_eq!(fs::read(temp.join("sub/b.txt")).unwrap(), b"678");
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(temp.join("link")).unwrap(),
+            Path::new("a.txt")
+        );
+    }
+}
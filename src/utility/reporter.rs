@@ -0,0 +1,588 @@
+use crate::cli::args::CopyOptions;
+use crate::error::CopyError;
+use crate::utility::control::CopyControl;
+use crate::utility::eta::Eta;
+use crate::utility::helper::truncate_filename;
+use crate::utility::json_progress::{self, ProgressEvent};
+use crate::utility::output::Verbosity;
+use crate::utility::progress_bar::{ProgressOptions, ScanProgress, format_file_count};
+use crate::utility::stats::CopyStats;
+use crate::utility::throughput::Throughput;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Receives progress from the copy engine. Every method has a no-op default
+/// so implementations only handle what they display.
+pub trait ProgressReporter: Send + Sync {
+    fn scan_started(&self, _sources: &[&Path]) {}
+
+    fn scan_finished(&self, _total_files: usize, _total_bytes: u64, _skipped_files: usize) {}
+
+    /// File copying is about to start; `already_done` files, `skipped_bytes`
+    /// in all, were skipped during planning and are not in `total_bytes`.
+    fn begin(
+        &self,
+        _total_files: usize,
+        _total_bytes: u64,
+        _already_done: usize,
+        _skipped_bytes: u64,
+    ) {
+    }
+
+    fn file_begin(&self, _source: &Path, _destination: &Path, _size: u64) {}
+
+    /// `delta` more bytes of `source` were written.
+    fn file_inc(&self, _source: &Path, _delta: u64) {}
+
+    fn file_done(&self, _source: &Path, _destination: &Path, _digest: Option<&str>) {}
+
+    fn file_failed(&self, _source: &Path, _destination: &Path, _error: &CopyError) {}
+
+    fn file_skipped(&self, _source: &Path, _reason: &str) {}
+
+    /// Transient status, such as a retry in progress.
+    fn message(&self, _message: &str) {}
+
+    /// Prints a line to stdout without tearing any live display.
+    fn println(&self, line: &str) {
+        println!("{}", line);
+    }
+
+    fn finish(&self, _message: &str) {}
+
+    fn abandon(&self, _message: &str) {}
+
+    fn summary(&self, _stats: &CopyStats) {}
+}
+
+/// Picks the reporter for a run: JSON lines, a progress bar, or nothing.
+pub fn for_options(options: &CopyOptions) -> Box<dyn ProgressReporter> {
+    if options.progress_bar.is_json() {
+        Box::new(JsonReporter)
+    } else if !options.prompts() && !options.attributes_only && options.verbosity > Verbosity::Quiet
+    {
+        let mut progress = options.progress_bar.clone();
+        // no more bars than workers
+        progress.file_bars = progress.file_bars.min(options.parallel);
+        Box::new(IndicatifReporter::new(
+            progress,
+            options.scan_progress.clone(),
+            options.control.clone(),
+        ))
+    } else {
+        Box::new(NoopReporter)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NoopReporter;
+
+impl ProgressReporter for NoopReporter {}
+
+/// `--progress=json`: one event per line on stdout.
+#[derive(Debug, Default)]
+pub struct JsonReporter;
+
+impl ProgressReporter for JsonReporter {
+    fn scan_started(&self, sources: &[&Path]) {
+        json_progress::emit(&ProgressEvent::ScanStarted {
+            sources: sources.to_vec(),
+        });
+    }
+
+    fn scan_finished(&self, total_files: usize, total_bytes: u64, skipped_files: usize) {
+        json_progress::emit(&ProgressEvent::ScanFinished {
+            total_files,
+            total_bytes,
+            skipped_files,
+        });
+    }
+
+    fn file_begin(&self, source: &Path, destination: &Path, size: u64) {
+        json_progress::emit(&ProgressEvent::FileStarted {
+            source,
+            destination,
+            size,
+        });
+    }
+
+    fn file_inc(&self, source: &Path, delta: u64) {
+        json_progress::emit(&ProgressEvent::Bytes { source, delta });
+    }
+
+    fn file_done(&self, source: &Path, destination: &Path, digest: Option<&str>) {
+        json_progress::emit(&ProgressEvent::FileDone {
+            source,
+            destination,
+            digest,
+        });
+    }
+
+    fn file_failed(&self, source: &Path, destination: &Path, error: &CopyError) {
+        json_progress::emit(&ProgressEvent::FileFailed {
+            source,
+            destination,
+            category: error.category(),
+            error: error.to_string(),
+        });
+    }
+
+    // stdout carries the event stream
+    fn println(&self, _line: &str) {}
+
+    fn summary(&self, stats: &CopyStats) {
+        json_progress::emit(&ProgressEvent::Summary {
+            files_copied: stats.files_copied(),
+            files_failed: stats.files_failed(),
+            files_skipped: stats.files_skipped(),
+            total_bytes: stats.bytes_copied(),
+            elapsed_ms: stats.elapsed().as_millis(),
+        });
+    }
+}
+
+/// A scan spinner while planning, then the overall indicatif bar with its
+/// `N/M files` counter and, with `[progress] file_bars`, per-file bars.
+#[derive(Debug)]
+pub struct IndicatifReporter {
+    options: ProgressOptions,
+    scan: Arc<ScanProgress>,
+    spinner: OnceLock<ProgressBar>,
+    bar: OnceLock<ProgressBar>,
+    throughput: Arc<Throughput>,
+    eta: Arc<Eta>,
+    file_bars: OnceLock<WorkerBars>,
+    control: Arc<CopyControl>,
+    /// `control.resumes()` as of the last update.
+    resumes: AtomicUsize,
+    total_files: AtomicUsize,
+    finished: AtomicUsize,
+    retrying: AtomicBool,
+    /// Bytes each file in flight has left, taken off the total if it is
+    /// skipped.
+    remaining: Mutex<HashMap<PathBuf, u64>>,
+    skipped_bytes: AtomicU64,
+}
+
+impl IndicatifReporter {
+    pub fn new(
+        options: ProgressOptions,
+        scan: Arc<ScanProgress>,
+        control: Arc<CopyControl>,
+    ) -> Self {
+        Self {
+            options,
+            scan,
+            spinner: OnceLock::new(),
+            bar: OnceLock::new(),
+            throughput: Arc::default(),
+            eta: Arc::default(),
+            file_bars: OnceLock::new(),
+            control,
+            resumes: AtomicUsize::new(0),
+            total_files: AtomicUsize::new(0),
+            finished: AtomicUsize::new(0),
+            retrying: AtomicBool::new(false),
+            remaining: Mutex::default(),
+            skipped_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Marks `source` finished, `copied` or not, and returns the bytes it
+    /// had left.
+    fn count_finished(&self, source: &Path, copied: bool) -> u64 {
+        self.eta.file_finished(source, copied);
+        if let Some(file_bars) = self.file_bars.get() {
+            file_bars.remove(source);
+        }
+        let left = self
+            .remaining
+            .lock()
+            .ok()
+            .and_then(|mut remaining| remaining.remove(source))
+            .unwrap_or(0);
+        let Some(pb) = self.bar.get() else {
+            return left;
+        };
+        let finished = self.finished.fetch_add(1, Ordering::Relaxed) + 1;
+        pb.set_prefix(format_file_count(
+            finished,
+            self.total_files.load(Ordering::Relaxed),
+        ));
+        if self.retrying.swap(false, Ordering::Relaxed) {
+            pb.set_message("Copying");
+        }
+        left
+    }
+}
+
+impl ProgressReporter for IndicatifReporter {
+    fn scan_started(&self, _sources: &[&Path]) {
+        self.scan.reset();
+        if let Some(spinner) = self.options.create_spinner(self.scan.clone()) {
+            let _ = self.spinner.set(spinner);
+        }
+    }
+
+    fn scan_finished(&self, _total_files: usize, _total_bytes: u64, _skipped_files: usize) {
+        if let Some(spinner) = self.spinner.get() {
+            spinner.finish_and_clear();
+        }
+    }
+
+    fn begin(&self, total_files: usize, total_bytes: u64, already_done: usize, skipped_bytes: u64) {
+        self.skipped_bytes.store(skipped_bytes, Ordering::Relaxed);
+        if total_files == 0 {
+            return;
+        }
+        let tracked = total_files + already_done;
+        let Some(pb) = self.options.create_bar(
+            total_bytes,
+            tracked,
+            self.throughput.clone(),
+            self.eta.clone(),
+        ) else {
+            return;
+        };
+        let pb = match self.options.create_multi() {
+            Some(multi) => {
+                let pb = multi.add(pb);
+                let _ = self.file_bars.set(WorkerBars::new(multi, &self.options));
+                pb
+            }
+            None => pb,
+        };
+        self.total_files.store(tracked, Ordering::Relaxed);
+        self.eta.begin(total_files);
+        self.finished.store(already_done, Ordering::Relaxed);
+        pb.set_prefix(format_file_count(already_done, tracked));
+        let _ = self.bar.set(pb);
+    }
+
+    fn file_begin(&self, source: &Path, _destination: &Path, size: u64) {
+        if let Ok(mut remaining) = self.remaining.lock() {
+            remaining.insert(source.to_path_buf(), size);
+        }
+        self.eta.file_started(source, size);
+        if let Some(file_bars) = self.file_bars.get() {
+            file_bars.add(source, size);
+        }
+    }
+
+    fn file_inc(&self, source: &Path, delta: u64) {
+        if let Some(pb) = self.bar.get() {
+            // the pause would otherwise drag the rate down and the ETA up
+            let resumes = self.control.resumes();
+            if self.resumes.swap(resumes, Ordering::Relaxed) != resumes {
+                pb.reset_eta();
+                self.eta.reset();
+            }
+            pb.inc(delta);
+            self.throughput.add(delta);
+        }
+        if let Ok(mut remaining) = self.remaining.lock()
+            && let Some(left) = remaining.get_mut(source)
+        {
+            *left = left.saturating_sub(delta);
+        }
+        if let Some(file_bars) = self.file_bars.get() {
+            file_bars.inc(source, delta);
+        }
+    }
+
+    fn file_done(&self, source: &Path, _destination: &Path, _digest: Option<&str>) {
+        self.count_finished(source, true);
+    }
+
+    fn file_failed(&self, source: &Path, _destination: &Path, error: &CopyError) {
+        if error.kind() != io::ErrorKind::Interrupted {
+            self.count_finished(source, false);
+        }
+    }
+
+    fn file_skipped(&self, source: &Path, _reason: &str) {
+        // bytes that will never be written come off the total, so the bar
+        // still reaches 100% and the ETA only counts real work
+        let left = self.count_finished(source, false);
+        self.skipped_bytes.fetch_add(left, Ordering::Relaxed);
+        if let Some(pb) = self.bar.get() {
+            pb.set_length(pb.length().unwrap_or(0).saturating_sub(left));
+        }
+    }
+
+    fn message(&self, message: &str) {
+        if let Some(pb) = self.bar.get() {
+            pb.set_message(message.to_string());
+            self.retrying.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn println(&self, line: &str) {
+        match self.bar.get() {
+            Some(pb) => pb.suspend(|| println!("{}", line)),
+            None => println!("{}", line),
+        }
+    }
+
+    fn finish(&self, message: &str) {
+        if let Some(file_bars) = self.file_bars.get() {
+            file_bars.clear();
+        }
+        if let Some(pb) = self.bar.get() {
+            let skipped = self.skipped_bytes.load(Ordering::Relaxed);
+            if skipped > 0 {
+                pb.finish_with_message(format!("{}, skipped {}", message, HumanBytes(skipped)));
+            } else {
+                pb.finish_with_message(message.to_string());
+            }
+        }
+    }
+
+    fn abandon(&self, message: &str) {
+        if let Some(file_bars) = self.file_bars.get() {
+            file_bars.clear();
+        }
+        if let Some(pb) = self.bar.get() {
+            pb.abandon_with_message(message.to_string());
+        }
+    }
+}
+
+/// Widest file name shown on a worker's bar; longer ones are cut short so
+/// the bars line up.
+const LABEL_WIDTH: usize = 40;
+
+/// One bar per worker slot under the overall bar, each showing the file
+/// that slot is copying. The bars stay in place as files come and go, so
+/// nothing flickers; files in flight beyond the slots are counted in a
+/// `+N more files` line until a slot frees up.
+#[derive(Debug)]
+struct WorkerBars {
+    options: ProgressOptions,
+    slots: Vec<ProgressBar>,
+    more: ProgressBar,
+    state: Mutex<WorkerBarState>,
+}
+
+#[derive(Debug, Default)]
+struct WorkerBarState {
+    active: HashMap<PathBuf, ActiveFile>,
+    /// The file each slot is showing.
+    shown: Vec<Option<PathBuf>>,
+}
+
+#[derive(Debug)]
+struct ActiveFile {
+    size: u64,
+    written: u64,
+    slot: Option<usize>,
+}
+
+impl WorkerBars {
+    fn new(multi: MultiProgress, options: &ProgressOptions) -> Self {
+        let slots: Vec<_> = (0..options.file_bars)
+            .map(|_| {
+                let bar = multi.add(ProgressBar::new(0));
+                idle(&bar);
+                bar
+            })
+            .collect();
+        let more = multi.add(
+            ProgressBar::new(0)
+                .with_style(ProgressStyle::default_bar().template("  {msg}").unwrap()),
+        );
+        Self {
+            options: options.clone(),
+            state: Mutex::new(WorkerBarState {
+                active: HashMap::new(),
+                shown: vec![None; slots.len()],
+            }),
+            slots,
+            more,
+        }
+    }
+
+    fn add(&self, source: &Path, size: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            let file = ActiveFile {
+                size,
+                written: 0,
+                slot: None,
+            };
+            if let Some(replaced) = state.active.insert(source.to_path_buf(), file)
+                && let Some(slot) = replaced.slot
+            {
+                state.shown[slot] = None;
+            }
+            if let Some(slot) = state.shown.iter().position(Option::is_none) {
+                self.show(&mut state, slot, source);
+            }
+            self.count_waiting(&state);
+        }
+    }
+
+    fn inc(&self, source: &Path, delta: u64) {
+        if let Ok(mut state) = self.state.lock()
+            && let Some(file) = state.active.get_mut(source)
+        {
+            file.written += delta;
+            if let Some(slot) = file.slot {
+                self.slots[slot].inc(delta);
+            }
+        }
+    }
+
+    /// Frees the file's slot for the largest file still waiting for one.
+    fn remove(&self, source: &Path) {
+        if let Ok(mut state) = self.state.lock()
+            && let Some(file) = state.active.remove(source)
+        {
+            if let Some(slot) = file.slot {
+                state.shown[slot] = None;
+                let next = state
+                    .active
+                    .iter()
+                    .filter(|(_, file)| file.slot.is_none())
+                    .max_by_key(|(_, file)| file.size)
+                    .map(|(path, _)| path.clone());
+                match next {
+                    Some(path) => self.show(&mut state, slot, &path),
+                    None => idle(&self.slots[slot]),
+                }
+            }
+            self.count_waiting(&state);
+        }
+    }
+
+    fn clear(&self) {
+        for bar in self.slots.iter().chain([&self.more]) {
+            bar.finish_and_clear();
+        }
+    }
+
+    /// Puts `path`, which is in flight, on the bar of `slot`.
+    fn show(&self, state: &mut WorkerBarState, slot: usize, path: &Path) {
+        let Some(file) = state.active.get_mut(path) else {
+            return;
+        };
+        file.slot = Some(slot);
+        state.shown[slot] = Some(path.to_path_buf());
+        let bar = &self.slots[slot];
+        // speed, elapsed time and ETA start over with each file
+        bar.reset();
+        bar.set_style(self.options.file_bar_style());
+        bar.set_length(file.size);
+        bar.set_position(file.written);
+        bar.set_message(truncate_filename(&file_label(path), LABEL_WIDTH));
+    }
+
+    fn count_waiting(&self, state: &WorkerBarState) {
+        let waiting = state
+            .active
+            .values()
+            .filter(|file| file.slot.is_none())
+            .count();
+        if waiting == 0 {
+            self.more.set_message("");
+        } else {
+            self.more.set_message(format!("+{} more files", waiting));
+        }
+    }
+}
+
+/// A slot with nothing to show.
+fn idle(bar: &ProgressBar) {
+    bar.reset();
+    bar.set_style(ProgressStyle::default_bar().template("  {msg}").unwrap());
+    bar.set_length(0);
+    bar.set_message("idle");
+}
+
+fn file_label(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indicatif::ProgressDrawTarget;
+
+    #[test]
+    fn test_indicatif_reporter_counts_files() {
+        let reporter =
+            IndicatifReporter::new(ProgressOptions::default(), Arc::default(), Arc::default());
+        reporter.total_files.store(3, Ordering::Relaxed);
+        reporter.finished.store(1, Ordering::Relaxed);
+        reporter.bar.set(ProgressBar::hidden()).unwrap();
+
+        reporter.message("Retrying a.txt (1/3)");
+        reporter.file_done(Path::new("a.txt"), Path::new("b.txt"), None);
+        reporter.file_inc(Path::new("c.txt"), 7);
+
+        let pb = reporter.bar.get().unwrap();
+        assert_eq!(pb.prefix(), "2/3 files");
+        assert_eq!(pb.message(), "Copying");
+        assert_eq!(pb.position(), 7);
+    }
+
+    #[test]
+    fn test_skipped_bytes_come_off_the_total() {
+        let reporter =
+            IndicatifReporter::new(ProgressOptions::default(), Arc::default(), Arc::default());
+        reporter.skipped_bytes.store(1024, Ordering::Relaxed);
+        reporter.bar.set(ProgressBar::new(100)).unwrap();
+
+        reporter.file_begin(Path::new("a.txt"), Path::new("b.txt"), 60);
+        reporter.file_inc(Path::new("a.txt"), 20);
+        reporter.file_skipped(Path::new("a.txt"), "skipped by user");
+        reporter.finish("Done");
+
+        let pb = reporter.bar.get().unwrap();
+        assert_eq!(pb.length(), Some(60));
+        assert_eq!(pb.message(), "Done, skipped 1.04 KiB");
+    }
+
+    #[test]
+    fn test_worker_bars_stay_in_place() {
+        let options = ProgressOptions {
+            file_bars: 2,
+            ..ProgressOptions::default()
+        };
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        let bars = WorkerBars::new(multi, &options);
+        let messages = || {
+            bars.slots
+                .iter()
+                .map(|bar| bar.message())
+                .collect::<Vec<_>>()
+        };
+
+        bars.add(Path::new("small"), 10);
+        bars.add(Path::new("large"), 300);
+        bars.add(Path::new("medium"), 200);
+        bars.inc(Path::new("medium"), 4);
+        assert_eq!(messages(), ["small", "large"]);
+        assert_eq!(bars.more.message(), "+1 more files");
+
+        // the waiting file takes the freed slot, with what it has written
+        bars.remove(Path::new("large"));
+        assert_eq!(messages(), ["small", "medium"]);
+        assert_eq!(bars.slots[1].length(), Some(200));
+        assert_eq!(bars.slots[1].position(), 4);
+        assert_eq!(bars.more.message(), "");
+
+        bars.remove(Path::new("small"));
+        assert_eq!(messages(), ["idle", "medium"]);
+
+        let long = "a".repeat(LABEL_WIDTH * 2);
+        bars.add(Path::new(&long), 1);
+        assert_eq!(bars.slots[0].message().len(), LABEL_WIDTH);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::cli::args::SanitizeMode;
+use crate::core::check::{INVALID_CHARS, RESERVED_NAMES};
+use crate::utility::preprocess::CopyPlan;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// `name` made acceptable to Windows: characters it refuses are replaced
+/// or percent-encoded, trailing dots and spaces are trimmed, and reserved
+/// device names get a `_` after them.
+pub fn sanitize_name(name: &str, mode: SanitizeMode) -> Cow<'_, str> {
+    let refused = |c: char| INVALID_CHARS.contains(&c) || c.is_control();
+    let trimmed = name.trim_end_matches(['.', ' ']);
+    let stem = trimmed.split('.').next().unwrap_or(trimmed).trim_end();
+    let reserved = RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved));
+    if trimmed.len() == name.len() && !reserved && !name.contains(refused) {
+        return Cow::Borrowed(name);
+    }
+
+    let mut out = String::with_capacity(name.len());
+    for c in trimmed.chars() {
+        if !refused(c) {
+            out.push(c);
+            continue;
+        }
+        match mode {
+            SanitizeMode::Replace => out.push('_'),
+            SanitizeMode::Percent => {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    out.push_str(&format!("%{:02X}", byte));
+                }
+            }
+        }
+    }
+    if reserved {
+        out.insert(stem.len(), '_');
+    }
+    if out.is_empty() {
+        out.push('_');
+    }
+    Cow::Owned(out)
+}
+
+/// Renames the planned destinations below `root` whose names the
+/// destination filesystem refuses, keeping every entry under its renamed
+/// parent. A new name that another entry already has gets a number.
+/// Returns the renames, as (planned, renamed) pairs.
+pub fn sanitize_plan(
+    plan: &mut CopyPlan,
+    root: &Path,
+    mode: SanitizeMode,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut destinations: Vec<PathBuf> = plan
+        .directories
+        .iter()
+        .map(|task| task.destination.clone())
+        .chain(plan.files.iter().map(|task| task.destination.clone()))
+        .chain(plan.symlinks.iter().map(|task| task.destination.clone()))
+        .chain(plan.hardlinks.iter().map(|task| task.destination.clone()))
+        .chain(
+            plan.duplicates
+                .iter()
+                .map(|task| task.file.destination.clone()),
+        )
+        .collect();
+    destinations.sort();
+    destinations.dedup();
+
+    let mut renamer = Renamer {
+        root,
+        mode,
+        taken: destinations.iter().cloned().collect(),
+        renamed: HashMap::new(),
+    };
+    let mut renames = Vec::new();
+    for destination in &destinations {
+        let name_changed = renamer.rename(destination);
+        if name_changed && let Some(to) = renamer.renamed.get(destination) {
+            renames.push((destination.clone(), to.clone()));
+        }
+    }
+    if renamer.renamed.is_empty() {
+        return renames;
+    }
+
+    let map = |path: &mut PathBuf| {
+        if let Some(to) = renamer.renamed.get(path) {
+            *path = to.clone();
+        }
+    };
+    for task in &mut plan.directories {
+        map(&mut task.destination);
+    }
+    for task in &mut plan.files {
+        map(&mut task.destination);
+        // a further name of a hard-linked file is copied from the first
+        // one's copy
+        map(&mut task.source);
+    }
+    for task in &mut plan.symlinks {
+        map(&mut task.destination);
+    }
+    for task in &mut plan.hardlinks {
+        map(&mut task.destination);
+    }
+    for task in &mut plan.duplicates {
+        map(&mut task.file.destination);
+        map(&mut task.file.source);
+        map(&mut task.original);
+    }
+    renames
+}
+
+struct Renamer<'a> {
+    root: &'a Path,
+    mode: SanitizeMode,
+    /// Every destination path in use, planned or renamed.
+    taken: HashSet<PathBuf>,
+    /// Destinations whose path changes, by name or through a parent.
+    renamed: HashMap<PathBuf, PathBuf>,
+}
+
+impl Renamer<'_> {
+    /// Works out where `path` ends up. Returns whether its own name
+    /// changed, rather than only a directory above it.
+    fn rename(&mut self, path: &Path) -> bool {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return false;
+        };
+        if path == self.root || !path.starts_with(self.root) {
+            return false;
+        }
+        let parent = self
+            .renamed
+            .get(parent)
+            .cloned()
+            .unwrap_or_else(|| parent.to_path_buf());
+        let sanitized = match name.to_str() {
+            Some(name) => sanitize_name(name, self.mode),
+            None => Cow::Owned(name.to_string_lossy().into_owned()),
+        };
+        let name_changed = sanitized != name.to_string_lossy();
+        let mut target = parent.join(sanitized.as_ref());
+        if name_changed {
+            let mut n = 1;
+            while self.taken.contains(&target) {
+                target = parent.join(numbered(&sanitized, n));
+                n += 1;
+            }
+        }
+        if target != path {
+            self.taken.insert(target.clone());
+            self.renamed.insert(path.to_path_buf(), target);
+        }
+        name_changed
+    }
+}
+
+/// `name` with `~n` before its extension: `a_b~1.txt`.
+fn numbered(name: &str, n: usize) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}~{}.{}", stem, n, extension),
+        _ => format!("{}~{}", name, n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_name() {
+        let replace = |name| sanitize_name(name, SanitizeMode::Replace).into_owned();
+        assert_eq!(replace("report.txt"), "report.txt");
+        assert_eq!(replace("12:30 <draft>?.txt"), "12_30 _draft__.txt");
+        assert_eq!(replace("notes. . "), "notes");
+        assert_eq!(replace("con.tar.gz"), "con_.tar.gz");
+        assert_eq!(replace("LPT1"), "LPT1_");
+        assert_eq!(replace("..."), "_");
+        assert_eq!(sanitize_name("a:b?", SanitizeMode::Percent), "a%3Ab%3F");
+    }
+
+    #[test]
+    fn test_sanitize_plan_renames_whole_subtrees() {
+        let root = Path::new("/dest");
+        let mut plan = CopyPlan::new();
+        plan.add_directory(None, root.join("src"));
+        plan.add_directory(None, root.join("src/logs: old"));
+        plan.add_file("/src/a".into(), root.join("src/logs: old/a.txt"), 1);
+        plan.add_file("/src/b".into(), root.join("src/b?.txt"), 1);
+        plan.add_file("/src/c".into(), root.join("src/b_.txt"), 1);
+
+        let renames = sanitize_plan(&mut plan, root, SanitizeMode::Replace);
+        assert_eq!(
+            renames,
+            [
+                (root.join("src/b?.txt"), root.join("src/b_~1.txt")),
+                (root.join("src/logs: old"), root.join("src/logs_ old")),
+            ]
+        );
+        let mut files: Vec<_> = plan.files.iter().map(|t| t.destination.clone()).collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                root.join("src/b_.txt"),
+                root.join("src/b_~1.txt"),
+                root.join("src/logs_ old/a.txt"),
+            ]
+        );
+        assert_eq!(plan.directories[1].destination, root.join("src/logs_ old"));
+    }
+}
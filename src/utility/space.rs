@@ -0,0 +1,102 @@
+use crate::utility::preprocess::FileTask;
+use std::io;
+use std::path::Path;
+
+/// Bytes still to be written for `files`: each destination's planned size
+/// less what it already holds. A finished copy adds nothing, and a file
+/// kept with `--partial` only its missing tail.
+pub fn remaining_bytes<'a>(files: impl IntoIterator<Item = &'a FileTask>) -> u64 {
+    files
+        .into_iter()
+        .map(|task| {
+            let written = std::fs::symlink_metadata(&task.destination)
+                .map(|meta| meta.len())
+                .unwrap_or(0);
+            task.size.saturating_sub(written)
+        })
+        .sum()
+}
+
+/// Free space an unprivileged writer can use on the filesystem holding
+/// `path`, measured at its nearest existing ancestor.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|dir| !dir.as_os_str().is_empty() && dir.exists())
+        .unwrap_or(Path::new("."));
+    filesystem_available(existing)
+}
+
+#[cfg(unix)]
+fn filesystem_available(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)] // the field types vary by platform
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn filesystem_available(path: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    if unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    } == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn filesystem_available(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space can't be measured on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_remaining_bytes_counts_missing_data() {
+        let temp_dir = TempDir::new().unwrap();
+        let task = |name: &str, size| FileTask {
+            source: temp_dir.path().join("source"),
+            destination: temp_dir.path().join(name),
+            size,
+            inode_group: None,
+        };
+        fs::write(temp_dir.path().join("done"), vec![0u8; 100]).unwrap();
+        fs::write(temp_dir.path().join("partial"), vec![0u8; 30]).unwrap();
+
+        let files = [task("done", 100), task("partial", 100), task("missing", 50)];
+        assert_eq!(remaining_bytes(&files), 120);
+    }
+
+    #[test]
+    fn test_available_space_of_a_path_not_yet_created() {
+        let temp_dir = TempDir::new().unwrap();
+        let planned = temp_dir.path().join("not/yet/there.txt");
+        assert!(available_space(&planned).unwrap() > 0);
+    }
+}
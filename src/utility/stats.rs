@@ -0,0 +1,373 @@
+use crate::utility::throughput::{self, Throughput};
+use clap::ValueEnum;
+use indicatif::BinaryBytes;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const SLOWEST_FILES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+/// Counters shared by all workers of a single run.
+pub struct CopyStats {
+    started: Instant,
+    files_copied: AtomicUsize,
+    files_skipped: AtomicUsize,
+    files_excluded: AtomicUsize,
+    files_failed: AtomicUsize,
+    bytes_copied: AtomicU64,
+    reflinks: AtomicUsize,
+    hardlinks: AtomicUsize,
+    symlinks: AtomicUsize,
+    deduplicated: AtomicUsize,
+    bytes_deduplicated: AtomicU64,
+    throughput: Throughput,
+    slowest: Mutex<Vec<(PathBuf, Duration)>>,
+    unreadable: Mutex<Vec<PathBuf>>,
+    renamed: Mutex<Vec<(PathBuf, PathBuf)>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowFile {
+    pub path: PathBuf,
+    pub seconds: f64,
+}
+
+/// A destination `--sanitize-names` gave another name.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenamedPath {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSummary {
+    pub files_copied: usize,
+    pub files_skipped: usize,
+    /// Entries left out by excludes, marker files or `--hidden=skip`.
+    pub files_excluded: usize,
+    pub files_failed: usize,
+    pub bytes_copied: u64,
+    pub elapsed_seconds: f64,
+    pub average_bytes_per_sec: f64,
+    pub peak_bytes_per_sec: f64,
+    /// Bytes per second over the last minute of the run, one per second.
+    pub throughput_history: Vec<u64>,
+    pub reflinks: usize,
+    pub hardlinks: usize,
+    pub symlinks: usize,
+    /// Files hard-linked to an identical copy by `--dedupe`.
+    pub deduplicated: usize,
+    pub bytes_deduplicated: u64,
+    pub slowest_files: Vec<SlowFile>,
+    /// Entries the walk was denied access to and left out.
+    pub unreadable: Vec<PathBuf>,
+    /// Entries renamed for the destination filesystem.
+    pub renamed: Vec<RenamedPath>,
+}
+
+impl Default for CopyStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CopyStats {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            files_copied: AtomicUsize::new(0),
+            files_skipped: AtomicUsize::new(0),
+            files_excluded: AtomicUsize::new(0),
+            files_failed: AtomicUsize::new(0),
+            bytes_copied: AtomicU64::new(0),
+            reflinks: AtomicUsize::new(0),
+            hardlinks: AtomicUsize::new(0),
+            symlinks: AtomicUsize::new(0),
+            deduplicated: AtomicUsize::new(0),
+            bytes_deduplicated: AtomicU64::new(0),
+            throughput: Throughput::new(),
+            slowest: Mutex::new(Vec::new()),
+            unreadable: Mutex::new(Vec::new()),
+            renamed: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Marks a file as done and returns how many files are done so far.
+    pub fn record_copied(&self) -> usize {
+        self.files_copied.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn record_skipped(&self, count: usize) {
+        self.files_skipped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_excluded(&self, count: usize) {
+        self.files_excluded.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_failed(&self) {
+        self.files_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_reflink(&self) {
+        self.reflinks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_hardlink(&self) {
+        self.hardlinks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_symlink(&self) {
+        self.symlinks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A file linked to an identical copy instead of writing `size` bytes.
+    pub fn record_deduplicated(&self, size: u64) {
+        self.deduplicated.fetch_add(1, Ordering::Relaxed);
+        self.bytes_deduplicated.fetch_add(size, Ordering::Relaxed);
+    }
+
+    pub fn record_unreadable(&self, paths: &[PathBuf]) {
+        if let Ok(mut unreadable) = self.unreadable.lock() {
+            unreadable.extend_from_slice(paths);
+        }
+    }
+
+    pub fn record_renamed(&self, renames: &[(PathBuf, PathBuf)]) {
+        if let Ok(mut renamed) = self.renamed.lock() {
+            renamed.extend_from_slice(renames);
+        }
+    }
+
+    pub fn add_bytes(&self, delta: u64) {
+        self.bytes_copied.fetch_add(delta, Ordering::Relaxed);
+        self.throughput.add(delta);
+    }
+
+    pub fn record_duration(&self, path: &Path, duration: Duration) {
+        if let Ok(mut slowest) = self.slowest.lock() {
+            if slowest.len() == SLOWEST_FILES && slowest.last().is_some_and(|(_, d)| *d >= duration)
+            {
+                return;
+            }
+            slowest.push((path.to_path_buf(), duration));
+            slowest.sort_by_key(|(_, d)| std::cmp::Reverse(*d));
+            slowest.truncate(SLOWEST_FILES);
+        }
+    }
+
+    pub fn files_copied(&self) -> usize {
+        self.files_copied.load(Ordering::Relaxed)
+    }
+
+    pub fn files_failed(&self) -> usize {
+        self.files_failed.load(Ordering::Relaxed)
+    }
+
+    pub fn files_skipped(&self) -> usize {
+        self.files_skipped.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_copied(&self) -> u64 {
+        self.bytes_copied.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_deduplicated(&self) -> u64 {
+        self.bytes_deduplicated.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn summary(&self) -> StatsSummary {
+        let elapsed = self.elapsed().as_secs_f64();
+        let bytes = self.bytes_copied();
+        let average = if elapsed > 0.0 {
+            bytes as f64 / elapsed
+        } else {
+            0.0
+        };
+        // runs shorter than one sample never close it, fall back to the average
+        let peak = self.throughput.peak().max(average);
+        let slowest_files = self
+            .slowest
+            .lock()
+            .map(|s| {
+                s.iter()
+                    .map(|(path, d)| SlowFile {
+                        path: path.clone(),
+                        seconds: d.as_secs_f64(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        StatsSummary {
+            files_copied: self.files_copied(),
+            files_skipped: self.files_skipped(),
+            files_excluded: self.files_excluded.load(Ordering::Relaxed),
+            files_failed: self.files_failed(),
+            bytes_copied: bytes,
+            elapsed_seconds: elapsed,
+            average_bytes_per_sec: average,
+            peak_bytes_per_sec: peak,
+            throughput_history: self.throughput.history(),
+            reflinks: self.reflinks.load(Ordering::Relaxed),
+            hardlinks: self.hardlinks.load(Ordering::Relaxed),
+            symlinks: self.symlinks.load(Ordering::Relaxed),
+            deduplicated: self.deduplicated.load(Ordering::Relaxed),
+            bytes_deduplicated: self.bytes_deduplicated(),
+            slowest_files,
+            unreadable: self
+                .unreadable
+                .lock()
+                .map(|u| u.clone())
+                .unwrap_or_default(),
+            renamed: self
+                .renamed
+                .lock()
+                .map(|renamed| {
+                    renamed
+                        .iter()
+                        .map(|(from, to)| RenamedPath {
+                            from: from.clone(),
+                            to: to.clone(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl StatsSummary {
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("Files copied:     {}\n", self.files_copied));
+        out.push_str(&format!("Files skipped:    {}\n", self.files_skipped));
+        out.push_str(&format!("Files excluded:   {}\n", self.files_excluded));
+        out.push_str(&format!("Files failed:     {}\n", self.files_failed));
+        out.push_str(&format!(
+            "Bytes copied:     {}\n",
+            BinaryBytes(self.bytes_copied)
+        ));
+        out.push_str(&format!("Elapsed:          {:.2}s\n", self.elapsed_seconds));
+        out.push_str(&format!(
+            "Avg throughput:   {}/s\n",
+            BinaryBytes(self.average_bytes_per_sec as u64)
+        ));
+        out.push_str(&format!(
+            "Peak throughput:  {}/s\n",
+            BinaryBytes(self.peak_bytes_per_sec as u64)
+        ));
+        if self.throughput_history.len() > 1 {
+            let label = format!("Last {}s:", self.throughput_history.len());
+            out.push_str(&format!(
+                "{:<18}{}\n",
+                label,
+                throughput::sparkline(&self.throughput_history)
+            ));
+        }
+        out.push_str(&format!("Reflinks:         {}\n", self.reflinks));
+        out.push_str(&format!("Hard links:       {}\n", self.hardlinks));
+        out.push_str(&format!("Symlinks:         {}\n", self.symlinks));
+        if self.deduplicated > 0 {
+            out.push_str(&format!(
+                "Deduplicated:     {} ({} saved)\n",
+                self.deduplicated,
+                BinaryBytes(self.bytes_deduplicated)
+            ));
+        }
+        if !self.unreadable.is_empty() {
+            out.push_str(&format!("Unreadable:       {}\n", self.unreadable.len()));
+            for path in &self.unreadable {
+                out.push_str(&format!("  {}\n", path.display()));
+            }
+        }
+        if !self.renamed.is_empty() {
+            out.push_str(&format!("Renamed:          {}\n", self.renamed.len()));
+            for renamed in &self.renamed {
+                out.push_str(&format!(
+                    "  {} -> {}\n",
+                    renamed.from.display(),
+                    renamed.to.display()
+                ));
+            }
+        }
+        if !self.slowest_files.is_empty() {
+            out.push_str("Slowest files:\n");
+            for file in &self.slowest_files {
+                out.push_str(&format!(
+                    "  {:>8.2}s  {}\n",
+                    file.seconds,
+                    file.path.display()
+                ));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_counters() {
+        let stats = CopyStats::new();
+        assert_eq!(stats.record_copied(), 1);
+        assert_eq!(stats.record_copied(), 2);
+        stats.record_skipped(3);
+        stats.record_excluded(2);
+        stats.record_failed();
+        stats.record_reflink();
+        stats.add_bytes(100);
+        stats.add_bytes(28);
+
+        let summary = stats.summary();
+        assert_eq!(summary.files_copied, 2);
+        assert_eq!(summary.files_skipped, 3);
+        assert_eq!(summary.files_excluded, 2);
+        assert_eq!(summary.files_failed, 1);
+        assert_eq!(summary.reflinks, 1);
+        assert_eq!(summary.bytes_copied, 128);
+        assert!(summary.peak_bytes_per_sec >= summary.average_bytes_per_sec);
+    }
+
+    #[test]
+    fn test_slowest_files_keeps_top_entries() {
+        let stats = CopyStats::new();
+        for i in 0..10u64 {
+            stats.record_duration(
+                Path::new(&format!("file{}", i)),
+                Duration::from_millis(i * 10),
+            );
+        }
+
+        let summary = stats.summary();
+        assert_eq!(summary.slowest_files.len(), SLOWEST_FILES);
+        assert_eq!(summary.slowest_files[0].path, PathBuf::from("file9"));
+        assert_eq!(summary.slowest_files[4].path, PathBuf::from("file5"));
+    }
+
+    #[test]
+    fn test_summary_text() {
+        let stats = CopyStats::new();
+        stats.record_copied();
+        stats.record_duration(Path::new("big.bin"), Duration::from_secs(2));
+        let text = stats.summary().to_text();
+        assert!(text.contains("Files copied:     1"));
+        assert!(text.contains("Slowest files:"));
+        assert!(text.contains("big.bin"));
+    }
+}
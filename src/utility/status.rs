@@ -0,0 +1,452 @@
+use crate::utility::control::CopyControl;
+use crate::utility::output::Verbosity;
+use crate::utility::pause;
+use indicatif::{BinaryBytes, FormattedDuration};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// What `cpx status` shows for one running copy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunStatus {
+    pub pid: u32,
+    pub command: String,
+    pub paused: bool,
+    pub total_files: usize,
+    pub files_done: usize,
+    pub total_bytes: u64,
+    pub bytes_done: u64,
+    pub elapsed_seconds: f64,
+    /// `--bwlimit` in bytes per second, if the run is held to one.
+    #[serde(default)]
+    pub bwlimit: Option<u64>,
+    /// Files being copied right now.
+    pub current: Vec<PathBuf>,
+}
+
+/// A request to a running copy; every one is answered with its status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusRequest {
+    Status,
+    Pause,
+    Resume,
+    /// Sets `--bwlimit` to this many bytes per second; 0 lifts it.
+    Limit(u64),
+}
+
+impl StatusRequest {
+    /// The line sent for the request.
+    fn to_line(self) -> String {
+        match self {
+            StatusRequest::Status => "status".to_string(),
+            StatusRequest::Pause => "pause".to_string(),
+            StatusRequest::Resume => "resume".to_string(),
+            StatusRequest::Limit(rate) => format!("limit {}", rate),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        match line.trim() {
+            "status" => Some(StatusRequest::Status),
+            "pause" => Some(StatusRequest::Pause),
+            "resume" => Some(StatusRequest::Resume),
+            other => other
+                .strip_prefix("limit ")?
+                .parse()
+                .ok()
+                .map(StatusRequest::Limit),
+        }
+    }
+}
+
+/// Parses the rate given to `cpx status --bwlimit`: a size such as 50M, or
+/// `off` (or 0) to lift the limit.
+pub fn parse_limit(s: &str) -> Result<u64, String> {
+    match s {
+        "off" | "0" => Ok(0),
+        _ => crate::core::bench::parse_size(s),
+    }
+}
+
+impl RunStatus {
+    fn new(control: &CopyControl, command: &str, started: Instant) -> Self {
+        let mut status = RunStatus {
+            pid: std::process::id(),
+            command: command.to_string(),
+            paused: control.is_paused(),
+            total_files: 0,
+            files_done: 0,
+            total_bytes: 0,
+            bytes_done: 0,
+            elapsed_seconds: started.elapsed().as_secs_f64(),
+            bwlimit: control.bwlimit(),
+            current: Vec::new(),
+        };
+        if let Some(activity) = control.activity() {
+            status.total_files = activity.total_files.load(Ordering::Relaxed);
+            status.files_done = activity.files_done.load(Ordering::Relaxed);
+            status.total_bytes = activity.total_bytes.load(Ordering::Relaxed);
+            status.bytes_done = activity.bytes_done.load(Ordering::Relaxed);
+            status.current = activity
+                .workers()
+                .into_iter()
+                .map(|(_, worker)| worker.source)
+                .collect();
+        }
+        status
+    }
+
+    pub fn to_text(&self) -> String {
+        let percent = (self.bytes_done * 100)
+            .checked_div(self.total_bytes)
+            .unwrap_or(0);
+        let mut out = format!("{}  {}\n", self.pid, self.command);
+        out.push_str(&format!(
+            "  {}/{} files, {} of {} ({}%), {} elapsed{}\n",
+            self.files_done,
+            self.total_files,
+            BinaryBytes(self.bytes_done),
+            BinaryBytes(self.total_bytes),
+            percent,
+            FormattedDuration(Duration::from_secs_f64(self.elapsed_seconds)),
+            if self.paused { ", paused" } else { "" }
+        ));
+        if let Some(rate) = self.bwlimit {
+            out.push_str(&format!("  limited to {}/s\n", BinaryBytes(rate)));
+        }
+        for path in &self.current {
+            out.push_str(&format!("  {}\n", path.display()));
+        }
+        out
+    }
+}
+
+/// Where running copies listen, one socket per process named by its pid.
+#[cfg(unix)]
+pub fn socket_dir() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("cpx"),
+        None => std::env::temp_dir().join(format!("cpx-{}", nix::unistd::getuid())),
+    }
+}
+
+/// Answers `cpx status` for this process until dropped: on a socket in
+/// [`socket_dir`] on Unix, which the drop removes, and on the named pipe
+/// `\\.\pipe\cpx-<pid>` on Windows, which goes away with the process.
+#[derive(Debug)]
+pub struct StatusServer {
+    #[cfg(unix)]
+    path: PathBuf,
+}
+
+impl StatusServer {
+    /// Starts answering; `command` is shown to whoever asks, and pauses
+    /// requested by them are announced at `verbosity`.
+    #[cfg(unix)]
+    pub fn start(
+        control: Arc<CopyControl>,
+        command: String,
+        verbosity: Verbosity,
+    ) -> io::Result<Self> {
+        use std::os::unix::fs::DirBuilderExt;
+
+        let dir = socket_dir();
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)?;
+        let path = dir.join(format!("{}.sock", std::process::id()));
+        // left behind by an earlier process with the same pid
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+
+        let started = Instant::now();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = answer(&stream, &control, &command, started, verbosity);
+            }
+        });
+        Ok(Self { path })
+    }
+
+    #[cfg(windows)]
+    pub fn start(
+        control: Arc<CopyControl>,
+        command: String,
+        verbosity: Verbosity,
+    ) -> io::Result<Self> {
+        let pipe = create_pipe(&pipe_name(std::process::id()))?;
+        let started = Instant::now();
+        // one instance, reconnected for each client in turn
+        std::thread::spawn(move || {
+            while connect_pipe(&pipe).is_ok() {
+                let _ = answer(&pipe, &control, &command, started, verbosity);
+                disconnect_pipe(&pipe);
+            }
+        });
+        Ok(Self {})
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    pub fn start(
+        _control: Arc<CopyControl>,
+        _command: String,
+        _verbosity: Verbosity,
+    ) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cpx status is not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for StatusServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Reads one request from `stream`, carries it out and replies with the
+/// run's status.
+#[cfg(any(unix, windows))]
+fn answer(
+    mut stream: impl Read + Write,
+    control: &CopyControl,
+    command: &str,
+    started: Instant,
+    verbosity: Verbosity,
+) -> io::Result<()> {
+    let mut request = String::new();
+    BufReader::new(&mut stream).read_line(&mut request)?;
+    match StatusRequest::parse(&request) {
+        Some(StatusRequest::Pause) => pause::set_paused(control, true, verbosity),
+        Some(StatusRequest::Resume) => pause::set_paused(control, false, verbosity),
+        Some(StatusRequest::Limit(rate)) => control.set_bwlimit(Some(rate)),
+        Some(StatusRequest::Status) | None => {}
+    }
+    let status = RunStatus::new(control, command, started);
+    serde_json::to_writer(&mut stream, &status)?;
+    writeln!(stream)
+}
+
+/// Sends `request` over `stream` and reads the reply.
+#[cfg(any(unix, windows))]
+fn exchange(mut stream: impl Read + Write, request: StatusRequest) -> io::Result<RunStatus> {
+    writeln!(stream, "{}", request.to_line())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    serde_json::from_str(&reply).map_err(io::Error::other)
+}
+
+/// Sends `request` to every running copy, or only to `pid`, and collects
+/// the replies. Sockets nobody answers on belong to copies that died and
+/// are removed.
+#[cfg(unix)]
+pub fn query(pid: Option<u32>, request: StatusRequest) -> io::Result<Vec<RunStatus>> {
+    let entries = match fs::read_dir(socket_dir()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut runs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(owner) = path
+            .file_name()
+            .and_then(|name| name.to_str()?.strip_suffix(".sock")?.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid.is_some_and(|pid| pid != owner) {
+            continue;
+        }
+        let stream = match UnixStream::connect(&path) {
+            Ok(stream) => stream,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::ConnectionRefused | io::ErrorKind::NotFound
+                ) =>
+            {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        runs.push(exchange(&stream, request)?);
+    }
+    runs.sort_by_key(|run: &RunStatus| run.pid);
+    Ok(runs)
+}
+
+/// Sends `request` to every running copy, or only to `pid`, found by their
+/// pipes, and collects the replies.
+#[cfg(windows)]
+pub fn query(pid: Option<u32>, request: StatusRequest) -> io::Result<Vec<RunStatus>> {
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(r"\\.\pipe\")?.flatten() {
+        let Some(owner) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("cpx-")?.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid.is_some_and(|pid| pid != owner) {
+            continue;
+        }
+        // gone if the copy ended since the listing
+        if let Some(pipe) = open_pipe(&pipe_name(owner))? {
+            runs.push(exchange(&pipe, request)?);
+        }
+    }
+    runs.sort_by_key(|run: &RunStatus| run.pid);
+    Ok(runs)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn query(_pid: Option<u32>, _request: StatusRequest) -> io::Result<Vec<RunStatus>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cpx status is not supported on this platform",
+    ))
+}
+
+#[cfg(windows)]
+fn pipe_name(pid: u32) -> String {
+    format!(r"\\.\pipe\cpx-{}", pid)
+}
+
+/// The server end of the pipe `name`, taking only clients from this
+/// machine. Fails if another process already holds the name.
+#[cfg(windows)]
+fn create_pipe(name: &str) -> io::Result<fs::File> {
+    use std::os::windows::io::FromRawHandle;
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT,
+    };
+
+    let wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+    let handle = unsafe {
+        CreateNamedPipeW(
+            wide.as_ptr(),
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            4096,
+            4096,
+            0,
+            std::ptr::null(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { fs::File::from_raw_handle(handle) })
+}
+
+/// Waits for a client on `pipe`.
+#[cfg(windows)]
+fn connect_pipe(pipe: &fs::File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::ERROR_PIPE_CONNECTED;
+    use windows_sys::Win32::System::Pipes::ConnectNamedPipe;
+
+    if unsafe { ConnectNamedPipe(pipe.as_raw_handle(), std::ptr::null_mut()) } != 0 {
+        return Ok(());
+    }
+    let e = io::Error::last_os_error();
+    // a client that opened the pipe before the wait started
+    if e.raw_os_error() == Some(ERROR_PIPE_CONNECTED as i32) {
+        Ok(())
+    } else {
+        Err(e)
+    }
+}
+
+/// Lets the client read the whole reply, then frees `pipe` for the next.
+#[cfg(windows)]
+fn disconnect_pipe(pipe: &fs::File) {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::System::Pipes::DisconnectNamedPipe;
+
+    let _ = pipe.sync_all();
+    unsafe { DisconnectNamedPipe(pipe.as_raw_handle()) };
+}
+
+/// The client end of the pipe `name`, waiting while the copy answers
+/// someone else; `None` if the pipe is gone.
+#[cfg(windows)]
+fn open_pipe(name: &str) -> io::Result<Option<fs::File>> {
+    use windows_sys::Win32::Foundation::ERROR_PIPE_BUSY;
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match fs::OpenOptions::new().read(true).write(true).open(name) {
+            Ok(pipe) => return Ok(Some(pipe)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e)
+                if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32)
+                    && Instant::now() < deadline =>
+            {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_status_text() {
+        let control = CopyControl::tracked();
+        control.set_totals(4, 4096);
+        control.start_file(std::path::Path::new("src/big.iso"), 4000);
+        control.add_bytes(1024);
+        control.send(crate::utility::control::ControlCommand::Pause);
+
+        let status = RunStatus::new(&control, "cpx -r src dst", Instant::now());
+        let text = status.to_text();
+        assert!(text.contains("cpx -r src dst"));
+        assert!(text.contains("0/4 files, 1.00 KiB of 4.00 KiB (25%)"));
+        assert!(text.contains("paused"));
+        assert!(text.contains("src/big.iso"));
+        assert!(!text.contains("limited"));
+
+        control.set_bwlimit(Some(10 << 20));
+        let text = RunStatus::new(&control, "cpx", Instant::now()).to_text();
+        assert!(text.contains("limited to 10.00 MiB/s"));
+    }
+
+    #[test]
+    fn test_status_request_lines() {
+        for request in [
+            StatusRequest::Status,
+            StatusRequest::Pause,
+            StatusRequest::Resume,
+            StatusRequest::Limit(0),
+            StatusRequest::Limit(1 << 20),
+        ] {
+            assert_eq!(StatusRequest::parse(&request.to_line()), Some(request));
+        }
+        assert_eq!(StatusRequest::parse("limit fast"), None);
+        assert_eq!(parse_limit("off"), Ok(0));
+        assert_eq!(parse_limit("2M"), Ok(2 << 20));
+    }
+}
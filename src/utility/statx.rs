@@ -0,0 +1,78 @@
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// What `std::fs::Metadata` leaves out: the birth time where the kernel
+/// records one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Statx {
+    pub birth_time: Option<SystemTime>,
+}
+
+/// Reads [`Statx`] for `path`, following a final symlink when `follow`.
+/// Fields the filesystem or kernel can't provide are `None`.
+#[cfg(target_os = "linux")]
+pub fn statx(path: &Path, follow: bool) -> io::Result<Statx> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::time::Duration;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+    let mut buf: libc::statx = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            flags | libc::AT_STATX_SYNC_AS_STAT,
+            libc::STATX_BTIME,
+            &mut buf,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let birth_time = (buf.stx_mask & libc::STATX_BTIME != 0).then(|| {
+        let since_epoch = Duration::new(buf.stx_btime.tv_sec.unsigned_abs(), buf.stx_btime.tv_nsec);
+        if buf.stx_btime.tv_sec >= 0 {
+            SystemTime::UNIX_EPOCH + since_epoch
+        } else {
+            SystemTime::UNIX_EPOCH - since_epoch
+        }
+    });
+    Ok(Statx { birth_time })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn statx(path: &Path, follow: bool) -> io::Result<Statx> {
+    let metadata = if follow {
+        std::fs::metadata(path)?
+    } else {
+        std::fs::symlink_metadata(path)?
+    };
+    Ok(Statx {
+        birth_time: metadata.created().ok(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_statx_matches_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("a.txt");
+        std::fs::write(&file, b"a").unwrap();
+
+        let stat = statx(&file, true).unwrap();
+        assert_eq!(
+            stat.birth_time,
+            std::fs::metadata(&file).unwrap().created().ok()
+        );
+        assert!(statx(&temp_dir.path().join("missing"), true).is_err());
+    }
+}
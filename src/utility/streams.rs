@@ -0,0 +1,130 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Stream Windows attaches to downloaded files ("mark of the web").
+pub const ZONE_IDENTIFIER: &str = "Zone.Identifier";
+
+/// Copies the NTFS alternate data streams of `source` onto `destination`,
+/// leaving out `Zone.Identifier` when `strip_zone` is set. Returns how many
+/// streams were copied.
+#[cfg(windows)]
+pub fn copy_streams(source: &Path, destination: &Path, strip_zone: bool) -> io::Result<usize> {
+    if strip_zone {
+        // an overwritten destination keeps the streams it already had
+        let _ = std::fs::remove_file(with_stream(destination, ZONE_IDENTIFIER));
+    }
+    let mut copied = 0;
+    for name in list_streams(source)? {
+        if strip_zone && name.eq_ignore_ascii_case(ZONE_IDENTIFIER) {
+            continue;
+        }
+        let mut reader = std::fs::File::open(with_stream(source, &name))?;
+        let mut writer = std::fs::File::create(with_stream(destination, &name))?;
+        io::copy(&mut reader, &mut writer)?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+/// Only NTFS has alternate data streams.
+#[cfg(not(windows))]
+pub fn copy_streams(_source: &Path, _destination: &Path, _strip_zone: bool) -> io::Result<usize> {
+    Ok(0)
+}
+
+#[cfg(windows)]
+fn list_streams(path: &Path) -> io::Result<Vec<String>> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{
+        ERROR_HANDLE_EOF, ERROR_INVALID_PARAMETER, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+        WIN32_FIND_STREAM_DATA,
+    };
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut data = WIN32_FIND_STREAM_DATA::default();
+    let handle = unsafe {
+        FindFirstStreamW(
+            wide.as_ptr(),
+            FindStreamInfoStandard,
+            (&mut data as *mut WIN32_FIND_STREAM_DATA).cast(),
+            0,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            // no streams at all, or a filesystem without them (FAT, exFAT)
+            Some(code) if code as u32 == ERROR_HANDLE_EOF => Ok(Vec::new()),
+            Some(code) if code as u32 == ERROR_INVALID_PARAMETER => Ok(Vec::new()),
+            _ => Err(e),
+        };
+    }
+
+    let mut names = Vec::new();
+    let result = loop {
+        let len = data
+            .cStreamName
+            .iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(data.cStreamName.len());
+        match String::from_utf16(&data.cStreamName[..len]) {
+            Ok(raw) => {
+                if let Some(name) = stream_name(&raw) {
+                    names.push(name.to_string());
+                }
+            }
+            Err(e) => break Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+        if unsafe { FindNextStreamW(handle, (&mut data as *mut WIN32_FIND_STREAM_DATA).cast()) }
+            == 0
+        {
+            let e = io::Error::last_os_error();
+            break match e.raw_os_error() {
+                Some(code) if code as u32 == ERROR_HANDLE_EOF => Ok(names),
+                _ => Err(e),
+            };
+        }
+    };
+    unsafe { FindClose(handle) };
+    result
+}
+
+/// `:name:$DATA` as listed by `FindFirstStreamW`, without the decoration;
+/// `None` for the unnamed main stream (`::$DATA`) and non-data streams.
+pub fn stream_name(raw: &str) -> Option<&str> {
+    raw.strip_prefix(':')?
+        .strip_suffix(":$DATA")
+        .filter(|name| !name.is_empty())
+}
+
+/// `path:name`, the path Windows opens a named stream by.
+pub fn with_stream(path: &Path, name: &str) -> PathBuf {
+    let mut full = OsString::from(path.as_os_str());
+    full.push(":");
+    full.push(name);
+    PathBuf::from(full)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_names() {
+        assert_eq!(
+            stream_name(":Zone.Identifier:$DATA"),
+            Some("Zone.Identifier")
+        );
+        assert_eq!(stream_name(":custom:$DATA"), Some("custom"));
+        assert_eq!(stream_name("::$DATA"), None);
+        assert_eq!(stream_name(":name:$OTHER"), None);
+        assert_eq!(
+            with_stream(Path::new("dir/file.txt"), ZONE_IDENTIFIER),
+            PathBuf::from("dir/file.txt:Zone.Identifier")
+        );
+    }
+}
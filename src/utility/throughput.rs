@@ -0,0 +1,142 @@
+use indicatif::BinaryBytes;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Length of one throughput sample.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Samples kept for the sparkline: the last minute.
+const HISTORY: usize = 60;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Bytes per second over the last minute, one sample per second, and the
+/// peak over the whole run. Fed with every chunk written.
+#[derive(Debug)]
+pub struct Throughput {
+    state: Mutex<Samples>,
+}
+
+#[derive(Debug)]
+struct Samples {
+    started: Instant,
+    bytes: u64,
+    history: VecDeque<u64>,
+    peak: f64,
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(Samples {
+                started: Instant::now(),
+                bytes: 0,
+                history: VecDeque::with_capacity(HISTORY),
+                peak: 0.0,
+            }),
+        }
+    }
+
+    pub fn add(&self, delta: u64) {
+        if let Ok(mut samples) = self.state.lock() {
+            samples.bytes += delta;
+            samples.close_due(Instant::now());
+        }
+    }
+
+    /// Completed samples, oldest first. A second with nothing written
+    /// reads as 0 once it is over.
+    pub fn history(&self) -> Vec<u64> {
+        self.state
+            .lock()
+            .map(|mut samples| {
+                samples.close_due(Instant::now());
+                samples.history.iter().copied().collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn peak(&self) -> f64 {
+        self.state.lock().map(|samples| samples.peak).unwrap_or(0.0)
+    }
+}
+
+impl Samples {
+    fn close_due(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.started);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+        let rate = self.bytes as f64 / elapsed.as_secs_f64();
+        self.peak = self.peak.max(rate);
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(rate as u64);
+        self.started = now;
+        self.bytes = 0;
+    }
+}
+
+/// `samples` as block characters scaled to the largest one.
+pub fn sparkline(samples: &[u64]) -> String {
+    let max = samples.iter().copied().max().unwrap_or(0).max(1);
+    samples
+        .iter()
+        .map(|&sample| {
+            let level = (sample as u128 * (SPARKS.len() - 1) as u128).div_ceil(max as u128);
+            SPARKS[level as usize]
+        })
+        .collect()
+}
+
+/// e.g. `▃▅▇█▆ now 412 MiB/s • avg 380 MiB/s • peak 455 MiB/s`
+pub fn format_line(history: &[u64], average: f64, peak: f64) -> String {
+    let current = history.last().copied().unwrap_or(average as u64);
+    let speeds = format!(
+        "now {}/s • avg {}/s • peak {}/s",
+        BinaryBytes(current),
+        BinaryBytes(average as u64),
+        BinaryBytes(peak.max(average) as u64),
+    );
+    if history.is_empty() {
+        speeds
+    } else {
+        format!("{} {}", sparkline(history), speeds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparkline_scales_to_the_largest_sample() {
+        assert_eq!(sparkline(&[0, 50, 100]), "▁▅█");
+        assert_eq!(sparkline(&[0, 0]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_samples_close_each_second() {
+        let mut samples = Samples {
+            started: Instant::now(),
+            bytes: 2048,
+            history: VecDeque::new(),
+            peak: 0.0,
+        };
+        let start = samples.started;
+        samples.close_due(start + Duration::from_millis(500));
+        assert!(samples.history.is_empty());
+
+        samples.close_due(start + Duration::from_secs(2));
+        samples.close_due(start + Duration::from_secs(3));
+        assert_eq!(samples.history, [1024, 0]);
+        assert_eq!(samples.peak, 1024.0);
+    }
+}
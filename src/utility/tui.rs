@@ -0,0 +1,259 @@
+use crate::error::CopyResult;
+use crate::utility::control::{Activity, ControlCommand, CopyControl};
+use indicatif::BinaryBytes;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Gauge, List, ListItem, ListState, Paragraph, Sparkline};
+use std::io;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+const TICK: Duration = Duration::from_millis(200);
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_SAMPLES: usize = 300;
+
+/// Runs `copy` on a background thread while the main thread draws the
+/// dashboard and turns key presses into control commands. The terminal is
+/// restored before returning, whatever the outcome.
+pub fn run<F>(control: &CopyControl, copy: F) -> io::Result<CopyResult<()>>
+where
+    F: FnOnce() -> CopyResult<()> + Send,
+{
+    let mut terminal = ratatui::try_init()?;
+    let (result, ui) = std::thread::scope(|scope| {
+        let worker = scope.spawn(copy);
+        let mut dashboard = Dashboard::new();
+        let ui = loop {
+            if worker.is_finished() {
+                break Ok(());
+            }
+            if let Err(e) = terminal.draw(|frame| dashboard.render(frame, control)) {
+                break Err(e);
+            }
+            if let Err(e) = dashboard.handle_events(control) {
+                break Err(e);
+            }
+        };
+        if ui.is_err() {
+            // don't leave the copy running behind a broken screen
+            control.abort();
+        }
+        (worker.join(), ui)
+    });
+    ratatui::restore();
+
+    let result = result.unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    ui.map(|()| result)
+}
+
+#[derive(Debug, Default)]
+struct Dashboard {
+    selected: ListState,
+    samples: Vec<u64>,
+    last_sample: Option<(Instant, u64)>,
+}
+
+impl Dashboard {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn handle_events(&mut self, control: &CopyControl) -> io::Result<()> {
+        if !event::poll(TICK)? {
+            return Ok(());
+        }
+        let Event::Key(key) = event::read()? else {
+            return Ok(());
+        };
+        if key.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        match key.code {
+            KeyCode::Char('p') | KeyCode::Char(' ') => control.send(if control.is_paused() {
+                ControlCommand::Resume
+            } else {
+                ControlCommand::Pause
+            }),
+            KeyCode::Char('s') => {
+                if let Some(activity) = control.activity()
+                    && let Some((worker, _)) = self
+                        .selected
+                        .selected()
+                        .and_then(|index| activity.workers().get(index).cloned())
+                {
+                    control.send(ControlCommand::Skip(worker));
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.selected.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.selected.select_next(),
+            KeyCode::Char('q') | KeyCode::Esc => control.abort(),
+            // raw mode swallows SIGINT, so Ctrl+C arrives as a key
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => control.abort(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn sample(&mut self, bytes: u64) {
+        let now = Instant::now();
+        match self.last_sample {
+            Some((at, previous)) if now.duration_since(at) >= SAMPLE_INTERVAL => {
+                let rate =
+                    (bytes.saturating_sub(previous)) as f64 / now.duration_since(at).as_secs_f64();
+                if self.samples.len() == MAX_SAMPLES {
+                    self.samples.remove(0);
+                }
+                self.samples.push(rate as u64);
+                self.last_sample = Some((now, bytes));
+            }
+            Some(_) => {}
+            None => self.last_sample = Some((now, bytes)),
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame, control: &CopyControl) {
+        let Some(activity) = control.activity() else {
+            return;
+        };
+        let bytes_done = activity.bytes_done.load(Ordering::Relaxed);
+        self.sample(bytes_done);
+
+        let [summary, graph, workers, errors, help] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(7),
+            Constraint::Min(5),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(overall_gauge(activity, control), summary);
+
+        let rate = self.samples.last().copied().unwrap_or(0);
+        let visible = self
+            .samples
+            .len()
+            .saturating_sub(graph.width.saturating_sub(2) as usize);
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::bordered().title(format!(" Throughput {}/s ", BinaryBytes(rate))))
+                .data(&self.samples[visible..])
+                .style(Style::default().fg(Color::Cyan)),
+            graph,
+        );
+
+        let active = activity.workers();
+        let items: Vec<ListItem> = active
+            .iter()
+            .map(|(id, status)| {
+                let percent = (status.copied * 100)
+                    .checked_div(status.size)
+                    .unwrap_or(100)
+                    .min(100);
+                ListItem::new(format!(
+                    "#{:<3} {:>3}%  {}",
+                    id,
+                    percent,
+                    status.source.display()
+                ))
+            })
+            .collect();
+        match self.selected.selected() {
+            _ if active.is_empty() => self.selected.select(None),
+            None => self.selected.select(Some(0)),
+            Some(index) if index >= active.len() => self.selected.select(Some(active.len() - 1)),
+            Some(_) => {}
+        }
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(format!(" Workers ({}) ", active.len())))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            workers,
+            &mut self.selected,
+        );
+
+        let recent = activity.errors();
+        let error_items: Vec<ListItem> = recent
+            .iter()
+            .rev()
+            .map(|error| ListItem::new(error.as_str()).style(Style::default().fg(Color::Red)))
+            .collect();
+        frame.render_widget(
+            List::new(error_items)
+                .block(Block::bordered().title(format!(" Errors ({}) ", recent.len()))),
+            errors,
+        );
+
+        frame.render_widget(
+            Paragraph::new("p pause/resume • s skip selected file • ↑/↓ select worker • q abort")
+                .style(Style::default().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+}
+
+fn overall_gauge<'a>(activity: &Activity, control: &CopyControl) -> Gauge<'a> {
+    let total_bytes = activity.total_bytes.load(Ordering::Relaxed);
+    let bytes_done = activity.bytes_done.load(Ordering::Relaxed);
+    let ratio = if total_bytes == 0 {
+        0.0
+    } else {
+        (bytes_done as f64 / total_bytes as f64).min(1.0)
+    };
+    let state = if control.is_aborted() {
+        " • stopping"
+    } else if control.is_paused() {
+        " • PAUSED"
+    } else {
+        ""
+    };
+    Gauge::default()
+        .block(Block::bordered().title(" cpx "))
+        .gauge_style(Style::default().fg(Color::Green))
+        .ratio(ratio)
+        .label(format!(
+            "{}/{} files • {}/{}{}",
+            activity.files_done.load(Ordering::Relaxed),
+            activity.total_files.load(Ordering::Relaxed),
+            BinaryBytes(bytes_done),
+            BinaryBytes(total_bytes),
+            state
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use std::path::Path;
+
+    #[test]
+    fn test_dashboard_shows_workers_and_errors() {
+        let control = CopyControl::tracked();
+        control.set_totals(2, 2048);
+        control.start_file(Path::new("big.bin"), 2048);
+        control.add_bytes(1024);
+        control.send(ControlCommand::Pause);
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        let mut dashboard = Dashboard::new();
+        terminal
+            .draw(|frame| dashboard.render(frame, &control))
+            .unwrap();
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("big.bin"));
+        assert!(screen.contains("50%"));
+        assert!(screen.contains("PAUSED"));
+        assert!(screen.contains("Errors (0)"));
+    }
+}
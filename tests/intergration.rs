@@ -158,6 +158,94 @@ fn test_copy_with_resume_flag() {
     dest.assert("Same content");
 }
 
+#[test]
+fn test_on_conflict_skip_and_rename() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("src");
+    source.child("a.txt").write_str("new a").unwrap();
+    source.child("b.txt").write_str("new b").unwrap();
+    let dest = temp.child("dest");
+    dest.child("src/a.txt").write_str("old a").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--on-conflict=skip")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+    dest.child("src/a.txt").assert("old a");
+    dest.child("src/b.txt").assert("new b");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--on-conflict=rename")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+    dest.child("src/a.txt").assert("old a");
+    dest.child("src/a (1).txt").assert("new a");
+    dest.child("src/b (1).txt").assert("new b");
+}
+
+#[test]
+fn test_on_conflict_error_fails_existing_files() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+
+    source.write_str("New content").unwrap();
+    dest.write_str("Old content").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--on-conflict=error")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("File already exists"));
+
+    dest.assert("Old content");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_on_conflict_overwrite_replaces_links() {
+    use std::os::unix::fs::MetadataExt;
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+
+    source.write_str("content").unwrap();
+    dest.write_str("in the way").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-l")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .failure();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-l")
+        .arg("--on-conflict=overwrite")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+
+    let source_meta = std::fs::metadata(source.path()).unwrap();
+    let dest_meta = std::fs::metadata(dest.path()).unwrap();
+    assert_eq!(source_meta.ino(), dest_meta.ino());
+}
+
 #[test]
 fn test_copy_with_force_flag() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -215,6 +303,41 @@ fn test_copy_with_parallel() {
     }
 }
 
+#[test]
+fn test_copy_with_auto_parallel() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source");
+    for i in 0..50 {
+        source
+            .child(format!("dir{}/file{}.txt", i % 5, i))
+            .write_str(&format!("Content {}", i))
+            .unwrap();
+    }
+    let dest = temp.child("dest");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("-j")
+        .arg("auto")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+
+    for i in 0..50 {
+        dest.child(format!("source/dir{}/file{}.txt", i % 5, i))
+            .assert(format!("Content {}", i));
+    }
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-j")
+        .arg("0")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .failure();
+}
+
 #[test]
 fn test_invalid_source() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -413,6 +536,43 @@ fn test_preserve_existing_symlink() {
     assert_eq!(original_target, copied_target);
 }
 
+#[test]
+#[cfg(unix)]
+fn test_archive_mode_copies_tree_as_is() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source");
+    let dest = temp.child("dest");
+    source.child("file.txt").write_str("content").unwrap();
+    dest.create_dir_all().unwrap();
+    symlink("file.txt", source.child("link").path()).unwrap();
+
+    let file = fs::File::options()
+        .write(true)
+        .open(source.child("file.txt").path())
+        .unwrap();
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    file.set_modified(mtime).unwrap();
+    file.set_permissions(fs::Permissions::from_mode(0o640))
+        .unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-a")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+
+    let copied = fs::metadata(dest.child("source/file.txt").path()).unwrap();
+    assert_eq!(copied.permissions().mode() & 0o777, 0o640);
+    assert_eq!(copied.modified().unwrap(), mtime);
+    let link = dest.child("source/link");
+    assert!(link.path().symlink_metadata().unwrap().is_symlink());
+    assert_eq!(
+        fs::read_link(link.path()).unwrap(),
+        std::path::Path::new("file.txt")
+    );
+}
+
 #[test]
 #[cfg(unix)]
 fn test_hardlink_single_file() {
@@ -579,6 +739,38 @@ fn test_preserve_mode() {
     assert_eq!(dest_mode, 0o755);
 }
 
+#[test]
+#[cfg(unix)]
+fn test_no_preserve_overrides_preserve() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+
+    source.write_str("content").unwrap();
+
+    let mut perms = fs::metadata(source.path()).unwrap().permissions();
+    perms.set_mode(0o700);
+    fs::set_permissions(source.path(), perms).unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--preserve=mode,timestamps")
+        .arg("--no-preserve=mode,attrs")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+
+    let dest_mode = fs::metadata(dest.path()).unwrap().permissions().mode() & 0o777;
+    assert_ne!(dest_mode, 0o700);
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-preserve=bogus")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .failure();
+}
+
 #[test]
 fn test_preserve_timestamps() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -708,6 +900,137 @@ fn test_exclude_multiple_patterns() {
     assert!(!dest_dir.child("source/.git").path().exists());
 }
 
+#[test]
+fn test_exclude_vcs_and_common() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    source_dir.child("src/main.rs").write_str("keep").unwrap();
+    source_dir.child(".git/HEAD").write_str("exclude").unwrap();
+    source_dir
+        .child("target/debug/app")
+        .write_str("build")
+        .unwrap();
+
+    let vcs_dest = temp.child("vcs");
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--exclude-vcs")
+        .arg(source_dir.path())
+        .arg(vcs_dest.path())
+        .assert()
+        .success();
+    vcs_dest.child("source/src/main.rs").assert("keep");
+    vcs_dest.child("source/target/debug/app").assert("build");
+    assert!(!vcs_dest.child("source/.git").path().exists());
+
+    let common_dest = temp.child("common");
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--exclude-common")
+        .arg("-e")
+        .arg("*.rs")
+        .arg(source_dir.path())
+        .arg(common_dest.path())
+        .assert()
+        .success();
+    assert!(!common_dest.child("source/src/main.rs").path().exists());
+    assert!(!common_dest.child("source/.git").path().exists());
+    assert!(!common_dest.child("source/target").path().exists());
+}
+
+#[test]
+fn test_confirm_asks_before_writing() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    source_dir.child("new.txt").write_str("new").unwrap();
+    source_dir.child("old.txt").write_str("updated").unwrap();
+    let dest_dir = temp.child("dest");
+    dest_dir.child("source/old.txt").write_str("old").unwrap();
+
+    // stdin isn't a terminal here, so the question must be answered up front
+    assert_cmd::Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--confirm")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .write_stdin("y\n")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("stdin is not a terminal"));
+
+    assert_cmd::Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--confirm")
+        .arg("--assume-no")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "2 files, 10 B, 1 to overwrite, 0 to skip",
+        ));
+    assert!(!dest_dir.child("source/new.txt").path().exists());
+    dest_dir.child("source/old.txt").assert("old");
+
+    assert_cmd::Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("-i")
+        .arg("--assume-yes")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success();
+    dest_dir.child("source/new.txt").assert("new");
+    dest_dir.child("source/old.txt").assert("updated");
+}
+
+#[test]
+fn test_assume_no_keeps_existing_files() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    source.write_str("new").unwrap();
+    let dest = temp.child("dest.txt");
+    dest.write_str("old").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-i")
+        .arg("--assume-no")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+    dest.assert("old");
+}
+
+#[test]
+fn test_list_conflicts_copies_nothing() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    source_dir.child("new.txt").write_str("new").unwrap();
+    source_dir.child("old.txt").write_str("updated").unwrap();
+    let dest_dir = temp.child("dest");
+    dest_dir.child("source/old.txt").write_str("old").unwrap();
+    let report = temp.child("conflicts.txt");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg(format!("--list-conflicts={}", report.path().display()))
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "1 existing destinations would be overwritten",
+        ));
+
+    report.assert(format!(
+        "{}\n",
+        dest_dir.child("source/old.txt").path().display()
+    ));
+    assert!(!dest_dir.child("source/new.txt").path().exists());
+    dest_dir.child("source/old.txt").assert("old");
+}
+
 #[test]
 fn test_exclude_relative_path() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -1020,58 +1343,178 @@ fn test_remove_destination_flag() {
 }
 
 #[test]
-fn test_copy_very_long_filename() {
+#[cfg(unix)]
+fn test_remove_destination_replaces_dangling_symlink() {
     let temp = assert_fs::TempDir::new().unwrap();
-    let long_name = "a".repeat(200) + ".txt";
-    let source = temp.child(&long_name);
-    let dest_dir = temp.child("dest");
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
 
-    source.write_str("content").unwrap();
-    dest_dir.create_dir_all().unwrap();
+    source.write_str("new").unwrap();
+    symlink("missing.txt", dest.path()).unwrap();
 
     Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--remove-destination")
         .arg(source.path())
-        .arg(dest_dir.path())
+        .arg(dest.path())
         .assert()
         .success();
 
-    dest_dir.child(&long_name).assert("content");
+    // the link itself is replaced, rather than written through
+    assert!(!dest.path().symlink_metadata().unwrap().is_symlink());
+    dest.assert("new");
+    temp.child("missing.txt").assert(predicate::path::missing());
 }
 
 #[test]
-fn test_config_init() {
+#[cfg(unix)]
+fn test_no_dereference_dest_replaces_links() {
     let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("app");
+    let release = temp.child("release");
+    let dest = temp.child("dest");
+    source.child("lib/code.txt").write_str("new").unwrap();
+    source.child("config.txt").write_str("new").unwrap();
+    release.child("code.txt").write_str("old").unwrap();
+    release.child("config.txt").write_str("old").unwrap();
+    dest.child("app").create_dir_all().unwrap();
+    symlink(release.path(), dest.child("app/lib").path()).unwrap();
+    symlink(
+        release.child("config.txt").path(),
+        dest.child("app/config.txt").path(),
+    )
+    .unwrap();
 
     Command::new(cargo::cargo_bin!("cpx"))
-        .arg("config")
-        .arg("init")
-        .env("HOME", temp.path())
-        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .arg("-r")
+        .arg("--no-dereference-dest")
+        .arg(source.path())
+        .arg(dest.path())
         .assert()
         .success();
 
-    let config_path = temp.path().join(".config/cpx/cpxconfig.toml");
-    assert!(config_path.exists());
-
-    let contents = fs::read_to_string(&config_path).unwrap();
-    assert!(contents.contains("[exclude]"));
-    assert!(contents.contains("[copy]"));
-    assert!(contents.contains("[preserve]"));
+    for path in ["app/lib", "app/config.txt"] {
+        let metadata = dest.child(path).path().symlink_metadata().unwrap();
+        assert!(!metadata.is_symlink(), "{}", path);
+    }
+    dest.child("app/lib/code.txt").assert("new");
+    dest.child("app/config.txt").assert("new");
+    release.child("code.txt").assert("old");
+    release.child("config.txt").assert("old");
 }
 
 #[test]
-fn test_config_init_force_overwrite() {
+#[cfg(unix)]
+fn test_keep_directory_symlink_writes_through_directory_links() {
     let temp = assert_fs::TempDir::new().unwrap();
-    let config_dir = temp.path().join(".config/cpx");
-    fs::create_dir_all(&config_dir).unwrap();
+    let source = temp.child("notes.txt");
+    let release = temp.child("release");
+    let current = temp.child("current");
+    let latest = temp.child("latest.txt");
+    source.write_str("new").unwrap();
+    release.child("notes.txt").write_str("old").unwrap();
+    symlink(release.path(), current.path()).unwrap();
+    symlink(release.child("notes.txt").path(), latest.path()).unwrap();
 
-    let config_path = config_dir.join("cpxconfig.toml");
-    fs::write(&config_path, "old config").unwrap();
+    for dest in [&current, &latest] {
+        Command::new(cargo::cargo_bin!("cpx"))
+            .arg("--keep-directory-symlink")
+            .arg(source.path())
+            .arg(dest.path())
+            .assert()
+            .success();
+    }
+
+    // the directory link is copied into, the file link replaced
+    assert!(current.path().symlink_metadata().unwrap().is_symlink());
+    release.child("notes.txt").assert("new");
+    assert!(!latest.path().symlink_metadata().unwrap().is_symlink());
+    latest.assert("new");
+}
+
+#[test]
+fn test_glob_expands_sources() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let dest = temp.child("dest");
+    temp.child("logs/a.log").write_str("a").unwrap();
+    temp.child("logs/b.log").write_str("b").unwrap();
+    temp.child("logs/c.txt").write_str("c").unwrap();
+    dest.create_dir_all().unwrap();
 
     Command::new(cargo::cargo_bin!("cpx"))
-        .arg("config")
-        .arg("init")
-        .arg("--force")
+        .current_dir(temp.path())
+        .arg("--glob")
+        .arg("logs/*.log")
+        .arg("dest")
+        .assert()
+        .success();
+
+    dest.child("a.log").assert("a");
+    dest.child("b.log").assert("b");
+    dest.child("c.txt").assert(predicate::path::missing());
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .current_dir(temp.path())
+        .arg("--glob")
+        .arg("logs/*.csv")
+        .arg("dest")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no files match"));
+}
+
+#[test]
+fn test_copy_very_long_filename() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let long_name = "a".repeat(200) + ".txt";
+    let source = temp.child(&long_name);
+    let dest_dir = temp.child("dest");
+
+    source.write_str("content").unwrap();
+    dest_dir.create_dir_all().unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg(source.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success();
+
+    dest_dir.child(&long_name).assert("content");
+}
+
+#[test]
+fn test_config_init() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("config")
+        .arg("init")
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join(".config"))
+        .assert()
+        .success();
+
+    let config_path = temp.path().join(".config/cpx/cpxconfig.toml");
+    assert!(config_path.exists());
+
+    let contents = fs::read_to_string(&config_path).unwrap();
+    assert!(contents.contains("[exclude]"));
+    assert!(contents.contains("[copy]"));
+    assert!(contents.contains("[preserve]"));
+}
+
+#[test]
+fn test_config_init_force_overwrite() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_dir = temp.path().join(".config/cpx");
+    fs::create_dir_all(&config_dir).unwrap();
+
+    let config_path = config_dir.join("cpxconfig.toml");
+    fs::write(&config_path, "old config").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("config")
+        .arg("init")
+        .arg("--force")
         .env("HOME", temp.path())
         .env("XDG_CONFIG_HOME", temp.path().join(".config"))
         .assert()
@@ -1498,3 +1941,1448 @@ fn test_copy_empty_directory() {
     assert!(dest_dir.child("empty_source").path().exists());
     assert!(dest_dir.child("empty_source").path().is_dir());
 }
+
+#[test]
+fn test_progress_json_events() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+
+    source.write_str("json progress").unwrap();
+
+    let output = Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--progress=json")
+        .arg("--no-config")
+        .arg(source.path())
+        .arg(dest.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let events: Vec<String> = stdout
+        .lines()
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            value["event"].as_str().unwrap().to_string()
+        })
+        .collect();
+
+    assert_eq!(events.first().map(String::as_str), Some("scan_started"));
+    assert!(events.iter().any(|e| e == "file_started"));
+    assert!(events.iter().any(|e| e == "file_done"));
+    assert_eq!(events.last().map(String::as_str), Some("summary"));
+    dest.assert("json progress");
+}
+
+#[test]
+fn test_verbose_prints_each_file() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+
+    source.write_str("verbose").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-v")
+        .arg("--no-config")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "'{}' -> '{}'",
+            source.path().display(),
+            dest.path().display()
+        )));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_verbose_prints_a_symlink_by_its_own_path() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+
+    source_dir.child("a.txt").write_str("a").unwrap();
+    symlink("a.txt", source_dir.child("link.txt").path()).unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("-v")
+        .arg("--no-config")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "'{}' -> '{}'",
+            source_dir.child("link.txt").path().display(),
+            dest_dir.child("source/link.txt").path().display()
+        )))
+        .stdout(predicate::str::contains("'a.txt' ->").not());
+}
+
+#[test]
+fn test_very_verbose_reports_excluded() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+
+    source_dir.child("keep.txt").write_str("keep").unwrap();
+    source_dir.child("skip.tmp").write_str("skip").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("-vv")
+        .arg("--no-config")
+        .arg("-e")
+        .arg("*.tmp")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("skip.tmp' (excluded)"))
+        .stdout(predicate::str::contains("keep.txt' -> '"));
+}
+
+#[test]
+fn test_quiet_suppresses_output() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest_dir = temp.child("dest");
+
+    source.write_str("quiet").unwrap();
+    dest_dir.create_dir_all().unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-s")
+        .arg("--quiet")
+        .arg("--no-config")
+        .arg(source.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn test_quiet_and_verbose_conflict() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    source.write_str("x").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-q")
+        .arg("-v")
+        .arg(source.path())
+        .arg(temp.child("dest.txt").path())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_log_file_records_copies() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+    let log = temp.child("cpx.log");
+
+    source_dir.child("a.txt").write_str("a").unwrap();
+    source_dir.child("b.tmp").write_str("b").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--no-config")
+        .arg("-e")
+        .arg("*.tmp")
+        .arg("--log-file")
+        .arg(log.path())
+        .arg("--log-format")
+        .arg("json")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success();
+
+    let contents = fs::read_to_string(log.path()).unwrap();
+    let records: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(
+        records
+            .iter()
+            .any(|r| r["event"] == "copied" && r["source"].as_str().unwrap().ends_with("a.txt"))
+    );
+    assert!(
+        records
+            .iter()
+            .any(|r| r["event"] == "skipped" && r["message"] == "excluded")
+    );
+    assert!(records.iter().all(|r| r["ts"].is_string()));
+}
+
+#[test]
+fn test_stats_text_summary() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+
+    source.write_str("stats").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--stats")
+        .arg("--no-config")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Files copied:     1"))
+        .stdout(predicate::str::contains("Peak throughput:"));
+}
+
+#[test]
+fn test_stats_json_summary() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+
+    source_dir.child("a.txt").write_str("aaaa").unwrap();
+    source_dir.child("b.txt").write_str("bb").unwrap();
+
+    let output = Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--stats=json")
+        .arg("--no-config")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = stdout.lines().last().unwrap();
+    let stats: serde_json::Value = serde_json::from_str(line).unwrap();
+    assert_eq!(stats["files_copied"], 2);
+    assert_eq!(stats["files_failed"], 0);
+    assert_eq!(stats["bytes_copied"], 6);
+    assert!(stats["slowest_files"].is_array());
+}
+
+#[test]
+fn test_output_manifest_sha256() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+
+    source_dir.child("abc.txt").write_str("abc").unwrap();
+    source_dir.child("sub/empty.txt").write_str("").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--no-config")
+        .arg("--output-manifest")
+        .arg(temp.path())
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success();
+
+    let manifest = std::fs::read_to_string(temp.path().join("SHA256SUMS")).unwrap();
+    assert_eq!(
+        manifest,
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  dest/source/abc.txt\n\
+         e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  dest/source/sub/empty.txt\n"
+    );
+}
+
+#[test]
+fn test_checksum_algo_applies_to_manifest_and_verify() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+
+    source_dir.child("abc.txt").write_str("abc").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--no-config")
+        .arg("--verify")
+        .arg("--checksum-algo")
+        .arg("crc32")
+        .arg("--output-manifest")
+        .arg(temp.path())
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success();
+
+    let manifest = std::fs::read_to_string(temp.path().join("CRC32SUMS")).unwrap();
+    assert_eq!(manifest, "352441c2  dest/source/abc.txt\n");
+}
+
+#[test]
+fn test_copy_at_lowered_priority() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("report.txt");
+    source.write_str("quarterly").unwrap();
+    let dest = temp.child("report-copy.txt");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .args(["--nice", "10", "--ionice", "idle"])
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Could not lower priority").not());
+    dest.assert("quarterly");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .args(["--nice", "20"])
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .code(2);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_deterministic_runs_log_the_same() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("src");
+    for name in ["m.txt", "b/z.txt", "b/a.txt", "a.txt", "c/d/e.txt"] {
+        source.child(name).write_str(name).unwrap();
+    }
+    std::os::unix::fs::symlink("a.txt", source.child("link").path()).unwrap();
+    let dest = temp.child("dest");
+
+    let run = |log: &str| {
+        Command::new(cargo::cargo_bin!("cpx"))
+            .env("SOURCE_DATE_EPOCH", "1700000000")
+            .arg("--no-config")
+            .arg("-r")
+            .arg("--deterministic")
+            .arg("--log-file")
+            .arg(temp.child(log).path())
+            .arg(source.path())
+            .arg(dest.path())
+            .assert()
+            .success();
+        std::fs::remove_dir_all(dest.path()).unwrap();
+        std::fs::read_to_string(temp.child(log).path()).unwrap()
+    };
+    let first = run("first.log");
+    let second = run("second.log");
+    assert_eq!(first, second);
+
+    let copied: Vec<&str> = first
+        .lines()
+        .filter(|line| line.contains(" copied "))
+        .collect();
+    assert!(copied[0].starts_with("2023-11-14T22:13:20.000Z"));
+    assert!(copied[0].contains("src/link'"));
+    assert!(copied[1].contains("src/a.txt'"));
+    assert!(copied[2].contains("src/b/a.txt'"));
+    assert!(copied[5].contains("src/m.txt'"));
+}
+
+#[test]
+fn test_verify_readback_copies_tree() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+
+    source_dir.child("a.txt").write_str("alpha").unwrap();
+    source_dir.child("sub/b.txt").write_str("beta").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--no-config")
+        .arg("--verify=readback")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success();
+
+    dest_dir.child("source/a.txt").assert("alpha");
+    dest_dir.child("source/sub/b.txt").assert("beta");
+}
+
+#[test]
+fn test_verify_reports_digest_in_json() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+
+    source.write_str("verified").unwrap();
+
+    let output = Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--verify")
+        .arg("--progress=json")
+        .arg("--no-config")
+        .arg(source.path())
+        .arg(dest.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let done: serde_json::Value = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|value| value["event"] == "file_done")
+        .unwrap();
+    assert_eq!(done["digest"].as_str().map(str::len), Some(16));
+    dest.assert("verified");
+}
+
+#[test]
+fn test_exit_code_usage_error() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    source.write_str("usage").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-l")
+        .arg("-s")
+        .arg("--no-config")
+        .arg(source.path())
+        .arg(temp.child("dest.txt").path())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_exit_code_partial_failure() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+
+    source_dir.child("good.txt").write_str("good").unwrap();
+    source_dir.child("bad.txt").write_str("bad").unwrap();
+    // a directory in the way makes this one file fail
+    dest_dir.child("source/bad.txt").create_dir_all().unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--no-config")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Failed to copy 1 file(s)"));
+
+    dest_dir.child("source/good.txt").assert("good");
+}
+
+#[test]
+fn test_help_documents_exit_codes() {
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exit status:"))
+        .stdout(predicate::str::contains("130"));
+}
+
+#[test]
+fn test_ignore_errors_continues_past_missing_source() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let present = temp.child("present.txt");
+    let missing = temp.child("missing.txt");
+    let dest_dir = temp.child("dest");
+    let report = temp.child("failures.txt");
+
+    present.write_str("present").unwrap();
+    dest_dir.create_dir_all().unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg(missing.path())
+        .arg(present.path())
+        .arg(dest_dir.path())
+        .assert()
+        .code(1);
+    dest_dir
+        .child("present.txt")
+        .assert(predicate::path::missing());
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--ignore-errors")
+        .arg("--error-report")
+        .arg(report.path())
+        .arg(missing.path())
+        .arg(present.path())
+        .arg(dest_dir.path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("missing.txt"));
+
+    dest_dir.child("present.txt").assert("present");
+    report.assert(predicate::str::contains("[not found]"));
+    report.assert(predicate::str::contains("missing.txt"));
+}
+
+#[test]
+fn test_error_report_as_tsv() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let present = temp.child("present.txt");
+    let missing = temp.child("missing.txt");
+    let dest_dir = temp.child("dest");
+    let report = temp.child("failures.tsv");
+    present.write_str("present").unwrap();
+    dest_dir.create_dir_all().unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--ignore-errors")
+        .arg("--error-report")
+        .arg(report.path())
+        .arg("--error-report-format")
+        .arg("tsv")
+        .arg(missing.path())
+        .arg(present.path())
+        .arg(dest_dir.path())
+        .assert()
+        .code(1);
+
+    let contents = std::fs::read_to_string(report.path()).unwrap();
+    let fields: Vec<&str> = contents.trim_end().split('\t').collect();
+    assert_eq!(fields.len(), 7, "{}", contents);
+    assert_eq!(fields[0], missing.path().to_string_lossy());
+    assert_eq!(fields[3], "not found");
+    assert_eq!(fields[5], "0");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--error-report-format")
+        .arg("json")
+        .arg(missing.path())
+        .arg(dest_dir.path())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_retry_options_accepted() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+
+    source.write_str("retry").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--retries")
+        .arg("3")
+        .arg("--retry-delay")
+        .arg("250ms")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+    dest.assert("retry");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--retry-delay")
+        .arg("soon")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_bwlimit_paces_the_copy() {
+    use std::time::{Duration, Instant};
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.bin");
+    let dest = temp.child("dest.bin");
+    source.write_binary(&vec![7u8; 256 * 1024]).unwrap();
+
+    let started = Instant::now();
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--bwlimit")
+        .arg("1M")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert_eq!(fs::read(dest.path()).unwrap().len(), 256 * 1024);
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--bwlimit")
+        .arg("0")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .code(2);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_status_lifts_the_bwlimit() {
+    use std::time::{Duration, Instant};
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.bin");
+    let dest = temp.child("dest.bin");
+    // a minute's worth at the limit
+    source.write_binary(&vec![7u8; 4 << 20]).unwrap();
+    let runtime = temp.child("run");
+    runtime.create_dir_all().unwrap();
+
+    let started = Instant::now();
+    let mut child = Command::new(cargo::cargo_bin!("cpx"))
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .arg("--no-config")
+        .arg("-q")
+        .arg("--status-socket")
+        .arg("--bwlimit")
+        .arg("64K")
+        .arg(source.path())
+        .arg(dest.path())
+        .spawn()
+        .unwrap();
+
+    let status = |args: &[&str]| {
+        let output = Command::new(cargo::cargo_bin!("cpx"))
+            .env("XDG_RUNTIME_DIR", runtime.path())
+            .arg("status")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    while !status(&[]).contains("limited to 64.00 KiB/s") {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "the copy never answered"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(!status(&["--bwlimit", "off"]).contains("limited"));
+
+    assert!(child.wait().unwrap().success());
+    assert!(started.elapsed() < Duration::from_secs(30));
+    assert_eq!(fs::read(dest.path()).unwrap().len(), 4 << 20);
+}
+
+#[test]
+fn test_max_errors_stops_remaining_files() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("source");
+    let dest_dir = temp.child("dest");
+
+    // larger files are copied first, so the failures happen before the small file
+    for name in ["bad1.bin", "bad2.bin", "bad3.bin"] {
+        source_dir.child(name).write_binary(&[0u8; 4096]).unwrap();
+        dest_dir
+            .child("source")
+            .child(name)
+            .create_dir_all()
+            .unwrap();
+    }
+    source_dir.child("good.txt").write_str("g").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("-j")
+        .arg("1")
+        .arg("--no-config")
+        .arg("--max-errors")
+        .arg("0")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("--max-errors 0"))
+        .stderr(predicate::str::contains("Completed:"));
+
+    dest_dir
+        .child("source/good.txt")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn test_destination_inside_source_is_rejected() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("tree");
+    source_dir.child("file.txt").write_str("data").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("-r")
+        .arg("--no-config")
+        .arg(source_dir.path())
+        .arg(source_dir.child("backup").path())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("into itself"));
+
+    source_dir
+        .child("backup")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn test_progress_plain_lines_when_not_a_tty() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+    source.write_str("piped progress").unwrap();
+
+    let output = Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg(source.path())
+        .arg(dest.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("100%"));
+    assert!(!stderr.contains('\r'));
+    assert!(!stderr.contains('\x1b'));
+    dest.assert("piped progress");
+
+    // the walk is over before it has anything to report
+    temp.child("tree/a.txt").write_str("a").unwrap();
+    let output = Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg(temp.child("tree").path())
+        .arg(temp.child("copy").path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(
+        !String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Scanning")
+    );
+}
+
+#[test]
+fn test_progress_never_is_silent() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+    source.write_str("quiet progress").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--progress=detailed")
+        .arg("--progress=never")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    dest.assert("quiet progress");
+}
+
+#[test]
+fn test_tui_requires_terminal() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    source.write_str("tui").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--tui")
+        .arg(source.path())
+        .arg(temp.child("dest.txt").path())
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--tui requires stdout to be a terminal",
+        ));
+
+    temp.child("dest.txt").assert(predicate::path::missing());
+}
+
+#[test]
+fn test_notify_skipped_for_short_runs() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    let dest = temp.child("dest.txt");
+    source.write_str("notify").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("--notify-after=1h")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+
+    dest.assert("notify");
+}
+
+#[test]
+fn test_progress_counts_files() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source_dir = temp.child("many");
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        source_dir.child(name).write_str(name).unwrap();
+    }
+    let dest_dir = temp.child("dest");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg(source_dir.path())
+        .arg(dest_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("3/3 files"));
+}
+
+#[test]
+fn test_completions_script() {
+    Command::new(cargo::cargo_bin!("cpx"))
+        .args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("_cpx()"))
+        .stdout(predicate::str::contains("--resume"));
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .args(["completions", "tcsh"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_remote_destination_rejects_local_only_flags() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    source.write_str("remote").unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .current_dir(temp.path())
+        .arg("--no-config")
+        .arg("--link")
+        .arg(source.path())
+        .arg("backup@example.invalid:/srv")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--link cannot be used with a remote destination",
+        ));
+}
+
+#[test]
+#[cfg(feature = "remote")]
+fn test_remote_destination_connection_failure() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    source.write_str("remote").unwrap();
+
+    // nothing listens on port 1, so the connection is refused straight away
+    Command::new(cargo::cargo_bin!("cpx"))
+        .current_dir(temp.path())
+        .arg("--no-config")
+        .arg(source.path())
+        .arg("ssh://127.0.0.1:1/srv")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("refused"));
+}
+
+#[test]
+#[cfg(feature = "remote")]
+fn test_remote_destination_with_default_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("source.txt");
+    source.write_str("remote").unwrap();
+
+    // no --no-config: the built-in defaults must not count as local-only
+    // flags
+    Command::new(cargo::cargo_bin!("cpx"))
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join("config"))
+        .arg(source.path())
+        .arg("ssh://127.0.0.1:1/srv")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used").not())
+        .stderr(predicate::str::contains("refused"));
+}
+
+#[test]
+fn test_archive_destination() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("data");
+    source.child("a.txt").write_str("alpha").unwrap();
+    source.child("skip.tmp").write_str("tmp").unwrap();
+    let archive = temp.child("backup.tar");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .args(["-e", "*.tmp"])
+        .arg(source.path())
+        .arg(archive.path())
+        .assert()
+        .success();
+
+    let mut entries: Vec<String> = tar::Archive::new(std::fs::File::open(archive.path()).unwrap())
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, ["data", "data/a.txt"]);
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--resume")
+        .arg(source.path())
+        .arg(temp.child("again.tar").path())
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--resume cannot be used with an archive destination",
+        ));
+}
+
+#[test]
+fn test_archive_destination_with_default_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("data");
+    source.child("a.txt").write_str("alpha").unwrap();
+    let archive = temp.child("out.tar");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .current_dir(temp.path())
+        .env("HOME", temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join("config"))
+        .arg("-r")
+        .arg(source.path())
+        .arg(archive.path())
+        .assert()
+        .success();
+    archive.assert(predicate::path::is_file());
+}
+
+#[test]
+fn test_archive_source_round_trip() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("data");
+    source.child("a.txt").write_str("alpha").unwrap();
+    source.child("nested/b.txt").write_str("beta").unwrap();
+    source.child("nested/c.log").write_str("log").unwrap();
+    let archive = temp.child("backup.tar.zst");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg(source.path())
+        .arg(archive.path())
+        .assert()
+        .success();
+
+    let restore = temp.child("restore");
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .args(["-e", "*.log"])
+        .arg(archive.path())
+        .arg(restore.path())
+        .assert()
+        .success();
+
+    restore.child("data/a.txt").assert("alpha");
+    restore.child("data/nested/b.txt").assert("beta");
+    restore
+        .child("data/nested/c.log")
+        .assert(predicate::path::missing());
+
+    // without -r the archive is an ordinary file
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg(archive.path())
+        .arg(temp.child("copy.tar.zst").path())
+        .assert()
+        .success();
+    temp.child("copy.tar.zst")
+        .assert(predicate::path::is_file());
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--verify")
+        .arg(archive.path())
+        .arg(restore.path())
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--verify cannot be used with an archive source",
+        ));
+}
+
+#[test]
+fn test_compress_destination_manifest() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("data");
+    source.child("a.txt").write_str("alpha").unwrap();
+    let backup = temp.child("backup");
+    let manifest = temp.child("MANIFEST");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .args(["--compress", "zstd", "--compress-level", "19"])
+        .arg("--output-manifest")
+        .arg(manifest.path())
+        .arg(source.path())
+        .arg(backup.path())
+        .assert()
+        .success();
+
+    backup
+        .child("data/a.txt.zst")
+        .assert(predicate::path::is_file());
+    // digest and size of the original contents
+    manifest.assert(predicate::str::contains("  5  backup/data/a.txt.zst"));
+
+    let restore = temp.child("restore");
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--decompress")
+        .arg(backup.child("data").path())
+        .arg(restore.path())
+        .assert()
+        .success();
+    restore.child("data/a.txt").assert("alpha");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_dedupe_hard_links_identical_files() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("photos");
+    source.child("a/1.jpg").write_str("same pixels").unwrap();
+    source
+        .child("b/1-copy.jpg")
+        .write_str("same pixels")
+        .unwrap();
+    source.child("b/2.jpg").write_str("other pixel").unwrap();
+    let dest = temp.child("backup");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--dedupe")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Deduplicated 1 files, saved 11 B"));
+
+    let original = fs::metadata(dest.child("photos/a/1.jpg").path()).unwrap();
+    let duplicate = fs::metadata(dest.child("photos/b/1-copy.jpg").path()).unwrap();
+    let other = fs::metadata(dest.child("photos/b/2.jpg").path()).unwrap();
+    assert_eq!(original.ino(), duplicate.ino());
+    assert_ne!(original.ino(), other.ino());
+    dest.child("photos/b/1-copy.jpg").assert("same pixels");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_hard_linked_sources_copied_separately() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("src");
+    source.child("a.txt").write_str("shared data").unwrap();
+    fs::hard_link(source.child("a.txt").path(), source.child("b.txt").path()).unwrap();
+    let dest = temp.child("dst");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+
+    let a = fs::metadata(dest.child("src/a.txt").path()).unwrap();
+    let b = fs::metadata(dest.child("src/b.txt").path()).unwrap();
+    assert_ne!(a.ino(), b.ino());
+    dest.child("src/a.txt").assert("shared data");
+    dest.child("src/b.txt").assert("shared data");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_watch_copies_changes_until_interrupted() {
+    use std::time::{Duration, Instant};
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("src");
+    source.child("a.txt").write_str("first").unwrap();
+    let dest = temp.child("mirror");
+
+    let mut child = Command::new(cargo::cargo_bin!("cpx"))
+        .arg("watch")
+        .arg("--no-config")
+        .arg("-r")
+        .arg("-q")
+        .arg("-e")
+        .arg("*.tmp")
+        .arg("--debounce")
+        .arg("100ms")
+        .arg(source.path())
+        .arg(dest.path())
+        .spawn()
+        .unwrap();
+
+    let wait_for = |path: &std::path::Path, contents: &str| {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while fs::read_to_string(path).ok().as_deref() != Some(contents) {
+            assert!(
+                Instant::now() < deadline,
+                "{} was not copied",
+                path.display()
+            );
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    };
+    wait_for(dest.child("src/a.txt").path(), "first");
+
+    source.child("a.txt").write_str("second").unwrap();
+    source.child("new/b.txt").write_str("added").unwrap();
+    source.child("scratch.tmp").write_str("ignored").unwrap();
+    wait_for(dest.child("src/a.txt").path(), "second");
+    wait_for(dest.child("src/new/b.txt").path(), "added");
+
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    assert!(child.wait().unwrap().success());
+    dest.child("src/scratch.tmp")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn test_verify_compares_copy_with_source() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("src");
+    source.child("a.txt").write_str("alpha").unwrap();
+    source.child("sub/b.txt").write_str("beta").unwrap();
+    source.child("cache/c.tmp").write_str("scratch").unwrap();
+    let dest = temp.child("backup");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("verify")
+        .arg(source.path())
+        .arg(dest.child("src").path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "3 matched, 0 missing, 0 extra, 0 differing",
+        ));
+
+    dest.child("src/sub/b.txt").write_str("bent").unwrap();
+    dest.child("src/extra.txt").write_str("new").unwrap();
+    fs::remove_dir_all(dest.child("src/cache").path()).unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("verify")
+        .arg("--format")
+        .arg("json")
+        .arg(source.path())
+        .arg(dest.child("src").path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            r#""missing":["cache"],"extra":["extra.txt"],"differing":[{"path":"sub/b.txt","reason":"checksum"}]"#,
+        ));
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("verify")
+        .arg("-e")
+        .arg("cache,extra.txt")
+        .arg("--quick")
+        .arg(source.path())
+        .arg(dest.child("src").path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_diff_lists_changes_without_writing() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("src");
+    source.child("same.txt").write_str("same").unwrap();
+    source.child("grown.txt").write_str("grown longer").unwrap();
+    source.child("new/a.bin").write_str("12345").unwrap();
+    let dest = temp.child("dst");
+    dest.child("grown.txt").write_str("grown").unwrap();
+    dest.child("stale.log").write_str("old").unwrap();
+    fs::copy(
+        source.child("same.txt").path(),
+        dest.child("same.txt").path(),
+    )
+    .unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("diff")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("+ new (5 B)"))
+        .stdout(predicate::str::contains("- stale.log (3 B)"))
+        .stdout(predicate::str::contains("~ grown.txt (size, +7 B)"))
+        .stdout(predicate::str::contains("1 modified, 1 unchanged"));
+    dest.child("new").assert(predicate::path::missing());
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("diff")
+        .arg("--format")
+        .arg("json")
+        .arg("-e")
+        .arg("new,stale.log,grown.txt")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""unchanged":1"#));
+}
+
+#[test]
+fn test_check_reports_names_the_destination_refuses() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("src");
+    let dest = temp.child("dest");
+    source.child("ok.txt").write_str("ok").unwrap();
+    dest.create_dir_all().unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("check")
+        .arg("-r")
+        .arg("--windows-names")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Every destination path can be created",
+        ));
+
+    source.child("report 12:30.txt").write_str("bad").unwrap();
+    source.child("aux.log").write_str("bad").unwrap();
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("check")
+        .arg("-r")
+        .arg("--windows-names")
+        .arg("--format")
+        .arg("json")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(r#""invalid_character""#))
+        .stdout(predicate::str::contains(r#""reserved_name""#));
+
+    // nothing was copied
+    assert_eq!(fs::read_dir(dest.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_plan_exports_and_apply_runs_it() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let source = temp.child("src");
+    source.child("a.txt").write_str("alpha").unwrap();
+    source.child("sub/b.txt").write_str("beta").unwrap();
+    source.child("debug.log").write_str("noise").unwrap();
+    let dest = temp.child("dst");
+    let plan = temp.child("plan.json");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("plan")
+        .arg("--no-config")
+        .arg("-r")
+        .arg("-e")
+        .arg("*.log")
+        .arg("--format")
+        .arg("csv")
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "action,source,destination,size,detail\n",
+        ))
+        .stdout(predicate::str::contains(",5,\n"))
+        .stdout(predicate::str::contains("debug.log,,,excluded\n"));
+    dest.assert(predicate::path::missing());
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("plan")
+        .arg("--no-config")
+        .arg("-r")
+        .arg("-e")
+        .arg("*.log")
+        .arg("-o")
+        .arg(plan.path())
+        .arg(source.path())
+        .arg(dest.path())
+        .assert()
+        .success();
+    plan.assert(predicate::str::contains(r#""total_files": 2"#));
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("apply")
+        .arg(plan.path())
+        .assert()
+        .success();
+    dest.child("src/a.txt").assert("alpha");
+    dest.child("src/sub/b.txt").assert("beta");
+    dest.child("src/debug.log")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn test_bench_reports_each_case_and_cleans_up() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("bench")
+        .arg("--dir")
+        .arg(temp.path())
+        .arg("--files")
+        .arg("2")
+        .arg("--file-size")
+        .arg("4K")
+        .arg("--backends")
+        .arg("buffered")
+        .arg("--buffer-sizes")
+        .arg("4K,16K")
+        .arg("--parallel")
+        .arg("1,2")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 files, 8.00 KiB per run"))
+        .stdout(predicate::str::contains("16.00 KiB").count(2))
+        .stdout(predicate::str::contains("Suggested config:"));
+    assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_undo_reverts_a_journaled_copy() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("src/a.txt").write_str("new").unwrap();
+    temp.child("src/sub/b.txt").write_str("beta").unwrap();
+    let dest = temp.child("dst");
+    dest.child("a.txt").write_str("old").unwrap();
+    let journal = temp.child("journal.jsonl");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--backup=simple")
+        .arg("--journal")
+        .arg(journal.path())
+        .arg(temp.child("src/a.txt").path())
+        .arg(temp.child("src/sub").path())
+        .arg(dest.path())
+        .assert()
+        .success();
+    dest.child("a.txt").assert("new");
+    dest.child("sub/b.txt").assert("beta");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("undo")
+        .arg(journal.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 undone, 0 could not be undone"));
+    dest.child("a.txt").assert("old");
+    dest.child("a.txt~").assert(predicate::path::missing());
+    dest.child("sub").assert(predicate::path::missing());
+}
+
+#[test]
+fn test_fsync_copies_trees_and_archives() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("src/a.txt").write_str("alpha").unwrap();
+    temp.child("src/sub/b.txt").write_str("beta").unwrap();
+    let archive = temp.child("backup.tar");
+
+    for (mode, dest) in [
+        ("--fsync", temp.child("all")),
+        ("--fsync=file", temp.child("file")),
+        ("--fsync=dir", temp.child("dir")),
+    ] {
+        Command::new(cargo::cargo_bin!("cpx"))
+            .arg("--no-config")
+            .arg("-r")
+            .arg(mode)
+            .arg(temp.child("src").path())
+            .arg(dest.path())
+            .assert()
+            .success();
+        dest.child("src/sub/b.txt").assert("beta");
+    }
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--fsync=all")
+        .arg(temp.child("src").path())
+        .arg(archive.path())
+        .assert()
+        .success();
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--fsync=all")
+        .arg(archive.path())
+        .arg(temp.child("restored").path())
+        .assert()
+        .success();
+    temp.child("restored/src/a.txt").assert("alpha");
+}
+
+#[test]
+fn test_metrics_file_written() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("src/a.txt").write_str("alpha").unwrap();
+    temp.child("src/b.txt").write_str("beta").unwrap();
+    let metrics = temp.child("cpx.prom");
+
+    Command::new(cargo::cargo_bin!("cpx"))
+        .arg("--no-config")
+        .arg("-r")
+        .arg("--metrics-file")
+        .arg(metrics.path())
+        .arg(temp.child("src").path())
+        .arg(temp.child("dest").path())
+        .assert()
+        .success();
+    metrics.assert(predicate::str::contains("\ncpx_files_copied 2\n"));
+    metrics.assert(predicate::str::contains("\ncpx_bytes_copied 9\n"));
+    metrics.assert(predicate::str::contains("\ncpx_last_run_success 1\n"));
+}